@@ -0,0 +1,375 @@
+//! Structural analyses of a CNF that do not solve it.
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{Args, Subcommand};
+use validator::Validate;
+
+use crate::{
+    autarky::{self, Step},
+    classes,
+    core::open_input,
+    dimacs::{read_dimacs, write_dimacs},
+    reconstruct::{Entry, Log},
+};
+
+#[derive(Args)]
+pub struct Arg {
+    #[command(subcommand)]
+    command: Analysis,
+}
+
+#[derive(Subcommand)]
+enum Analysis {
+    /// Report which tractable or structured classes the formula, or parts of it, belong to
+    Class(ClassArg),
+    /// Find pure literals and autarkies, and optionally remove the clauses they satisfy
+    Autarky(AutarkyArg),
+    /// Find equivalent literals through the binary clauses, and optionally substitute them
+    Equiv(EquivArg),
+    /// Extend a model of a reduced formula to the original with a reconstruction log
+    Reconstruct(ReconstructArg),
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        match &self.command {
+            Analysis::Class(arg) => arg.run(),
+            Analysis::Autarky(arg) => arg.run(),
+            Analysis::Equiv(arg) => arg.run(),
+            Analysis::Reconstruct(arg) => arg.run(),
+        }
+    }
+}
+
+/// Options every analysis shares.
+#[derive(Args)]
+struct InputArg {
+    /// CNF to analyze (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+impl InputArg {
+    fn read(&self) -> anyhow::Result<Vec<Vec<i32>>> {
+        let reader = open_input(self.input.as_deref())?;
+        let mut clauses = Vec::new();
+        read_dimacs(reader, false, &mut clauses)?;
+        Ok(clauses)
+    }
+}
+
+#[derive(Args, Validate)]
+struct ClassArg {
+    #[command(flatten)]
+    input: InputArg,
+    /// Largest XOR, in variables, looked for in the clauses
+    #[arg(long = "max-xor", value_name = "N", default_value_t = 6)]
+    #[validate(range(min = 3, max = 20, message = "Max XOR must be in [3, 20]"))]
+    max_xor: usize,
+}
+
+impl ClassArg {
+    fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let clauses = self.input.read()?;
+        let total = clauses.len();
+        let vars = clauses
+            .iter()
+            .flatten()
+            .map(|lit| lit.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        let count = |f: fn(&[i32]) -> bool| clauses.iter().filter(|c| f(c)).count();
+        let binary = count(|c| c.len() <= 2);
+        let horn = count(classes::is_horn_clause);
+        let dual_horn = count(classes::is_dual_horn_clause);
+        let renaming = classes::horn_renaming(&clauses);
+        let xors = classes::find_xors(&clauses, self.max_xor);
+        let xor_clauses: usize = xors.iter().map(|x| x.clauses).sum();
+
+        if self.input.json {
+            let json = serde_json::json!({
+                "clauses": total,
+                "variables": vars,
+                "2sat": { "member": binary == total, "clauses": binary },
+                "horn": { "member": horn == total, "clauses": horn },
+                "dual_horn": { "member": dual_horn == total, "clauses": dual_horn },
+                "renamable_horn": {
+                    "member": renaming.is_some(),
+                    "flipped": renaming,
+                },
+                "xor": {
+                    "constraints": xors.len(),
+                    "clauses": xor_clauses,
+                    "max_len": self.max_xor,
+                },
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            return Ok(0);
+        }
+        let share = |n: usize| {
+            if total == 0 {
+                100.0
+            } else {
+                100.0 * n as f64 / total as f64
+            }
+        };
+        let member = |n: usize| if n == total { "yes" } else { "no" };
+        println!("clauses          {}", total);
+        println!("variables        {}", vars);
+        println!(
+            "2-SAT            {:<4}{:.1}% of clauses have at most 2 literals",
+            member(binary),
+            share(binary)
+        );
+        println!(
+            "Horn             {:<4}{:.1}% of clauses have at most 1 positive literal",
+            member(horn),
+            share(horn)
+        );
+        println!(
+            "dual Horn        {:<4}{:.1}% of clauses have at most 1 negative literal",
+            member(dual_horn),
+            share(dual_horn)
+        );
+        match &renaming {
+            Some(flipped) => println!(
+                "renamable Horn   yes Horn after flipping {} variables",
+                flipped.len()
+            ),
+            None => println!("renamable Horn   no"),
+        }
+        println!(
+            "XOR              {} constraints of 3-{} variables in {:.1}% of clauses",
+            xors.len(),
+            self.max_xor,
+            share(xor_clauses)
+        );
+        Ok(0)
+    }
+}
+
+#[derive(Args)]
+struct AutarkyArg {
+    #[command(flatten)]
+    input: InputArg,
+    /// Write the clauses no pure literal or autarky satisfies to this CNF
+    #[arg(long, value_name = "FILE", requires = "log")]
+    apply: Option<PathBuf>,
+    /// Write the literals set by the reduction to this file, for `analyze reconstruct`
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+}
+
+impl AutarkyArg {
+    fn run(&self) -> anyhow::Result<i32> {
+        let clauses = self.input.read()?;
+        let reduction = autarky::reduce(&clauses);
+        let pure: Vec<i32> = reduction
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Pure(lit) => Some(*lit),
+                Step::Autarky(_) => None,
+            })
+            .collect();
+        let autarkies: Vec<&[i32]> = reduction
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Pure(_) => None,
+                Step::Autarky(lits) => Some(lits.as_slice()),
+            })
+            .collect();
+        let satisfied = clauses.len() - reduction.remaining.len();
+        if self.input.json {
+            let json = serde_json::json!({
+                "clauses": clauses.len(),
+                "pure_literals": pure,
+                "autarkies": autarkies,
+                "satisfied_clauses": satisfied,
+                "remaining_clauses": reduction.remaining.len(),
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        } else {
+            println!("pure literals    {}", pure.len());
+            println!(
+                "autarkies        {} ({} variables)",
+                autarkies.len(),
+                autarkies.iter().map(|a| a.len()).sum::<usize>()
+            );
+            println!(
+                "satisfied        {} of {} clauses",
+                satisfied,
+                clauses.len()
+            );
+            println!("remaining        {} clauses", reduction.remaining.len());
+        }
+        let log = Log {
+            entries: reduction
+                .steps
+                .iter()
+                .map(|step| Entry::Set(step.literals().to_vec()))
+                .collect(),
+        };
+        write_reduction(
+            self.apply.as_deref(),
+            self.log.as_deref(),
+            &reduction.remaining,
+            &log,
+        )?;
+        Ok(0)
+    }
+}
+
+#[derive(Args)]
+struct EquivArg {
+    #[command(flatten)]
+    input: InputArg,
+    /// Write the clauses with every literal replaced by its representative to this CNF
+    #[arg(long, value_name = "FILE", requires = "log")]
+    apply: Option<PathBuf>,
+    /// Write the substituted variables to this file, for `analyze reconstruct`
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+}
+
+impl EquivArg {
+    fn run(&self) -> anyhow::Result<i32> {
+        let clauses = self.input.read()?;
+        let Some(representatives) = classes::equivalences(&clauses) else {
+            if self.input.json {
+                println!("{}", serde_json::json!({ "unsatisfiable": true }));
+            } else {
+                println!("UNSAT: a literal is equivalent to its negation");
+            }
+            return Ok(20);
+        };
+        // Every class listed by its representative first.
+        let mut groups: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for (var, &repr) in (1..).zip(&representatives) {
+            let lit = if repr > 0 { var } else { -var };
+            groups.entry(repr.abs()).or_default().push(lit);
+        }
+        let groups: Vec<Vec<i32>> = groups.into_values().filter(|g| g.len() > 1).collect();
+        let substituted = groups.iter().map(|g| g.len() - 1).sum::<usize>();
+        if self.input.json {
+            let json = serde_json::json!({
+                "variables": representatives.len(),
+                "classes": groups,
+                "substituted_variables": substituted,
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        } else {
+            println!("classes          {}", groups.len());
+            println!(
+                "substituted      {} of {} variables",
+                substituted,
+                representatives.len()
+            );
+            for group in &groups {
+                let lits: Vec<_> = group.iter().map(i32::to_string).collect();
+                println!("equivalent       {}", lits.join(" "));
+            }
+        }
+        let log = Log::equivalences(&representatives);
+        let remaining = classes::substitute(&clauses, &representatives);
+        write_reduction(self.apply.as_deref(), self.log.as_deref(), &remaining, &log)?;
+        Ok(0)
+    }
+}
+
+/// Writes the `--apply` formula and the `--log` reconstruction log of a
+/// simplifying analysis.
+fn write_reduction(
+    apply: Option<&Path>,
+    log: Option<&Path>,
+    clauses: &[Vec<i32>],
+    reconstruction: &Log,
+) -> anyhow::Result<()> {
+    if let Some(path) = apply {
+        write_dimacs(
+            BufWriter::new(
+                File::create(path).with_context(|| format!("Cannot create {}", path.display()))?,
+            ),
+            clauses,
+        )?;
+    }
+    if let Some(path) = log {
+        fs::write(path, reconstruction.text())
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Args)]
+struct ReconstructArg {
+    /// Reconstruction log written by `analyze autarky --log`, `analyze equiv --log`, `pipeline --log` or a solver's --reconstruction-log
+    #[arg(long, value_name = "FILE", required = true)]
+    log: PathBuf,
+    /// Model of the reduced formula: a result file of this tool, or `v` lines; default for stdin
+    #[arg(value_name = "MODEL")]
+    model: Option<PathBuf>,
+}
+
+impl ReconstructArg {
+    fn run(&self) -> anyhow::Result<i32> {
+        let text = fs::read_to_string(&self.log)
+            .with_context(|| format!("Cannot read {}", self.log.display()))?;
+        let log = Log::parse(&text)
+            .with_context(|| format!("Invalid reconstruction log {}", self.log.display()))?;
+        let text = match &self.model {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Cannot read {}", path.display()))?,
+            None => io::read_to_string(io::stdin())?,
+        };
+        if text
+            .lines()
+            .any(|line| matches!(line.trim(), "UNSAT" | "s UNSATISFIABLE"))
+        {
+            println!("UNSAT");
+            return Ok(20);
+        }
+        let model: Vec<i32> = literal_lines(&text)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("Invalid model")?
+            .concat();
+        let extended = log.extend(&model);
+        println!("SAT");
+        let literals: Vec<_> = extended.iter().map(i32::to_string).collect();
+        println!("{} 0", literals.join(" "));
+        Ok(0)
+    }
+}
+
+/// The literals of every line that is not a comment or a status, with a
+/// leading `v` and the terminating `0` dropped.
+pub(crate) fn literal_lines(text: &str) -> impl Iterator<Item = anyhow::Result<Vec<i32>>> + '_ {
+    text.lines()
+        .map(|line| line.trim())
+        .map(|line| line.strip_prefix("v ").unwrap_or(line))
+        .filter(|line| {
+            !line.is_empty()
+                && !line.starts_with(['c', 's'])
+                && !matches!(*line, "SAT" | "UNSAT" | "UNKNOWN")
+        })
+        .map(|line| {
+            line.split_whitespace()
+                .map(|token| {
+                    token
+                        .parse::<i32>()
+                        .map_err(|_| anyhow::anyhow!("invalid literal `{token}`"))
+                })
+                .filter(|lit| !matches!(lit, Ok(0)))
+                .collect()
+        })
+}
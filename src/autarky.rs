@@ -0,0 +1,192 @@
+//! Pure literals and autarkies: partial assignments that satisfy every
+//! clause they touch, so removing those clauses preserves satisfiability.
+//!
+//! General autarky search is NP-hard; besides pure literals this finds the
+//! autarkies that unit propagation from a single literal closes off.
+
+/// Result of [`reduce`].
+pub struct Reduction {
+    /// Steps in the order they were found; each is a pure literal or an
+    /// autarky, given as the literals it sets true.
+    pub steps: Vec<Step>,
+    /// Clauses no step satisfied.
+    pub remaining: Vec<Vec<i32>>,
+}
+
+pub enum Step {
+    Pure(i32),
+    Autarky(Vec<i32>),
+}
+
+impl Step {
+    pub fn literals(&self) -> &[i32] {
+        match self {
+            Step::Pure(lit) => std::slice::from_ref(lit),
+            Step::Autarky(lits) => lits,
+        }
+    }
+}
+
+struct State<'a> {
+    clauses: &'a [Vec<i32>],
+    active: Vec<bool>,
+    /// Active clauses each literal occurs in.
+    count: Vec<usize>,
+    occurs: Vec<Vec<usize>>,
+    /// 1 true, -1 false, 0 unassigned; indexed by variable.
+    value: Vec<i8>,
+}
+
+fn index(lit: i32) -> usize {
+    2 * (lit.unsigned_abs() as usize - 1) + usize::from(lit < 0)
+}
+
+impl<'a> State<'a> {
+    fn new(clauses: &'a [Vec<i32>]) -> Self {
+        let vars = clauses
+            .iter()
+            .flatten()
+            .map(|lit| lit.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0);
+        let mut count = vec![0; 2 * vars];
+        let mut occurs = vec![Vec::new(); 2 * vars];
+        for (i, clause) in clauses.iter().enumerate() {
+            for &lit in clause {
+                count[index(lit)] += 1;
+                occurs[index(lit)].push(i);
+            }
+        }
+        Self {
+            clauses,
+            active: vec![true; clauses.len()],
+            count,
+            occurs,
+            value: vec![0; vars + 1],
+        }
+    }
+
+    fn vars(&self) -> i32 {
+        self.value.len() as i32 - 1
+    }
+
+    fn lit_value(&self, lit: i32) -> i8 {
+        let value = self.value[lit.unsigned_abs() as usize];
+        if lit > 0 { value } else { -value }
+    }
+
+    /// Sets `lits` for good and drops the clauses they satisfy; returns the
+    /// variables whose occurrence counts changed.
+    fn commit(&mut self, lits: &[i32]) -> Vec<i32> {
+        let mut touched = Vec::new();
+        for &lit in lits {
+            self.value[lit.unsigned_abs() as usize] = if lit > 0 { 1 } else { -1 };
+        }
+        for &lit in lits {
+            for i in 0..self.occurs[index(lit)].len() {
+                let c = self.occurs[index(lit)][i];
+                if !self.active[c] {
+                    continue;
+                }
+                self.active[c] = false;
+                for &other in &self.clauses[c] {
+                    self.count[index(other)] -= 1;
+                    touched.push(other.abs());
+                }
+            }
+        }
+        touched
+    }
+
+    /// Assigns pure literals until none are left, starting from `queue`.
+    fn pure_literals(&mut self, mut queue: Vec<i32>, steps: &mut Vec<Step>) {
+        while let Some(var) = queue.pop() {
+            if self.value[var as usize] != 0 {
+                continue;
+            }
+            let (pos, neg) = (self.count[index(var)], self.count[index(-var)]);
+            let lit = match (pos, neg) {
+                (0, 0) => continue,
+                (_, 0) => var,
+                (0, _) => -var,
+                _ => continue,
+            };
+            queue.extend(self.commit(&[lit]));
+            steps.push(Step::Pure(lit));
+        }
+    }
+
+    /// Propagates `lit` through the active clauses it falsifies literals of;
+    /// succeeds if every such clause ends up satisfied without branching.
+    fn propagation_autarky(&mut self, lit: i32) -> Option<Vec<i32>> {
+        let clauses = self.clauses;
+        let mut trail = vec![lit];
+        self.value[lit.unsigned_abs() as usize] = if lit > 0 { 1 } else { -1 };
+        let mut next = 0;
+        let mut ok = true;
+        'propagate: while next < trail.len() {
+            let falsified = index(-trail[next]);
+            next += 1;
+            for i in 0..self.occurs[falsified].len() {
+                let c = self.occurs[falsified][i];
+                if !self.active[c] || clauses[c].iter().any(|&l| self.lit_value(l) > 0) {
+                    continue;
+                }
+                let mut open = clauses[c]
+                    .iter()
+                    .copied()
+                    .filter(|&l| self.lit_value(l) == 0);
+                match (open.next(), open.next().is_some()) {
+                    (Some(unit), false) => {
+                        self.value[unit.unsigned_abs() as usize] = if unit > 0 { 1 } else { -1 };
+                        trail.push(unit);
+                    }
+                    _ => {
+                        ok = false;
+                        break 'propagate;
+                    }
+                }
+            }
+        }
+        for &l in &trail {
+            self.value[l.unsigned_abs() as usize] = 0;
+        }
+        ok.then_some(trail)
+    }
+}
+
+/// Removes pure literals and propagation autarkies from `clauses` until
+/// neither is left.
+pub fn reduce(clauses: &[Vec<i32>]) -> Reduction {
+    let mut state = State::new(clauses);
+    let mut steps = Vec::new();
+    state.pure_literals((1..=state.vars()).collect(), &mut steps);
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for var in 1..=state.vars() {
+            if state.value[var as usize] != 0 {
+                continue;
+            }
+            for lit in [var, -var] {
+                if state.count[index(-lit)] == 0 {
+                    continue;
+                }
+                if let Some(autarky) = state.propagation_autarky(lit) {
+                    let touched = state.commit(&autarky);
+                    steps.push(Step::Autarky(autarky));
+                    state.pure_literals(touched, &mut steps);
+                    progress = true;
+                    break;
+                }
+            }
+        }
+    }
+    let remaining = clauses
+        .iter()
+        .zip(&state.active)
+        .filter(|(_, active)| **active)
+        .map(|(clause, _)| clause.clone())
+        .collect();
+    Reduction { steps, remaining }
+}
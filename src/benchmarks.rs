@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use clap::Args;
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::{
+    core::hex,
+    dimacs::{AsDimacs, decompress, read_dimacs},
+    fetch::{self, FetchArg, UrlSource},
+};
+
+const MANIFEST: &str = "SHA256SUMS";
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// Benchmark family to download, e.g. satlib/uf250 or satcomp2023/main
+    #[arg(value_name = "FAMILY", required_unless_present = "list")]
+    family: Option<String>,
+    /// Directory the family is stored under (as DIR/FAMILY)
+    #[arg(long, value_name = "DIR", default_value = "benchmarks")]
+    dir: PathBuf,
+    /// List the known benchmark families
+    #[arg(long)]
+    list: bool,
+    /// Only check already downloaded instances against the manifest
+    #[arg(long)]
+    verify: bool,
+    #[command(flatten)]
+    #[validate(nested)]
+    fetch: FetchArg,
+}
+
+enum Source {
+    /// A (gzipped) tarball whose `.cnf` members are extracted.
+    Archive(String),
+    /// A text file listing one instance URL per line.
+    List(String),
+}
+
+struct Family {
+    name: String,
+    description: String,
+    source: Source,
+}
+
+fn catalog() -> Vec<Family> {
+    const SATLIB: &str = "https://www.cs.ubc.ca/~hoos/SATLIB/Benchmarks/SAT/RND3SAT";
+    const GBD: &str = "https://benchmark-database.de/getinstances";
+    let mut families = Vec::new();
+    let sizes = [
+        (20, 91),
+        (50, 218),
+        (75, 325),
+        (100, 430),
+        (125, 538),
+        (150, 645),
+        (175, 753),
+        (200, 860),
+        (225, 960),
+        (250, 1065),
+    ];
+    for (prefix, status) in [("uf", "satisfiable"), ("uuf", "unsatisfiable")] {
+        for (vars, clauses) in sizes {
+            if prefix == "uuf" && vars == 20 {
+                continue;
+            }
+            families.push(Family {
+                name: format!("satlib/{prefix}{vars}"),
+                description: format!(
+                    "SATLIB uniform random 3-SAT, {vars} variables, {clauses} clauses, {status}"
+                ),
+                source: Source::Archive(format!("{SATLIB}/{prefix}{vars}-{clauses}.tar.gz")),
+            });
+        }
+    }
+    for year in [2022, 2023, 2024] {
+        families.push(Family {
+            name: format!("satcomp{year}/main"),
+            description: format!("SAT Competition {year} main track (Global Benchmark Database)"),
+            source: Source::List(format!("{GBD}?track=main_{year}&context=cnf")),
+        });
+    }
+    families
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let families = catalog();
+        if self.list {
+            for family in &families {
+                println!("{:<20} {}", family.name, family.description);
+            }
+            return Ok(0);
+        }
+        let name = self.family.as_deref().unwrap_or_default();
+        let family = families
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| anyhow!("Unknown benchmark family `{name}`, see --list"))?;
+        let dir = self.dir.join(&family.name);
+        let mut manifest = Manifest::load(&dir)?;
+        if self.verify {
+            return manifest.verify(&dir);
+        }
+        fs::create_dir_all(&dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        // The target directory already acts as a cache for the instances.
+        let fetch = FetchArg {
+            no_cache: true,
+            ..self.fetch.clone()
+        };
+        match &family.source {
+            Source::Archive(url) => {
+                println!("Downloading {url}");
+                let archive = decompress(open_url(url, &fetch)?)?;
+                extract_tar(archive, |path, body| {
+                    let Some(file) = Path::new(path).file_name().and_then(|f| f.to_str()) else {
+                        return Ok(());
+                    };
+                    if file.ends_with(".cnf") {
+                        manifest.store(&dir, file, body)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Source::List(url) => {
+                let mut list = String::new();
+                open_url(url, &fetch)?.read_to_string(&mut list)?;
+                let urls: Vec<_> = list.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+                for (i, url) in urls.iter().enumerate() {
+                    let parsed = url::Url::parse(url)?;
+                    let stem = parsed
+                        .path_segments()
+                        .and_then(|mut s| s.next_back())
+                        .filter(|s| !s.is_empty())
+                        .ok_or_else(|| anyhow!("Cannot name instance `{url}`"))?;
+                    let file = format!("{stem}.cnf.xz");
+                    if manifest.verified(&dir, &file) {
+                        continue;
+                    }
+                    println!("[{}/{}] {file}", i + 1, urls.len());
+                    manifest.store(&dir, &file, &mut open_url(url, &fetch)?)?;
+                }
+            }
+        }
+        println!("{} instances in {}", manifest.entries.len(), dir.display());
+        Ok(0)
+    }
+}
+
+fn open_url(url: &str, fetch: &FetchArg) -> anyhow::Result<Box<dyn Read>> {
+    let url = url::Url::parse(url)?;
+    Ok(match fetch::open(&url, fetch)? {
+        UrlSource::Cached(file) => Box::new(file),
+        UrlSource::Remote(reader) => reader,
+    })
+}
+
+/// `sha256sum`-compatible list of the instances stored in a family directory.
+struct Manifest {
+    entries: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        };
+        let entries = text
+            .lines()
+            .filter_map(|l| l.split_once("  "))
+            .map(|(hash, file)| (file.to_string(), hash.to_string()))
+            .collect();
+        Ok(Self { entries })
+    }
+
+    fn verified(&self, dir: &Path, file: &str) -> bool {
+        self.entries
+            .get(file)
+            .is_some_and(|hash| sha256_file(&dir.join(file)).is_ok_and(|h| h == *hash))
+    }
+
+    /// Writes `body` to `dir/file`, checks that it is a well-formed CNF and
+    /// records its digest.
+    fn store(&mut self, dir: &Path, file: &str, body: &mut dyn Read) -> anyhow::Result<()> {
+        let path = dir.join(file);
+        let mut hasher = Sha256::new();
+        let mut out = BufWriter::new(File::create(&path)?);
+        let mut buf = vec![0u8; 1 << 16];
+        loop {
+            let n = body.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n])?;
+        }
+        out.flush()?;
+        if let Err(e) = check_cnf(&path) {
+            fs::remove_file(&path)?;
+            return Err(e.context(format!("{file} is not a valid CNF")));
+        }
+        self.entries.insert(file.to_string(), hex(&hasher.finalize()));
+        self.save(dir)
+    }
+
+    fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(file, hash)| format!("{hash}  {file}\n"))
+            .collect();
+        fs::write(dir.join(MANIFEST), text)?;
+        Ok(())
+    }
+
+    fn verify(&self, dir: &Path) -> anyhow::Result<i32> {
+        if self.entries.is_empty() {
+            return Err(anyhow!("No {MANIFEST} found in {}", dir.display()));
+        }
+        let mut failed = 0;
+        for (file, hash) in &self.entries {
+            let path = dir.join(file);
+            let result = sha256_file(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|h| {
+                    if h == *hash {
+                        check_cnf(&path)
+                    } else {
+                        Err(anyhow!("checksum mismatch"))
+                    }
+                });
+            match result {
+                Ok(()) => println!("{file}: OK"),
+                Err(e) => {
+                    failed += 1;
+                    println!("{file}: FAILED ({e})");
+                }
+            }
+        }
+        println!("{} of {} instances failed", failed, self.entries.len());
+        Ok(if failed > 0 { 1 } else { 0 })
+    }
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+struct ClauseCount(usize);
+
+impl AsDimacs for ClauseCount {
+    fn add_clause(&mut self, _clause: &[i32]) {
+        self.0 += 1;
+    }
+}
+
+fn check_cnf(path: &Path) -> anyhow::Result<()> {
+    let mut count = ClauseCount(0);
+    read_dimacs(File::open(path)?, true, &mut count)?;
+    if count.0 == 0 {
+        return Err(anyhow!("no clauses"));
+    }
+    Ok(())
+}
+
+/// Calls `entry` for every regular file of a ustar/GNU tar stream.
+fn extract_tar<R: Read>(
+    mut reader: R,
+    mut entry: impl FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut header = [0u8; 512];
+    loop {
+        if !read_block(&mut reader, &mut header)? || header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let bytes = &header[range];
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+        let mut name = field(0..100);
+        if &header[257..262] == b"ustar" {
+            let prefix = field(345..500);
+            if !prefix.is_empty() {
+                name = format!("{prefix}/{name}");
+            }
+        }
+        let size = u64::from_str_radix(field(124..136).trim(), 8)
+            .with_context(|| format!("Corrupt tar header for `{name}`"))?;
+        let mut body = (&mut reader).take(size);
+        if matches!(header[156], b'0' | 0) {
+            entry(&name, &mut body)?;
+        }
+        io::copy(&mut body, &mut io::sink())?;
+        let padding = (512 - size % 512) % 512;
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+}
+
+/// Reads one 512-byte block; `false` at a clean end of stream.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8; 512]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
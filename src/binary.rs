@@ -0,0 +1,312 @@
+//! Compact binary CNF format, for instance archives where DIMACS text costs
+//! too much space and parse time.
+//!
+//! A file starts with the magic bytes `SGBCNF` and the format version `1`,
+//! followed by unsigned LEB128 varints: the number of variables, the number
+//! of clauses, and then the literals of every clause, each as `2·var` for a
+//! positive and `2·var + 1` for a negative literal, with `0` ending the
+//! clause. Comments are not kept. The file may be gzip, xz or zstd
+//! compressed as a whole; the readers of this crate detect the format by
+//! its magic bytes, so it can be given wherever a DIMACS CNF can.
+use std::{
+    cell::Cell,
+    io::{self, BufRead, Write},
+};
+
+use crate::dimacs::{AsDimacs, DimacsError, Limits};
+
+pub const MAGIC: &[u8] = b"SGBCNF\x01";
+
+/// A CNF held as its literals with a `0` after every clause.
+#[derive(Default)]
+pub struct FlatCnf {
+    pub literals: Vec<i32>,
+    pub vars: i32,
+    pub clauses: u64,
+}
+
+impl AsDimacs for FlatCnf {
+    fn add_clause(&mut self, clause: &[i32]) {
+        for &lit in clause {
+            self.vars = self.vars.max(lit.abs());
+        }
+        self.literals.extend_from_slice(clause);
+        self.literals.push(0);
+        self.clauses += 1;
+    }
+}
+
+/// Writes `cnf` in the binary format.
+pub fn write<W: Write>(mut writer: W, cnf: &FlatCnf) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(64 * 1024);
+    buf.extend_from_slice(MAGIC);
+    put_varint(&mut buf, cnf.vars as u64);
+    put_varint(&mut buf, cnf.clauses);
+    for &lit in &cnf.literals {
+        let code = match lit {
+            0 => 0,
+            lit if lit < 0 => 2 * lit.unsigned_abs() as u64 + 1,
+            lit => 2 * lit as u64,
+        };
+        put_varint(&mut buf, code);
+        if buf.len() >= 60 * 1024 {
+            writer.write_all(&buf)?;
+            buf.clear();
+        }
+    }
+    writer.write_all(&buf)?;
+    writer.flush()
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Whether `reader`, already decompressed, holds the binary format.
+pub fn detect<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+/// Reads the binary format from `reader`, already decompressed, into `dim`.
+/// In strict mode the counts of the header must match the clauses. A
+/// binary input has no lines: errors give the clause they occur in as the
+/// line and column 0.
+pub fn read<R: BufRead, D: AsDimacs>(
+    reader: R,
+    strict: bool,
+    limits: Limits,
+    dim: &mut D,
+) -> Result<(), DimacsError> {
+    let header = Cell::new(None);
+    let mut num_vars = 0;
+    let mut num_clauses: i32 = 0;
+    let on_header = |vars, clauses| {
+        header.set(Some((vars, clauses)));
+        Ok(())
+    };
+    let on_clause = |clause: &[i32]| {
+        let line = num_clauses as usize + 1;
+        for &lit in clause {
+            num_vars = num_vars.max(lit.abs());
+        }
+        if limits.vars > 0 && num_vars > limits.vars {
+            return Err(DimacsError::LimitExceeded {
+                line,
+                column: 0,
+                what: "variables",
+                max: limits.vars,
+            });
+        }
+        num_clauses += 1;
+        if limits.clauses > 0 && num_clauses > limits.clauses {
+            return Err(DimacsError::LimitExceeded {
+                line,
+                column: 0,
+                what: "clauses",
+                max: limits.clauses,
+            });
+        }
+        if let (true, Some((vars, clauses))) = (strict, header.get()) {
+            if num_clauses > clauses {
+                return Err(DimacsError::TooManyClauses {
+                    line,
+                    column: 0,
+                    found: num_clauses,
+                    max: clauses,
+                });
+            }
+            if num_vars > vars {
+                return Err(DimacsError::TooManyVariables {
+                    line,
+                    column: 0,
+                    found: num_vars,
+                    max: vars,
+                });
+            }
+        }
+        dim.add_clause(clause);
+        Ok(())
+    };
+    decode(reader, on_header, on_clause)
+}
+
+/// Decodes the binary format, handing the counts of the header to
+/// `on_header` and then every clause to `on_clause`.
+pub fn decode<R: BufRead>(
+    mut reader: R,
+    mut on_header: impl FnMut(i32, i32) -> Result<(), DimacsError>,
+    mut on_clause: impl FnMut(&[i32]) -> Result<(), DimacsError>,
+) -> Result<(), DimacsError> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a binary CNF of version 1"));
+    }
+    // Values read so far: the two counts, then literals.
+    let mut counts = Vec::with_capacity(2);
+    let mut clause = Vec::new();
+    let (mut value, mut shift) = (0u64, 0u32);
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = buf.len();
+        for &byte in buf {
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 != 0 {
+                shift += 7;
+                if shift > 63 {
+                    return Err(invalid("varint too long"));
+                }
+                continue;
+            }
+            let code = std::mem::take(&mut value);
+            shift = 0;
+            if counts.len() < 2 {
+                counts.push(i32::try_from(code).map_err(|_| invalid("count out of range"))?);
+                if let [vars, clauses] = counts[..] {
+                    on_header(vars, clauses)?;
+                }
+                continue;
+            }
+            match code {
+                0 => {
+                    on_clause(&clause)?;
+                    clause.clear();
+                }
+                1 => return Err(invalid("literal of variable 0")),
+                code => {
+                    let var = i32::try_from(code / 2).map_err(|_| invalid("variable out of range"))?;
+                    clause.push(if code % 2 == 1 { -var } else { var });
+                }
+            }
+        }
+        reader.consume(len);
+    }
+    if shift > 0 || counts.len() < 2 || !clause.is_empty() {
+        return Err(invalid("truncated binary CNF"));
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> DimacsError {
+    DimacsError::Io(io::Error::new(io::ErrorKind::InvalidData, msg.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(clauses: &[Vec<i32>]) -> Vec<u8> {
+        let mut cnf = FlatCnf::default();
+        for clause in clauses {
+            cnf.add_clause(clause);
+        }
+        let mut bytes = Vec::new();
+        write(&mut bytes, &cnf).unwrap();
+        bytes
+    }
+
+    fn decode_all(bytes: &[u8], strict: bool) -> Result<Vec<Vec<i32>>, DimacsError> {
+        let mut clauses = Vec::new();
+        read(bytes, strict, Limits::default(), &mut clauses)?;
+        Ok(clauses)
+    }
+
+    fn invalid_data(result: Result<Vec<Vec<i32>>, DimacsError>) -> bool {
+        matches!(result, Err(DimacsError::Io(e)) if e.kind() == io::ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn round_trip() {
+        let clauses = vec![vec![1, -2], vec![], vec![-1, 300, 70000], vec![-70000]];
+        let bytes = encode(&clauses);
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(decode_all(&bytes, true).unwrap(), clauses);
+        // The DIMACS reader detects the format by its magic bytes.
+        let mut read_back = Vec::new();
+        crate::dimacs::read_dimacs(&bytes[..], true, &mut read_back).unwrap();
+        assert_eq!(read_back, clauses);
+    }
+
+    #[test]
+    fn header_counts() {
+        let mut header = None;
+        decode(
+            &encode(&[vec![3, -5]])[..],
+            |vars, clauses| {
+                header = Some((vars, clauses));
+                Ok(())
+            },
+            |_| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(header, Some((5, 1)));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = encode(&[vec![1]]);
+        bytes[MAGIC.len() - 1] = 2;
+        assert!(invalid_data(decode_all(&bytes, false)));
+        assert!(matches!(
+            decode_all(b"SGB", false),
+            Err(DimacsError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = encode(&[vec![1, -2], vec![2]]);
+        for len in [MAGIC.len(), MAGIC.len() + 1, bytes.len() - 1] {
+            assert!(invalid_data(decode_all(&bytes[..len], false)), "{len}");
+        }
+        let mut bytes = bytes;
+        bytes.push(0x80);
+        assert!(invalid_data(decode_all(&bytes, false)));
+    }
+
+    #[test]
+    fn malformed_literals() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 1, 1, 0]);
+        assert!(invalid_data(decode_all(&bytes, false)));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 1, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0]);
+        assert!(invalid_data(decode_all(&bytes, false)));
+    }
+
+    #[test]
+    fn strict_counts_and_limits() {
+        let mut bytes = MAGIC.to_vec();
+        // Declares 1 variable and 1 clause, but has clauses `1 0` and `2 0`.
+        bytes.extend_from_slice(&[1, 1, 2, 0, 4, 0]);
+        assert!(decode_all(&bytes, false).is_ok());
+        assert!(matches!(
+            decode_all(&bytes, true),
+            Err(DimacsError::TooManyClauses {
+                line: 2,
+                found: 2,
+                max: 1,
+                ..
+            })
+        ));
+        let limits = Limits {
+            vars: 1,
+            clauses: 0,
+        };
+        assert!(matches!(
+            read(&bytes[..], false, limits, &mut Vec::<Vec<i32>>::new()),
+            Err(DimacsError::LimitExceeded {
+                line: 2,
+                what: "variables",
+                ..
+            })
+        ));
+    }
+}
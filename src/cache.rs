@@ -0,0 +1,230 @@
+//! Cache of solve results, keyed by the SHA-256 of the input and of
+//! everything in the request that can change the answer.
+//!
+//! Only SATISFIABLE and UNSATISFIABLE answers are stored: an UNKNOWN depends
+//! on the limits and the machine. Each entry is a JSON file `<key>.json` in
+//! the cache directory.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use clap::ValueEnum;
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    SolveRequest, SolveResult, Status,
+    core::{HashingReader, SmartPath, SmartReader, Stat, hex, write_atomic},
+};
+
+/// Solves `request` through the cache in `dir`: a stored answer is returned
+/// without solving, a new one is stored.
+///
+/// The input is hashed as it streams. A local file is then read again by
+/// the solve; any other input can be read only once, so it is copied to a
+/// file in `dir` on the way and solved from there.
+pub(crate) fn solve(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+    dir: &Path,
+) -> anyhow::Result<SolveResult> {
+    let request = SolveRequest {
+        result_cache: None,
+        ..request.clone()
+    };
+    let local = input.is_none() && matches!(request.input, Some(SmartPath::FilePath(_)));
+    let (digest, spool) = match input {
+        None if local => (
+            hash(&mut SmartReader::open(&request)?, &mut io::sink())?,
+            None,
+        ),
+        input => {
+            let spool = Spool::create(dir)?;
+            let mut copy = BufWriter::new(File::create(&spool.0)?);
+            let digest = match input {
+                Some(input) => hash(input, &mut copy)?,
+                None => hash(&mut SmartReader::open(&request)?, &mut copy)?,
+            };
+            copy.flush()?;
+            (digest, Some(spool))
+        }
+    };
+    let key = key(&request, &digest)?;
+    let path = dir.join(format!("{key}.json"));
+    let matches = request
+        .sha256
+        .as_ref()
+        .is_none_or(|expected| *expected == digest);
+    if matches && let Some(result) = load(&path, stat, &digest) {
+        return Ok(result);
+    }
+    let mut result = match &spool {
+        Some(spool) => crate::solve_with(&request, Some(&mut File::open(&spool.0)?), stat)?,
+        None => crate::solve_with(&request, None, stat)?,
+    };
+    // A local file changed between the hash and the solve is not stored
+    // under the digest of its earlier contents.
+    let unchanged = stat
+        .lock()
+        .unwrap()
+        .input_sha256
+        .as_ref()
+        .is_none_or(|read| *read == digest);
+    if result.status != Status::Unknown
+        && unchanged
+        && let Err(e) = store(dir, &path, &result)
+    {
+        result.warnings.push(format!(
+            "--result-cache: cannot write {}: {}",
+            path.display(),
+            e
+        ));
+    }
+    Ok(result)
+}
+
+/// Hex SHA-256 of everything `input` holds, copied to `copy` on the way.
+fn hash(input: &mut dyn Read, copy: &mut dyn Write) -> io::Result<String> {
+    let mut reader = HashingReader::new(input);
+    io::copy(&mut reader, copy)?;
+    reader.finish()
+}
+
+/// Hex SHA-256 of the contents of the file at `path`.
+fn hash_file(path: &Path) -> io::Result<String> {
+    let file = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot open {}: {}", path.display(), e)))?;
+    hash(&mut io::BufReader::new(file), &mut io::sink())
+}
+
+/// A copy of an input that can be read only once, removed when dropped.
+pub(crate) struct Spool(pub(crate) PathBuf);
+
+impl Spool {
+    pub(crate) fn create(dir: &Path) -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(dir)?;
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        Ok(Spool(dir.join(format!(
+            ".input-{}-{}.part",
+            process::id(),
+            n
+        ))))
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Hex SHA-256 of the input digest and of every field of the request that
+/// can change the answer, by name; files that feed the solve count by their
+/// contents. Left out are the input's source and what only affects how it
+/// is fetched, reported or limited, and the races, schedules and
+/// repetitions that never reach the cache.
+fn key(request: &SolveRequest, digest: &str) -> io::Result<String> {
+    let extra_clauses = request
+        .extra_clauses
+        .iter()
+        .map(|path| hash_file(path))
+        .collect::<io::Result<Vec<_>>>()?;
+    let symbols = request.symbols.as_deref().map(hash_file).transpose()?;
+    let at_most: Vec<Value> = request
+        .at_most
+        .iter()
+        .map(|constraint| json!({"bound": constraint.bound, "lits": constraint.lits}))
+        .collect();
+    let pipeline: Vec<String> = request
+        .pipeline
+        .iter()
+        .filter_map(|pass| pass.to_possible_value())
+        .map(|pass| pass.get_name().to_string())
+        .collect();
+    let options: Map<String, Value> = request
+        .solver
+        .fields()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.into()))
+        .collect();
+    let fields = json!({
+        "input_sha256": digest,
+        "solver": request.solver.name(),
+        "options": options,
+        "preprocess": request.preprocess,
+        "freeze": request.freeze,
+        "solve": request.solve,
+        "parse_only": request.parse_only,
+        "strict": request.strict,
+        "lenient": request.lenient,
+        "limits": [request.limits.vars, request.limits.clauses],
+        "fast_paths": request.fast_paths,
+        "partial_model": request.partial_model,
+        "equivalences": request.equivalences,
+        "extra_clauses_sha256": extra_clauses,
+        "at_most": at_most,
+        "unique_check": request.unique_check,
+        "pipeline": pipeline,
+        "components": request.components,
+        "symbols_sha256": symbols,
+        "show": request.show,
+        "keep_comments": request.keep_comments,
+    });
+    Ok(hex(&Sha256::digest(fields.to_string())))
+}
+
+/// The stored answer at `path`, with the statistics of this run.
+fn load(path: &Path, stat: &Mutex<Stat>, digest: &str) -> Option<SolveResult> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    let status = match json["status"].as_str()? {
+        "SATISFIABLE" => Status::Satisfiable,
+        "UNSATISFIABLE" => Status::Unsatisfiable,
+        _ => return None,
+    };
+    let model = serde_json::from_value(json["model"].clone()).ok()?;
+    let named = serde_json::from_value(json["named"].clone()).ok()?;
+    let comments = serde_json::from_value(json["comments"].clone()).ok()?;
+    let unique = json["unique"].as_bool();
+    let mut stat = stat.lock().unwrap();
+    stat.input_sha256 = Some(digest.to_string());
+    Some(SolveResult {
+        status,
+        model,
+        stats: stat.stats(),
+        warnings: Vec::new(),
+        fast_path: None,
+        components: None,
+        named,
+        comments,
+        unique,
+        passes: Vec::new(),
+        reason: None,
+        race_winner: None,
+        stage: None,
+        parse_only: false,
+        repetitions: None,
+        cached: true,
+    })
+}
+
+/// Stores the answer of `result` at `path`.
+fn store(dir: &Path, path: &Path, result: &SolveResult) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let json = serde_json::json!({
+        "status": result.status.as_str(),
+        "model": result.model,
+        "named": result.named,
+        "comments": result.comments,
+        "unique": result.unique,
+    });
+    write_atomic(path, serde_json::to_string(&json)? + "\n")
+}
@@ -0,0 +1,107 @@
+//! Capture of what the embedded C solvers print with `--verb`.
+//!
+//! The solvers write their progress straight to file descriptor 1 with C
+//! stdio, past every Rust writer. While a capture is active, descriptor 1 is
+//! a pipe, and a thread reads it line by line and writes each line to the
+//! real stdout as a timestamped `c` comment or as a JSON object, so solver
+//! chatter can no longer be mistaken for result lines.
+use std::{
+    ffi::{c_int, c_void},
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::atomic::{AtomicI32, Ordering},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::core::VerbFormat;
+
+unsafe extern "C" {
+    fn dup(fd: c_int) -> c_int;
+    fn dup2(old: c_int, new: c_int) -> c_int;
+    fn fflush(stream: *mut c_void) -> c_int;
+}
+
+const STDOUT: c_int = 1;
+
+/// Descriptor of the real stdout while a capture is active, -1 otherwise.
+static SAVED: AtomicI32 = AtomicI32::new(-1);
+
+pub struct Capture {
+    saved: OwnedFd,
+    thread: JoinHandle<()>,
+}
+
+impl Capture {
+    /// Redirects stdout into the pipe until [`Capture::finish`]; `solver` and
+    /// times relative to `start` label the lines.
+    pub fn start(format: VerbFormat, solver: &'static str, start: Instant) -> io::Result<Self> {
+        io::stdout().flush()?;
+        let saved = duplicate(STDOUT)?;
+        let mut out = File::from(duplicate(saved.as_raw_fd())?);
+        let (reader, writer) = io::pipe()?;
+        // SAFETY: both descriptors are open; dup2 only replaces descriptor 1.
+        if unsafe { dup2(writer.as_raw_fd(), STDOUT) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        drop(writer);
+        SAVED.store(saved.as_raw_fd(), Ordering::SeqCst);
+        let thread = thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let time = start.elapsed().as_secs_f64();
+                let line = match format {
+                    VerbFormat::Text => format!("c [{:.3}s {}] {}", time, solver, line),
+                    VerbFormat::Json => serde_json::json!({
+                        "time": time,
+                        "solver": solver,
+                        "message": line,
+                    })
+                    .to_string(),
+                };
+                if writeln!(out, "{}", line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { saved, thread })
+    }
+
+    /// Restores stdout and waits until every captured line is written.
+    pub fn finish(self) -> io::Result<()> {
+        release()?;
+        let _ = self.thread.join();
+        drop(self.saved);
+        Ok(())
+    }
+}
+
+/// Puts the real stdout back if a capture is active, e.g. before an
+/// interrupted run reports and exits. Lines not forwarded yet are lost.
+pub fn release() -> io::Result<()> {
+    let saved = SAVED.swap(-1, Ordering::SeqCst);
+    if saved < 0 {
+        return Ok(());
+    }
+    io::stdout().flush()?;
+    // SAFETY: fflush(NULL) flushes every C stream; `saved` is open until the
+    // capture is finished.
+    unsafe {
+        fflush(std::ptr::null_mut());
+        if dup2(saved, STDOUT) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn duplicate(fd: c_int) -> io::Result<OwnedFd> {
+    // SAFETY: dup returns a new descriptor owned by nobody else, or -1.
+    match unsafe { dup(fd) } {
+        -1 => Err(io::Error::last_os_error()),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
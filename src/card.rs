@@ -0,0 +1,261 @@
+//! CNF encodings of cardinality and pseudo-Boolean constraints.
+use std::{cmp::Reverse, collections::HashMap, str::FromStr};
+
+use anyhow::anyhow;
+
+/// At most `bound` of `lits` are true.
+#[derive(Clone, Debug)]
+pub struct AtMost {
+    pub bound: usize,
+    pub lits: Vec<i32>,
+}
+
+/// Parses `k: l1 l2 ...`, e.g. `2: 1 -4 7`.
+impl FromStr for AtMost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bound, lits) = s
+            .split_once(':')
+            .ok_or_else(|| format!("`{s}` is not of the form `k: v1 v2 ...`"))?;
+        let bound = bound
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid bound `{}`", bound.trim()))?;
+        let lits = lits
+            .split_whitespace()
+            .map(|token| match token.parse::<i32>() {
+                Ok(lit) if lit != 0 => Ok(lit),
+                _ => Err(format!("invalid literal `{token}`")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if lits.is_empty() {
+            return Err(format!("`{s}` constrains no literals"));
+        }
+        Ok(Self { bound, lits })
+    }
+}
+
+/// Clauses that are satisfiable exactly when at most `bound` of `lits` are
+/// true, by Sinz's sequential counter. Auxiliary variables are numbered from
+/// `*next_var` on, which is advanced past them.
+pub fn at_most(lits: &[i32], bound: usize, next_var: &mut i32) -> Vec<Vec<i32>> {
+    let n = lits.len();
+    if bound >= n {
+        return Vec::new();
+    }
+    if bound == 0 {
+        return lits.iter().map(|&lit| vec![-lit]).collect();
+    }
+    // s[i][j]: at least j + 1 of the first i + 1 literals are true.
+    let s: Vec<Vec<i32>> = (0..n - 1)
+        .map(|_| {
+            (0..bound)
+                .map(|_| {
+                    *next_var += 1;
+                    *next_var
+                })
+                .collect()
+        })
+        .collect();
+    let mut clauses = vec![vec![-lits[0], s[0][0]]];
+    for &aux in &s[0][1..] {
+        clauses.push(vec![-aux]);
+    }
+    for i in 1..n - 1 {
+        clauses.push(vec![-lits[i], s[i][0]]);
+        clauses.push(vec![-s[i - 1][0], s[i][0]]);
+        for j in 1..bound {
+            clauses.push(vec![-lits[i], -s[i - 1][j - 1], s[i][j]]);
+            clauses.push(vec![-s[i - 1][j], s[i][j]]);
+        }
+        clauses.push(vec![-lits[i], -s[i - 1][bound - 1]]);
+    }
+    clauses.push(vec![-lits[n - 1], -s[n - 2][bound - 1]]);
+    clauses
+}
+
+/// Clauses that are satisfiable exactly when the weights of the true literals
+/// of `terms` sum to at most `bound`, through a BDD as in Eén and Sörensson's
+/// MiniSat+. Weights must be positive; auxiliary variables are numbered as in
+/// [`at_most`]. An error if the weights sum beyond `i64::MAX`.
+pub fn at_most_weighted(
+    terms: &[(i64, i32)],
+    bound: i64,
+    next_var: &mut i32,
+) -> anyhow::Result<Vec<Vec<i32>>> {
+    let mut terms = terms.to_vec();
+    terms.sort_by_key(|&(weight, _)| Reverse(weight));
+    // rest[i]: total weight of terms[i..].
+    let mut rest = vec![0; terms.len() + 1];
+    for i in (0..terms.len()).rev() {
+        rest[i] = rest[i + 1]
+            .checked_add(terms[i].0)
+            .ok_or_else(|| anyhow!("the weights sum beyond {}", i64::MAX))?;
+    }
+    let mut bdd = Bdd {
+        terms: &terms,
+        rest: &rest,
+        memo: HashMap::new(),
+        clauses: Vec::new(),
+        next_var,
+    };
+    match bdd.node(0, bound) {
+        Node::True => {}
+        Node::False => bdd.clauses.push(Vec::new()),
+        Node::Var(root) => bdd.clauses.push(vec![root]),
+    }
+    Ok(bdd.clauses)
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    True,
+    False,
+    Var(i32),
+}
+
+struct Bdd<'a> {
+    terms: &'a [(i64, i32)],
+    rest: &'a [i64],
+    memo: HashMap<(usize, i64), Node>,
+    clauses: Vec<Vec<i32>>,
+    next_var: &'a mut i32,
+}
+
+impl Bdd<'_> {
+    /// The node implying that the terms from `i` on weigh at most `bound`.
+    ///
+    /// Only the implication from the node is encoded, which is all an upper
+    /// bound needs. The high branch implies the low one, so the node may
+    /// imply the low branch outright.
+    fn node(&mut self, i: usize, bound: i64) -> Node {
+        if bound < 0 {
+            return Node::False;
+        }
+        if self.rest[i] <= bound {
+            return Node::True;
+        }
+        if let Some(&node) = self.memo.get(&(i, bound)) {
+            return node;
+        }
+        let (weight, lit) = self.terms[i];
+        let high = self.node(i + 1, bound - weight);
+        let low = self.node(i + 1, bound);
+        *self.next_var += 1;
+        let var = *self.next_var;
+        match high {
+            Node::True => {}
+            Node::False => self.clauses.push(vec![-var, -lit]),
+            Node::Var(high) => self.clauses.push(vec![-var, -lit, high]),
+        }
+        match low {
+            Node::True => {}
+            Node::False => self.clauses.push(vec![-var]),
+            Node::Var(low) => self.clauses.push(vec![-var, low]),
+        }
+        self.memo.insert((i, bound), Node::Var(var));
+        Node::Var(var)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opb;
+
+    /// Whether `clauses` have a model extending `assigned`, a list of true
+    /// literals, by a plain backtracking search.
+    fn extends(clauses: &[Vec<i32>], assigned: &mut Vec<i32>) -> bool {
+        let value = |assigned: &[i32], lit: i32| {
+            assigned
+                .iter()
+                .find(|a| a.abs() == lit.abs())
+                .map(|&a| a == lit)
+        };
+        let Some(clause) = clauses
+            .iter()
+            .find(|clause| !clause.iter().any(|&lit| value(assigned, lit) == Some(true)))
+        else {
+            return true;
+        };
+        let Some(&lit) = clause.iter().find(|&&lit| value(assigned, lit).is_none()) else {
+            return false;
+        };
+        for choice in [lit, -lit] {
+            assigned.push(choice);
+            if extends(clauses, assigned) {
+                return true;
+            }
+            assigned.pop();
+        }
+        false
+    }
+
+    /// Every assignment of the variables `1..=n`, as a model.
+    fn models(n: usize) -> impl Iterator<Item = Vec<i32>> {
+        (0..1u32 << n).map(move |mask| {
+            (1..=n as i32)
+                .map(|var| {
+                    if mask >> (var - 1) & 1 == 1 {
+                        var
+                    } else {
+                        -var
+                    }
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn at_most_counts_true_literals() {
+        for n in 3..=5 {
+            let lits = &[1, -2, 3, -4, 5][..n];
+            for bound in 0..=n + 1 {
+                let mut next_var = n as i32;
+                let clauses = at_most(lits, bound, &mut next_var);
+                for mut model in models(n) {
+                    let count = lits.iter().filter(|lit| model.contains(lit)).count();
+                    assert_eq!(
+                        extends(&clauses, &mut model),
+                        count <= bound,
+                        "{lits:?} <= {bound}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn at_most_weighted_sums_true_weights() {
+        let linear = [(3, 1), (-2, -2), (5, 3), (1, 4), (-4, -5)];
+        for n in 3..=5 {
+            let linear = &linear[..n];
+            // Negative coefficients make the normalized bound negative or
+            // zero for the low bounds.
+            for bound in -8..=10 {
+                let mut next_var = n as i32;
+                let clauses = opb::at_most(linear, bound, &mut next_var).unwrap();
+                for mut model in models(n) {
+                    let sum = opb::value(linear, &model);
+                    assert_eq!(
+                        extends(&clauses, &mut model),
+                        sum <= bound,
+                        "{linear:?} <= {bound}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weights_beyond_i64_max() {
+        let mut next_var = 2;
+        assert!(at_most_weighted(&[(i64::MAX, 1), (1, 2)], 0, &mut next_var).is_err());
+        assert!(opb::at_most(&[(i64::MAX, 1), (-1, 2)], 0, &mut next_var).is_err());
+        assert!(opb::at_most(&[(i64::MIN, 1)], 0, &mut next_var).is_err());
+        let clauses = at_most_weighted(&[(i64::MAX, 1)], i64::MAX - 1, &mut next_var).unwrap();
+        assert!(!extends(&clauses, &mut vec![1]));
+        assert!(extends(&clauses, &mut vec![-1]));
+    }
+}
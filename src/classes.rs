@@ -0,0 +1,511 @@
+//! Tractable formula classes: detection, and the linear-time algorithms that
+//! solve them without CDCL.
+//!
+//! Models are returned like the backends return theirs, one literal per
+//! variable from 1 to the largest variable in the clauses.
+use std::collections::{HashMap, HashSet};
+
+/// Every clause has at most two literals.
+pub fn is_2sat(clauses: &[Vec<i32>]) -> bool {
+    clauses.iter().all(|clause| clause.len() <= 2)
+}
+
+/// Every clause has at most one positive literal.
+pub fn is_horn(clauses: &[Vec<i32>]) -> bool {
+    clauses.iter().all(|clause| is_horn_clause(clause))
+}
+
+/// At most one distinct positive literal.
+pub fn is_horn_clause(clause: &[i32]) -> bool {
+    let mut positive = clause.iter().filter(|&&lit| lit > 0);
+    let first = positive.next();
+    positive.all(|lit| Some(lit) == first)
+}
+
+/// At most one distinct negative literal.
+pub fn is_dual_horn_clause(clause: &[i32]) -> bool {
+    let mut negative = clause.iter().filter(|&&lit| lit < 0);
+    let first = negative.next();
+    negative.all(|lit| Some(lit) == first)
+}
+
+/// Variables whose polarity can be flipped throughout to make the formula
+/// Horn, or `None` if it is not renamable Horn.
+///
+/// Renaming is itself 2-SAT: no two literals of a clause may both be
+/// positive after flipping. This is quadratic in the clause length.
+pub fn horn_renaming(clauses: &[Vec<i32>]) -> Option<Vec<i32>> {
+    let mut renaming = Vec::new();
+    for clause in clauses {
+        for (i, &a) in clause.iter().enumerate() {
+            for &b in &clause[i + 1..] {
+                // Variable v of the renaming formula is true if v is flipped,
+                // which makes `a` negative exactly when `a` holds there.
+                if a != b && a != -b {
+                    renaming.push(vec![a, b]);
+                }
+            }
+        }
+    }
+    let model = solve_2sat(&renaming)?;
+    Some(model.into_iter().filter(|&lit| lit > 0).collect())
+}
+
+/// An XOR constraint found in the CNF encoding.
+pub struct Xor {
+    pub vars: Vec<i32>,
+    /// The XOR of `vars` is true.
+    pub parity: bool,
+    /// Number of clauses encoding it.
+    pub clauses: usize,
+}
+
+/// XOR constraints over 3 to `max_len` variables that are encoded in full,
+/// i.e. by all 2^(k-1) clauses over the same variables that forbid the
+/// assignments of the wrong parity.
+pub fn find_xors(clauses: &[Vec<i32>], max_len: usize) -> Vec<Xor> {
+    let mut groups: HashMap<(Vec<i32>, bool), HashSet<u64>> = HashMap::new();
+    for clause in clauses {
+        if clause.len() < 3 || clause.len() > max_len {
+            continue;
+        }
+        let mut lits = clause.clone();
+        lits.sort_by_key(|lit| lit.abs());
+        if lits.windows(2).any(|w| w[0].abs() == w[1].abs()) {
+            continue;
+        }
+        let signs = lits
+            .iter()
+            .enumerate()
+            .fold(0u64, |mask, (i, &lit)| mask | (u64::from(lit < 0) << i));
+        // An XOR that is true forbids the assignments with an even number of
+        // true variables; the clause forbidding one negates exactly those.
+        let parity = signs.count_ones() % 2 == 0;
+        let vars = lits.iter().map(|lit| lit.abs()).collect();
+        groups.entry((vars, parity)).or_default().insert(signs);
+    }
+    let mut xors: Vec<_> = groups
+        .into_iter()
+        .filter(|((vars, _), signs)| signs.len() == 1 << (vars.len() - 1))
+        .map(|((vars, parity), signs)| Xor {
+            vars,
+            parity,
+            clauses: signs.len(),
+        })
+        .collect();
+    xors.sort_by(|a, b| a.vars.cmp(&b.vars));
+    xors
+}
+
+fn vars(clauses: &[Vec<i32>]) -> usize {
+    clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.unsigned_abs() as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Node of `lit` in the implication graph.
+fn node(lit: i32) -> usize {
+    2 * (lit.unsigned_abs() as usize - 1) + usize::from(lit < 0)
+}
+
+/// Solves a 2-SAT formula through the strongly connected components of its
+/// implication graph; `None` if it is unsatisfiable.
+pub fn solve_2sat(clauses: &[Vec<i32>]) -> Option<Vec<i32>> {
+    let vars = vars(clauses);
+    let mut graph = vec![Vec::new(); 2 * vars];
+    for clause in clauses {
+        match clause.as_slice() {
+            [] => return None,
+            [a] => graph[node(-a)].push(node(*a)),
+            [a, b] => {
+                graph[node(-a)].push(node(*b));
+                graph[node(-b)].push(node(*a));
+            }
+            _ => panic!("solve_2sat called on a clause with more than two literals"),
+        }
+    }
+    let component = components(&graph);
+    (1..=vars as i32)
+        .map(|var| {
+            let (pos, neg) = (component[node(var)], component[node(-var)]);
+            // Tarjan finds components in reverse topological order, so the
+            // literal whose component came first is implied, not implying.
+            (pos != neg).then_some(if pos < neg { var } else { -var })
+        })
+        .collect()
+}
+
+/// Equivalent literals, from the strongly connected components of the
+/// implication graph of the binary and unit clauses.
+///
+/// Maps every variable `v` (at index `v - 1`) to the literal that stands for
+/// it: the smallest variable of its component, negated if `v` is equivalent
+/// to that variable's negation. Variables in no larger component stand for
+/// themselves. `None` if a variable is equivalent to its own negation, which
+/// makes the formula unsatisfiable.
+pub fn equivalences(clauses: &[Vec<i32>]) -> Option<Vec<i32>> {
+    let vars = vars(clauses);
+    let mut graph = vec![Vec::new(); 2 * vars];
+    for clause in clauses {
+        match clause.as_slice() {
+            [a] => graph[node(-a)].push(node(*a)),
+            [a, b] => {
+                graph[node(-a)].push(node(*b));
+                graph[node(-b)].push(node(*a));
+            }
+            _ => {}
+        }
+    }
+    let component = components(&graph);
+    let mut representative: HashMap<usize, i32> = HashMap::new();
+    (1..=vars as i32)
+        .map(|var| {
+            let (pos, neg) = (component[node(var)], component[node(-var)]);
+            if pos == neg {
+                return None;
+            }
+            // Variables are visited in ascending order, so the first one
+            // seen in a component is its smallest; the component of the
+            // negated literals gets the negated representative.
+            let lit = *representative.entry(pos).or_insert(var);
+            representative.entry(neg).or_insert(-var);
+            Some(lit)
+        })
+        .collect()
+}
+
+/// Replaces every literal by its representative from [`equivalences`],
+/// dropping duplicate literals and the clauses that become tautologies.
+pub fn substitute(clauses: &[Vec<i32>], representatives: &[i32]) -> Vec<Vec<i32>> {
+    let map = |lit: i32| {
+        let repr = representatives[lit.unsigned_abs() as usize - 1];
+        if lit > 0 { repr } else { -repr }
+    };
+    clauses
+        .iter()
+        .filter_map(|clause| {
+            let mut lits: Vec<i32> = clause.iter().map(|&lit| map(lit)).collect();
+            lits.sort_unstable_by_key(|lit| (lit.abs(), *lit));
+            lits.dedup();
+            let tautology = lits.windows(2).any(|w| w[0] == -w[1]);
+            (!tautology).then_some(lits)
+        })
+        .collect()
+}
+
+/// Extends a model of the substituted formula to every variable of the
+/// original one; variables the model does not mention are false.
+pub fn unsubstitute(model: &[i32], representatives: &[i32]) -> Vec<i32> {
+    (1..=representatives.len() as i32)
+        .map(|var| {
+            let repr = representatives[var as usize - 1];
+            let value = model
+                .get(repr.unsigned_abs() as usize - 1)
+                .is_some_and(|&l| l > 0);
+            if value == (repr > 0) { var } else { -var }
+        })
+        .collect()
+}
+
+/// Tarjan's strongly connected components, iteratively; returns the
+/// component index of every node, numbered in the order they complete.
+fn components(graph: &[Vec<usize>]) -> Vec<usize> {
+    const UNSEEN: usize = usize::MAX;
+    let n = graph.len();
+    let mut index = vec![UNSEEN; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut component = vec![0; n];
+    let mut stack = Vec::new();
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    let (mut next_index, mut next_component) = (0, 0);
+    for root in 0..n {
+        if index[root] != UNSEEN {
+            continue;
+        }
+        index[root] = next_index;
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        calls.push((root, 0));
+        while let Some(top) = calls.last_mut() {
+            let (v, edge) = *top;
+            if let Some(&w) = graph[v].get(edge) {
+                top.1 += 1;
+                if index[w] == UNSEEN {
+                    index[w] = next_index;
+                    low[w] = next_index;
+                    next_index += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if low[v] == index[v] {
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    component[w] = next_component;
+                    if w == v {
+                        break;
+                    }
+                }
+                next_component += 1;
+            }
+        }
+    }
+    component
+}
+
+/// Solves a Horn formula by unit propagation from the all-false assignment,
+/// which yields its minimal model; `None` if it is unsatisfiable.
+pub fn solve_horn(clauses: &[Vec<i32>]) -> Option<Vec<i32>> {
+    let vars = vars(clauses);
+    let mut value = vec![false; vars + 1];
+    // Negative literals of each clause that are not yet false.
+    let mut open: Vec<usize> = clauses
+        .iter()
+        .map(|clause| clause.iter().filter(|&&lit| lit < 0).count())
+        .collect();
+    let mut occurs = vec![Vec::new(); vars + 1];
+    for (i, clause) in clauses.iter().enumerate() {
+        for &lit in clause.iter().filter(|&&lit| lit < 0) {
+            occurs[lit.unsigned_abs() as usize].push(i);
+        }
+    }
+    let mut queue: Vec<usize> = (0..clauses.len()).filter(|&i| open[i] == 0).collect();
+    while let Some(i) = queue.pop() {
+        let var = clauses[i].iter().find(|&&lit| lit > 0)?.unsigned_abs() as usize;
+        if value[var] {
+            continue;
+        }
+        value[var] = true;
+        for &j in &occurs[var] {
+            open[j] -= 1;
+            if open[j] == 0 {
+                queue.push(j);
+            }
+        }
+    }
+    Some(
+        (1..=vars as i32)
+            .map(|var| if value[var as usize] { var } else { -var })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satisfies(clauses: &[Vec<i32>], model: &[i32]) -> bool {
+        clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|&lit| model[lit.unsigned_abs() as usize - 1] == lit)
+        })
+    }
+
+    /// Whether some assignment of `vars` variables satisfies `clauses`.
+    fn satisfiable(clauses: &[Vec<i32>], vars: usize) -> bool {
+        (0..1u32 << vars).any(|bits| {
+            let model: Vec<i32> = (1..=vars as i32)
+                .map(|var| {
+                    if bits >> (var - 1) & 1 == 1 {
+                        var
+                    } else {
+                        -var
+                    }
+                })
+                .collect();
+            satisfies(clauses, &model)
+        })
+    }
+
+    /// Formulas of up to `len` clauses of one or two literals over `vars`
+    /// variables, from a fixed pseudo-random sequence.
+    fn formulas(count: usize, vars: u32, len: u32, width: u32) -> Vec<Vec<Vec<i32>>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |bound: u32| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % u64::from(bound)) as u32
+        };
+        (0..count)
+            .map(|_| {
+                (0..next(len) + 1)
+                    .map(|_| {
+                        (0..next(width) + 1)
+                            .map(|_| {
+                                let var = (next(vars) + 1) as i32;
+                                if next(2) == 0 { var } else { -var }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_sat_models() {
+        let clauses = vec![vec![1, 2], vec![-1, 3], vec![-3, -2], vec![2, 4], vec![-4]];
+        let model = solve_2sat(&clauses).unwrap();
+        assert_eq!(model.len(), 4);
+        assert!(satisfies(&clauses, &model));
+    }
+
+    #[test]
+    fn two_sat_unsatisfiable() {
+        let all = vec![vec![1, 2], vec![1, -2], vec![-1, 2], vec![-1, -2]];
+        assert_eq!(solve_2sat(&all), None);
+        assert_eq!(solve_2sat(&[vec![1], vec![-1]]), None);
+        assert_eq!(solve_2sat(&[vec![1, 2], vec![]]), None);
+        // x1 -> x2 -> x3 -> -x1, and x1 forced.
+        let chain = vec![vec![-1, 2], vec![-2, 3], vec![-3, -1], vec![1]];
+        assert_eq!(solve_2sat(&chain), None);
+    }
+
+    #[test]
+    fn two_sat_agrees_with_brute_force() {
+        for clauses in formulas(2000, 4, 8, 2) {
+            match solve_2sat(&clauses) {
+                Some(model) => {
+                    assert_eq!(model.len(), vars(&clauses));
+                    assert!(satisfies(&clauses, &model), "{:?} {:?}", clauses, model);
+                }
+                None => assert!(!satisfiable(&clauses, 4), "{:?}", clauses),
+            }
+        }
+    }
+
+    #[test]
+    fn components_of_a_graph() {
+        // 0 -> 1 -> 2 -> 0 leads to 3 <-> 4; 5 is alone.
+        let graph = vec![vec![1], vec![2], vec![0, 3], vec![4], vec![3], vec![]];
+        let component = components(&graph);
+        assert_eq!(component[0], component[1]);
+        assert_eq!(component[1], component[2]);
+        assert_eq!(component[3], component[4]);
+        assert_ne!(component[0], component[3]);
+        assert_ne!(component[5], component[0]);
+        assert_ne!(component[5], component[3]);
+        // Components complete in reverse topological order.
+        assert!(component[3] < component[0]);
+    }
+
+    #[test]
+    fn components_of_a_long_path() {
+        // Deep enough to overflow the stack of a recursive search.
+        let n = 200_000;
+        let mut graph: Vec<Vec<usize>> = (0..n).map(|v| vec![v + 1]).collect();
+        graph[n - 1] = vec![0];
+        let component = components(&graph);
+        assert!(component.iter().all(|&c| c == component[0]));
+        graph[n - 1].clear();
+        let component = components(&graph);
+        assert!(component.windows(2).all(|w| w[0] == w[1] + 1));
+    }
+
+    #[test]
+    fn horn_minimal_model() {
+        let clauses = vec![vec![1], vec![-1, 2], vec![-2, -3], vec![-1, -4, 5]];
+        assert_eq!(solve_horn(&clauses), Some(vec![1, 2, -3, -4, -5]));
+    }
+
+    #[test]
+    fn horn_conflict_after_propagation() {
+        // No clause is empty, but propagating 1 and then 2 falsifies the
+        // last one.
+        let clauses = vec![vec![1], vec![-1, 2], vec![-1, -2]];
+        assert_eq!(solve_horn(&clauses), None);
+    }
+
+    #[test]
+    fn horn_agrees_with_brute_force() {
+        for clauses in formulas(2000, 4, 6, 3) {
+            if !is_horn(&clauses) {
+                continue;
+            }
+            match solve_horn(&clauses) {
+                Some(model) => assert!(satisfies(&clauses, &model), "{:?}", clauses),
+                None => assert!(!satisfiable(&clauses, 4), "{:?}", clauses),
+            }
+        }
+    }
+
+    #[test]
+    fn equivalence_cycle_with_negations() {
+        // 1 -> 2 -> -3 -> 1, so 1 = 2 = -3.
+        let clauses = vec![vec![-1, 2], vec![-2, -3], vec![3, 1], vec![3, 4]];
+        let representatives = equivalences(&clauses).unwrap();
+        assert_eq!(representatives, vec![1, 1, -1, 4]);
+        let substituted = substitute(&clauses, &representatives);
+        assert_eq!(substituted, vec![vec![-1, 4]]);
+        let model = unsubstitute(&[1, -2, -3, 4], &representatives);
+        assert_eq!(model, vec![1, 2, -3, 4]);
+        assert!(satisfies(&clauses, &model));
+    }
+
+    #[test]
+    fn equivalent_to_own_negation() {
+        // 1 -> 2 -> -1 -> 3 -> 1.
+        let clauses = vec![vec![-1, 2], vec![-2, -1], vec![1, 3], vec![-3, 1]];
+        assert_eq!(equivalences(&clauses), None);
+    }
+
+    #[test]
+    fn renaming_to_horn() {
+        let clauses = vec![vec![1, 2, -3], vec![-1, 3], vec![2, 4]];
+        let flipped = horn_renaming(&clauses).unwrap();
+        let renamed: Vec<Vec<i32>> = clauses
+            .iter()
+            .map(|clause| {
+                clause
+                    .iter()
+                    .map(|&lit| {
+                        if flipped.contains(&lit.abs()) {
+                            -lit
+                        } else {
+                            lit
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        assert!(is_horn(&renamed));
+        let all = vec![vec![1, 2], vec![-1, -2], vec![1, -2], vec![-1, 2]];
+        assert_eq!(horn_renaming(&all), None);
+    }
+
+    #[test]
+    fn xor_in_full() {
+        // x1 ^ x2 ^ x3, forbidding the assignments with an even number of
+        // true variables.
+        let mut clauses = vec![
+            vec![1, 2, 3],
+            vec![-1, -2, 3],
+            vec![-1, 2, -3],
+            vec![1, -2, -3],
+        ];
+        let xors = find_xors(&clauses, 4);
+        assert_eq!(xors.len(), 1);
+        assert_eq!(xors[0].vars, vec![1, 2, 3]);
+        assert!(xors[0].parity);
+        assert_eq!(xors[0].clauses, 4);
+        assert!(find_xors(&clauses, 2).is_empty());
+        clauses.pop();
+        assert!(find_xors(&clauses, 4).is_empty());
+    }
+}
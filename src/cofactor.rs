@@ -0,0 +1,86 @@
+//! A CNF conditioned on a partial assignment: the assigned literals are
+//! propagated, and the clauses are simplified by every literal that is
+//! assigned or implied.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::Args;
+
+use crate::{
+    analyze::literal_lines,
+    core::{non_dash, open_input},
+    dimacs::{read_commented, write_comments, write_dimacs},
+    pipeline,
+};
+
+#[derive(Args)]
+pub struct Arg {
+    /// CNF to condition (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Comma-separated literals of the assignment, e.g. 1,-3,7
+    #[arg(long, value_name = "LITS", value_delimiter = ',', allow_hyphen_values = true, value_parser = parse_literal)]
+    assign: Vec<i32>,
+    /// File with more literals of the assignment, as in a model or `v` lines
+    #[arg(long = "assign-file", value_name = "FILE")]
+    assign_file: Option<PathBuf>,
+    /// Where the conditioned CNF is written; default for stdout
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Keep the input's comment lines that start with these comma-separated prefixes, or all if none are given, and write them ahead of the conditioned CNF
+    #[arg(long = "keep-comments", value_name = "PREFIXES", num_args(0..=1), value_delimiter = ',')]
+    keep_comments: Option<Vec<String>>,
+}
+
+fn parse_literal(s: &str) -> Result<i32, String> {
+    s.trim()
+        .parse()
+        .ok()
+        .filter(|&lit| lit != 0)
+        .ok_or_else(|| format!("`{}` is not a literal", s))
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let mut lits = self.assign.clone();
+        if let Some(path) = &self.assign_file {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("Cannot read {}", path.display()))?;
+            let file = literal_lines(&text)
+                .collect::<anyhow::Result<Vec<_>>>()
+                .with_context(|| format!("Invalid assignment {}", path.display()))?;
+            lits.extend(file.concat());
+        }
+        let reader = open_input(self.input.as_deref())?;
+        let (clauses, comments) = read_commented(reader, self.keep_comments.as_deref())?;
+        let clauses_before = clauses.len();
+        // The implied literals go ahead of the formula as a comment.
+        let mut out: Box<dyn Write> = match non_dash(self.out.as_deref()) {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).with_context(|| format!("Cannot create {}", path.display()))?,
+            )),
+            None => Box::new(io::stdout()),
+        };
+        let Some((clauses, implied)) = pipeline::cofactor(clauses, &lits) else {
+            println!("c The assignment conflicts with the clauses");
+            println!("c UNSATISFIABLE");
+            write_dimacs(&mut out, &[Vec::new()])?;
+            return Ok(20);
+        };
+        let implied_lits: Vec<String> = implied.iter().map(i32::to_string).collect();
+        println!(
+            "c {} literals assigned or implied, {} of {} clauses remain",
+            implied.len(),
+            clauses.len(),
+            clauses_before
+        );
+        write_comments(&mut out, &comments)?;
+        writeln!(out, "c implied {} 0", implied_lits.join(" "))?;
+        write_dimacs(&mut out, &clauses)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,284 @@
+//! Translations between the formats of SAT, pseudo-Boolean and MaxSAT tools.
+//!
+//! * `cnf-to-opb`: every clause becomes the constraint that at least one of
+//!   its literals is true, and a `c objective` comment the `min:` line.
+//! * `opb-to-cnf`: the constraints are encoded into clauses as `optimize`
+//!   does, with auxiliary variables after the problem's own, and the
+//!   objective is kept as a `c objective` comment.
+//! * `cnf-to-wcnf`: the clauses chosen by `--soft` become soft clauses of
+//!   weight `--weight` and the others hard; each term `c·l` of a
+//!   `c objective` comment becomes a soft clause `¬l` of weight `c`.
+//! * `to-binary` and `from-binary`: DIMACS CNF to the compact binary
+//!   format of [`crate::binary`] and back.
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, anyhow};
+use clap::{Args, Subcommand};
+
+use crate::{
+    binary::{self, FlatCnf},
+    core::{non_dash, open_input},
+    dimacs::{decompress, read_dimacs, write_dimacs},
+    opb::{self, Problem},
+};
+
+#[derive(Args)]
+pub struct Arg {
+    #[command(subcommand)]
+    conversion: Conversion,
+}
+
+#[derive(Subcommand)]
+enum Conversion {
+    /// CNF to OPB, each clause as a pseudo-Boolean constraint
+    CnfToOpb(Files),
+    /// OPB to CNF, each constraint encoded into clauses
+    OpbToCnf(Files),
+    /// CNF to WCNF, with the clauses made soft or hard
+    CnfToWcnf(WcnfArg),
+    /// CNF to the compact binary format
+    ToBinary(BinaryArg),
+    /// Binary CNF back to DIMACS
+    FromBinary(Files),
+}
+
+#[derive(Args)]
+struct Files {
+    /// Instance to convert (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Where the converted instance is written; default for stdout
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct WcnfArg {
+    #[command(flatten)]
+    files: Files,
+    /// Clauses made soft: `all`, `none`, or comma-separated clause numbers and ranges such as 1-100,250; the others are hard
+    #[arg(long, value_name = "CLAUSES", default_value = "all", value_parser = parse_clauses)]
+    soft: Selection,
+    /// Weight of the soft clauses
+    #[arg(long, value_name = "W", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    weight: u64,
+    /// Write the pre-2022 format with a `p wcnf` header and the top weight on hard clauses
+    #[arg(long = "old-format")]
+    old_format: bool,
+}
+
+#[derive(Args)]
+struct BinaryArg {
+    #[command(flatten)]
+    files: Files,
+    /// Compress with zstd, at LEVEL 1 to 22
+    #[cfg(feature = "compression")]
+    #[arg(long, value_name = "LEVEL", num_args(0..=1), default_missing_value = "3", value_parser = clap::value_parser!(i32).range(1..=22))]
+    zstd: Option<i32>,
+}
+
+/// Clauses chosen by number, from 1.
+#[derive(Clone)]
+enum Selection {
+    All,
+    Ranges(Vec<(usize, usize)>),
+}
+
+impl Selection {
+    fn contains(&self, number: usize) -> bool {
+        match self {
+            Selection::All => true,
+            Selection::Ranges(ranges) => ranges
+                .iter()
+                .any(|&(first, last)| (first..=last).contains(&number)),
+        }
+    }
+}
+
+fn parse_clauses(s: &str) -> Result<Selection, String> {
+    match s {
+        "all" => return Ok(Selection::All),
+        "none" => return Ok(Selection::Ranges(Vec::new())),
+        _ => {}
+    }
+    let number = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("`{n}` is not a clause number"))
+    };
+    s.split(',')
+        .map(|part| match part.split_once('-') {
+            Some((first, last)) => Ok((number(first)?, number(last)?)),
+            None => number(part).map(|n| (n, n)),
+        })
+        .collect::<Result<_, _>>()
+        .map(Selection::Ranges)
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        match &self.conversion {
+            Conversion::CnfToOpb(files) => {
+                let problem = Problem::read_cnf(files.reader()?)?;
+                let mut out = files.writer()?;
+                opb::write_opb(&mut out, &problem.clauses, problem.objective.as_deref())?;
+                out.flush()?;
+                eprintln!("c {} clauses written as constraints", problem.clauses.len());
+            }
+            Conversion::OpbToCnf(files) => {
+                let problem = Problem::read_opb(files.reader()?)?;
+                let mut out = files.writer()?;
+                if let Some(objective) = &problem.objective {
+                    writeln!(out, "c objective {}", opb::format_terms(objective))?;
+                }
+                write_dimacs(&mut out, &problem.clauses)?;
+                out.flush()?;
+                let vars = problem.clauses.iter().flatten().map(|l| l.abs()).max();
+                eprintln!(
+                    "c {} clauses, {} auxiliary variables",
+                    problem.clauses.len(),
+                    vars.unwrap_or(0).saturating_sub(problem.vars).max(0)
+                );
+            }
+            Conversion::CnfToWcnf(arg) => arg.run()?,
+            Conversion::ToBinary(arg) => arg.run()?,
+            Conversion::FromBinary(files) => {
+                let mut reader = decompress(files.reader()?)?;
+                if !binary::detect(&mut reader)? {
+                    return Err(anyhow!("the input is not a binary CNF"));
+                }
+                // Written to by both the header and the clause handler.
+                let out = RefCell::new(files.writer()?);
+                let mut clauses = 0;
+                binary::decode(
+                    reader,
+                    |vars, clauses| Ok(writeln!(out.borrow_mut(), "p cnf {} {}", vars, clauses)?),
+                    |clause| {
+                        clauses += 1;
+                        let mut out = out.borrow_mut();
+                        for lit in clause {
+                            write!(out, "{} ", lit)?;
+                        }
+                        Ok(writeln!(out, "0")?)
+                    },
+                )?;
+                out.borrow_mut().flush()?;
+                eprintln!("c {} clauses written", clauses);
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl Files {
+    fn reader(&self) -> anyhow::Result<Box<dyn Read>> {
+        open_input(self.input.as_deref())
+    }
+
+    fn writer(&self) -> anyhow::Result<Box<dyn Write>> {
+        Ok(match non_dash(self.out.as_deref()) {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).with_context(|| format!("Cannot create {}", path.display()))?,
+            )),
+            None => Box::new(BufWriter::new(io::stdout())),
+        })
+    }
+}
+
+impl WcnfArg {
+    fn run(&self) -> anyhow::Result<()> {
+        let problem = Problem::read_cnf(self.files.reader()?)?;
+        // (weight, clause), with `None` for hard clauses.
+        let mut clauses: Vec<(Option<u64>, Vec<i32>)> = problem
+            .clauses
+            .into_iter()
+            .enumerate()
+            .map(|(i, clause)| (self.soft.contains(i + 1).then_some(self.weight), clause))
+            .collect();
+        if let Some(objective) = &problem.objective {
+            // Minimizing `c·l` is satisfying `¬l` at cost `c`; the constant
+            // from negative coefficients does not change the optimum.
+            let (terms, _) = opb::normalize(objective, 0);
+            for (coef, lit) in terms {
+                clauses.push((Some(coef as u64), vec![-lit]));
+            }
+        }
+        let soft = clauses
+            .iter()
+            .filter_map(|(weight, _)| *weight)
+            .try_fold(0u64, u64::checked_add)
+            .ok_or_else(|| anyhow!("the soft weights sum beyond 2^64"))?;
+        let mut out = self.files.writer()?;
+        if self.old_format {
+            let vars = clauses
+                .iter()
+                .flat_map(|(_, clause)| clause)
+                .map(|lit| lit.abs())
+                .max()
+                .unwrap_or(0);
+            let top = soft
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("no top weight above the soft weights"))?;
+            writeln!(out, "p wcnf {} {} {}", vars, clauses.len(), top)?;
+            for (weight, clause) in &clauses {
+                write_clause(&mut out, &weight.unwrap_or(top).to_string(), clause)?;
+            }
+        } else {
+            for (weight, clause) in &clauses {
+                let weight = weight.map_or("h".to_string(), |w| w.to_string());
+                write_clause(&mut out, &weight, clause)?;
+            }
+        }
+        out.flush()?;
+        let hard = clauses.iter().filter(|(weight, _)| weight.is_none()).count();
+        eprintln!(
+            "c {} hard and {} soft clauses, total soft weight {}",
+            hard,
+            clauses.len() - hard,
+            soft
+        );
+        Ok(())
+    }
+}
+
+impl BinaryArg {
+    fn run(&self) -> anyhow::Result<()> {
+        let mut cnf = FlatCnf::default();
+        read_dimacs(self.files.reader()?, false, &mut cnf)?;
+        let mut out = self.files.writer()?;
+        #[cfg(feature = "compression")]
+        let level = self.zstd;
+        #[cfg(not(feature = "compression"))]
+        let level: Option<i32> = None;
+        match level {
+            #[cfg(feature = "compression")]
+            Some(level) => {
+                let mut encoder = zstd::Encoder::new(&mut out, level)?;
+                binary::write(&mut encoder, &cnf)?;
+                encoder.finish()?;
+            }
+            _ => binary::write(&mut out, &cnf)?,
+        }
+        out.flush()?;
+        eprintln!(
+            "c {} clauses over {} variables written",
+            cnf.clauses, cnf.vars
+        );
+        Ok(())
+    }
+}
+
+fn write_clause<W: Write>(out: &mut W, weight: &str, clause: &[i32]) -> io::Result<()> {
+    write!(out, "{}", weight)?;
+    for lit in clause {
+        write!(out, " {}", lit)?;
+    }
+    writeln!(out, " 0")
+}
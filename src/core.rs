@@ -1,33 +1,1912 @@
 use std::{
-    io::{Read, Stdin, stdin,self, Write},
+    io::{Read, Stdin, stdin,self, Write, BufWriter},
     path::{PathBuf,Path},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
     time::{Duration, Instant},
-    fs::File
+    fs::{self, File}
 };
 
-use cpu_time::ProcessTime;
+use anyhow::Context;
+use clap::{ArgMatches, Args, Command, FromArgMatches, ValueEnum};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use satgalaxy::solver::RawStatus;
+use sha2::{Digest, Sha256};
+use validator::Validate;
 
-use crate::utils::get_memory;
+use crate::{
+    Reason, Repetitions, Size, SolveRequest, SolveResult, Solver, Stage, Stats, Status, Summary,
+    card::{self, AtMost},
+    classes,
+    dimacs::{
+        AsDimacs, Concatenated, Counting, DimacsError, Limits, WithComments, decompress,
+        kept_comment, read_dimacs, read_dimacs_within,
+    },
+    hook,
+    pipeline::{self, Pass, PassStats},
+    reconstruct::Log,
+    split,
+    symbols::Symbols,
+    trace::Tracer,
+    utils::{self, ProcessTime, get_memory},
+    version,
+};
+#[cfg(feature = "fetch")]
+use crate::fetch::{self, FetchArg, UrlReader, UrlSource};
+
+/// Options shared by every solver backend.
+#[derive(Args, Validate, Clone)]
+pub struct CommonArg {
+    /// Input source: local file (.cnf, .xz, .tar.gz), URL (http, https, s3, gs), `-` or default for stdin
+    #[arg(value_name = "INPUT",value_parser = parse_path)]
+    pub input: Option<SmartPath>,
+    /// Result and model output, compressed when it ends in .gz, .xz or .zst; `-` or default for stdout
+    #[arg(value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// Result and model output, as the positional OUTPUT; `--output -` writes to stdout
+    #[arg(long = "output", short = 'o', value_name = "OUTPUT", conflicts_with = "output")]
+    pub output_opt: Option<PathBuf>,
+    /// Compress the output regardless of its extension
+    #[arg(long = "compress", value_name = "FORMAT", value_enum)]
+    pub compress: Option<Compression>,
+    /// Expected SHA-256 of the input as read (before decompression); refuse to solve on mismatch
+    #[arg(long = "sha256", value_name = "HEX", value_parser = parse_sha256)]
+    pub sha256: Option<String>,
+    #[cfg(feature = "fetch")]
+    #[command(flatten)]
+    #[validate(nested)]
+    pub fetch: FetchArg,
+
+    #[arg(long = "verb", default_value_t = 0, help_heading = "Main")]
+    #[validate(range(min = 0, max = 2, message = "Verbosity level must be 0, 1, or 2"))]
+    /// Verbosity level (0=silent, 1=some, 2=more).
+    pub verb: i32,
+
+    #[arg(long = "verb-format", value_name = "FORMAT", value_enum, default_value_t = VerbFormat::Text, help_heading = "Main")]
+    /// How the solver's --verb output is written: timestamped `c` lines or JSON objects, one per line. Unix only; elsewhere it is printed as is.
+    pub verb_format: VerbFormat,
+
+    #[arg(long = "pre", num_args(0..=1), default_value_t = true, help_heading = "Main")]
+    /// Completely turn on/off any preprocessing.
+    pub pre: bool,
+    #[arg(long = "freeze", value_name = "VARS", value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..), help_heading = "Main")]
+    /// Comma-separated variables that variable elimination must keep.
+    pub freeze: Vec<i32>,
+    #[arg(long = "solve", num_args(0..=1), default_value_t = true, help_heading = "Main")]
+    /// Completely turn on/off solving after preprocessing.
+    pub solve: bool,
+    #[arg(long = "parse-only", conflicts_with_all = ["race_seeds", "components", "unique_check"], help_heading = "Main")]
+    /// Stop after parsing and report the parse time, the numbers of variables and clauses and the memory; exits with 0 if the input is valid. Use --solve=false to simplify as well.
+    pub parse_only: bool,
+
+    // #[arg(long = "dimacs")]
+    // /// If given, stop after preprocessing and write the result to this file.
+    // dimacs: Option<String>,
+    #[arg(long = "cpu-lim", value_name = "DURATION", default_value = "0", value_parser = parse_seconds, help_heading = "Main")]
+    /// Limit on CPU time allowed, e.g. 90s, 30m or 2h; bare numbers are seconds.
+    pub cpu_lim: u64,
+
+    #[arg(long = "mem-lim", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes, help_heading = "Main")]
+    /// Limit on memory usage, e.g. 512MB or 8GiB; bare numbers are megabytes.
+    pub mem_lim: u64,
+
+    #[arg(long = "strictp", num_args(0..=1), default_value_t = false, help_heading = "Main")]
+    /// Validate DIMACS header during parsing.
+    pub strictp: bool,
+
+    #[arg(long = "lenient", conflicts_with = "strictp", help_heading = "Main")]
+    /// Accept a missing or wrong header, comments after literals, non-ASCII whitespace and an unterminated last clause, with a warning for each.
+    pub lenient: bool,
+
+    #[arg(long = "max-vars", value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..), help_heading = "Main")]
+    /// Refuse an input with a variable above N, without reading the rest of it; 0 for no limit.
+    pub max_vars: i32,
+
+    #[arg(long = "max-clauses", value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..), help_heading = "Main")]
+    /// Refuse an input with more than N clauses, without reading the rest of it; 0 for no limit.
+    pub max_clauses: i32,
+
+    #[arg(long = "json", value_name = "FILE", help_heading = "Main")]
+    /// Also write the result, statistics and resolved options as JSON to this file, or the error with its category and location if the run fails.
+    pub json: Option<PathBuf>,
+
+    #[arg(long = "trace-file", value_name = "FILE", help_heading = "Main")]
+    /// Sample time, CPU time, memory and stage into this CSV file while running.
+    pub trace_file: Option<PathBuf>,
+    #[arg(long = "trace-interval", value_name = "DURATION", default_value = "1s", value_parser = parse_duration, help_heading = "Main")]
+    /// Sampling interval of --trace-file, e.g. 500ms, 10s or 2m.
+    pub trace_interval: Duration,
+
+    #[arg(long = "on-complete", value_name = "CMD", help_heading = "Main")]
+    /// Run this shell command when the job finishes; it gets the result summary on stdin and in SATGALAXY_* variables.
+    pub on_complete: Option<String>,
+
+    #[arg(long = "stats-file", value_name = "FILE", help_heading = "Main")]
+    /// Write the statistics to this file instead of stdout, where they interleave with the result.
+    pub stats_file: Option<PathBuf>,
+    #[arg(long = "stats-format", value_name = "FORMAT", value_enum, default_value_t = StatsFormat::Human, help_heading = "Main")]
+    /// Format of the statistics: `c` lines, a JSON object, or a CSV header and row.
+    pub stats_format: StatsFormat,
+
+    #[arg(long = "multi", conflicts_with_all = ["starexec", "stats_file"], help_heading = "Main")]
+    /// Read the input as several CNFs one after another, each from its `p cnf` header, as `cat` joins them, and solve each on its own with its own result as it is read; --json gets an array of them.
+    pub multi: bool,
+
+    #[arg(long = "dry-run", help_heading = "Main")]
+    /// Print the backend, the input source after URL and cache resolution, the limits and the output sinks this run would use, then exit without reading the input.
+    pub dry_run: bool,
+
+    #[arg(long = "on-interrupt", value_name = "MODE", value_enum, default_value_t = OnInterrupt::Stats, help_heading = "Main")]
+    /// What SIGINT, SIGTERM and SIGHUP do: print the statistics and exit, or report the run as UNKNOWN (INTERRUPTED) to OUTPUT, --json and --on-complete like a finished one.
+    pub on_interrupt: OnInterrupt,
+
+    #[arg(long = "no-signal-handler", conflicts_with = "on_interrupt", help_heading = "Main")]
+    /// Install no handler for SIGINT, SIGTERM and SIGHUP, leaving them to their default action or to the process driving this one.
+    pub no_signal_handler: bool,
+
+    #[arg(long = "starexec", conflicts_with_all = ["output", "output_opt", "compress"], help_heading = "Main")]
+    /// Report in the SAT competition format StarExec post-processors expect: `s` and `v` lines on stdout, runsolver-style statistics, exit code 10/20/0.
+    pub starexec: bool,
+
+    #[arg(long = "fast-paths", num_args(0..=1), default_value_t = false, help_heading = "Main")]
+    /// Solve 2-SAT formulas by strongly connected components and Horn formulas by unit propagation instead of CDCL.
+    pub fast_paths: bool,
+
+    #[arg(long = "partial-model", num_args(0..=1), default_value_t = false, help_heading = "Main")]
+    /// Report a partial model: literals are dropped in variable order while every clause keeps a true one, which is not necessarily the smallest such model.
+    pub partial_model: bool,
+
+    #[arg(long = "equivalences", num_args(0..=1), default_value_t = false, help_heading = "Main")]
+    /// Substitute literals the binary clauses make equivalent by one representative each before solving.
+    pub equivalences: bool,
+
+    #[arg(long = "extra-clauses", value_name = "FILE", help_heading = "Main")]
+    /// Add the clauses of this DIMACS file (plain, gzip or xz) to the input; may be repeated.
+    pub extra_clauses: Vec<PathBuf>,
+
+    #[arg(long = "at-most", value_name = "K: LITS", help_heading = "Main")]
+    /// Add the constraint that at most K of the literals are true, e.g. "2: 1 -4 7"; may be repeated.
+    pub at_most: Vec<AtMost>,
+
+    #[arg(long = "unique-check", value_name = "VARS", num_args(0..=1), value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..), help_heading = "Main")]
+    /// Block the model and solve again to report whether it is unique, over these comma-separated variables or all if none are given.
+    pub unique_check: Option<Vec<i32>>,
+
+    #[arg(long = "deterministic", num_args(0..=1), default_value_t = false, conflicts_with_all = ["cpu_lim", "race_seeds", "repeat"], help_heading = "Main")]
+    /// Make repeated runs print identical output: times and memory are left out of the statistics, and no CPU limit may cut the search short. The backends are single-threaded and seeded by --rnd-seed, so their search is reproducible.
+    pub deterministic: bool,
+
+    #[arg(long = "race-seeds", value_name = "N", default_value_t = 1, help_heading = "Main")]
+    #[validate(range(min = 1, message = "Race seeds must be at least 1"))]
+    /// Race N copies of the backend with different seeds in parallel, each in its own process, and report the first answer; --cpu-lim is split evenly between the copies.
+    pub race_seeds: usize,
+
+    #[arg(long = "schedule", value_name = "STAGES", value_delimiter = ',', conflicts_with_all = ["race_seeds", "repeat", "deterministic"], help_heading = "Main")]
+    /// Run configurations one after another, each started when the one before has run for its budget without an answer, e.g. "preset:agile@30s,preset:default@rest"; presets are default, agile and thorough. An earlier stage cannot be stopped and runs on beside the later ones; --cpu-lim counts them all.
+    pub schedule: Vec<Stage>,
+
+    #[arg(long = "repeat", value_name = "N", default_value_t = 1, conflicts_with = "race_seeds", help_heading = "Main")]
+    #[validate(range(min = 1, message = "Repeat must be at least 1"))]
+    /// Run the instance N times one after another and report min, median, mean and standard deviation of the parse and solve times and the memory; the result is that of the last run.
+    pub repeat: usize,
+
+    #[arg(long = "repeat-seeds", num_args(0..=1), default_value_t = false, requires = "repeat", help_heading = "Main")]
+    /// Give every run of --repeat but the first its own seed, to measure the spread over seeds rather than the noise of the machine.
+    pub repeat_seeds: bool,
+
+    #[arg(long = "result-cache", value_name = "DIR", conflicts_with = "repeat", help_heading = "Main")]
+    /// Look up the answer in this directory, keyed by the SHA-256 of the input and the options that affect it, and store new SATISFIABLE and UNSATISFIABLE answers there.
+    pub result_cache: Option<PathBuf>,
+
+    #[arg(long = "pipeline", value_name = "PASSES", value_delimiter = ',', value_enum, help_heading = "Main")]
+    /// Run these comma-separated preprocessing passes in order before solving, e.g. bce,probe,vivify,elim.
+    pub pipeline: Vec<Pass>,
+
+    #[arg(long = "reconstruction-log", value_name = "FILE", help_heading = "Main")]
+    /// Write how --pipeline and --equivalences changed the formula to FILE as a reconstruction log, which `analyze reconstruct` applies to a model of the simplified formula; the backend's own elimination is not in it.
+    pub reconstruction_log: Option<PathBuf>,
+
+    #[arg(long = "components", num_args(0..=1), default_value_t = false, conflicts_with = "unique_check", help_heading = "Main")]
+    /// Solve the variable-disjoint components of the formula separately, each with its own solver, and join their models.
+    pub components: bool,
+
+    #[arg(long = "component-threads", value_name = "N", default_value_t = 1, requires = "components", help_heading = "Main")]
+    #[validate(range(min = 1, message = "Component threads must be at least 1"))]
+    /// Solve up to N components of --components at a time.
+    pub component_threads: usize,
+
+    #[arg(long = "symbols", value_name = "FILE", help_heading = "Main")]
+    /// Read variable names from this file of `ID NAME` lines, in addition to `c varname ID NAME` comments in the input.
+    pub symbols: Option<PathBuf>,
+
+    #[arg(long = "show", value_name = "PATTERNS", value_delimiter = ',', help_heading = "Main")]
+    /// Print the values of the named variables matching these comma-separated patterns, e.g. "sel_*"; `*` matches any run of characters and `?` any one.
+    pub show: Vec<String>,
+
+    #[arg(long = "keep-comments", value_name = "PREFIXES", num_args(0..=1), value_delimiter = ',', help_heading = "Main")]
+    /// Keep the input's comment lines that start with these comma-separated prefixes, or all if none are given, and echo them into the JSON result.
+    pub keep_comments: Option<Vec<String>>,
+}
+
+impl CommonArg {
+    /// `self` with `--output` moved to OUTPUT, and an INPUT or OUTPUT of
+    /// `-` dropped, which is how the other options name stdin and stdout.
+    fn resolve_dashes(&self) -> CommonArg {
+        let mut arg = self.clone();
+        if arg.input == Some(SmartPath::FilePath(PathBuf::from("-"))) {
+            arg.input = None;
+        }
+        let output = arg.output_opt.take().or(arg.output.take());
+        arg.output = non_dash(output.as_deref()).map(Path::to_path_buf);
+        arg
+    }
+}
+
+/// What the signal handler does when the run is interrupted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnInterrupt {
+    /// Print the statistics gathered so far and exit.
+    Stats,
+    /// Report UNKNOWN (INTERRUPTED) to every sink of a finished run and exit.
+    Report,
+}
+
+/// Options whose values may carry credentials and are never echoed.
+const SECRET_OPTIONS: &[&str] = &["header", "bearer", "basic", "proxy"];
+
+/// Every option of `command` as `(name, value)`, defaults included, in
+/// declaration order; long names are used where an option has one.
+pub fn resolved_options(command: &Command, matches: &ArgMatches) -> Vec<(String, String)> {
+    command
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let values = matches.get_raw(id)?;
+            let name = arg.get_long().unwrap_or(id).to_string();
+            let value = if SECRET_OPTIONS.contains(&name.as_str()) {
+                "<redacted>".to_string()
+            } else {
+                values
+                    .map(|v| v.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// The solver operations used by the shared solving pipeline.
+pub trait Backend: AsDimacs + Sized {
+    fn new() -> Self;
+    fn eliminate(&mut self, turn_off_simp: bool);
+    fn okay(&self) -> bool;
+    fn solve_limited(&mut self, assumps: &[i32]) -> RawStatus;
+    fn vars(&self) -> i32;
+    fn model_value(&self, var: i32) -> bool;
+}
+
+/// Solver options are process-wide statics read when a solver is constructed,
+/// so installing them and constructing must not interleave between threads.
+static CONFIGURE: Mutex<()> = Mutex::new(());
+
+pub(crate) fn new_solver<S: Backend>(configure: impl FnOnce()) -> S {
+    let _guard = CONFIGURE.lock().unwrap_or_else(|e| e.into_inner());
+    configure();
+    S::new()
+}
+
+/// Default values of a clap argument group, as if no flag were given.
+pub fn defaults<T: Args + FromArgMatches>() -> T {
+    let command = T::augment_args(Command::new("satgalaxy"));
+    T::from_arg_matches(&command.get_matches_from(["satgalaxy"]))
+        .expect("argument defaults are valid")
+}
+
+/// Parses `(long flag, value)` pairs into `T` and validates the result; an
+/// empty value passes the flag alone.
+pub fn parse_args<T: Args + FromArgMatches + Validate>(
+    args: &[(String, String)],
+) -> anyhow::Result<T> {
+    let command = T::augment_args(Command::new("satgalaxy").no_binary_name(true));
+    let argv = args.iter().map(|(name, value)| {
+        if value.is_empty() {
+            format!("--{name}")
+        } else {
+            format!("--{name}={value}")
+        }
+    });
+    let matches = command
+        .try_get_matches_from(argv)
+        .map_err(|e| {
+            let message = e.render().to_string();
+            let first = message.lines().next().unwrap_or_default();
+            anyhow::anyhow!("{}", first.trim_start_matches("error: "))
+        })?;
+    let value = T::from_arg_matches(&matches)?;
+    value.validate()?;
+    Ok(value)
+}
+
+/// Solves the input described by `arg` with `solver` and reports the result;
+/// returns the process exit code (0 = SAT, 20 = UNSAT, 30 = UNKNOWN, 31-35
+/// for an UNKNOWN whose [`Reason`] is known).
+pub fn run(
+    arg: &CommonArg,
+    solver: Solver,
+    options: &[(String, String)],
+) -> anyhow::Result<i32> {
+    let arg = &arg.resolve_dashes();
+    for (name, value) in options {
+        println!("c o {}={}", name, value);
+    }
+    if arg.cpu_lim > 0 {
+        println!("c CPU limit: {}", describe_seconds(arg.cpu_lim));
+    }
+    if arg.mem_lim > 0 {
+        println!("c Memory limit: {}", describe_megabytes(arg.mem_lim));
+    }
+    if arg.dry_run {
+        print_plan(arg, &solver)?;
+        return Ok(0);
+    }
+    // Fail before the solve rather than lose its answer after it.
+    for path in [&arg.json, &arg.stats_file, &arg.trace_file].into_iter().flatten() {
+        check_writable(path)?;
+    }
+    let stat = Arc::new(Mutex::new(Stat::of(arg)));
+    let output = Writer::create(arg.output.as_deref(), arg.compress)?;
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let _ = UNFINISHED.set(Unfinished {
+            arg: arg.clone(),
+            stat: stat.clone(),
+            options: options.to_vec(),
+        });
+        if !arg.no_signal_handler {
+            ctrlc::set_handler(|| exit_unfinished(Reason::Interrupted))?;
+        }
+        if arg.cpu_lim > 0 {
+            cpu_watchdog(Duration::from_secs(arg.cpu_lim));
+        }
+    }
+    let request = SolveRequest {
+        input: arg.input.clone(),
+        sha256: arg.sha256.clone(),
+        #[cfg(feature = "fetch")]
+        fetch: arg.fetch.clone(),
+        solver,
+        verbosity: arg.verb,
+        preprocess: arg.pre,
+        freeze: arg.freeze.clone(),
+        solve: arg.solve,
+        parse_only: arg.parse_only,
+        strict: arg.strictp,
+        lenient: arg.lenient,
+        limits: Limits {
+            vars: arg.max_vars,
+            clauses: arg.max_clauses,
+        },
+        // The watchdog reports the limit; the rlimit, which kills the process
+        // without a word, only backs it up a second later.
+        cpu_limit: if cfg!(target_family = "wasm") || arg.cpu_lim == 0 {
+            arg.cpu_lim
+        } else {
+            arg.cpu_lim + 1
+        },
+        mem_limit: arg.mem_lim,
+        // StarExec merges stderr into the job output.
+        progress: !arg.starexec,
+        fast_paths: arg.fast_paths,
+        partial_model: arg.partial_model,
+        equivalences: arg.equivalences,
+        extra_clauses: arg.extra_clauses.clone(),
+        at_most: arg.at_most.clone(),
+        unique_check: arg.unique_check.clone(),
+        pipeline: arg.pipeline.clone(),
+        reconstruction_log: arg.reconstruction_log.clone(),
+        race_seeds: arg.race_seeds,
+        schedule: arg.schedule.clone(),
+        repeat: arg.repeat,
+        repeat_seeds: arg.repeat_seeds,
+        result_cache: arg.result_cache.clone(),
+        components: arg.components,
+        component_threads: arg.component_threads,
+        symbols: arg.symbols.clone(),
+        show: arg.show.clone(),
+        keep_comments: arg.keep_comments.clone(),
+    };
+    let tracer = match &arg.trace_file {
+        Some(path) => Some(
+            Tracer::start(path, arg.trace_interval, stat.clone())
+                .map_err(|e| anyhow::anyhow!("Cannot write {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    if arg.multi {
+        let code = run_multi(arg, &request, &stat, output, options);
+        if let Some(tracer) = tracer {
+            tracer.finish()?;
+        }
+        return code;
+    }
+    #[cfg(unix)]
+    let capture = start_capture(arg, &request, &stat)?;
+    let result = crate::solve_with(&request, None, &stat);
+    #[cfg(unix)]
+    if let Some(capture) = capture {
+        capture.finish()?;
+    }
+    if let Some(tracer) = tracer {
+        tracer.finish()?;
+    }
+    let input = arg.input.as_ref().map(SmartPath::to_string);
+    let mut result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            let summary = hook::error_summary(&e, input);
+            // The error takes the place of the result for harnesses reading
+            // --json.
+            if let Some(path) = &arg.json {
+                write_atomic(path, serde_json::to_string_pretty(&summary)? + "\n")?;
+            }
+            if let Some(cmd) = &arg.on_complete {
+                on_complete(cmd, &summary);
+            }
+            return Err(e);
+        }
+    };
+    if arg.deterministic {
+        result.stats.clear_measurements();
+    }
+    print_notes(arg, &result);
+    stat.lock().unwrap().print();
+    let code = report(&result, output, arg.starexec, arg.json.as_deref(), options)?;
+    if let Some(cmd) = &arg.on_complete {
+        let path = |p: &Option<PathBuf>| p.as_ref().map(|p| p.display().to_string());
+        let summary = hook::summary(
+            result_json(&result, options),
+            input,
+            path(&arg.output),
+            path(&arg.json),
+        );
+        on_complete(cmd, &summary);
+    }
+    Ok(code)
+}
+
+/// Starts capturing what the solver prints, if `--verb` asks for it.
+#[cfg(unix)]
+fn start_capture(
+    arg: &CommonArg,
+    request: &SolveRequest,
+    stat: &Mutex<Stat>,
+) -> io::Result<Option<crate::capture::Capture>> {
+    if arg.verb == 0 {
+        return Ok(None);
+    }
+    let start = stat.lock().unwrap().run_time;
+    crate::capture::Capture::start(arg.verb_format, request.solver.name(), start).map(Some)
+}
+
+/// Solves every CNF of the concatenated input of `request` in turn, for
+/// `--multi`. Each is reported as a run of its own would be, with its result
+/// written to `output` after the previous one; `--json` gets an array with a
+/// result or error per instance. Returns the largest exit code of the
+/// instances, so 0 only if all are satisfiable.
+fn run_multi(
+    arg: &CommonArg,
+    request: &SolveRequest,
+    stat: &Mutex<Stat>,
+    mut output: Writer,
+    options: &[(String, String)],
+) -> anyhow::Result<i32> {
+    let mut reader = HashingReader::new(SmartReader::open(request)?);
+    let mut instances = Concatenated::new(decompress(&mut reader)?)?;
+    // The digest covers the stream, checked once it is read; each instance
+    // records its own.
+    let expected = request.sha256.clone();
+    let request = &SolveRequest {
+        sha256: None,
+        ..request.clone()
+    };
+    let input = arg.input.as_ref().map(SmartPath::to_string);
+    let path = |p: &Option<PathBuf>| p.as_ref().map(|p| p.display().to_string());
+    let mut documents = Vec::new();
+    let mut code = 0;
+    let mut number = 0;
+    while instances.next_instance()? {
+        number += 1;
+        println!("c Instance {}", number);
+        if number > 1 {
+            *stat.lock().unwrap() = Stat::of(arg);
+        }
+        #[cfg(unix)]
+        let capture = start_capture(arg, request, stat)?;
+        let result = crate::solve_with(request, Some(&mut instances), stat);
+        #[cfg(unix)]
+        if let Some(capture) = capture {
+            capture.finish()?;
+        }
+        let (document, summary) = match result {
+            Ok(mut result) => {
+                if arg.deterministic {
+                    result.stats.clear_measurements();
+                }
+                print_notes(arg, &result);
+                stat.lock().unwrap().print();
+                write_result(&result, &mut output)?;
+                code = code.max(result.exit_code());
+                let document = result_json(&result, options);
+                let summary = hook::summary(
+                    document.clone(),
+                    input.clone(),
+                    path(&arg.output),
+                    path(&arg.json),
+                );
+                (document, summary)
+            }
+            Err(e) => {
+                println!("c ERROR: {}", e);
+                code = code.max(Status::Unknown.exit_code());
+                let summary = hook::error_summary(&e, input.clone());
+                (summary.clone(), summary)
+            }
+        };
+        if let Some(cmd) = &arg.on_complete {
+            on_complete(cmd, &summary);
+        }
+        documents.push(document);
+    }
+    drop(instances);
+    let digest = reader.finish()?;
+    output.finish()?;
+    if let Some(path) = &arg.json {
+        write_atomic(path, serde_json::to_string_pretty(&documents)? + "\n")?;
+    }
+    if let Some(expected) = expected
+        && expected != digest
+    {
+        return Err(anyhow::anyhow!(
+            "Input SHA-256 mismatch: expected {}, got {}",
+            expected,
+            digest
+        ));
+    }
+    Ok(code)
+}
+
+/// Prints the warnings of `result` and how it was reached.
+fn print_notes(arg: &CommonArg, result: &SolveResult) {
+    for warning in &result.warnings {
+        println!("c WARNING: {}", warning);
+    }
+    for pass in &result.passes {
+        println!("{}", pass.line());
+    }
+    if let Some(copy) = result.race_winner {
+        println!("c Copy {} of the seed race answered first", copy);
+    }
+    if let Some(stage) = result.stage {
+        let preset = arg.schedule[stage].preset;
+        println!("c Stage {} (preset:{}) of the schedule answered", stage + 1, preset.name());
+    }
+    if result.cached {
+        println!("c Answer taken from the result cache");
+    }
+    if let Some(repetitions) = &result.repetitions {
+        print_repetitions(repetitions);
+    }
+    if let Some(class) = result.fast_path {
+        println!("c Solved by the {} fast path", class);
+    }
+    if let Some(components) = result.components {
+        println!("c Solved {} components separately", components);
+    }
+    if !arg.show.is_empty() {
+        for (name, value) in &result.named {
+            println!("c {} = {}", name, value);
+        }
+    }
+    match result.unique {
+        Some(true) => println!("c The solution is unique"),
+        Some(false) => println!("c The solution is not unique"),
+        None => {}
+    }
+}
+
+/// Prints what a run with `arg` would read, use and write, for `--dry-run`.
+fn print_plan(arg: &CommonArg, solver: &Solver) -> anyhow::Result<()> {
+    let mut plan = vec![("Backend", format!("{} {}", solver.name(), solver.version()))];
+    match &arg.input {
+        None => plan.push(("Input", "stdin".to_string())),
+        Some(SmartPath::FilePath(path)) => {
+            let size = fs::metadata(path)
+                .map_err(|e| anyhow::anyhow!("Cannot open {}: {}", path.display(), e))?
+                .len();
+            let size = human_bytes::human_bytes(size as f64);
+            plan.push(("Input", format!("{} ({})", path.display(), size)));
+        }
+        #[cfg(feature = "fetch")]
+        Some(SmartPath::Url(url)) => {
+            plan.push(("Input", url.to_string()));
+            plan.extend(fetch::plan(url, &arg.fetch)?);
+        }
+    }
+    let limit = |value: u64, describe: fn(u64) -> String| match value {
+        0 => "none".to_string(),
+        value => describe(value),
+    };
+    plan.push(("CPU limit", limit(arg.cpu_lim, describe_seconds)));
+    plan.push(("Memory limit", limit(arg.mem_lim, describe_megabytes)));
+    let output = match (&arg.output, arg.starexec) {
+        (_, true) => "stdout, SAT competition format".to_string(),
+        (None, false) => "stdout".to_string(),
+        (Some(path), false) => path.display().to_string(),
+    };
+    let compress = match (arg.compress, &arg.output) {
+        (Some(compress), _) => Some(compress),
+        (None, Some(path)) => Compression::from_path(path)?,
+        (None, None) => None,
+    };
+    plan.push(match compress {
+        Some(compress) => ("Output", format!("{} ({:?})", output, compress)),
+        None => ("Output", output),
+    });
+    if let Some(path) = &arg.json {
+        plan.push(("JSON", path.display().to_string()));
+    }
+    if let Some(dir) = &arg.result_cache {
+        plan.push(("Result cache", dir.display().to_string()));
+    }
+    let stats = match &arg.stats_file {
+        Some(path) => path.display().to_string(),
+        None => "stdout".to_string(),
+    };
+    plan.push(("Statistics", format!("{} ({:?})", stats, arg.stats_format)));
+    if let Some(path) = &arg.trace_file {
+        plan.push(("Trace", format!("{} every {:?}", path.display(), arg.trace_interval)));
+    }
+    if let Some(cmd) = &arg.on_complete {
+        plan.push(("On complete", cmd.clone()));
+    }
+    let interrupt = match (arg.no_signal_handler, arg.on_interrupt) {
+        (true, _) => "no handler",
+        (false, OnInterrupt::Stats) => "print statistics and exit",
+        (false, OnInterrupt::Report) => "report UNKNOWN (INTERRUPTED) and exit",
+    };
+    plan.push(("On interrupt", interrupt.to_string()));
+    for (what, how) in plan {
+        println!("c {:<16}{}", format!("{}:", what), how);
+    }
+    println!("c Dry run, nothing was read or solved");
+    Ok(())
+}
+
+/// What a run cut short by an interrupt, `--cpu-lim` or `--mem-lim` is
+/// reported with.
+#[cfg(not(target_family = "wasm"))]
+struct Unfinished {
+    arg: CommonArg,
+    stat: Arc<Mutex<Stat>>,
+    options: Vec<(String, String)>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+static UNFINISHED: OnceLock<Unfinished> = OnceLock::new();
+
+/// Set once a thread has started to end the process.
+#[cfg(not(target_family = "wasm"))]
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(target_family = "wasm"))]
+thread_local! {
+    /// Whether this thread is the one ending the process.
+    static IN_EXIT: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Ends a run cut short for `reason` and exits with its code. The limits,
+/// and an interrupt under `--on-interrupt report`, are reported like a
+/// finished run, as UNKNOWN to OUTPUT, `--json` and `--on-complete`; a plain
+/// interrupt prints the statistics gathered so far. A failed allocation
+/// ends the process with [`exit_memout`] instead.
+#[cfg(not(target_family = "wasm"))]
+pub fn exit_unfinished(reason: Reason) -> ! {
+    if IN_EXIT.with(|flag| flag.replace(true)) {
+        let _ = writeln!(io::stdout(), "c UNKNOWN ({})", reason.as_str());
+        std::process::exit(reason.exit_code());
+    }
+    if EXITING.swap(true, Ordering::SeqCst) {
+        // Another thread is reporting and exits the process.
+        loop {
+            std::thread::park();
+        }
+    }
+    #[cfg(unix)]
+    let _ = crate::capture::release();
+    let Some(unfinished) = UNFINISHED.get() else {
+        println!("c UNKNOWN ({})", reason.as_str());
+        remove_parts(true);
+        std::process::exit(reason.exit_code());
+    };
+    let mut locked = unfinished.stat.lock().ok();
+    let mut empty;
+    let stat = match &mut locked {
+        Some(stat) => &mut **stat,
+        None => {
+            empty = Stat::of(&unfinished.arg);
+            &mut empty
+        }
+    };
+    match (reason, unfinished.arg.on_interrupt) {
+        (Reason::Interrupted, OnInterrupt::Stats) => {
+            if stat.print() {
+                println!("c Interrupted");
+            }
+        }
+        _ => report_unfinished(&unfinished.arg, stat, &unfinished.options, reason),
+    }
+    remove_parts(true);
+    std::process::exit(reason.exit_code());
+}
+
+/// Ends the process after an allocation failed, usually under `--mem-lim`,
+/// from inside the allocator: the fixed status line is written with
+/// write(2) and the temporary files of unfinished outputs are removed.
+/// Nothing else is reported, since that would need memory.
+#[cfg(not(target_family = "wasm"))]
+pub fn exit_memout() -> ! {
+    static ENTERED: AtomicBool = AtomicBool::new(false);
+    const LINE: &[u8] = b"c UNKNOWN (MEMOUT)\n";
+    // Removing the files may allocate, and fail again.
+    if !ENTERED.swap(true, Ordering::SeqCst) {
+        #[cfg(unix)]
+        // SAFETY: `LINE` is valid for reads of its length.
+        let _ = unsafe { libc::write(libc::STDOUT_FILENO, LINE.as_ptr().cast(), LINE.len()) };
+        #[cfg(not(unix))]
+        let _ = io::stdout().write_all(LINE);
+        remove_parts(false);
+    }
+    std::process::exit(Reason::Memout.exit_code());
+}
+
+/// Reports a run cut short for `reason` as UNKNOWN, with the statistics
+/// gathered so far, to the sinks `arg` names. The output written so far is
+/// replaced.
+#[cfg(not(target_family = "wasm"))]
+fn report_unfinished(
+    arg: &CommonArg,
+    stat: &mut Stat,
+    options: &[(String, String)],
+    reason: Reason,
+) {
+    stat.print();
+    let mut stats = stat.stats();
+    if arg.deterministic {
+        stats.clear_measurements();
+    }
+    let result = SolveResult {
+        status: Status::Unknown,
+        model: None,
+        stats,
+        warnings: Vec::new(),
+        fast_path: None,
+        components: None,
+        named: Vec::new(),
+        comments: Vec::new(),
+        unique: None,
+        passes: Vec::new(),
+        reason: Some(reason),
+        race_winner: None,
+        stage: None,
+        parse_only: false,
+        repetitions: None,
+        cached: false,
+    };
+    let reported = Writer::create(arg.output.as_deref(), arg.compress)
+        .map_err(anyhow::Error::from)
+        .and_then(|output| report(&result, output, arg.starexec, arg.json.as_deref(), options));
+    if let Err(e) = reported {
+        println!("c WARNING: {}", e);
+    }
+    if let Some(cmd) = &arg.on_complete {
+        let path = |p: &Option<PathBuf>| p.as_ref().map(|p| p.display().to_string());
+        let summary = hook::summary(
+            result_json(&result, options),
+            arg.input.as_ref().map(SmartPath::to_string),
+            path(&arg.output),
+            path(&arg.json),
+        );
+        on_complete(cmd, &summary);
+    }
+}
+
+/// The summaries of the parse times, solve times (in seconds) and memory (in
+/// bytes) of the runs of `--repeat`.
+fn repetition_summaries(repetitions: &Repetitions) -> [(&'static str, Option<Summary>); 3] {
+    let seconds = |times: &[Duration]| Summary::of(times.iter().map(Duration::as_secs_f64));
+    [
+        ("parse_time", seconds(&repetitions.parse_times)),
+        ("solve_time", seconds(&repetitions.solve_times)),
+        ("memory", Summary::of(repetitions.memory.iter().map(|&m| m as f64))),
+    ]
+}
+
+fn print_repetitions(repetitions: &Repetitions) {
+    println!("c Repeated {} runs", repetitions.solve_times.len());
+    if !repetitions.consistent {
+        println!("c WARNING: --repeat: the runs did not all reach the same status");
+    }
+    for (name, summary) in repetition_summaries(repetitions) {
+        let Some(s) = summary else {
+            continue;
+        };
+        let line = if name == "memory" {
+            let bytes = |v: f64| human_bytes::human_bytes(v);
+            format!(
+                "min {} median {} mean {} stddev {}",
+                bytes(s.min),
+                bytes(s.median),
+                bytes(s.mean),
+                bytes(s.stddev)
+            )
+        } else {
+            format!(
+                "min {:.3}s median {:.3}s mean {:.3}s stddev {:.3}s",
+                s.min, s.median, s.mean, s.stddev
+            )
+        };
+        println!("c {:<22}{}", format!("{}:", name), line);
+    }
+}
+
+/// Prints the status, writes the result and model to `output` (or stdout in
+/// the competition format when `starexec` is set), and the `--json` document
+/// to `json` if given; returns the process exit code.
+pub(crate) fn report(
+    result: &SolveResult,
+    mut output: Writer,
+    starexec: bool,
+    json: Option<&Path>,
+    options: &[(String, String)],
+) -> anyhow::Result<i32> {
+    if starexec {
+        report_competition(result)?;
+    } else {
+        write_result(result, &mut output)?;
+    }
+    output.finish()?;
+    if let Some(path) = json {
+        let json = result_json(result, options);
+        write_atomic(path, serde_json::to_string_pretty(&json)? + "\n")?;
+    }
+    Ok(if starexec {
+        result.status.competition_exit_code()
+    } else {
+        result.exit_code()
+    })
+}
+
+/// Prints the status of `result` and writes it, with the model, to `output`.
+fn write_result(result: &SolveResult, output: &mut Writer) -> io::Result<()> {
+    if result.parse_only {
+        println!("c PARSED");
+        return Ok(());
+    }
+    match result.status {
+        Status::Satisfiable => {
+            println!("c SATISFIABLE");
+            writeln!(output, "SAT")?;
+            write_model(output, result.model.as_deref().unwrap_or_default(), None)?;
+        }
+        Status::Unsatisfiable => {
+            println!("c UNSATISFIABLE");
+            writeln!(output, "UNSAT")?;
+        }
+        Status::Unknown => {
+            match result.reason {
+                Some(reason) => println!("c UNKNOWN ({})", reason.as_str()),
+                None => println!("c UNKNOWN"),
+            }
+            writeln!(output, "UNKNOWN")?;
+        }
+    }
+    Ok(())
+}
+
+/// Maximum length of a `v` line.
+const V_LINE_WIDTH: usize = 78;
+
+/// The `s` and `v` lines of the SAT competition output format, followed by
+/// the statistics named as in runsolver's watcher file.
+fn report_competition(result: &SolveResult) -> io::Result<()> {
+    let stats = &result.stats;
+    println!("c Real time (s): {:.3}", stats.run_time.as_secs_f64());
+    // The CPU time of a solver run in child processes is theirs as well.
+    let cpu_time = stats.total_time + stats.child_time.unwrap_or_default();
+    println!("c CPU time (s): {:.3}", cpu_time.as_secs_f64());
+    if let Some(memory) = stats.memory.max(stats.child_memory) {
+        println!("c Resident memory (KiB): {}", memory / 1024);
+    }
+    if let Some(reason) = result.reason {
+        println!("c Reason: {}", reason.as_str());
+    }
+    println!("s {}", result.status.as_str());
+    if let Some(model) = &result.model {
+        write_model(&mut io::stdout().lock(), model, Some(V_LINE_WIDTH))?;
+    }
+    Ok(())
+}
+
+/// Size in bytes of the chunks a model is written in.
+const MODEL_CHUNK: usize = 1 << 16;
+
+/// Writes `model` terminated by 0, on one line or as `v` lines of at most
+/// `width` characters. Each chunk is flushed once complete, so a huge model
+/// streams out, and an interrupt loses at most the chunk being formatted.
+pub(crate) fn write_model(out: &mut impl Write, model: &[i32], width: Option<usize>) -> io::Result<()> {
+    use std::fmt::Write as _;
+
+    let mut chunk = String::with_capacity(MODEL_CHUNK + 64);
+    if width.is_some() {
+        chunk.push('v');
+    }
+    // Length of the current `v` line.
+    let mut line = 1;
+    for lit in model.iter().chain([&0]) {
+        let start = chunk.len();
+        match width {
+            Some(width) => {
+                let _ = write!(chunk, " {}", lit);
+                let len = chunk.len() - start;
+                if line + len > width {
+                    chunk.insert_str(start, "\nv");
+                    line = 1;
+                }
+                line += len;
+            }
+            None if *lit == 0 => chunk.push('0'),
+            None => {
+                let _ = write!(chunk, "{} ", lit);
+            }
+        }
+        if chunk.len() >= MODEL_CHUNK {
+            out.write_all(chunk.as_bytes())?;
+            out.flush()?;
+            chunk.clear();
+        }
+    }
+    chunk.push('\n');
+    out.write_all(chunk.as_bytes())?;
+    out.flush()
+}
+
+fn on_complete(cmd: &str, summary: &serde_json::Value) {
+    if let Err(e) = hook::run_command(cmd, summary) {
+        println!("c WARNING: {}", e);
+    }
+}
+
+pub(crate) fn result_json(result: &SolveResult, options: &[(String, String)]) -> serde_json::Value {
+    let options: serde_json::Map<_, _> = options
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone().into()))
+        .collect();
+    serde_json::json!({
+        "status": result.status.as_str(),
+        "exit_code": result.exit_code(),
+        "reason": result.reason.map(Reason::as_str),
+        "race_winner": result.race_winner,
+        "stage": result.stage,
+        "cached": result.cached,
+        "model": result.model,
+        "named_model": (!result.named.is_empty()).then(|| {
+            result
+                .named
+                .iter()
+                .map(|(name, value)| (name.clone(), serde_json::Value::from(*value)))
+                .collect::<serde_json::Map<_, _>>()
+        }),
+        "parse_only": result.parse_only,
+        "stats": stats_json(&result.stats),
+        "options": options,
+        "build": version::build_json(),
+        "host": version::host_json(),
+        "comments": (!result.comments.is_empty()).then_some(&result.comments),
+        "warnings": result.warnings,
+        "fast_path": result.fast_path,
+        "components": result.components,
+        "unique": result.unique,
+        "passes": result.passes.iter().map(PassStats::json).collect::<Vec<_>>(),
+        "repetitions": result.repetitions.as_ref().map(|repetitions| {
+            let mut json = serde_json::json!({
+                "runs": repetitions.solve_times.len(),
+                "consistent": repetitions.consistent,
+            });
+            for (name, summary) in repetition_summaries(repetitions) {
+                json[name] = summary.map_or(serde_json::Value::Null, |s| {
+                    serde_json::json!({
+                        "min": s.min,
+                        "median": s.median,
+                        "mean": s.mean,
+                        "stddev": s.stddev,
+                    })
+                });
+            }
+            json
+        }),
+    })
+}
+
+fn stats_json(stats: &Stats) -> serde_json::Value {
+    serde_json::json!({
+        "input_sha256": stats.input_sha256,
+        "parsed_size": stats.parsed_size.map(size_json),
+        "simplified_size": stats.simplified_size.map(|size| {
+            let mut json = size_json(size);
+            if let Some(parsed) = stats.parsed_size {
+                let (vars, clauses) = size.reduction(parsed);
+                json["vars_reduction"] = vars.into();
+                json["clauses_reduction"] = clauses.into();
+            }
+            json
+        }),
+        "parse_memory": stats.parse_memory,
+        "simplify_memory": stats.simplify_memory,
+        "parse_time": stats.parse_time.as_secs_f64(),
+        "simplify_time": stats.simplify_time.as_secs_f64(),
+        "solve_time": stats.solve_time.map(|t| t.as_secs_f64()),
+        "total_time": stats.total_time.as_secs_f64(),
+        "run_time": stats.run_time.as_secs_f64(),
+        "memory": stats.memory,
+        "child_time": stats.child_time.map(|t| t.as_secs_f64()),
+        "child_memory": stats.child_memory,
+    })
+}
+
+fn size_json(size: Size) -> serde_json::Value {
+    serde_json::json!({
+        "vars": size.vars,
+        "clauses": size.clauses,
+    })
+}
+
+/// The solving pipeline: parse, simplify and solve with a fresh `S`.
+///
+/// `configure` installs the backend options; the CNF is read from `input`, or
+/// opened from `request.input` if that is `None`.
+pub fn solve<S: Backend>(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+    configure: impl Fn() + Sync,
+) -> anyhow::Result<SolveResult> {
+    let mut symbols = match &request.symbols {
+        Some(path) => Symbols::read(path)?,
+        None => Symbols::default(),
+    };
+    let mut comments = Vec::new();
+    let on_comment = |comment: &str| {
+        symbols.add_comment(comment);
+        if let Some(prefixes) = &request.keep_comments
+            && let Some(text) = kept_comment(comment, prefixes)
+        {
+            comments.push(text);
+        }
+    };
+    let mut result = solve_formula::<S>(request, input, stat, configure, on_comment)?;
+    if let Some(model) = &result.model {
+        result.named = symbols.project(model, &request.show);
+    }
+    result.comments = comments;
+    Ok(result)
+}
+
+/// [`solve`], handing the comments of the input to `on_comment`.
+fn solve_formula<S: Backend>(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+    configure: impl Fn() + Sync,
+    on_comment: impl FnMut(&str),
+) -> anyhow::Result<SolveResult> {
+    // The passes keep one model of each solution they remove, not all of them.
+    if !request.pipeline.is_empty() && request.unique_check.is_some() {
+        return Err(anyhow::anyhow!(
+            "--unique-check cannot be combined with --pipeline, which does not preserve every solution"
+        ));
+    }
+    // The blocking clause of the uniqueness check spans every component.
+    if request.components && request.unique_check.is_some() {
+        return Err(anyhow::anyhow!(
+            "--unique-check cannot be combined with --components"
+        ));
+    }
+    let mut warnings = Vec::new();
+    let mut solver = new_solver::<S>(&configure);
+    if let Err(e) = utils::limit_time(request.cpu_limit) {
+        warnings.push(e.to_string());
+    }
+    if let Err(e) = utils::limit_memory(request.mem_limit.saturating_mul(1024 * 1024)) {
+        warnings.push(e.to_string());
+    }
+    // The bindings cannot freeze single variables, so elimination as a whole
+    // is turned off to keep them.
+    if request.preprocess && !request.freeze.is_empty() {
+        warnings.push(
+            "--freeze: the solver binding cannot freeze single variables, \
+             variable elimination is disabled instead"
+                .to_string(),
+        );
+    }
+    // The blocking clause of --unique-check may mention any variable, so none
+    // may be eliminated.
+    if !request.preprocess || !request.freeze.is_empty() || request.unique_check.is_some() {
+        solver.eliminate(true);
+    }
+    stat.lock().unwrap().start_log();
+    let mut opened;
+    let (reader, size): (&mut dyn Read, _) = match input {
+        Some(input) => (input, None),
+        None => {
+            opened = SmartReader::open(request)?;
+            let size = opened.len();
+            (&mut opened, size)
+        }
+    };
+    let size = size.filter(|_| request.progress);
+    let mut reader = HashingReader::new(ProgressReader::new(reader, size));
+    // The fast paths need the whole formula to decide whether they apply,
+    // partial models to check which literals every clause still has,
+    // equivalence detection to find its binary clauses, the pipeline passes
+    // to rewrite them, the component split to group them. The uniqueness
+    // check solves again incrementally, so it needs the backend.
+    let fast_paths = request.fast_paths && request.solve && request.unique_check.is_none();
+    let components = request.components && request.solve;
+    let keep_clauses = fast_paths
+        || components
+        || request.partial_model
+        || request.equivalences
+        || !request.pipeline.is_empty();
+    let mut clauses = Vec::new();
+    let (deviations, size) = if keep_clauses {
+        let mut dimacs = Counting::new(&mut WithComments {
+            dimacs: &mut clauses,
+            comment: on_comment,
+        });
+        (read_input(&mut reader, request, &mut dimacs)?, dimacs.size)
+    } else {
+        let mut dimacs = Counting::new(&mut WithComments {
+            dimacs: &mut solver,
+            comment: on_comment,
+        });
+        (read_input(&mut reader, request, &mut dimacs)?, dimacs.size)
+    };
+    warnings.extend(deviations.into_iter().map(|d| format!("--lenient: {}", d)));
+    let digest = reader.finish()?;
+    if let Some(expected) = &request.sha256
+        && *expected != digest
+    {
+        return Err(anyhow::anyhow!(
+            "Input SHA-256 mismatch: expected {}, got {}",
+            expected,
+            digest
+        ));
+    }
+    stat.lock().unwrap().input_sha256 = Some(digest);
+    stat.lock().unwrap().parsed_size = Some(size);
+    stat.lock().unwrap().parsed();
+    if request.parse_only {
+        return Ok(SolveResult {
+            status: Status::Unknown,
+            model: None,
+            stats: stat.lock().unwrap().stats(),
+            warnings,
+            fast_path: None,
+            components: None,
+            named: Vec::new(),
+            comments: Vec::new(),
+            unique: None,
+            passes: Vec::new(),
+            reason: None,
+            race_winner: None,
+            stage: None,
+            parse_only: true,
+            repetitions: None,
+            cached: false,
+        });
+    }
+    let input_vars = if keep_clauses {
+        max_var(&clauses)
+    } else {
+        solver.vars()
+    };
+    let (extra, vars) = extra_constraints(request, input_vars)?;
+    if keep_clauses {
+        clauses.extend(extra);
+    } else {
+        for clause in &extra {
+            solver.add_clause(clause);
+        }
+    }
+    // The input clauses stay as they are for partial models.
+    let (piped, log, passes) = if request.pipeline.is_empty() {
+        (None, Log::default(), Vec::new())
+    } else {
+        let simplified = pipeline::run(clauses.clone(), &request.pipeline, &request.freeze);
+        (Some(simplified.clauses), simplified.log, simplified.stats)
+    };
+    let piped = piped.as_ref().unwrap_or(&clauses);
+    let mut representatives = None;
+    let mut substituted = Vec::new();
+    if request.equivalences {
+        match classes::equivalences(piped) {
+            Some(repr) => {
+                substituted = classes::substitute(piped, &repr);
+                representatives = Some(repr);
+            }
+            None => {
+                let mut stat = stat.lock().unwrap();
+                stat.simplified();
+                stat.solved();
+                return Ok(SolveResult {
+                    status: Status::Unsatisfiable,
+                    model: None,
+                    stats: stat.stats(),
+                    warnings,
+                    fast_path: None,
+                    components: None,
+                    named: Vec::new(),
+                    comments: Vec::new(),
+                    unique: None,
+                    passes,
+                    reason: None,
+                    race_winner: None,
+                    stage: None,
+                    parse_only: false,
+                    repetitions: None,
+                    cached: false,
+                });
+            }
+        }
+    }
+    let formula = if representatives.is_some() {
+        &substituted
+    } else {
+        piped
+    };
+    if representatives.is_some() || !request.pipeline.is_empty() {
+        stat.lock().unwrap().simplified_size = Some(Size::of(formula));
+    }
+    if let Some(path) = &request.reconstruction_log {
+        // The substitution came last, so its entries are undone first.
+        let mut audit = Log {
+            entries: log.entries.clone(),
+        };
+        if let Some(repr) = &representatives {
+            audit.entries.extend(Log::equivalences(repr).entries);
+        }
+        write_atomic(path, audit.text())?;
+    }
+    let expand = |model: Vec<i32>| match &representatives {
+        Some(repr) => classes::unsubstitute(&model, repr),
+        None => model,
+    };
+    let reconstruct = |model: Vec<i32>| {
+        let mut model = log.extend(&expand(model));
+        // Variables no pass left a clause or log entry for are free.
+        model.extend((model.len() as i32 + 1..=vars).map(|var| -var));
+        let mut model = if request.partial_model {
+            partial_model(&clauses, &model)
+        } else {
+            model
+        };
+        // Drop the auxiliary variables of the cardinality encodings.
+        model.retain(|lit| lit.abs() <= vars);
+        model
+    };
+    if fast_paths && let Some((class, model)) = fast_path(formula) {
+        let mut stat = stat.lock().unwrap();
+        stat.simplified();
+        stat.solved();
+        return Ok(SolveResult {
+            status: if model.is_some() {
+                Status::Satisfiable
+            } else {
+                Status::Unsatisfiable
+            },
+            model: model.map(reconstruct),
+            stats: stat.stats(),
+            warnings,
+            fast_path: Some(class),
+            components: None,
+            named: Vec::new(),
+            comments: Vec::new(),
+            unique: None,
+            passes,
+            reason: None,
+            race_winner: None,
+            stage: None,
+            parse_only: false,
+            repetitions: None,
+            cached: false,
+        });
+    }
+    if components {
+        let parts = split::components(formula);
+        if parts.len() > 1 {
+            stat.lock().unwrap().simplified();
+            // As for the whole formula, --freeze turns elimination off.
+            let eliminate = request.preprocess && request.freeze.is_empty();
+            let (status, model) = solve_components::<S>(
+                &parts,
+                request.component_threads,
+                eliminate,
+                &configure,
+            );
+            let mut stat = stat.lock().unwrap();
+            stat.solved();
+            return Ok(SolveResult {
+                status,
+                model: model.map(reconstruct),
+                stats: stat.stats(),
+                warnings,
+                fast_path: None,
+                components: Some(parts.len()),
+                named: Vec::new(),
+                comments: Vec::new(),
+                unique: None,
+                passes,
+                reason: (status == Status::Unknown).then_some(Reason::Budget),
+                race_winner: None,
+                stage: None,
+                parse_only: false,
+                repetitions: None,
+                cached: false,
+            });
+        }
+    }
+    for clause in formula {
+        solver.add_clause(clause);
+    }
+    solver.eliminate(true);
+    stat.lock().unwrap().simplified();
+    let status = if !solver.okay() {
+        Status::Unsatisfiable
+    } else {
+        let mut ret = Default::default();
+        if request.solve {
+            ret = solver.solve_limited(&[]);
+        }
+        stat.lock().unwrap().solved();
+        ret.into()
+    };
+    let model = (status == Status::Satisfiable).then(|| model(&solver));
+    let mut unique = None;
+    if let (Some(projection), Some(model)) = (&request.unique_check, &model) {
+        let blocking = blocking_clause(&expand(model.clone()), projection, vars);
+        let blocking = match &representatives {
+            Some(repr) => classes::substitute(&[blocking], repr).pop(),
+            None => Some(blocking),
+        };
+        if let Some(clause) = blocking {
+            solver.add_clause(&clause);
+        }
+        match Status::from(solver.solve_limited(&[])) {
+            Status::Satisfiable => unique = Some(false),
+            Status::Unsatisfiable => unique = Some(true),
+            Status::Unknown => {
+                warnings.push("--unique-check: the second solve was inconclusive".to_string());
+            }
+        }
+    }
+    Ok(SolveResult {
+        status,
+        model: model.map(reconstruct),
+        stats: stat.lock().unwrap().stats(),
+        warnings,
+        fast_path: None,
+        components: None,
+        named: Vec::new(),
+        comments: Vec::new(),
+        unique,
+        passes,
+        // The bindings set no limits of their own, so the solver only gives
+        // up on its internal budgets.
+        reason: (status == Status::Unknown && request.solve).then_some(Reason::Budget),
+        race_winner: None,
+        stage: None,
+        parse_only: false,
+        repetitions: None,
+        cached: false,
+    })
+}
+
+/// Parses the input as `request.strict`, `request.lenient` and
+/// `request.limits` ask, returning
+/// the deviations from the format the lenient mode accepted.
+fn read_input<D: AsDimacs>(
+    reader: &mut impl Read,
+    request: &SolveRequest,
+    dimacs: &mut D,
+) -> Result<Vec<String>, DimacsError> {
+    read_dimacs_within(reader, request.strict, request.lenient, request.limits, dimacs)
+}
+
+/// Reports the CPU limit and exits once the process has used `limit`.
+#[cfg(not(target_family = "wasm"))]
+fn cpu_watchdog(limit: Duration) {
+    let start = ProcessTime::now();
+    std::thread::spawn(move || {
+        loop {
+            let used = start.elapsed();
+            if used >= limit {
+                exit_unfinished(Reason::CpuLimit);
+            }
+            std::thread::sleep((limit - used).min(Duration::from_millis(100)));
+        }
+    });
+}
+
+/// The clause that excludes the values `model` gives to the variables of
+/// `projection` (every variable up to `vars` if it is empty).
+fn blocking_clause(model: &[i32], projection: &[i32], vars: i32) -> Vec<i32> {
+    model
+        .iter()
+        .filter(|lit| {
+            lit.abs() <= vars && (projection.is_empty() || projection.contains(&lit.abs()))
+        })
+        .map(|lit| -lit)
+        .collect()
+}
+
+fn max_var(clauses: &[Vec<i32>]) -> i32 {
+    clauses.iter().flatten().map(|lit| lit.abs()).max().unwrap_or(0)
+}
+
+/// The clauses of `--extra-clauses` and `--at-most`, and the number of
+/// variables of the problem they extend an input of `input_vars` variables
+/// to, which the auxiliary variables of the encodings come after.
+fn extra_constraints(
+    request: &SolveRequest,
+    input_vars: i32,
+) -> anyhow::Result<(Vec<Vec<i32>>, i32)> {
+    let mut clauses = Vec::new();
+    for path in &request.extra_clauses {
+        let file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("Cannot open {}: {}", path.display(), e))?;
+        read_dimacs(file, false, &mut clauses)
+            .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+    }
+    let constrained = request.at_most.iter().flat_map(|c| &c.lits);
+    let vars = max_var(&clauses)
+        .max(input_vars)
+        .max(constrained.map(|lit| lit.abs()).max().unwrap_or(0));
+    let mut next_var = vars;
+    for constraint in &request.at_most {
+        clauses.extend(card::at_most(&constraint.lits, constraint.bound, &mut next_var));
+    }
+    Ok((clauses, vars))
+}
+
+/// The class and the answer (a model, or `None` if unsatisfiable) of a
+/// formula one of the fast paths can solve.
+fn fast_path(clauses: &[Vec<i32>]) -> Option<(&'static str, Option<Vec<i32>>)> {
+    if classes::is_2sat(clauses) {
+        Some(("2-SAT", classes::solve_2sat(clauses)))
+    } else if classes::is_horn(clauses) {
+        Some(("Horn", classes::solve_horn(clauses)))
+    } else {
+        None
+    }
+}
+
+/// Solves variable-disjoint `components` with a fresh `S` each, on up to
+/// `threads` threads, and joins their models. An unsatisfiable component
+/// stops the others from being started. Variables are eliminated only if
+/// `eliminate` is set.
+fn solve_components<S: Backend>(
+    components: &[Vec<Vec<i32>>],
+    threads: usize,
+    eliminate: bool,
+    configure: &(impl Fn() + Sync),
+) -> (Status, Option<Vec<i32>>) {
+    let next = AtomicUsize::new(0);
+    let unsatisfiable = AtomicBool::new(false);
+    let unknown = AtomicBool::new(false);
+    let models = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.min(components.len()) {
+            scope.spawn(|| {
+                while !unsatisfiable.load(Ordering::Relaxed) {
+                    let Some(clauses) = components.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    match solve_component::<S>(clauses, eliminate, configure) {
+                        (Status::Satisfiable, Some(model)) => models.lock().unwrap().push(model),
+                        (Status::Unsatisfiable, _) => unsatisfiable.store(true, Ordering::Relaxed),
+                        _ => unknown.store(true, Ordering::Relaxed),
+                    }
+                }
+            });
+        }
+    });
+    if unsatisfiable.into_inner() {
+        return (Status::Unsatisfiable, None);
+    }
+    if unknown.into_inner() {
+        return (Status::Unknown, None);
+    }
+    let models = models.into_inner().unwrap();
+    let vars = components.iter().map(|c| max_var(c)).max().unwrap_or(0);
+    let mut model: Vec<i32> = (1..=vars).map(|var| -var).collect();
+    for lit in models.into_iter().flatten() {
+        model[lit.unsigned_abs() as usize - 1] = lit;
+    }
+    (Status::Satisfiable, Some(model))
+}
+
+/// Solves one component with its variables renumbered from 1, so a solver
+/// for a small component stays small; the model uses the original numbers.
+fn solve_component<S: Backend>(
+    clauses: &[Vec<i32>],
+    eliminate: bool,
+    configure: impl FnOnce(),
+) -> (Status, Option<Vec<i32>>) {
+    let mut vars: Vec<i32> = clauses.iter().flatten().map(|lit| lit.abs()).collect();
+    vars.sort_unstable();
+    vars.dedup();
+    let rename = |lit: i32, var: i32| if lit > 0 { var } else { -var };
+    let renamed: Vec<Vec<i32>> = clauses
+        .iter()
+        .map(|clause| {
+            clause
+                .iter()
+                .map(|&lit| {
+                    let var = vars
+                        .binary_search(&lit.abs())
+                        .expect("variable of the component");
+                    rename(lit, var as i32 + 1)
+                })
+                .collect()
+        })
+        .collect();
+    let (status, model) = solve_clauses::<S>(&renamed, eliminate, configure);
+    let model = model.map(|model| {
+        model
+            .iter()
+            .zip(&vars)
+            .map(|(&lit, &var)| rename(lit, var))
+            .collect()
+    });
+    (status, model)
+}
+
+/// Solves `clauses` with a fresh `S`, simplifying first like [`solve`];
+/// without `eliminate`, elimination is turned off as for `--pre=false`.
+pub fn solve_clauses<S: Backend>(
+    clauses: &[Vec<i32>],
+    eliminate: bool,
+    configure: impl FnOnce(),
+) -> (Status, Option<Vec<i32>>) {
+    let mut solver = new_solver::<S>(configure);
+    if !eliminate {
+        solver.eliminate(true);
+    }
+    for clause in clauses {
+        solver.add_clause(clause);
+    }
+    if eliminate {
+        solver.eliminate(true);
+    }
+    let status = if solver.okay() {
+        solver.solve_limited(&[]).into()
+    } else {
+        Status::Unsatisfiable
+    };
+    let model = (status == Status::Satisfiable).then(|| model(&solver));
+    (status, model)
+}
+
+pub(crate) fn model<S: Backend>(solver: &S) -> Vec<i32> {
+    (1..=solver.vars())
+        .map(|v| if solver.model_value(v) { v } else { -v })
+        .collect()
+}
+
+/// Drops literals from `model`, in order, while every clause keeps a true
+/// one. Every remaining literal is the last true one of some clause, but
+/// the order decides which are dropped, and no smaller set is searched for.
+pub fn partial_model(clauses: &[Vec<i32>], model: &[i32]) -> Vec<i32> {
+    let index = |lit: i32| 2 * (lit.unsigned_abs() as usize - 1) + usize::from(lit < 0);
+    let mut occurs = vec![Vec::new(); 2 * model.len()];
+    // True literals of every clause that are still in the partial model.
+    let mut support = vec![0; clauses.len()];
+    for (c, clause) in clauses.iter().enumerate() {
+        for &lit in clause {
+            if model.get(lit.unsigned_abs() as usize - 1) == Some(&lit) {
+                occurs[index(lit)].push(c);
+                support[c] += 1;
+            }
+        }
+    }
+    model
+        .iter()
+        .copied()
+        .filter(|&lit| {
+            let needed = occurs[index(lit)].iter().any(|&c| support[c] == 1);
+            if !needed {
+                for &c in &occurs[index(lit)] {
+                    support[c] -= 1;
+                }
+            }
+            needed
+        })
+        .collect()
+}
+
+/// Whether `model` satisfies every clause; unassigned variables count as false.
+pub fn verify_model(clauses: &[Vec<i32>], model: &[i32]) -> bool {
+    let value = |lit: i32| {
+        let var = lit.unsigned_abs() as usize;
+        model.get(var - 1).is_some_and(|v| (*v > 0) == (lit > 0))
+    };
+    clauses.iter().all(|clause| clause.iter().any(|&lit| value(lit)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// The format implied by the extension of `path`, if any.
+    fn from_path(path: &Path) -> io::Result<Option<Self>> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Ok(Some(Compression::Gzip)),
+            Some("xz") => Ok(Some(Compression::Xz)),
+            Some("zst") => Ok(Some(Compression::Zstd)),
+            _ => Ok(None),
+        }
+    }
+}
 
 pub enum Writer {
-    File(File),
+    File(AtomicFile),
     Stdout(io::Stdout),
+    #[cfg(feature = "compression")]
+    Gzip(flate2::write::GzEncoder<Box<Writer>>),
+    #[cfg(feature = "compression")]
+    Xz(xz2::write::XzEncoder<Box<Writer>>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::Encoder<'static, Box<Writer>>),
 }
 
-impl<P: AsRef<Path>> From<Option<P>> for Writer {
-    fn from(path: Option<P>) -> Self {
-        match path {
-            Some(p) => Writer::File(File::create(p).unwrap()),
+/// `path`, unless it is `-`, which names stdin or stdout as `None` does.
+pub fn non_dash(path: Option<&Path>) -> Option<&Path> {
+    path.filter(|path| *path != Path::new("-"))
+}
+
+/// Opens the file at `path`, or stdin if it is `None` or `-`.
+pub fn open_input(path: Option<&Path>) -> anyhow::Result<Box<dyn Read>> {
+    Ok(match non_dash(path) {
+        Some(path) => Box::new(
+            File::open(path).with_context(|| format!("Cannot open {}", path.display()))?,
+        ),
+        None => Box::new(io::stdin()),
+    })
+}
+
+impl Writer {
+    /// Opens `path` (stdout if `None` or `-`), compressing with `compress` or
+    /// the format its extension names.
+    pub fn create(path: Option<&Path>, compress: Option<Compression>) -> io::Result<Self> {
+        let path = non_dash(path);
+        let compress = match (compress, path) {
+            (Some(compress), _) => Some(compress),
+            (None, Some(path)) => Compression::from_path(path)?,
+            (None, None) => None,
+        };
+        let inner = match path {
+            Some(path) => Writer::File(AtomicFile::create(path)?),
             None => Writer::Stdout(io::stdout()),
+        };
+        match compress {
+            None => Ok(inner),
+            #[cfg(feature = "compression")]
+            Some(Compression::Gzip) => Ok(Writer::Gzip(flate2::write::GzEncoder::new(
+                Box::new(inner),
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "compression")]
+            Some(Compression::Xz) => Ok(Writer::Xz(xz2::write::XzEncoder::new(Box::new(inner), 6))),
+            #[cfg(feature = "compression")]
+            Some(Compression::Zstd) => Ok(Writer::Zstd(zstd::Encoder::new(Box::new(inner), 3)?)),
+            #[cfg(not(feature = "compression"))]
+            Some(_) => Err(io::Error::other(
+                "compressed output, but this build has no `compression` feature",
+            )),
+        }
+    }
+
+    /// Flushes everything, writing the trailer of compressed formats, and
+    /// moves a file into place. A writer dropped without it leaves an
+    /// existing file as it was.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Writer::File(file) => file.commit(),
+            Writer::Stdout(mut stdout) => stdout.flush(),
+            #[cfg(feature = "compression")]
+            Writer::Gzip(encoder) => encoder.finish()?.finish(),
+            #[cfg(feature = "compression")]
+            Writer::Xz(encoder) => encoder.finish()?.finish(),
+            #[cfg(feature = "compression")]
+            Writer::Zstd(encoder) => encoder.finish()?.finish(),
+        }
+    }
+}
+
+/// A file written under a temporary name next to its path and renamed to
+/// it once complete, so a crash or interrupt never leaves a truncated file
+/// behind for downstream tools to misread. A path that exists but is not a
+/// regular file, such as a device, a pipe or a symlink, which a rename would
+/// replace, is written in place instead.
+pub struct AtomicFile {
+    file: BufWriter<File>,
+    /// The temporary name, `None` when writing in place.
+    part: Option<PathBuf>,
+    path: PathBuf,
+}
+
+/// The temporary files of the [`AtomicFile`]s neither committed nor
+/// dropped, for [`exit_unfinished`], which exits without running `Drop`.
+static PARTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A temporary name next to `path` unique to one writer,
+/// `<path>.<pid>.<n>.part`, so that processes and threads writing the same
+/// path never write the same file.
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let mut part = path.as_os_str().to_owned();
+    part.push(format!(".{}.{}.part", std::process::id(), n));
+    PathBuf::from(part)
+}
+
+/// Whether `path` is written in place rather than through a temporary file.
+fn in_place(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| !meta.is_file())
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if in_place(path) {
+            let file = File::create(path).map_err(|e| {
+                io::Error::new(e.kind(), format!("Cannot create {}: {}", path.display(), e))
+            })?;
+            return Ok(Self {
+                file: BufWriter::new(file),
+                part: None,
+                path: path.to_path_buf(),
+            });
+        }
+        let part = part_path(path);
+        let file = File::create(&part).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot create {}: {}", part.display(), e))
+        })?;
+        // The file replaced keeps its permissions.
+        if let Ok(meta) = fs::metadata(path) {
+            let _ = file.set_permissions(meta.permissions());
+        }
+        if let Ok(mut parts) = PARTS.lock() {
+            parts.push(part.clone());
+        }
+        Ok(Self {
+            file: BufWriter::new(file),
+            part: Some(part),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Writes the file to disk and moves it into place.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let Some(part) = self.part.take() else {
+            return Ok(());
+        };
+        forget_part(&part);
+        let renamed = self.file.get_ref().sync_all().and_then(|()| fs::rename(&part, &self.path));
+        renamed.map_err(|e| {
+            let _ = fs::remove_file(&part);
+            io::Error::new(e.kind(), format!("Cannot write {}: {}", self.path.display(), e))
+        })
+    }
+}
+
+fn forget_part(part: &Path) {
+    if let Ok(mut parts) = PARTS.lock() {
+        parts.retain(|p| p != part);
+    }
+}
+
+/// Removes the temporary files of the [`AtomicFile`]s still being written.
+/// Unless `wait`, they are left if another thread is using the list.
+#[cfg(not(target_family = "wasm"))]
+fn remove_parts(wait: bool) {
+    let parts = match wait {
+        true => PARTS.lock().ok(),
+        false => PARTS.try_lock().ok(),
+    };
+    for part in parts.iter().flat_map(|parts| parts.iter()) {
+        let _ = fs::remove_file(part);
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if let Some(part) = &self.part {
+            forget_part(part);
+            let _ = fs::remove_file(part);
         }
     }
 }
 
+/// Writes `contents` to `path` through an [`AtomicFile`].
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+/// Fails unless `path` can be written: it is not a directory or read-only,
+/// and its directory takes new files.
+pub fn check_writable(path: &Path) -> io::Result<()> {
+    let fail = |reason: &str| io::Error::other(format!("Cannot write {}: {}", path.display(), reason));
+    if let Ok(meta) = fs::metadata(path) {
+        if meta.is_dir() {
+            return Err(fail("it is a directory"));
+        }
+        if meta.permissions().readonly() {
+            return Err(fail("it is read-only"));
+        }
+    }
+    // Opened without truncating, to leave what is behind a symlink as it is.
+    if in_place(path) {
+        return fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map(drop)
+            .map_err(|e| fail(&e.to_string()));
+    }
+    AtomicFile::create(path).map(drop)
+}
+
 impl Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self {
             Writer::File(file) => file.write(buf),
             Writer::Stdout(stdout) => stdout.write(buf),
+            #[cfg(feature = "compression")]
+            Writer::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "compression")]
+            Writer::Xz(encoder) => encoder.write(buf),
+            #[cfg(feature = "compression")]
+            Writer::Zstd(encoder) => encoder.write(buf),
         }
     }
 
@@ -35,6 +1914,12 @@ impl Write for Writer {
         match self {
             Writer::File(file) => file.flush(),
             Writer::Stdout(stdout) => stdout.flush(),
+            #[cfg(feature = "compression")]
+            Writer::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "compression")]
+            Writer::Xz(encoder) => encoder.flush(),
+            #[cfg(feature = "compression")]
+            Writer::Zstd(encoder) => encoder.flush(),
         }
     }
 }
@@ -47,6 +1932,36 @@ pub struct Stat {
     pub total_time: ProcessTime,
     least_time: ProcessTime,
     pub printed: bool,
+    /// Whether `print` reports times and memory, which vary between runs.
+    pub measured: bool,
+    pub input_sha256: Option<String>,
+    pub parsed_size: Option<Size>,
+    pub simplified_size: Option<Size>,
+    pub parse_memory: Option<u64>,
+    pub simplify_memory: Option<u64>,
+    /// Format `print` reports in.
+    pub format: StatsFormat,
+    /// File `print` writes to instead of stdout.
+    pub file: Option<PathBuf>,
+}
+
+/// Format of the solver's own progress output under `--verb`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum VerbFormat {
+    /// `c` lines with the time since the start and the solver name.
+    Text,
+    /// `{"time", "solver", "message"}` objects.
+    Json,
+}
+
+/// Format of the statistics report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    /// `c` lines, as in the rest of the output.
+    Human,
+    Json,
+    /// A header line and one row.
+    Csv,
 }
 
 impl Drop for Stat {
@@ -57,17 +1972,108 @@ impl Drop for Stat {
     }
 }
 
+impl Default for Stat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Stat {
     pub fn new() -> Self {
-        return Self {
+        Self {
             run_time: Instant::now(),
             total_time: ProcessTime::now(),
             least_time: ProcessTime::now(),
             printed: false,
+            measured: true,
             parsed_time: Default::default(),
             simplified_time: Default::default(),
             solve_time: Default::default(),
+            input_sha256: Default::default(),
+            parsed_size: Default::default(),
+            simplified_size: Default::default(),
+            parse_memory: Default::default(),
+            simplify_memory: Default::default(),
+            format: StatsFormat::Human,
+            file: None,
+        }
+    }
+    /// A `Stat` reporting as `arg` asks.
+    fn of(arg: &CommonArg) -> Self {
+        let mut stat = Self::new();
+        stat.measured = !arg.deterministic;
+        stat.format = arg.stats_format;
+        stat.file = arg.stats_file.clone();
+        stat
+    }
+    /// A `Stat` that never prints, for runs driven through the library.
+    pub fn silent() -> Self {
+        let mut stat = Self::new();
+        stat.printed = true;
+        stat
+    }
+    /// The timings recorded so far.
+    pub fn stats(&self) -> Stats {
+        let children = utils::children_usage();
+        Stats {
+            input_sha256: self.input_sha256.clone().unwrap_or_default(),
+            parsed_size: self.parsed_size,
+            simplified_size: self.simplified_size,
+            parse_memory: self.parse_memory,
+            simplify_memory: self.simplify_memory,
+            parse_time: self.parsed_time.unwrap_or_default(),
+            simplify_time: self.simplified_time.unwrap_or_default(),
+            solve_time: self.solve_time,
+            total_time: self.total_time.elapsed(),
+            run_time: self.run_time.elapsed(),
+            memory: get_memory(),
+            child_time: children.map(|usage| usage.cpu_time),
+            child_memory: children.map(|usage| usage.peak_memory),
+        }
+    }
+    /// The pipeline stage the run is currently in.
+    pub fn stage(&self) -> &'static str {
+        if self.solve_time.is_some() {
+            "done"
+        } else if self.simplified_time.is_some() {
+            "solve"
+        } else if self.parsed_time.is_some() {
+            "simplify"
+        } else {
+            "parse"
+        }
+    }
+    /// Takes over the progress of a child run from the `stats` of its
+    /// `--json` document; what it lacks is left as it is.
+    pub fn adopt_json(&mut self, stats: &serde_json::Value) {
+        let size = |json: &serde_json::Value| {
+            Some(Size {
+                vars: json["vars"].as_u64()? as usize,
+                clauses: json["clauses"].as_u64()? as usize,
+            })
         };
+        let time = |json: &serde_json::Value| Duration::try_from_secs_f64(json.as_f64()?).ok();
+        if let Some(sha256) = stats["input_sha256"].as_str().filter(|s| !s.is_empty()) {
+            self.input_sha256 = Some(sha256.to_string());
+        }
+        self.parsed_size = size(&stats["parsed_size"]).or(self.parsed_size);
+        self.simplified_size = size(&stats["simplified_size"]).or(self.simplified_size);
+        self.parse_memory = stats["parse_memory"].as_u64().or(self.parse_memory);
+        self.simplify_memory = stats["simplify_memory"].as_u64().or(self.simplify_memory);
+        self.parsed_time = time(&stats["parse_time"]).or(self.parsed_time);
+        self.simplified_time = time(&stats["simplify_time"]).or(self.simplified_time);
+        self.solve_time = time(&stats["solve_time"]).or(self.solve_time);
+    }
+    /// Takes over the progress of the run recorded in `other`.
+    pub fn adopt(&mut self, other: &Stat) {
+        self.input_sha256 = other.input_sha256.clone();
+        self.parsed_size = other.parsed_size;
+        self.simplified_size = other.simplified_size;
+        self.parse_memory = other.parse_memory;
+        self.simplify_memory = other.simplify_memory;
+        self.parsed_time = other.parsed_time;
+        self.simplified_time = other.simplified_time;
+        self.solve_time = other.solve_time;
     }
     pub fn start_log(&mut self) {
         self.total_time = ProcessTime::now();
@@ -75,10 +2081,12 @@ impl Stat {
     }
     pub fn parsed(&mut self) {
         self.parsed_time = Some(self.least_time.elapsed());
+        self.parse_memory = get_memory();
         self.least_time = ProcessTime::now();
     }
     pub fn simplified(&mut self) {
         self.simplified_time = Some(self.least_time.elapsed());
+        self.simplify_memory = get_memory();
         self.least_time = ProcessTime::now();
     }
     pub fn solved(&mut self) {
@@ -86,40 +2094,163 @@ impl Stat {
         self.least_time = ProcessTime::now();
     }
 
+    /// Reports the statistics in `format` to `file`, or stdout if it is
+    /// `None`, unless they have been reported already.
     pub fn print(&mut self) -> bool {
         if self.printed {
             return false;
         }
-        self.parsed_time.map(|v| {
-            println!("c Parse time:           {:?}", v);
-        });
-        self.simplified_time.map(|v| {
-            println!("c Simplification time:  {:?}", v);
-        });
-        self.solve_time.map(|v| {
-            println!("c Solve time:           {:?}", v);
-        });
-        println!("c Total time:           {:?}", self.total_time.elapsed());
-        println!("c Run time:             {:?}", self.run_time.elapsed());
-        get_memory().map(|v| {
-            println!(
-                "c Memory:               {}",
-                human_bytes::human_bytes(v as f64)
-            );
-        });
+        let text = match self.format {
+            StatsFormat::Human => self.human(),
+            StatsFormat::Json => {
+                let mut json = stats_json(&self.measurements());
+                json["build"] = version::build_json();
+                json["host"] = version::host_json();
+                serde_json::to_string_pretty(&json).expect("statistics serialize") + "\n"
+            }
+            StatsFormat::Csv => stats_csv(&self.measurements()),
+        };
+        match &self.file {
+            Some(path) => {
+                if let Err(e) = write_atomic(path, text) {
+                    println!("c WARNING: {}", e);
+                }
+            }
+            None => print!("{}", text),
+        }
         std::io::stdout().flush().unwrap();
         self.printed = true;
-        return true;
+        true
+    }
+
+    /// The statistics, without those that vary between runs unless
+    /// `measured`.
+    fn measurements(&self) -> Stats {
+        let mut stats = self.stats();
+        if !self.measured {
+            stats.clear_measurements();
+        }
+        stats
+    }
+
+    /// The statistics as `c` lines.
+    fn human(&self) -> String {
+        let mut text = String::new();
+        if let Some(v) = &self.input_sha256 {
+            text += &format!("c Input SHA-256:        {}\n", v);
+        }
+        if let Some(size) = self.parsed_size {
+            text += &format!("c Variables:            {}\n", size.vars);
+            text += &format!("c Clauses:              {}\n", size.clauses);
+            if let Some(simplified) = self.simplified_size {
+                let (vars, clauses) = simplified.reduction(size);
+                text += &format!(
+                    "c Simplified variables: {} (-{:.1}%)\n",
+                    simplified.vars, vars
+                );
+                text += &format!(
+                    "c Simplified clauses:   {} (-{:.1}%)\n",
+                    simplified.clauses, clauses
+                );
+            }
+        }
+        if self.measured {
+            let bytes = |v: u64| human_bytes::human_bytes(v as f64);
+            if let Some(v) = self.parsed_time {
+                text += &format!("c Parse time:           {:?}\n", v);
+            }
+            if let Some(v) = self.parse_memory {
+                text += &format!("c Parse memory:         {}\n", bytes(v));
+            }
+            if let Some(v) = self.simplified_time {
+                text += &format!("c Simplification time:  {:?}\n", v);
+            }
+            if let Some(v) = self.simplify_memory {
+                text += &format!("c Simplify memory:      {}\n", bytes(v));
+            }
+            if let Some(v) = self.solve_time {
+                text += &format!("c Solve time:           {:?}\n", v);
+            }
+            text += &format!("c Total time:           {:?}\n", self.total_time.elapsed());
+            text += &format!("c Run time:             {:?}\n", self.run_time.elapsed());
+            if let Some(v) = get_memory() {
+                text += &format!("c Memory:               {}\n", bytes(v));
+            }
+            if let Some(usage) = utils::children_usage() {
+                text += &format!("c Child CPU time:       {:?}\n", usage.cpu_time);
+                text += &format!("c Child memory:         {}\n", bytes(usage.peak_memory));
+            }
+        }
+        text
     }
 }
 
+/// Columns of `--stats-format csv`.
+const STATS_COLUMNS: &str = "input_sha256,vars,clauses,simplified_vars,simplified_clauses,\
+parse_time,simplify_time,solve_time,total_time,run_time,memory,parse_memory,simplify_memory,\
+child_time,child_memory,version,git_commit,hostname,cpu,cores,os";
+
+/// `stats` as a CSV header and row; missing values are empty.
+fn stats_csv(stats: &Stats) -> String {
+    let opt = |v: Option<String>| v.unwrap_or_default();
+    let row = [
+        stats.input_sha256.clone(),
+        opt(stats.parsed_size.map(|s| s.vars.to_string())),
+        opt(stats.parsed_size.map(|s| s.clauses.to_string())),
+        opt(stats.simplified_size.map(|s| s.vars.to_string())),
+        opt(stats.simplified_size.map(|s| s.clauses.to_string())),
+        stats.parse_time.as_secs_f64().to_string(),
+        stats.simplify_time.as_secs_f64().to_string(),
+        opt(stats.solve_time.map(|t| t.as_secs_f64().to_string())),
+        stats.total_time.as_secs_f64().to_string(),
+        stats.run_time.as_secs_f64().to_string(),
+        opt(stats.memory.map(|m| m.to_string())),
+        opt(stats.parse_memory.map(|m| m.to_string())),
+        opt(stats.simplify_memory.map(|m| m.to_string())),
+        opt(stats.child_time.map(|t| t.as_secs_f64().to_string())),
+        opt(stats.child_memory.map(|m| m.to_string())),
+    ];
+    let (build, host) = (version::build_json(), version::host_json());
+    let field = |value: &serde_json::Value| match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => crate::report::quote(s),
+        value => value.to_string(),
+    };
+    let metadata = [
+        &build["version"],
+        &build["git_commit"],
+        &host["hostname"],
+        &host["cpu"],
+        &host["cores"],
+        &host["os"],
+    ];
+    let row = row.into_iter().chain(metadata.into_iter().map(field));
+    format!("{}\n{}\n", STATS_COLUMNS, row.collect::<Vec<_>>().join(","))
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum SmartPath {
     FilePath(PathBuf),
+    #[cfg(feature = "fetch")]
     Url(url::Url),
 }
 
+impl std::fmt::Display for SmartPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmartPath::FilePath(path) => path.display().fmt(f),
+            #[cfg(feature = "fetch")]
+            SmartPath::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "fetch")]
 pub fn parse_path(s: &str) -> Result<SmartPath, String> {
+    // Stdin, which `run` turns into no path.
+    if s == "-" {
+        return Ok(SmartPath::FilePath(PathBuf::from(s)));
+    }
     url::Url::parse(s).map(SmartPath::Url).or_else(|_| {
         let path = PathBuf::from(s);
         if path.exists() {
@@ -130,10 +2261,23 @@ pub fn parse_path(s: &str) -> Result<SmartPath, String> {
     })
 }
 
+#[cfg(not(feature = "fetch"))]
+pub fn parse_path(s: &str) -> Result<SmartPath, String> {
+    let path = PathBuf::from(s);
+    if s == "-" || path.exists() {
+        Ok(SmartPath::FilePath(path))
+    } else if s.contains("://") {
+        Err(format!("`{s}` is a URL, but this build has no `fetch` feature"))
+    } else {
+        Err(format!("`{s}` is not a valid file path"))
+    }
+}
+
 pub(crate) enum SmartReader {
     Stdin(Stdin),
     File(File),
-    Url(reqwest::blocking::Response),
+    #[cfg(feature = "fetch")]
+    Url(Box<UrlReader>),
 }
 
 impl Read for SmartReader {
@@ -141,21 +2285,197 @@ impl Read for SmartReader {
         match self {
             SmartReader::Stdin(reader) => reader.read(buf),
             SmartReader::File(reader) => reader.read(buf),
+            #[cfg(feature = "fetch")]
             SmartReader::Url(reader) => reader.read(buf),
         }
     }
 }
 
-impl TryFrom<Option<&SmartPath>> for SmartReader {
-    fn try_from(value: Option<&SmartPath>) -> Result<Self, Self::Error> {
-        match value {
+impl SmartReader {
+    /// Size of the underlying input in bytes, if known up front.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            SmartReader::Stdin(_) => None,
+            SmartReader::File(file) => file.metadata().ok().map(|m| m.len()),
+            #[cfg(feature = "fetch")]
+            SmartReader::Url(reader) => reader.len(),
+        }
+    }
+
+    pub fn open(request: &SolveRequest) -> io::Result<Self> {
+        match &request.input {
             Some(SmartPath::FilePath(path)) => File::open(path).map(SmartReader::File),
-            Some(SmartPath::Url(url)) => reqwest::blocking::get(url.clone())
-                .map(|resp| SmartReader::Url(resp))
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e)),
+            #[cfg(feature = "fetch")]
+            Some(SmartPath::Url(url)) => match fetch::open(url, &request.fetch)? {
+                UrlSource::Cached(file) => Ok(SmartReader::File(file)),
+                UrlSource::Remote(reader) => Ok(SmartReader::Url(reader)),
+            },
             None => Ok(SmartReader::Stdin(stdin())),
         }
     }
+}
+
+/// Computes the SHA-256 of everything read through it.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Consumes any unread remainder of the input and returns the hex digest.
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(hex(&self.hasher.finalize()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Parses durations like `250ms`, `10s`, `5m` or `1h`; bare numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{s}` is not a duration such as 500ms, 10s or 2m"))?;
+    let scale = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown unit in `{s}`, expected ms, s, m or h")),
+    };
+    Duration::try_from_secs_f64(number * scale).map_err(|e| e.to_string())
+}
+
+/// Parses CPU time limits like `90s`, `30m` or `2h` into whole seconds,
+/// rounded up; bare numbers are seconds.
+pub fn parse_seconds(s: &str) -> Result<u64, String> {
+    let duration = parse_duration(s)?;
+    Ok(duration.as_secs() + u64::from(duration.subsec_nanos() > 0))
+}
+
+/// Parses memory sizes like `512MB`, `8GiB` or `2G` into megabytes of
+/// 1024 KiB, rounded up; bare numbers are megabytes. `kB`, `MB`, `GB` and
+/// `TB` are powers of 1000, `KiB`, `MiB`, `GiB`, `TiB` and the bare letters
+/// powers of 1024.
+pub fn parse_megabytes(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{s}` is not a size such as 512MB, 8GiB or 2G"))?;
+    let bytes: f64 = match unit.trim() {
+        "" => 1024.0 * 1024.0,
+        "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "K" | "KiB" => 1024.0,
+        "M" | "MiB" => 1024.0 * 1024.0,
+        "G" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "T" | "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => {
+            return Err(format!(
+                "unknown unit in `{s}`, expected B, kB, MB, GB, TB, KiB, MiB, GiB or TiB"
+            ));
+        }
+    };
+    let megabytes = (number * bytes / (1024.0 * 1024.0)).ceil();
+    if megabytes >= u32::MAX as f64 {
+        return Err(format!("`{s}` is too large"));
+    }
+    Ok(megabytes as u64)
+}
+
+/// A limit in seconds as given to the solver, followed by hours, minutes
+/// and seconds: `5400 s (1h 30m)`.
+pub(crate) fn describe_seconds(secs: u64) -> String {
+    let parts = [(secs / 3600, "h"), (secs % 3600 / 60, "m"), (secs % 60, "s")];
+    let human: Vec<_> = parts
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, unit)| format!("{n}{unit}"))
+        .collect();
+    format!("{} s ({})", secs, human.join(" "))
+}
+
+/// A limit in megabytes as given to the solver, followed by gibibytes when
+/// it is that large: `8192 MiB (8.0 GiB)`.
+pub(crate) fn describe_megabytes(megabytes: u64) -> String {
+    if megabytes >= 1024 {
+        format!("{} MiB ({:.1} GiB)", megabytes, megabytes as f64 / 1024.0)
+    } else {
+        format!("{} MiB", megabytes)
+    }
+}
+
+pub fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err(format!("`{s}` is not a hex-encoded SHA-256 digest"))
+    }
+}
+
+/// Draws a progress bar on stderr while the wrapped reader is consumed.
+///
+/// Only active when stderr is a terminal and the total size is known; the bar
+/// is erased again once the reader is dropped.
+pub struct ProgressReader<R> {
+    inner: R,
+    bar: Option<ProgressBar>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total: Option<u64>) -> Self {
+        let bar = total.filter(|&total| total > 0).map(|total| {
+            // Hidden unless stderr is a terminal.
+            let bar = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr());
+            let style = ProgressStyle::with_template(
+                "c Reading [{bar:30}] {percent:>3}% {bytes} / {total_bytes}",
+            )
+            .expect("the progress template is valid")
+            .progress_chars("#-");
+            bar.set_style(style);
+            bar
+        });
+        Self { inner, bar }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(bar) = &self.bar {
+            bar.inc(n as u64);
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
 
-    type Error = io::Error;
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
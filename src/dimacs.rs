@@ -0,0 +1,864 @@
+//! Streaming DIMACS CNF reader.
+//!
+//! Clauses are handed to the sink as soon as their terminating `0` is read, so
+//! only the current line is ever held in memory regardless of the input size.
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+#[cfg(feature = "compression")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "glucose")]
+use satgalaxy::solver::GlucoseSolver;
+#[cfg(feature = "minisat")]
+use satgalaxy::solver::MinisatSolver;
+use thiserror::Error;
+
+use crate::{Size, binary};
+#[cfg(feature = "compression")]
+use xz2::read::XzDecoder;
+
+#[derive(Error, Debug)]
+pub enum DimacsError {
+    #[error("Failed to read input: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line}, column {column}: {msg}")]
+    Syntax {
+        line: usize,
+        column: usize,
+        msg: String,
+    },
+    #[error(
+        "line {line}, column {column}: Number of variables ({found}) exceeds expected maximum ({max})"
+    )]
+    TooManyVariables {
+        line: usize,
+        column: usize,
+        found: i32,
+        max: i32,
+    },
+    #[error(
+        "line {line}, column {column}: Number of clauses ({found}) exceeds expected maximum ({max})"
+    )]
+    TooManyClauses {
+        line: usize,
+        column: usize,
+        found: i32,
+        max: i32,
+    },
+    #[error("line {line}, column {column}: Number of {what} exceeds the limit ({max})")]
+    LimitExceeded {
+        line: usize,
+        column: usize,
+        what: &'static str,
+        max: i32,
+    },
+}
+
+/// Largest input accepted, by its highest variable and its number of
+/// clauses; 0 is no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub vars: i32,
+    pub clauses: i32,
+}
+
+/// Receiver of parsed clauses and comments.
+pub trait AsDimacs {
+    fn add_clause(&mut self, clause: &[i32]);
+    fn add_comment(&mut self, _comment: &str) {}
+}
+
+#[cfg(feature = "minisat")]
+impl AsDimacs for MinisatSolver {
+    fn add_clause(&mut self, clause: &[i32]) {
+        MinisatSolver::add_clause(self, clause);
+    }
+}
+
+#[cfg(feature = "glucose")]
+impl AsDimacs for GlucoseSolver {
+    fn add_clause(&mut self, clause: &[i32]) {
+        GlucoseSolver::add_clause(self, clause);
+    }
+}
+
+impl AsDimacs for Vec<Vec<i32>> {
+    fn add_clause(&mut self, clause: &[i32]) {
+        self.push(clause.to_vec());
+    }
+}
+
+/// A receiver that also hands every comment to `comment`.
+pub struct WithComments<'a, D, F> {
+    pub dimacs: &'a mut D,
+    pub comment: F,
+}
+
+impl<D: AsDimacs, F: FnMut(&str)> AsDimacs for WithComments<'_, D, F> {
+    fn add_clause(&mut self, clause: &[i32]) {
+        self.dimacs.add_clause(clause);
+    }
+
+    fn add_comment(&mut self, comment: &str) {
+        (self.comment)(comment);
+        self.dimacs.add_comment(comment);
+    }
+}
+
+/// A receiver that also counts the variables and clauses passed on.
+pub struct Counting<'a, D> {
+    pub dimacs: &'a mut D,
+    pub size: Size,
+    seen: Vec<bool>,
+}
+
+impl<'a, D> Counting<'a, D> {
+    pub fn new(dimacs: &'a mut D) -> Self {
+        Self {
+            dimacs,
+            size: Size::default(),
+            seen: Vec::new(),
+        }
+    }
+}
+
+impl<D: AsDimacs> AsDimacs for Counting<'_, D> {
+    fn add_clause(&mut self, clause: &[i32]) {
+        self.size.clauses += 1;
+        for lit in clause {
+            let var = lit.unsigned_abs() as usize;
+            if var > self.seen.len() {
+                self.seen.resize(var, false);
+            }
+            if !self.seen[var - 1] {
+                self.seen[var - 1] = true;
+                self.size.vars += 1;
+            }
+        }
+        self.dimacs.add_clause(clause);
+    }
+
+    fn add_comment(&mut self, comment: &str) {
+        self.dimacs.add_comment(comment);
+    }
+}
+
+/// The text of `comment` if `--keep-comments` selects it: if it starts with
+/// one of `prefixes`, or always if there are none.
+pub fn kept_comment(comment: &str, prefixes: &[String]) -> Option<String> {
+    let text = comment.trim();
+    (prefixes.is_empty() || prefixes.iter().any(|p| text.starts_with(p.as_str())))
+        .then(|| text.to_string())
+}
+
+/// Reads the clauses of a (possibly compressed) DIMACS CNF, with the
+/// comments `keep` selects (see [`kept_comment`]); none if it is `None`.
+pub fn read_commented<R: Read>(
+    reader: R,
+    keep: Option<&[String]>,
+) -> Result<(Vec<Vec<i32>>, Vec<String>), DimacsError> {
+    let mut clauses = Vec::new();
+    let mut comments = Vec::new();
+    let mut dimacs = WithComments {
+        dimacs: &mut clauses,
+        comment: |comment: &str| {
+            if let Some(text) = keep.and_then(|prefixes| kept_comment(comment, prefixes)) {
+                comments.push(text);
+            }
+        },
+    };
+    read_dimacs(reader, false, &mut dimacs)?;
+    Ok((clauses, comments))
+}
+
+/// Writes `comments` as `c` lines.
+pub fn write_comments<W: Write>(mut writer: W, comments: &[String]) -> io::Result<()> {
+    for comment in comments {
+        writeln!(writer, "c {}", comment)?;
+    }
+    Ok(())
+}
+
+const XZ_MAGIC: &[u8] = &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// Wraps `reader` in a decompressor chosen by sniffing the gzip/xz/zstd magic
+/// bytes.
+#[cfg(feature = "compression")]
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let header = reader.fill_buf()?;
+    if header.starts_with(XZ_MAGIC) {
+        Ok(Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))))
+    } else if header.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if header.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// A decompressed stream of DIMACS CNFs written one after another, as `cat`
+/// makes them, read one CNF at a time: a new one starts at every `p` header
+/// that follows clauses, together with the comments right before it. Only
+/// the current line and those comments are held in memory. A binary CNF is
+/// never split.
+pub struct Concatenated<R> {
+    inner: R,
+    binary: bool,
+    /// Read from the stream but not yet handed out.
+    out: Vec<u8>,
+    pos: usize,
+    /// Comments and blank lines after the last clause, which go with the
+    /// next CNF if a header follows them.
+    held: Vec<u8>,
+    /// The start of the next CNF, read past the end of the current one.
+    pending: Vec<u8>,
+    line: Vec<u8>,
+    clauses: bool,
+    /// Whether the current CNF has been read to its end.
+    ended: bool,
+    /// Whether the stream has been read to its end.
+    eof: bool,
+    started: bool,
+}
+
+impl<R: BufRead> Concatenated<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let binary = inner.fill_buf()?.starts_with(binary::MAGIC);
+        Ok(Self {
+            inner,
+            binary,
+            out: Vec::new(),
+            pos: 0,
+            held: Vec::new(),
+            pending: Vec::new(),
+            line: Vec::new(),
+            clauses: false,
+            ended: true,
+            eof: false,
+            started: false,
+        })
+    }
+
+    /// Moves on to the next CNF, skipping what is left of the current one;
+    /// false once the stream has no more. The first CNF is always there,
+    /// even in an empty stream.
+    pub fn next_instance(&mut self) -> io::Result<bool> {
+        if self.started {
+            io::copy(self, &mut io::sink())?;
+            if self.eof {
+                return Ok(false);
+            }
+        }
+        self.started = true;
+        self.out = std::mem::take(&mut self.pending);
+        self.pos = 0;
+        self.clauses = false;
+        self.ended = false;
+        Ok(true)
+    }
+
+    /// Reads the next line of the stream into `out`, or ends the current
+    /// CNF at a header after clauses or at the end of the stream.
+    fn fill(&mut self) -> io::Result<()> {
+        self.line.clear();
+        if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+            self.out.append(&mut self.held);
+            self.ended = true;
+            self.eof = true;
+            return Ok(());
+        }
+        match self.line.trim_ascii().first() {
+            None | Some(b'c') if self.clauses => self.held.extend_from_slice(&self.line),
+            Some(b'p') if self.clauses => {
+                self.held.extend_from_slice(&self.line);
+                self.pending = std::mem::take(&mut self.held);
+                self.ended = true;
+            }
+            None | Some(b'c' | b'p') => self.out.extend_from_slice(&self.line),
+            Some(_) => {
+                self.out.append(&mut self.held);
+                self.out.extend_from_slice(&self.line);
+                self.clauses = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The current CNF; [`Concatenated::next_instance`] moves on to the next.
+impl<R: BufRead> Read for Concatenated<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.binary {
+            if self.ended {
+                return Ok(0);
+            }
+            let n = self.inner.read(buf)?;
+            if n == 0 && !buf.is_empty() {
+                self.ended = true;
+                self.eof = true;
+            }
+            return Ok(n);
+        }
+        while self.pos == self.out.len() {
+            if self.ended {
+                return Ok(0);
+            }
+            self.out.clear();
+            self.pos = 0;
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Passes `reader` through, rejecting compressed input this build cannot read.
+#[cfg(not(feature = "compression"))]
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let header = reader.fill_buf()?;
+    if [XZ_MAGIC, GZIP_MAGIC, ZSTD_MAGIC]
+        .iter()
+        .any(|magic| header.starts_with(magic))
+    {
+        return Err(io::Error::other(
+            "compressed input, but this build has no `compression` feature",
+        ));
+    }
+    Ok(Box::new(reader))
+}
+
+/// Reads a (possibly compressed) DIMACS CNF stream into `dim`.
+///
+/// In strict mode the clause and variable counts are checked against the
+/// `p cnf` header.
+pub fn read_dimacs<R: Read, D: AsDimacs>(
+    reader: R,
+    strict: bool,
+    dim: &mut D,
+) -> Result<(), DimacsError> {
+    parse(reader, strict, None, Limits::default(), dim)
+}
+
+/// Reads a (possibly compressed) DIMACS CNF stream into `dim`, accepting the
+/// common deviations from the format: a missing, malformed or repeated
+/// header, counts that disagree with the header, a comment after the
+/// literals of a line, non-ASCII whitespace, a byte order mark and an
+/// unterminated last clause. Returns a warning for each kind of deviation
+/// found, with the first line it occurs on.
+pub fn read_dimacs_lenient<R: Read, D: AsDimacs>(
+    reader: R,
+    dim: &mut D,
+) -> Result<Vec<String>, DimacsError> {
+    let mut deviations = Deviations::default();
+    parse(reader, false, Some(&mut deviations), Limits::default(), dim)?;
+    Ok(deviations.warnings())
+}
+
+/// Reads like [`read_dimacs`], or [`read_dimacs_lenient`] if `lenient`, but
+/// stops with [`DimacsError::LimitExceeded`] as soon as the input exceeds
+/// `limits`.
+pub fn read_dimacs_within<R: Read, D: AsDimacs>(
+    reader: R,
+    strict: bool,
+    lenient: bool,
+    limits: Limits,
+    dim: &mut D,
+) -> Result<Vec<String>, DimacsError> {
+    if !lenient {
+        return parse(reader, strict, None, limits, dim).map(|()| Vec::new());
+    }
+    let mut deviations = Deviations::default();
+    parse(reader, false, Some(&mut deviations), limits, dim)?;
+    Ok(deviations.warnings())
+}
+
+/// Deviations from the format accepted in lenient mode: the first line of
+/// each kind found in the lines and how many lines have it, and those of
+/// the input as a whole.
+#[derive(Default)]
+struct Deviations {
+    lines: Vec<(&'static str, usize, usize)>,
+    input: Vec<String>,
+}
+
+impl Deviations {
+    fn note(&mut self, what: &'static str, line: usize) {
+        match self.lines.iter_mut().find(|(kind, ..)| *kind == what) {
+            Some((_, _, count)) => *count += 1,
+            None => self.lines.push((what, line, 1)),
+        }
+    }
+
+    fn warnings(self) -> Vec<String> {
+        self.lines
+            .into_iter()
+            .map(|(what, line, count)| match count {
+                1 => format!("line {}: {}", line, what),
+                _ => format!("line {}: {} (and {} more lines)", line, what, count - 1),
+            })
+            .chain(self.input)
+            .collect()
+    }
+}
+
+const BYTE_ORDER_MARK: &[u8] = b"\xEF\xBB\xBF";
+
+fn parse<R: Read, D: AsDimacs>(
+    reader: R,
+    strict: bool,
+    mut lenient: Option<&mut Deviations>,
+    limits: Limits,
+    dim: &mut D,
+) -> Result<(), DimacsError> {
+    let mut reader = decompress(reader)?;
+    if binary::detect(&mut reader)? {
+        return binary::read(reader, strict, limits, dim);
+    }
+    let mut line = Vec::new();
+    let mut lineno = 0;
+    let mut column = 0;
+    let mut clause = Vec::new();
+    let mut header: Option<(i32, i32)> = None;
+    let mut num_vars = 0;
+    let mut num_clauses = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        lineno += 1;
+        let mut text = line.trim_ascii();
+        if let (Some(deviations), 1) = (lenient.as_deref_mut(), lineno)
+            && let Some(rest) = text.strip_prefix(BYTE_ORDER_MARK)
+        {
+            deviations.note("byte order mark skipped", lineno);
+            text = rest.trim_ascii_start();
+        }
+        match text.first() {
+            None => continue,
+            Some(b'c') => {
+                dim.add_comment(&String::from_utf8_lossy(&text[1..]));
+                continue;
+            }
+            // SATLIB instances end with a `%` line followed by a stray `0`.
+            Some(b'%') => break,
+            Some(b'p') => {
+                let at = offset(&line, text);
+                if header.is_some() {
+                    match lenient.as_deref_mut() {
+                        Some(deviations) => {
+                            deviations.note("repeated `p cnf` header ignored", lineno)
+                        }
+                        None => return Err(syntax(lineno, at, "duplicate `p cnf` header")),
+                    }
+                    continue;
+                }
+                match (parse_header(text), lenient.as_deref_mut()) {
+                    (Some(parsed), _) => header = Some(parsed),
+                    (None, Some(deviations)) => {
+                        deviations.note("malformed `p cnf` header ignored", lineno)
+                    }
+                    (None, None) => {
+                        return Err(syntax(
+                            lineno,
+                            at,
+                            "expected header of the form `p cnf <vars> <clauses>`",
+                        ));
+                    }
+                }
+                continue;
+            }
+            Some(_) => {}
+        }
+        for token in tokens(text, lenient.as_deref_mut(), lineno) {
+            column = offset(&line, token);
+            let lit = match parse_literal(token) {
+                Some(lit) => lit,
+                // The rest of the line is a comment.
+                None if token.starts_with(b"c") && lenient.is_some() => {
+                    if let Some(deviations) = lenient.as_deref_mut() {
+                        deviations.note("comment after literals", lineno);
+                    }
+                    let comment = &line[column - 1..];
+                    dim.add_comment(&String::from_utf8_lossy(comment[1..].trim_ascii_end()));
+                    break;
+                }
+                None => {
+                    return Err(syntax(
+                        lineno,
+                        column,
+                        &format!("invalid literal `{}`", String::from_utf8_lossy(token)),
+                    ));
+                }
+            };
+            if lit != 0 {
+                num_vars = num_vars.max(lit.abs());
+                if limits.vars > 0 && num_vars > limits.vars {
+                    return Err(DimacsError::LimitExceeded {
+                        line: lineno,
+                        column,
+                        what: "variables",
+                        max: limits.vars,
+                    });
+                }
+                clause.push(lit);
+                continue;
+            }
+            num_clauses += 1;
+            if limits.clauses > 0 && num_clauses > limits.clauses {
+                return Err(DimacsError::LimitExceeded {
+                    line: lineno,
+                    column,
+                    what: "clauses",
+                    max: limits.clauses,
+                });
+            }
+            if let (true, Some((vars, clauses))) = (strict, header) {
+                if num_clauses > clauses {
+                    return Err(DimacsError::TooManyClauses {
+                        line: lineno,
+                        column,
+                        found: num_clauses,
+                        max: clauses,
+                    });
+                }
+                if num_vars > vars {
+                    return Err(DimacsError::TooManyVariables {
+                        line: lineno,
+                        column,
+                        found: num_vars,
+                        max: vars,
+                    });
+                }
+            }
+            dim.add_clause(&clause);
+            clause.clear();
+        }
+    }
+    let Some(deviations) = lenient else {
+        if !clause.is_empty() {
+            return Err(syntax(
+                lineno,
+                column,
+                "last clause is not terminated by `0`",
+            ));
+        }
+        return Ok(());
+    };
+    if !clause.is_empty() {
+        deviations.note(
+            "last clause is not terminated by `0`, taken as it is",
+            lineno,
+        );
+        num_clauses += 1;
+        dim.add_clause(&clause);
+    }
+    match header {
+        None => deviations.input.push("no `p cnf` header".to_string()),
+        Some((vars, clauses)) => {
+            if num_vars > vars {
+                deviations.input.push(format!(
+                    "the header declares {} variables, the clauses use {}",
+                    vars, num_vars
+                ));
+            }
+            if num_clauses != clauses {
+                deviations.input.push(format!(
+                    "the header declares {} clauses, the input has {}",
+                    clauses, num_clauses
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The whitespace-separated tokens of `text`; in lenient mode non-ASCII
+/// whitespace separates them too.
+fn tokens<'a>(text: &'a [u8], lenient: Option<&mut Deviations>, lineno: usize) -> Tokens<'a> {
+    if let (Some(deviations), Ok(unicode)) = (lenient, std::str::from_utf8(text))
+        && unicode.contains(|c: char| c.is_whitespace() && !c.is_ascii())
+    {
+        deviations.note("non-ASCII whitespace", lineno);
+        return Tokens::Unicode(unicode);
+    }
+    Tokens::Ascii(text)
+}
+
+/// The tokens of a line still to come, split at ASCII or at any Unicode
+/// whitespace.
+enum Tokens<'a> {
+    Ascii(&'a [u8]),
+    Unicode(&'a str),
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        match self {
+            Tokens::Ascii(rest) => {
+                let text: &'a [u8] = *rest;
+                let start = text.iter().position(|b| !b.is_ascii_whitespace())?;
+                let text = &text[start..];
+                let end = text
+                    .iter()
+                    .position(u8::is_ascii_whitespace)
+                    .unwrap_or(text.len());
+                *rest = &text[end..];
+                Some(&text[..end])
+            }
+            Tokens::Unicode(rest) => {
+                let text: &'a str = *rest;
+                let text = text.trim_start();
+                let end = text.find(char::is_whitespace).unwrap_or(text.len());
+                *rest = &text[end..];
+                (end > 0).then(|| text[..end].as_bytes())
+            }
+        }
+    }
+}
+
+/// 1-based column of `part`, a slice of `line`.
+fn offset(line: &[u8], part: &[u8]) -> usize {
+    part.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+fn syntax(line: usize, column: usize, msg: &str) -> DimacsError {
+    DimacsError::Syntax {
+        line,
+        column,
+        msg: msg.to_string(),
+    }
+}
+
+fn parse_header(text: &[u8]) -> Option<(i32, i32)> {
+    let mut tokens = text
+        .split(u8::is_ascii_whitespace)
+        .filter(|t| !t.is_empty());
+    if tokens.next()? != b"p" || tokens.next()? != b"cnf" {
+        return None;
+    }
+    let vars = parse_int(tokens.next()?).filter(|v| *v >= 0)?;
+    let clauses = parse_int(tokens.next()?).filter(|v| *v >= 0)?;
+    tokens.next().is_none().then_some((vars, clauses))
+}
+
+fn parse_int(token: &[u8]) -> Option<i32> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// A literal; `-2147483648` is none, as its variable is beyond `i32`.
+fn parse_literal(token: &[u8]) -> Option<i32> {
+    parse_int(token).filter(|&lit| lit != i32::MIN)
+}
+
+/// Writes `clauses` as a DIMACS CNF with a header sized to fit them.
+pub fn write_dimacs<W: Write>(mut writer: W, clauses: &[Vec<i32>]) -> io::Result<()> {
+    let vars = clauses.iter().flatten().map(|l| l.abs()).max().unwrap_or(0);
+    writeln!(writer, "p cnf {} {}", vars, clauses.len())?;
+    for clause in clauses {
+        for lit in clause {
+            write!(writer, "{} ", lit)?;
+        }
+        writeln!(writer, "0")?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str, strict: bool) -> Result<Vec<Vec<i32>>, DimacsError> {
+        let mut clauses = Vec::new();
+        read_dimacs(text.as_bytes(), strict, &mut clauses)?;
+        Ok(clauses)
+    }
+
+    fn read_lenient(text: &str) -> (Vec<Vec<i32>>, Vec<String>) {
+        let mut clauses = Vec::new();
+        let warnings = read_dimacs_lenient(text.as_bytes(), &mut clauses).unwrap();
+        (clauses, warnings)
+    }
+
+    #[test]
+    fn round_trip() {
+        let clauses = vec![vec![1, -2], vec![-1, 3, 2], vec![-3]];
+        let mut text = Vec::new();
+        write_dimacs(&mut text, &clauses).unwrap();
+        assert_eq!(text, b"p cnf 3 3\n1 -2 0\n-1 3 2 0\n-3 0\n");
+        assert_eq!(
+            read(std::str::from_utf8(&text).unwrap(), true).unwrap(),
+            clauses
+        );
+    }
+
+    #[test]
+    fn clauses_span_lines() {
+        let clauses = read("c comment\np cnf 3 2\n1 2\n3 0 -1\n0\n", true).unwrap();
+        assert_eq!(clauses, vec![vec![1, 2, 3], vec![-1]]);
+    }
+
+    #[test]
+    fn satlib_end_marker() {
+        let clauses = read("p cnf 2 1\n1 2 0\n%\n0\n", true).unwrap();
+        assert_eq!(clauses, vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn invalid_literal() {
+        match read("p cnf 2 1\n1 x 0\n", false) {
+            Err(DimacsError::Syntax { line, column, .. }) => assert_eq!((line, column), (2, 3)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn literal_beyond_i32() {
+        assert!(matches!(
+            read("p cnf 1 1\n-2147483648 0\n", false),
+            Err(DimacsError::Syntax {
+                line: 2,
+                column: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_header() {
+        assert!(matches!(
+            read("p cnf x 1\n1 0\n", false),
+            Err(DimacsError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            read("p cnf 1 1\np cnf 1 1\n1 0\n", false),
+            Err(DimacsError::Syntax { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn unterminated_clause() {
+        assert!(matches!(
+            read("p cnf 2 1\n1 2\n", false),
+            Err(DimacsError::Syntax { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn strict_counts() {
+        assert!(matches!(
+            read("p cnf 2 1\n1 0\n2 0\n", true),
+            Err(DimacsError::TooManyClauses {
+                found: 2,
+                max: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read("p cnf 1 1\n1 2 0\n", true),
+            Err(DimacsError::TooManyVariables {
+                found: 2,
+                max: 1,
+                ..
+            })
+        ));
+        assert!(read("p cnf 1 1\n1 2 0\n", false).is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        let mut clauses = Vec::new();
+        let limits = Limits {
+            vars: 2,
+            clauses: 0,
+        };
+        let read = read_dimacs_within(&b"1 2 0\n3 0\n"[..], false, false, limits, &mut clauses);
+        assert!(matches!(
+            read,
+            Err(DimacsError::LimitExceeded {
+                line: 2,
+                what: "variables",
+                max: 2,
+                ..
+            })
+        ));
+        let mut clauses = Vec::new();
+        let limits = Limits {
+            vars: 0,
+            clauses: 1,
+        };
+        let read = read_dimacs_within(&b"1 0\n2 0\n"[..], false, true, limits, &mut clauses);
+        assert!(matches!(
+            read,
+            Err(DimacsError::LimitExceeded {
+                line: 2,
+                what: "clauses",
+                max: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn lenient_deviations() {
+        let text = "\u{FEFF}1 -2 0 c trailing\n2\u{00A0}3 0\np cnf 3 1\np cnf 3 1\n-3";
+        let (clauses, warnings) = read_lenient(text);
+        assert_eq!(clauses, vec![vec![1, -2], vec![2, 3], vec![-3]]);
+        assert_eq!(
+            warnings,
+            vec![
+                "line 1: byte order mark skipped",
+                "line 1: comment after literals",
+                "line 2: non-ASCII whitespace",
+                "line 4: repeated `p cnf` header ignored",
+                "line 5: last clause is not terminated by `0`, taken as it is",
+                "the header declares 1 clauses, the input has 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_without_header() {
+        let (clauses, warnings) = read_lenient("1 0\n");
+        assert_eq!(clauses, vec![vec![1]]);
+        assert_eq!(warnings, vec!["no `p cnf` header"]);
+    }
+
+    #[test]
+    fn concatenated() {
+        let text = "p cnf 1 1\n1 0\nc second\n\np cnf 2 1\n-1 2 0\nc end\n";
+        let mut stream = Concatenated::new(text.as_bytes()).unwrap();
+        let mut parts = Vec::new();
+        while stream.next_instance().unwrap() {
+            let mut part = String::new();
+            stream.read_to_string(&mut part).unwrap();
+            parts.push(part);
+        }
+        assert_eq!(
+            parts,
+            vec!["p cnf 1 1\n1 0\n", "c second\n\np cnf 2 1\n-1 2 0\nc end\n"]
+        );
+    }
+
+    #[test]
+    fn concatenated_skips_unread_rest() {
+        let text = "p cnf 1 1\n1 0\np cnf 1 1\n-1 0\n";
+        let mut stream = Concatenated::new(text.as_bytes()).unwrap();
+        assert!(stream.next_instance().unwrap());
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        assert!(stream.next_instance().unwrap());
+        let mut clauses = Vec::new();
+        read_dimacs(&mut stream, true, &mut clauses).unwrap();
+        assert_eq!(clauses, vec![vec![-1]]);
+        assert!(!stream.next_instance().unwrap());
+    }
+}
@@ -0,0 +1,548 @@
+//! Solving spread over several machines: a coordinator hands out jobs, and
+//! workers solve them with a local backend and send back the answers.
+//!
+//! A job is one input CNF, or one cube of it when `--cube-vars` splits the
+//! input by every assignment of those variables; the cube is added to the
+//! CNF as unit clauses. An input is satisfiable as soon as one of its cubes
+//! is, and unsatisfiable once all of them are. When the queue runs dry, jobs
+//! still out are handed out again, which covers both stragglers and workers
+//! that disappeared.
+//!
+//! Each exchange is one connection with a line-based protocol:
+//!
+//! ```text
+//! worker:      token TOKEN               only if the coordinator has one
+//!              next
+//! coordinator: job ID LEN CUBE... 0      followed by LEN bytes of CNF
+//!              done                      if every input is decided
+//! worker:      token TOKEN               only if the coordinator has one
+//!              result ID STATUS          STATUS as on an `s` line
+//!              v LITS... 0               the model, if satisfiable
+//! coordinator: ok
+//! ```
+//!
+//! Every connection is served by its own thread, so a slow peer holds up
+//! no other. A model is checked against the input and its cube before it
+//! decides the input; an unsatisfiable answer cannot be checked, so the
+//! coordinator listens on localhost unless told otherwise, and with
+//! `--token-file` only answers from workers that present its token.
+//!
+//! Learnt clauses cannot be read from the backends, so workers share no
+//! clauses; every job is solved from scratch.
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use clap::Args;
+use validator::Validate;
+
+use crate::{
+    Solver, Status,
+    core::{check_writable, verify_model, write_atomic},
+    dimacs::read_dimacs,
+    split,
+};
+
+/// Longest a peer may take to send or receive one message.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Args, Validate)]
+pub struct CoordinateArg {
+    /// CNFs to solve (plain, gzip or xz), each on its own
+    #[arg(value_name = "INPUT", required = true)]
+    inputs: Vec<PathBuf>,
+    /// Address workers connect to; localhost only by default, e.g. 0.0.0.0:7878 for every interface
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7878")]
+    listen: SocketAddr,
+    /// File holding a token every worker must present
+    #[arg(long = "token-file", value_name = "FILE")]
+    token_file: Option<PathBuf>,
+    /// Comma-separated variables whose assignments split every input into cubes
+    #[arg(long = "cube-vars", value_name = "VARS", value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..))]
+    #[validate(length(max = 20, message = "At most 20 cube variables are supported"))]
+    cube_vars: Vec<i32>,
+    /// Also write the result of every input as JSON to this file
+    #[arg(long, value_name = "FILE")]
+    json: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WorkArg {
+    /// Address of the coordinator, e.g. 10.0.0.1:7878
+    #[arg(long, value_name = "HOST:PORT")]
+    connect: String,
+    /// Backend the jobs are solved with
+    #[arg(long, default_value = "minisat")]
+    solver: Solver,
+    /// File holding the token of the coordinator
+    #[arg(long = "token-file", value_name = "FILE")]
+    token_file: Option<PathBuf>,
+}
+
+struct Input {
+    path: PathBuf,
+    /// Shared with the threads sending it to workers and checking models.
+    data: Arc<[u8]>,
+    /// Cubes without an answer yet.
+    open: usize,
+    /// Whether a cube ended UNKNOWN, so the input cannot be unsatisfiable.
+    unknown: bool,
+    status: Option<Status>,
+    model: Option<Vec<i32>>,
+    time: Option<Duration>,
+}
+
+struct Job {
+    input: usize,
+    cube: Vec<i32>,
+    done: bool,
+}
+
+struct Coordinator {
+    inputs: Vec<Input>,
+    jobs: Vec<Job>,
+    queue: VecDeque<usize>,
+    /// Jobs handed out, oldest first.
+    out: VecDeque<usize>,
+    start: Instant,
+}
+
+impl CoordinateArg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        if let Some(path) = &self.json {
+            check_writable(path)?;
+        }
+        let token = self.token_file.as_deref().map(read_token).transpose()?;
+        let cubes: Vec<_> = split::cubes(&self.cube_vars).collect();
+        let mut coordinator = Coordinator {
+            inputs: Vec::new(),
+            jobs: Vec::new(),
+            queue: VecDeque::new(),
+            out: VecDeque::new(),
+            start: Instant::now(),
+        };
+        for (i, path) in self.inputs.iter().enumerate() {
+            let data = fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+            coordinator.inputs.push(Input {
+                path: path.clone(),
+                data: data.into(),
+                open: cubes.len(),
+                unknown: false,
+                status: None,
+                model: None,
+                time: None,
+            });
+            for cube in &cubes {
+                coordinator.queue.push_back(coordinator.jobs.len());
+                coordinator.jobs.push(Job {
+                    input: i,
+                    cube: cube.clone(),
+                    done: false,
+                });
+            }
+        }
+        let listener = TcpListener::bind(self.listen)
+            .map_err(|e| anyhow!("Cannot listen on {}: {}", self.listen, e))?;
+        println!(
+            "c Coordinating {} jobs over {} inputs on {}{}",
+            coordinator.jobs.len(),
+            coordinator.inputs.len(),
+            self.listen,
+            if token.is_some() {
+                ", token required"
+            } else {
+                ""
+            }
+        );
+        // Polled, so that the loop ends once a connection's thread has
+        // decided the last input.
+        listener.set_nonblocking(true)?;
+        let coordinator = Arc::new(Mutex::new(coordinator));
+        let token: Option<Arc<str>> = token.map(Into::into);
+        while !coordinator.lock().unwrap().finished() {
+            let (stream, peer) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(50));
+                    continue;
+                }
+                Err(e) => {
+                    println!("c WARNING: accept failed: {}", e);
+                    continue;
+                }
+            };
+            let coordinator = Arc::clone(&coordinator);
+            let token = token.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(&coordinator, stream, token.as_deref()) {
+                    println!("c {} exchange failed: {}", peer, e);
+                }
+            });
+        }
+        let coordinator = coordinator.lock().unwrap();
+        for input in &coordinator.inputs {
+            let status = input.status.unwrap_or(Status::Unknown);
+            println!(
+                "c {}: {} in {:?}",
+                input.path.display(),
+                status.as_str(),
+                input.time.unwrap_or_default()
+            );
+        }
+        if let Some(path) = &self.json {
+            let results: Vec<_> = coordinator
+                .inputs
+                .iter()
+                .map(|input| {
+                    serde_json::json!({
+                        "input": input.path.display().to_string(),
+                        "status": input.status.unwrap_or(Status::Unknown).as_str(),
+                        "model": input.model,
+                        "time": input.time.map(|t| t.as_secs_f64()),
+                    })
+                })
+                .collect();
+            write_atomic(path, serde_json::to_string_pretty(&results)? + "\n")?;
+        }
+        Ok(match coordinator.inputs.as_slice() {
+            [input] => input.status.unwrap_or(Status::Unknown).exit_code(),
+            _ => 0,
+        })
+    }
+}
+
+impl Coordinator {
+    fn finished(&self) -> bool {
+        self.inputs.iter().all(|input| input.status.is_some())
+    }
+
+    /// The next job to hand out: a queued one, or else the longest
+    /// outstanding one again.
+    fn next_job(&mut self) -> Option<usize> {
+        while let Some(id) = self.queue.pop_front() {
+            if self.undecided(id) {
+                self.out.push_back(id);
+                return Some(id);
+            }
+        }
+        let out = std::mem::take(&mut self.out);
+        self.out = out.into_iter().filter(|&id| self.undecided(id)).collect();
+        let id = self.out.pop_front()?;
+        self.out.push_back(id);
+        Some(id)
+    }
+
+    /// Whether job `id` may still change the result of its input.
+    fn undecided(&self, id: usize) -> bool {
+        let job = &self.jobs[id];
+        !job.done && self.inputs[job.input].status.is_none()
+    }
+
+    fn record(&mut self, id: usize, status: Status, model: Option<Vec<i32>>) {
+        let job = &mut self.jobs[id];
+        // A job handed out twice may be answered twice.
+        if job.done {
+            return;
+        }
+        job.done = true;
+        let input = &mut self.inputs[job.input];
+        if input.status.is_some() {
+            return;
+        }
+        input.open -= 1;
+        match status {
+            Status::Satisfiable => {
+                input.status = Some(Status::Satisfiable);
+                input.model = model;
+            }
+            Status::Unknown => input.unknown = true,
+            Status::Unsatisfiable => {}
+        }
+        if input.status.is_none() && input.open == 0 {
+            input.status = Some(if input.unknown {
+                Status::Unknown
+            } else {
+                Status::Unsatisfiable
+            });
+        }
+        if input.status.is_some() {
+            input.time = Some(self.start.elapsed());
+            println!(
+                "c {}: {}",
+                input.path.display(),
+                input.status.unwrap_or(Status::Unknown).as_str()
+            );
+        }
+    }
+}
+
+/// Serves one connection: hands out a job or takes an answer. The
+/// coordinator is only locked between the reads and writes, which may take
+/// up to [`TIMEOUT`] each.
+fn handle(
+    coordinator: &Mutex<Coordinator>,
+    stream: TcpStream,
+    token: Option<&str>,
+) -> anyhow::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = read_line(&mut reader)?;
+    if let Some(expected) = token {
+        let presented = line.strip_prefix("token ").unwrap_or_default();
+        if !same(presented, expected) {
+            bail!("missing or wrong token");
+        }
+        line = read_line(&mut reader)?;
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["next"] => {
+            let job = {
+                let mut coordinator = coordinator.lock().unwrap();
+                coordinator.next_job().map(|id| {
+                    let job = &coordinator.jobs[id];
+                    (
+                        id,
+                        job.cube.clone(),
+                        Arc::clone(&coordinator.inputs[job.input].data),
+                    )
+                })
+            };
+            let stream = reader.get_mut();
+            match job {
+                Some((id, cube, data)) => {
+                    write!(stream, "job {} {}", id, data.len())?;
+                    for lit in &cube {
+                        write!(stream, " {}", lit)?;
+                    }
+                    writeln!(stream, " 0")?;
+                    stream.write_all(&data)?;
+                }
+                None => writeln!(stream, "done")?,
+            }
+        }
+        ["result", id, status] => {
+            let id: usize = id.parse().map_err(|_| anyhow!("invalid job `{}`", id))?;
+            let status = match *status {
+                "SATISFIABLE" => Status::Satisfiable,
+                "UNSATISFIABLE" => Status::Unsatisfiable,
+                "UNKNOWN" => Status::Unknown,
+                _ => bail!("invalid status `{}`", status),
+            };
+            let model = if status == Status::Satisfiable {
+                Some(parse_model(&read_line(&mut reader)?)?)
+            } else {
+                None
+            };
+            let job = {
+                let coordinator = coordinator.lock().unwrap();
+                if id >= coordinator.jobs.len() {
+                    bail!("unknown job {}", id);
+                }
+                let job = &coordinator.jobs[id];
+                (coordinator.undecided(id)).then(|| {
+                    (
+                        job.cube.clone(),
+                        Arc::clone(&coordinator.inputs[job.input].data),
+                    )
+                })
+            };
+            // An answer that can no longer change anything is not checked.
+            if let (Some((cube, data)), Some(model)) = (job, &model)
+                && !satisfies(&data, &cube, model)?
+            {
+                bail!(
+                    "the model of job {} does not satisfy its input and cube",
+                    id
+                );
+            }
+            coordinator.lock().unwrap().record(id, status, model);
+            writeln!(reader.get_mut(), "ok")?;
+        }
+        _ => bail!("unexpected `{}`", line),
+    }
+    Ok(())
+}
+
+/// Whether `model`, the value of every variable in order, satisfies the CNF
+/// `data` with the unit clauses of `cube`.
+fn satisfies(data: &[u8], cube: &[i32], model: &[i32]) -> anyhow::Result<bool> {
+    if model
+        .iter()
+        .zip(1u32..)
+        .any(|(lit, var)| lit.unsigned_abs() != var)
+    {
+        return Ok(false);
+    }
+    let mut clauses = Vec::new();
+    read_dimacs(data, false, &mut clauses)?;
+    clauses.extend(cube.iter().map(|&lit| vec![lit]));
+    Ok(verify_model(&clauses, model))
+}
+
+/// Whether the tokens `a` and `b` are equal, compared in constant time.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The token in the file at `path`, its first line.
+fn read_token(path: &Path) -> anyhow::Result<String> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let token = text.lines().next().unwrap_or_default().trim();
+    if token.is_empty() {
+        bail!("{} holds no token", path.display());
+    }
+    Ok(token.to_string())
+}
+
+impl WorkArg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let token = self.token_file.as_deref().map(read_token).transpose()?;
+        let mut solved = 0;
+        loop {
+            let mut stream = match self.connect(solved == 0, token.as_deref()) {
+                Ok(stream) => stream,
+                // The coordinator stops once every input is decided.
+                Err(_) if solved > 0 => break,
+                Err(e) => return Err(e),
+            };
+            writeln!(stream, "next")?;
+            let mut reader = BufReader::new(stream);
+            let line = read_line(&mut reader)?;
+            let Some(header) = line.strip_prefix("job ") else {
+                if line == "done" {
+                    break;
+                }
+                bail!("unexpected `{}` from the coordinator", line);
+            };
+            let (id, len, cube) = parse_job(header)?;
+            let mut data = vec![0; len];
+            reader.read_exact(&mut data)?;
+            let mut clauses = Vec::new();
+            read_dimacs(data.as_slice(), false, &mut clauses)?;
+            clauses.extend(cube.iter().map(|&lit| vec![lit]));
+            let start = Instant::now();
+            let (status, model) = self.solver.solve_clauses(&clauses);
+            println!("c Job {}: {} in {:?}", id, status.as_str(), start.elapsed());
+            let mut stream = self.connect(false, token.as_deref())?;
+            writeln!(stream, "result {} {}", id, status.as_str())?;
+            if let Some(model) = model {
+                write!(stream, "v")?;
+                for lit in model {
+                    write!(stream, " {}", lit)?;
+                }
+                writeln!(stream, " 0")?;
+            }
+            // The answer is only lost if the coordinator has finished.
+            let _ = read_line(&mut BufReader::new(stream));
+            solved += 1;
+        }
+        println!("c Solved {} jobs", solved);
+        Ok(0)
+    }
+
+    /// Connects to the coordinator, waiting for it to come up on the first
+    /// connection, and presents `token`.
+    fn connect(&self, wait: bool, token: Option<&str>) -> anyhow::Result<TcpStream> {
+        let attempts = if wait { 30 } else { 1 };
+        let mut last = None;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                thread::sleep(Duration::from_secs(1));
+            }
+            match TcpStream::connect(&self.connect) {
+                Ok(mut stream) => {
+                    stream.set_read_timeout(Some(TIMEOUT))?;
+                    stream.set_write_timeout(Some(TIMEOUT))?;
+                    if let Some(token) = token {
+                        writeln!(stream, "token {}", token)?;
+                    }
+                    return Ok(stream);
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(anyhow!(
+            "Cannot connect to {}: {}",
+            self.connect,
+            last.map_or_else(String::new, |e| e.to_string())
+        ))
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/// The id, CNF length and cube of a `job` line without its keyword.
+fn parse_job(header: &str) -> anyhow::Result<(usize, usize, Vec<i32>)> {
+    let mut words = header.split_whitespace();
+    let mut number = |what: &str| -> anyhow::Result<usize> {
+        words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(|| anyhow!("job line without a valid {}", what))
+    };
+    let id = number("id")?;
+    let len = number("length")?;
+    let cube = parse_model(&words.collect::<Vec<_>>().join(" "))?;
+    Ok((id, len, cube))
+}
+
+/// Literals up to a terminating `0`, after an optional `v`.
+fn parse_model(line: &str) -> anyhow::Result<Vec<i32>> {
+    line.trim_start_matches('v')
+        .split_whitespace()
+        .map(|w| {
+            w.parse::<i32>()
+                .map_err(|_| anyhow!("invalid literal `{}`", w))
+        })
+        .take_while(|lit| !matches!(lit, Ok(0)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CNF: &[u8] = b"p cnf 3 2\n1 2 0\n-1 3 0\n";
+
+    #[test]
+    fn models_are_checked_against_input_and_cube() {
+        assert!(satisfies(CNF, &[], &[1, -2, 3]).unwrap());
+        assert!(!satisfies(CNF, &[], &[1, 2, -3]).unwrap());
+        assert!(satisfies(CNF, &[-1], &[-1, 2, 3]).unwrap());
+        assert!(!satisfies(CNF, &[-1], &[1, -2, 3]).unwrap());
+        // Every variable in order.
+        assert!(!satisfies(CNF, &[], &[3, -2, 1]).unwrap());
+        assert!(!satisfies(CNF, &[], &[1, -2]).unwrap());
+    }
+
+    #[test]
+    fn tokens() {
+        assert!(same("secret", "secret"));
+        assert!(!same("secret", "secreT"));
+        assert!(!same("secret", "secret2"));
+        assert!(!same("", "secret"));
+    }
+}
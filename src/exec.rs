@@ -0,0 +1,458 @@
+//! Supervision of an external SAT solver: the solver runs under CPU, memory
+//! and wall-clock limits, its `s`/`v` output is parsed, a claimed model is
+//! verified against the input, and the outcome is reported like the built-in
+//! backends report theirs.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use clap::Args;
+use validator::Validate;
+
+use crate::{
+    Reason, SolveResult, Status, binary,
+    core::{
+        Compression, HashingReader, Stat, Writer, check_writable, describe_megabytes,
+        describe_seconds, parse_duration, parse_megabytes, parse_seconds, report, verify_model,
+    },
+    dimacs::{decompress, read_dimacs},
+};
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// CNF handed to the solver and used to verify its model
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
+    /// Result and model output, compressed when it ends in .gz, .xz or .zst; default for stdout
+    #[arg(value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+    /// Compress the output with this format, regardless of its extension
+    #[arg(long, value_enum)]
+    compress: Option<Compression>,
+    /// CPU time limit of the solver, e.g. 90s or 2h; bare numbers are seconds, 0 for none
+    #[arg(long = "cpu-lim", value_name = "DURATION", default_value = "0", value_parser = parse_seconds)]
+    cpu_lim: u64,
+    /// Memory limit of the solver, e.g. 512MB or 8GiB; bare numbers are megabytes, 0 for none
+    #[arg(long = "mem-lim", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes)]
+    mem_lim: u64,
+    /// Wall-clock limit of the solver, e.g. 30s, 90m or 2h
+    #[arg(long = "wall-lim", value_name = "DURATION", value_parser = parse_duration)]
+    wall_lim: Option<Duration>,
+    /// Also write the result, statistics and resolved options as JSON to this file
+    #[arg(long, value_name = "FILE")]
+    json: Option<PathBuf>,
+    /// Report in the SAT competition format StarExec post-processors expect, exit code 10/20/0
+    #[arg(long, conflicts_with_all = ["output", "compress"])]
+    starexec: bool,
+    /// Solver command line after `--`; `{}` is replaced by INPUT, which is appended if absent
+    #[arg(last = true, required = true, value_name = "SOLVER")]
+    command: Vec<String>,
+}
+
+/// What the solver printed: the `s` line and the `v` literals.
+#[derive(Default)]
+struct Answer {
+    status: Option<Status>,
+    literals: Vec<i32>,
+    /// Why the `v` literals are no model of the input, if they are not.
+    invalid: Option<String>,
+}
+
+impl Arg {
+    pub fn run(&self, options: &[(String, String)]) -> anyhow::Result<i32> {
+        self.validate()?;
+        for (name, value) in options {
+            println!("c o {}={}", name, value);
+        }
+        if self.cpu_lim > 0 {
+            println!("c CPU limit: {}", describe_seconds(self.cpu_lim));
+        }
+        if self.mem_lim > 0 {
+            println!("c Memory limit: {}", describe_megabytes(self.mem_lim));
+        }
+        if let Some(limit) = self.wall_lim {
+            println!("c Wall-clock limit: {:?}", limit);
+        }
+        if let Some(path) = &self.json {
+            check_writable(path)?;
+        }
+        let output = Writer::create(self.output.as_deref(), self.compress)?;
+        let mut stat = Stat::new();
+        stat.start_log();
+        let mut reader = HashingReader::new(
+            File::open(&self.input)
+                .with_context(|| format!("Cannot open {}", self.input.display()))?,
+        );
+        let mut clauses = Vec::new();
+        read_dimacs(&mut reader, false, &mut clauses)?;
+        stat.input_sha256 = Some(reader.finish()?);
+        stat.parsed();
+        let vars = clauses
+            .iter()
+            .flatten()
+            .map(|lit| lit.abs())
+            .max()
+            .unwrap_or(0)
+            .max(declared_vars(&self.input).unwrap_or(0));
+
+        let mut warnings = Vec::new();
+        let start = Instant::now();
+        let mut child = self.spawn(&mut warnings)?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(parse_answer(BufReader::new(stdout), vars)));
+        let (exit, timed_out) = self.wait(&mut child)?;
+        // Only the solver's wall-clock time is visible from here.
+        stat.solve_time = Some(start.elapsed());
+        // A killed solver's own children may still hold its stdout open, so
+        // do not wait for the end of the output forever after a timeout.
+        let answer = if timed_out {
+            receiver
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap_or_else(|_| Ok(Answer::default()))
+        } else {
+            receiver.recv().expect("output parser panicked")
+        }?;
+
+        let claim = answer.status.or(match exit.code() {
+            Some(10) => Some(Status::Satisfiable),
+            Some(20) => Some(Status::Unsatisfiable),
+            _ => None,
+        });
+        if timed_out {
+            warnings.push(format!(
+                "Wall-clock limit of {:?} reached, solver killed",
+                self.wall_lim.unwrap_or_default()
+            ));
+        } else if claim.is_none() {
+            warnings.push(format!("Solver gave no answer ({})", exit));
+        }
+        let mut model = None;
+        let status = match (claim, &answer.invalid) {
+            (Some(Status::Satisfiable), Some(invalid)) => {
+                warnings.push(format!(
+                    "Solver answered SATISFIABLE with an invalid model: {}",
+                    invalid
+                ));
+                Status::Unknown
+            }
+            (Some(Status::Satisfiable), None) if answer.literals.is_empty() => {
+                warnings.push("Solver answered SATISFIABLE without a model".to_string());
+                Status::Unknown
+            }
+            (Some(Status::Satisfiable), None) => {
+                let full = full_model(&clauses, &answer.literals);
+                if verify_model(&clauses, &full) {
+                    model = Some(full);
+                    Status::Satisfiable
+                } else {
+                    warnings.push(
+                        "Solver answered SATISFIABLE, but its model does not satisfy the input"
+                            .to_string(),
+                    );
+                    Status::Unknown
+                }
+            }
+            (claim, _) => claim.unwrap_or(Status::Unknown),
+        };
+        let reason = if status != Status::Unknown {
+            None
+        } else if timed_out {
+            Some(Reason::Timeout)
+        } else {
+            self.reason(&exit)
+        };
+
+        for warning in &warnings {
+            println!("c WARNING: {}", warning);
+        }
+        stat.print();
+        let mut stats = stat.stats();
+        // The memory of this process says nothing about the solver's, which
+        // is the child memory.
+        stats.memory = None;
+        let result = SolveResult {
+            status,
+            model,
+            stats,
+            warnings,
+            fast_path: None,
+            components: None,
+            named: Vec::new(),
+            comments: Vec::new(),
+            unique: None,
+            passes: Vec::new(),
+            reason,
+            race_winner: None,
+            stage: None,
+            parse_only: false,
+            repetitions: None,
+            cached: false,
+        };
+        report(&result, output, self.starexec, self.json.as_deref(), options)
+    }
+
+    fn spawn(&self, warnings: &mut Vec<String>) -> anyhow::Result<Child> {
+        let input = self.input.display().to_string();
+        let mut args: Vec<String> = self
+            .command
+            .iter()
+            .map(|a| a.replace("{}", &input))
+            .collect();
+        if !self.command.iter().any(|a| a.contains("{}")) {
+            args.push(input);
+        }
+        let mut command = Command::new(&args[0]);
+        command
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped());
+        limit(
+            &mut command,
+            self.cpu_lim,
+            self.mem_lim.saturating_mul(1024 * 1024),
+            warnings,
+        )?;
+        command
+            .spawn()
+            .with_context(|| format!("Cannot run `{}`", args[0]))
+    }
+
+    /// Waits for the solver, killing it at the wall-clock limit; returns its
+    /// exit status and whether it was killed for the limit.
+    fn wait(&self, child: &mut Child) -> io::Result<(ExitStatus, bool)> {
+        let Some(limit) = self.wall_lim else {
+            return Ok((child.wait()?, false));
+        };
+        let deadline = Instant::now() + limit;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok((status, false));
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                return Ok((child.wait()?, true));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Why the solver ended without an answer, judged by the signal that
+    /// ended it and the limits it ran under.
+    #[cfg(unix)]
+    fn reason(&self, exit: &ExitStatus) -> Option<Reason> {
+        use std::os::unix::process::ExitStatusExt;
+
+        // Signal numbers are the same on Linux and the BSDs.
+        const SIGHUP: i32 = 1;
+        const SIGINT: i32 = 2;
+        const SIGABRT: i32 = 6;
+        const SIGKILL: i32 = 9;
+        const SIGSEGV: i32 = 11;
+        const SIGTERM: i32 = 15;
+        const SIGXCPU: i32 = 24;
+        match exit.signal()? {
+            SIGXCPU => Some(Reason::CpuLimit),
+            // The hard limit of the solver is a second after its soft one.
+            SIGKILL if self.cpu_lim > 0 => Some(Reason::CpuLimit),
+            // A failed allocation usually ends in an abort or a crash.
+            SIGABRT | SIGSEGV if self.mem_lim > 0 => Some(Reason::Memout),
+            SIGHUP | SIGINT | SIGTERM => Some(Reason::Interrupted),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn reason(&self, _exit: &ExitStatus) -> Option<Reason> {
+        None
+    }
+}
+
+/// Installs the CPU and memory limits in the child between fork and exec.
+#[cfg(unix)]
+fn limit(
+    command: &mut Command,
+    cpu: u64,
+    memory: u64,
+    _warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    use rlimit::Resource;
+    use std::os::unix::process::CommandExt;
+
+    // Hard limits can only be lowered, so clamp to the current ones here
+    // rather than fail in the child.
+    let (_, cpu_max) = Resource::CPU.get()?;
+    let (_, memory_max) = Resource::AS.get()?;
+    let cpu = (cpu > 0).then(|| (cpu.min(cpu_max), cpu_max));
+    let memory = (memory > 0).then(|| memory.min(memory_max));
+    // SAFETY: the closure only issues setrlimit system calls, which are
+    // async-signal-safe, and allocates nothing.
+    unsafe {
+        command.pre_exec(move || {
+            // SIGXCPU at the soft limit, SIGKILL at the hard limit.
+            if let Some((soft, hard)) = cpu {
+                rlimit::setrlimit(Resource::CPU, soft, hard.min(soft.saturating_add(1)))?;
+            }
+            if let Some(memory) = memory {
+                rlimit::setrlimit(Resource::AS, memory, memory)?;
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn limit(
+    _command: &mut Command,
+    cpu: u64,
+    memory: u64,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if cpu > 0 || memory > 0 {
+        warnings.push("CPU and memory limits are not supported on this platform".to_string());
+    }
+    Ok(())
+}
+
+/// Variables the `p cnf` header of the DIMACS CNF at `path` declares, if
+/// it has one; a binary CNF declares those its clauses use.
+fn declared_vars(path: &Path) -> Option<i32> {
+    let mut reader = decompress(File::open(path).ok()?).ok()?;
+    if binary::detect(&mut reader).ok()? {
+        return None;
+    }
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).ok()? == 0 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('c') {
+            continue;
+        }
+        let mut tokens = text.split_whitespace();
+        return match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some("p"), Some("cnf"), Some(vars)) => vars.parse().ok(),
+            _ => None,
+        };
+    }
+}
+
+/// Reads the solver's stdout, echoing everything but `v` lines as comments.
+/// The `v` literals are no model of an input of `vars` variables if one is
+/// beyond them or two are of the same variable.
+fn parse_answer<R: BufRead>(reader: R, vars: i32) -> io::Result<Answer> {
+    let mut answer = Answer::default();
+    let mut seen = vec![false; vars as usize];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if let Some(values) = line.strip_prefix("v ").or(line.strip_prefix("V ")) {
+            let literals = values
+                .split_whitespace()
+                .filter_map(|v| v.parse::<i32>().ok())
+                .filter(|&lit| lit != 0);
+            for lit in literals {
+                if answer.invalid.is_some() {
+                    break;
+                }
+                let var = lit.unsigned_abs() as usize;
+                match seen.get_mut(var - 1) {
+                    None => {
+                        answer.invalid = Some(format!(
+                            "literal {} is beyond the {} variables of the input",
+                            lit, vars
+                        ))
+                    }
+                    Some(true) => {
+                        answer.invalid = Some(format!("variable {} is assigned twice", var))
+                    }
+                    Some(seen) => {
+                        *seen = true;
+                        answer.literals.push(lit);
+                    }
+                }
+            }
+            continue;
+        }
+        if let Some(status) = line.strip_prefix("s ") {
+            answer.status = match status.trim() {
+                "SATISFIABLE" => Some(Status::Satisfiable),
+                "UNSATISFIABLE" => Some(Status::Unsatisfiable),
+                _ => Some(Status::Unknown),
+            };
+        }
+        println!("c | {}", line);
+    }
+    Ok(answer)
+}
+
+/// The solver's literals as a model over every variable of `clauses`;
+/// variables it left out are false.
+fn full_model(clauses: &[Vec<i32>], literals: &[i32]) -> Vec<i32> {
+    let vars = clauses
+        .iter()
+        .flatten()
+        .chain(literals)
+        .map(|lit| lit.unsigned_abs() as usize)
+        .max()
+        .unwrap_or(0);
+    let mut model: Vec<i32> = (1..=vars as i32).map(|var| -var).collect();
+    for &lit in literals {
+        model[lit.unsigned_abs() as usize - 1] = lit;
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(output: &str, vars: i32) -> Answer {
+        parse_answer(output.as_bytes(), vars).unwrap()
+    }
+
+    #[test]
+    fn model() {
+        let answer = answer("c solved\ns SATISFIABLE\nv 1 -2\nv 3 0\n", 4);
+        assert_eq!(answer.status, Some(Status::Satisfiable));
+        assert_eq!(answer.literals, vec![1, -2, 3]);
+        assert_eq!(answer.invalid, None);
+        assert_eq!(
+            full_model(&[vec![1, 4]], &answer.literals),
+            vec![1, -2, 3, -4]
+        );
+    }
+
+    #[test]
+    fn invalid_models() {
+        for (output, invalid) in [
+            ("v 1 -1 0", "variable 1 is assigned twice"),
+            (
+                "v 1 3 0",
+                "literal 3 is beyond the 2 variables of the input",
+            ),
+            (
+                "v 1000000000 0",
+                "literal 1000000000 is beyond the 2 variables of the input",
+            ),
+            (
+                "v -2147483648 0",
+                "literal -2147483648 is beyond the 2 variables of the input",
+            ),
+        ] {
+            let answer = answer(&format!("s SATISFIABLE\n{}\n", output), 2);
+            assert_eq!(answer.invalid.as_deref(), Some(invalid), "{}", output);
+        }
+    }
+}
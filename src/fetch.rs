@@ -0,0 +1,537 @@
+//! Download of URL inputs with timeouts, retries and range-based resumption.
+//!
+//! Completed downloads are kept in a local cache and revalidated with the
+//! server's ETag on later runs.
+//!
+//! A request that fails is retried unless the server answered with a client
+//! error (4xx) other than 408 or 429, which a retry would only repeat. The
+//! error finally returned carries an [`HttpError`] with the status, the
+//! redirects followed and the number of attempts.
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use clap::Args;
+use reqwest::{
+    Proxy, StatusCode,
+    blocking::{Client, Response},
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    redirect,
+};
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::{
+    core::{hex, part_path},
+    objstore::{self, Auth},
+};
+
+#[derive(Args, Validate, Clone, Debug)]
+pub struct FetchArg {
+    /// Number of times a failed download is retried before giving up
+    #[arg(long = "retries", default_value_t = 3, help_heading = "Download")]
+    pub retries: u32,
+
+    /// Delay before the first retry in seconds, doubled after each failure
+    #[arg(long = "retry-backoff", default_value_t = 1.0, help_heading = "Download")]
+    #[validate(range(min = 0.0, message = "Retry backoff must be non-negative"))]
+    pub retry_backoff: f64,
+
+    /// Timeout for establishing a connection in seconds (0 = none)
+    #[arg(long = "connect-timeout", default_value_t = 30, help_heading = "Download")]
+    pub connect_timeout: u64,
+
+    /// Timeout for a single read from the connection in seconds (0 = none)
+    #[arg(long = "read-timeout", default_value_t = 60, help_heading = "Download")]
+    pub read_timeout: u64,
+
+    /// Do not read or write the download cache (~/.cache/satgalaxy)
+    #[arg(long = "no-cache", help_heading = "Download")]
+    pub no_cache: bool,
+
+    /// Download again even if a cached copy exists, replacing it
+    #[arg(long = "refresh", conflicts_with = "no_cache", help_heading = "Download")]
+    pub refresh: bool,
+
+    /// Proxy for all URL requests, overriding HTTP(S)_PROXY from the environment
+    #[arg(long = "proxy", value_name = "URL", help_heading = "Download")]
+    pub proxy: Option<url::Url>,
+
+    /// Extra request header, e.g. "X-Api-Key: abc" (repeatable)
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header, help_heading = "Download")]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Send "Authorization: Bearer TOKEN"
+    #[arg(long = "bearer", value_name = "TOKEN", help_heading = "Download")]
+    pub bearer: Option<String>,
+
+    /// Send HTTP basic authentication, given as USER:PASSWORD
+    #[arg(long = "basic", value_name = "USER:PASSWORD", conflicts_with = "bearer", help_heading = "Download")]
+    pub basic: Option<String>,
+}
+
+/// Redirects followed before a request fails, as reqwest's default policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// The URLs the last request of a client was redirected to, in order.
+type Redirects = Arc<Mutex<Vec<String>>>;
+
+impl FetchArg {
+    fn client(&self) -> io::Result<(Client, Redirects)> {
+        let secs = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+        let redirects = Redirects::default();
+        let chain = redirects.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            chain.lock().unwrap().push(attempt.url().to_string());
+            attempt.follow()
+        });
+        let mut builder = Client::builder()
+            .connect_timeout(secs(self.connect_timeout))
+            .timeout(secs(self.read_timeout))
+            .redirect(policy)
+            .default_headers(self.default_headers()?);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.clone()).map_err(io::Error::other)?);
+        }
+        Ok((builder.build().map_err(io::Error::other)?, redirects))
+    }
+
+    fn default_headers(&self) -> io::Result<HeaderMap> {
+        let mut headers: HeaderMap = self.headers.iter().cloned().collect();
+        let auth = match (&self.bearer, &self.basic) {
+            (Some(token), _) => Some(format!("Bearer {token}")),
+            (_, Some(credentials)) => Some(format!("Basic {}", BASE64.encode(credentials))),
+            _ => None,
+        };
+        if let Some(auth) = auth {
+            let mut value = HeaderValue::try_from(auth).map_err(io::Error::other)?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+
+    fn backoff(&self, failures: u32) -> Duration {
+        Duration::from_secs_f64(self.retry_backoff * 2f64.powi(failures as i32))
+    }
+}
+
+/// Where the body of a URL input comes from.
+pub enum UrlSource {
+    Cached(File),
+    Remote(Box<UrlReader>),
+}
+
+/// Opens `url`, serving it from the cache when the cached copy is still valid.
+pub fn open(url: &url::Url, arg: &FetchArg) -> io::Result<UrlSource> {
+    let entry = if arg.no_cache {
+        None
+    } else {
+        CacheEntry::new(url)
+    };
+    let meta = entry
+        .as_ref()
+        .filter(|_| !arg.refresh)
+        .and_then(CacheEntry::meta);
+    if let (Some(entry), Some(meta)) = (&entry, &meta)
+        && meta.etag.is_none()
+    {
+        println!("c Using cached copy of {}", url);
+        return entry.open().map(UrlSource::Cached);
+    }
+    let etag = meta.and_then(|m| m.etag);
+    let reader = UrlReader::open(url, arg, etag.as_deref())?;
+    match (entry, reader.response.status()) {
+        (Some(entry), StatusCode::NOT_MODIFIED) => {
+            println!("c Using cached copy of {} (not modified)", url);
+            entry.open().map(UrlSource::Cached)
+        }
+        (Some(entry), _) => {
+            let etag = reader
+                .response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            match entry.writer(etag) {
+                Ok(tee) => Ok(UrlSource::Remote(Box::new(UrlReader {
+                    tee: Some(tee),
+                    ..reader
+                }))),
+                Err(e) => {
+                    println!("c WARNING: cannot write download cache: {}", e);
+                    Ok(UrlSource::Remote(Box::new(reader)))
+                }
+            }
+        }
+        (None, _) => Ok(UrlSource::Remote(Box::new(reader))),
+    }
+}
+
+/// How `url` would be fetched, as `(what, how)` pairs, without fetching it:
+/// the URL requested, its authentication and what the cache would do.
+pub fn plan(url: &url::Url, arg: &FetchArg) -> io::Result<Vec<(&'static str, String)>> {
+    let (target, auth) = objstore::resolve(url)?;
+    let mut plan = vec![("Request", target.to_string())];
+    let auth = match (auth, &arg.bearer, &arg.basic) {
+        (Auth::S3 { region, .. }, _, _) => format!("AWS SigV4 for {region}"),
+        (Auth::Bearer(_), _, _) => "bearer token from the environment".to_string(),
+        (Auth::Anonymous, Some(_), _) => "--bearer".to_string(),
+        (Auth::Anonymous, _, Some(_)) => "--basic".to_string(),
+        (Auth::Anonymous, None, None) => "none".to_string(),
+    };
+    plan.push(("Authentication", auth));
+    if let Some(proxy) = &arg.proxy {
+        // A proxy URL may carry a user and password.
+        let mut shown = proxy.clone();
+        let _ = shown.set_username("");
+        let _ = shown.set_password(None);
+        plan.push(("Proxy", shown.to_string()));
+    }
+    let entry = if arg.no_cache {
+        None
+    } else {
+        CacheEntry::new(url)
+    };
+    let cache = match entry {
+        None => "not used".to_string(),
+        Some(entry) => match entry.meta().filter(|_| !arg.refresh) {
+            Some(meta) if meta.etag.is_none() => format!("copy in {}", entry.body.display()),
+            Some(_) => format!("copy in {}, revalidated", entry.body.display()),
+            None => format!("download into {}", entry.body.display()),
+        },
+    };
+    plan.push(("Cache", cache));
+    Ok(plan)
+}
+
+/// A streaming HTTP body that transparently reconnects after a failure and
+/// resumes from the last received byte using a `Range` request.
+pub struct UrlReader {
+    client: Client,
+    redirects: Redirects,
+    url: url::Url,
+    target: url::Url,
+    auth: Auth,
+    arg: FetchArg,
+    response: Response,
+    offset: u64,
+    total: Option<u64>,
+    failures: u32,
+    tee: Option<CacheWriter>,
+}
+
+impl UrlReader {
+    fn open(url: &url::Url, arg: &FetchArg, etag: Option<&str>) -> io::Result<Self> {
+        let (client, redirects) = arg.client()?;
+        let (target, auth) = objstore::resolve(url)?;
+        let mut failures = 0;
+        let response = loop {
+            match request(&client, &redirects, &target, &auth, 0, etag) {
+                Ok(resp) => break resp,
+                Err(e) => retry_or_fail(arg, &mut failures, e)?,
+            }
+        };
+        Ok(Self {
+            client,
+            redirects,
+            url: url.clone(),
+            target,
+            auth,
+            arg: arg.clone(),
+            total: response.content_length(),
+            response,
+            offset: 0,
+            failures,
+            tee: None,
+        })
+    }
+
+    /// Size of the complete body, if announced by the server.
+    pub(crate) fn len(&self) -> Option<u64> {
+        self.total
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        let mut response = request(
+            &self.client,
+            &self.redirects,
+            &self.target,
+            &self.auth,
+            self.offset,
+            None,
+        )?;
+        if self.offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The server ignored the range; skip what has already been consumed.
+            io::copy(&mut (&mut response).take(self.offset), &mut io::sink())?;
+        }
+        self.response = response;
+        Ok(())
+    }
+
+    fn resume_with_retries(&mut self) -> io::Result<()> {
+        loop {
+            match self.resume() {
+                Ok(()) => return Ok(()),
+                Err(e) => retry_or_fail(&self.arg, &mut self.failures, e)?,
+            }
+        }
+    }
+}
+
+impl Read for UrlReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.response.read(buf) {
+                Ok(n) => {
+                    if n > 0 {
+                        self.failures = 0;
+                    } else if self.total.is_some_and(|t| self.offset < t) {
+                        let e = io::Error::from(io::ErrorKind::UnexpectedEof);
+                        retry_or_fail(&self.arg, &mut self.failures, e)?;
+                        self.resume_with_retries()?;
+                        continue;
+                    }
+                    self.offset += n as u64;
+                    if let Some(tee) = &mut self.tee {
+                        if let Err(e) = tee.write(&buf[..n]) {
+                            println!("c WARNING: cannot write download cache: {}", e);
+                            self.tee = None;
+                        } else if n == 0 {
+                            self.tee.take().unwrap().commit(&self.url)?;
+                        }
+                    }
+                    return Ok(n);
+                }
+                Err(e) => {
+                    retry_or_fail(&self.arg, &mut self.failures, e)?;
+                    self.resume_with_retries()?;
+                }
+            }
+        }
+    }
+}
+
+/// A failed HTTP request: no answer, or an error status.
+#[derive(Debug)]
+pub struct HttpError {
+    /// The status the server answered with; `None` without an answer.
+    pub status: Option<u16>,
+    /// The URLs redirected to, in order.
+    pub redirects: Vec<String>,
+    /// Requests made for the download, retries included.
+    pub attempts: u32,
+    source: reqwest::Error,
+}
+
+impl HttpError {
+    /// Whether a retry may succeed: not for a client error, unless the
+    /// server timed out waiting for the request or asked to slow down.
+    pub fn retryable(&self) -> bool {
+        !matches!(self.status, Some(400..=499)) || matches!(self.status, Some(408 | 429))
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        if self.attempts > 1 {
+            write!(f, " after {} attempts", self.attempts)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// The [`HttpError`] inside `err`, if it is one.
+pub fn http_error(err: &io::Error) -> Option<&HttpError> {
+    err.get_ref()?.downcast_ref()
+}
+
+fn request(
+    client: &Client,
+    redirects: &Redirects,
+    url: &url::Url,
+    auth: &Auth,
+    offset: u64,
+    etag: Option<&str>,
+) -> io::Result<Response> {
+    let mut req = auth.apply(client.get(url.clone()), url);
+    if offset > 0 {
+        req = req.header(header::RANGE, format!("bytes={offset}-"));
+    }
+    if let Some(etag) = etag {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    redirects.lock().unwrap().clear();
+    req.send()
+        .and_then(Response::error_for_status)
+        .map_err(|source| {
+            io::Error::other(HttpError {
+                status: source.status().map(|status| status.as_u16()),
+                redirects: redirects.lock().unwrap().clone(),
+                attempts: 1,
+                source,
+            })
+        })
+}
+
+/// Sleeps before the next attempt, or returns `err` once retries are
+/// exhausted or a retry cannot help.
+fn retry_or_fail(arg: &FetchArg, failures: &mut u32, err: io::Error) -> io::Result<()> {
+    if *failures >= arg.retries || http_error(&err).is_some_and(|e| !e.retryable()) {
+        return Err(with_attempts(err, *failures + 1));
+    }
+    let delay = arg.backoff(*failures);
+    *failures += 1;
+    println!(
+        "c WARNING: download failed ({}), retrying in {:?} ({}/{})",
+        err, delay, failures, arg.retries
+    );
+    thread::sleep(delay);
+    Ok(())
+}
+
+/// `err` with the attempts recorded, if it is an [`HttpError`].
+fn with_attempts(err: io::Error, attempts: u32) -> io::Error {
+    if http_error(&err).is_none() {
+        return err;
+    }
+    let kind = err.kind();
+    let inner = err.into_inner().expect("an HTTP error is wrapped");
+    let mut http = inner.downcast::<HttpError>().expect("checked above");
+    http.attempts = attempts;
+    io::Error::new(kind, *http)
+}
+
+/// Metadata stored next to a cached body.
+struct CacheMeta {
+    etag: Option<String>,
+    sha256: String,
+}
+
+/// A cache slot for one URL: `<dir>/<sha256(url)>` plus a `.meta` file.
+struct CacheEntry {
+    body: PathBuf,
+    meta: PathBuf,
+}
+
+impl CacheEntry {
+    fn new(url: &url::Url) -> Option<Self> {
+        let dir = cache_dir()?;
+        let key = hex(&Sha256::digest(url.as_str()));
+        Some(Self {
+            body: dir.join(&key),
+            meta: dir.join(format!("{key}.meta")),
+        })
+    }
+
+    /// The metadata of the cached copy, if there is one and its body still
+    /// has the digest the metadata records; a body left half-written or
+    /// replaced by a concurrent download does not.
+    fn meta(&self) -> Option<CacheMeta> {
+        if !self.body.is_file() {
+            return None;
+        }
+        let text = fs::read_to_string(&self.meta).ok()?;
+        let field = |name: &str| {
+            text.lines()
+                .find_map(|l| l.strip_prefix(name))
+                .map(str::to_string)
+        };
+        let meta = CacheMeta {
+            etag: field("etag="),
+            sha256: field("sha256=")?,
+        };
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(&self.body).ok()?, &mut hasher).ok()?;
+        (hex(&hasher.finalize()) == meta.sha256).then_some(meta)
+    }
+
+    fn open(&self) -> io::Result<File> {
+        File::open(&self.body)
+    }
+
+    fn writer(self, etag: Option<String>) -> io::Result<CacheWriter> {
+        if let Some(dir) = self.body.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let part = part_path(&self.body);
+        Ok(CacheWriter {
+            file: File::create(&part)?,
+            part,
+            entry: self,
+            etag,
+            hasher: Sha256::new(),
+        })
+    }
+}
+
+/// Copies the body into the cache while it is being parsed; the entry only
+/// becomes visible once the download has completed.
+struct CacheWriter {
+    file: File,
+    part: PathBuf,
+    entry: CacheEntry,
+    etag: Option<String>,
+    hasher: Sha256,
+}
+
+impl CacheWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.hasher.update(buf);
+        self.file.write_all(buf)
+    }
+
+    fn commit(self, url: &url::Url) -> io::Result<()> {
+        let mut meta = format!(
+            "url={}\nsha256={}\n",
+            url,
+            hex(&self.hasher.clone().finalize())
+        );
+        if let Some(etag) = &self.etag {
+            meta.push_str(&format!("etag={etag}\n"));
+        }
+        fs::write(&self.entry.meta, meta)?;
+        fs::rename(&self.part, &self.entry.body)
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.part);
+    }
+}
+
+/// `$XDG_CACHE_HOME/satgalaxy`, falling back to `~/.cache/satgalaxy`.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .map(|d| d.join("satgalaxy"))
+}
+
+fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("`{s}` is not of the form `NAME: VALUE`"))?;
+    let name = HeaderName::try_from(name.trim()).map_err(|e| e.to_string())?;
+    let value = HeaderValue::try_from(value.trim()).map_err(|e| e.to_string())?;
+    Ok((name, value))
+}
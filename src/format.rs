@@ -0,0 +1,211 @@
+//! A formatter for hand-maintained DIMACS CNF files, so they diff cleanly
+//! under version control: one clause per line with single spaces, long
+//! clauses wrapped onto indented lines, blank lines dropped, and the
+//! `p cnf` header recomputed from the clauses. Comments stay where they
+//! are; with `--sort`, each moves with the clause after it.
+use std::{
+    fs,
+    io::{self, Read, Write},
+    mem,
+    path::PathBuf,
+};
+
+use anyhow::{Context, anyhow, bail};
+use clap::Args;
+
+use crate::{
+    core::{non_dash, write_atomic},
+    dimacs::decompress,
+};
+
+/// A CNF as the formatter sees it.
+#[derive(Default)]
+struct Formula {
+    /// Comments ahead of the header.
+    preamble: Vec<String>,
+    /// Every clause with the comments right before it.
+    clauses: Vec<(Vec<String>, Vec<i32>)>,
+    /// Comments after the last clause.
+    trailing: Vec<String>,
+}
+
+fn parse(text: &str) -> anyhow::Result<Formula> {
+    let mut formula = Formula::default();
+    let mut header = false;
+    let mut comments = Vec::new();
+    let mut clause = Vec::new();
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('c') {
+            comments.push(line.to_string());
+            continue;
+        }
+        // The end marker of the SATLIB files.
+        if line.starts_with('%') {
+            break;
+        }
+        if line.starts_with('p') {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !matches!(fields[..], ["p", "cnf", _, _]) {
+                bail!("line {}: `{}` is not a CNF header", number, line);
+            }
+            if header || !formula.clauses.is_empty() || !clause.is_empty() {
+                bail!("line {}: a header after the first one or after clauses", number);
+            }
+            header = true;
+            formula.preamble.append(&mut comments);
+            continue;
+        }
+        for token in line.split_whitespace() {
+            let lit: i32 = token
+                .parse()
+                .map_err(|_| anyhow!("line {}: `{}` is not a literal", number, token))?;
+            if lit == 0 {
+                formula
+                    .clauses
+                    .push((mem::take(&mut comments), mem::take(&mut clause)));
+            } else {
+                clause.push(lit);
+            }
+        }
+    }
+    // An unterminated last clause is a clause.
+    if !clause.is_empty() {
+        formula.clauses.push((mem::take(&mut comments), clause));
+    }
+    formula.trailing = comments;
+    // Without a header, the leading comments still go ahead of the new one.
+    if !header {
+        match formula.clauses.first_mut() {
+            Some((comments, _)) => formula.preamble = mem::take(comments),
+            None => formula.preamble = mem::take(&mut formula.trailing),
+        }
+    }
+    Ok(formula)
+}
+
+impl Formula {
+    /// Sorts the literals of every clause by variable, then the clauses.
+    fn sort(&mut self) {
+        for (_, clause) in &mut self.clauses {
+            clause.sort_by_key(|lit| (lit.abs(), *lit));
+        }
+        self.clauses.sort_by_cached_key(|(_, clause)| {
+            clause.iter().map(|lit| (lit.abs(), *lit)).collect::<Vec<_>>()
+        });
+    }
+
+    /// The formatted text, with clause lines of at most `width` characters
+    /// unless a single literal is longer; 0 for no wrapping.
+    fn text(&self, width: usize) -> String {
+        let vars = self
+            .clauses
+            .iter()
+            .flat_map(|(_, clause)| clause)
+            .map(|lit| lit.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        let mut text = String::new();
+        for comment in &self.preamble {
+            text += comment;
+            text.push('\n');
+        }
+        text += &format!("p cnf {} {}\n", vars, self.clauses.len());
+        for (comments, clause) in &self.clauses {
+            for comment in comments {
+                text += comment;
+                text.push('\n');
+            }
+            let mut lines = vec![String::new()];
+            for token in clause.iter().map(i32::to_string).chain(["0".to_string()]) {
+                let line = lines.last_mut().unwrap();
+                if width > 0 && !line.is_empty() && line.len() + 1 + token.len() > width {
+                    lines.push(format!("  {}", token));
+                } else {
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+                    line.push_str(&token);
+                }
+            }
+            text += &lines.join("\n");
+            text.push('\n');
+        }
+        for comment in &self.trailing {
+            text += comment;
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[derive(Args)]
+pub struct Arg {
+    /// CNF to format (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Where the formatted CNF is written; default for stdout
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["in_place", "check"])]
+    out: Option<PathBuf>,
+    /// Rewrite INPUT with the formatted CNF
+    #[arg(long = "in-place", requires = "input", conflicts_with = "check")]
+    in_place: bool,
+    /// Write nothing, and exit with 1 if the input is not formatted
+    #[arg(long)]
+    check: bool,
+    /// Wrap clause lines longer than this onto indented lines; 0 for no wrapping
+    #[arg(long, value_name = "N", default_value_t = 80)]
+    width: usize,
+    /// Sort the literals of every clause by variable, then the clauses
+    #[arg(long)]
+    sort: bool,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let input = non_dash(self.input.as_deref());
+        let raw = match input {
+            Some(path) => {
+                fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?
+            }
+            None => {
+                let mut raw = Vec::new();
+                io::stdin().read_to_end(&mut raw)?;
+                raw
+            }
+        };
+        let mut text = String::new();
+        decompress(&raw[..])?.read_to_string(&mut text)?;
+        let name = input.map_or("stdin".to_string(), |path| path.display().to_string());
+        let mut formula = parse(&text).with_context(|| format!("Cannot format {}", name))?;
+        if self.sort {
+            formula.sort();
+        }
+        let formatted = formula.text(self.width);
+        if self.check {
+            if formatted == text {
+                return Ok(0);
+            }
+            println!("c {} is not formatted", name);
+            return Ok(1);
+        }
+        match (input, non_dash(self.out.as_deref())) {
+            (None, _) if self.in_place => bail!("--in-place needs an INPUT file, not stdin"),
+            (Some(path), _) if self.in_place => {
+                if raw != text.as_bytes() {
+                    return Err(anyhow!("{} is compressed; format it to --out", name));
+                }
+                if formatted != text {
+                    write_atomic(path, &formatted)?;
+                }
+            }
+            (_, Some(path)) => fs::write(path, &formatted)
+                .with_context(|| format!("Cannot write {}", path.display()))?,
+            _ => io::stdout().write_all(formatted.as_bytes())?,
+        }
+        Ok(0)
+    }
+}
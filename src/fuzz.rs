@@ -0,0 +1,126 @@
+//! Differential fuzzing: random small CNFs are solved by every compiled-in
+//! backend, and disagreeing statuses or invalid models are reported.
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::Args;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use validator::Validate;
+
+use crate::{Solver, Status, core::verify_model, dimacs::write_dimacs};
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// Number of instances to generate, 0 to run until interrupted
+    #[arg(long, default_value_t = 1000)]
+    count: u64,
+    /// Seed of the first instance; instance i uses SEED + i [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Maximum number of variables per instance
+    #[arg(long = "max-vars", default_value_t = 30)]
+    #[validate(range(min = 1, max = 10000, message = "Max vars must be in [1, 10000]"))]
+    max_vars: i32,
+    /// Clause to variable ratio, around 4.26 instances are hardest
+    #[arg(long, default_value_t = 4.26)]
+    #[validate(range(exclusive_min = 0.0, message = "Ratio must be positive"))]
+    ratio: f64,
+    /// Directory failing instances are saved to
+    #[arg(long, value_name = "DIR", default_value = "fuzz-failures")]
+    out: PathBuf,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let solvers = Solver::all();
+        let seed = self.seed.unwrap_or_else(rand::random);
+        println!(
+            "c Fuzzing {} with seed {}",
+            solvers
+                .iter()
+                .map(Solver::name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            seed
+        );
+        let mut failures = 0;
+        let mut i = 0;
+        while self.count == 0 || i < self.count {
+            let instance_seed = seed.wrapping_add(i);
+            i += 1;
+            let clauses = self.generate(instance_seed);
+            let Some(problem) = check(&solvers, &clauses) else {
+                continue;
+            };
+            failures += 1;
+            fs::create_dir_all(&self.out)
+                .with_context(|| format!("Cannot create {}", self.out.display()))?;
+            let path = self.out.join(format!("fuzz-{instance_seed}.cnf"));
+            write_dimacs(BufWriter::new(File::create(&path)?), &clauses)?;
+            println!(
+                "c FAILED seed {instance_seed}: {problem} (saved to {})",
+                path.display()
+            );
+        }
+        println!("c {} of {} instances failed", failures, i);
+        Ok(if failures > 0 { 1 } else { 0 })
+    }
+
+    /// A random CNF of 1-4 literal clauses over at most `max_vars` variables.
+    fn generate(&self, seed: u64) -> Vec<Vec<i32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let vars = rng.random_range(1..=self.max_vars);
+        let count = (vars as f64 * self.ratio * rng.random_range(0.5..1.5)).ceil() as usize;
+        (0..count)
+            .map(|_| {
+                let len = match rng.random_range(0..10) {
+                    0 => 1,
+                    1..=2 => 2,
+                    3..=8 => 3,
+                    _ => 4,
+                };
+                (0..len)
+                    .map(|_| {
+                        let var = rng.random_range(1..=vars);
+                        if rng.random_bool(0.5) { var } else { -var }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Describes the first disagreement between `solvers` on `clauses`, if any.
+fn check(solvers: &[Solver], clauses: &[Vec<i32>]) -> Option<String> {
+    let mut statuses = Vec::new();
+    for solver in solvers {
+        let (status, model) = solver.solve_clauses(clauses);
+        if let Some(model) = model
+            && !verify_model(clauses, &model)
+        {
+            return Some(format!(
+                "{} returned a model that fails verification",
+                solver.name()
+            ));
+        }
+        if status != Status::Unknown {
+            statuses.push((solver.name(), status));
+        }
+    }
+    let (first, expected) = *statuses.first()?;
+    statuses
+        .iter()
+        .find(|(_, status)| *status != expected)
+        .map(|(name, status)| {
+            format!(
+                "{first} answered {}, {name} answered {}",
+                expected.as_str(),
+                status.as_str()
+            )
+        })
+}
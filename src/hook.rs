@@ -0,0 +1,176 @@
+//! Notifications sent when a job finishes: a shell command (`--on-complete`)
+//! and, in server mode, a webhook.
+//!
+//! Both receive the result summary, which is the `--json` document without
+//! the model plus the `input`, `output` and `json` paths of the job. A failing
+//! hook is reported as a warning and does not change the job's result.
+use std::{
+    io::{self, Write},
+    process::Stdio,
+};
+
+use anyhow::{Context, anyhow};
+
+use crate::{dimacs::DimacsError, utils::shell};
+
+/// Turns a `--json` document into a hook summary.
+pub fn summary(
+    mut result: serde_json::Value,
+    input: Option<String>,
+    output: Option<String>,
+    json: Option<String>,
+) -> serde_json::Value {
+    if let Some(object) = result.as_object_mut() {
+        object.remove("model");
+        object.insert("input".into(), input.into());
+        object.insert("output".into(), output.into());
+        object.insert("json".into(), json.into());
+    }
+    result
+}
+
+/// Summary of a job that failed before producing a result.
+pub fn error_summary(error: &anyhow::Error, input: Option<String>) -> serde_json::Value {
+    serde_json::json!({
+        "status": "ERROR",
+        "error": error.to_string(),
+        "error_details": error_details(error),
+        "input": input,
+    })
+}
+
+/// A failure as harnesses classify it: its `category` (`parse` for an
+/// invalid CNF, `limit` for one larger than allowed, `io` for a failed read
+/// or write, `network` for a failed download, `other`), the whole chain of
+/// messages, the input line and column of a parse error, the OS error
+/// number of an I/O error, and the HTTP status, redirects, attempts and
+/// whether a retry may help of a failed download.
+pub fn error_details(error: &anyhow::Error) -> serde_json::Value {
+    let mut details = serde_json::json!({
+        "category": "other",
+        "message": format!("{:#}", error),
+        "line": null,
+        "column": null,
+        "errno": null,
+    });
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<DimacsError>() {
+            match e {
+                DimacsError::Io(e) => {
+                    if !network_details(&mut details, e) {
+                        details["category"] = "io".into();
+                        details["errno"] = e.raw_os_error().into();
+                    }
+                }
+                DimacsError::Syntax { line, column, .. }
+                | DimacsError::TooManyVariables { line, column, .. }
+                | DimacsError::TooManyClauses { line, column, .. } => {
+                    details["category"] = "parse".into();
+                    details["line"] = (*line).into();
+                    details["column"] = (*column).into();
+                }
+                DimacsError::LimitExceeded {
+                    line,
+                    column,
+                    what,
+                    max,
+                } => {
+                    details["category"] = "limit".into();
+                    details["line"] = (*line).into();
+                    details["column"] = (*column).into();
+                    details["limit"] = (*what).into();
+                    details["max"] = (*max).into();
+                }
+            }
+            break;
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            if !network_details(&mut details, e) {
+                details["category"] = "io".into();
+                details["errno"] = e.raw_os_error().into();
+            }
+            break;
+        }
+        #[cfg(feature = "fetch")]
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            details["category"] = "network".into();
+            details["http_status"] = e.status().map(|status| status.as_u16()).into();
+            break;
+        }
+    }
+    details
+}
+
+/// Fills in the `network` details if `error` is a failed download.
+#[cfg(feature = "fetch")]
+fn network_details(details: &mut serde_json::Value, error: &io::Error) -> bool {
+    let Some(e) = crate::fetch::http_error(error) else {
+        return false;
+    };
+    details["category"] = "network".into();
+    details["http_status"] = e.status.into();
+    details["redirects"] = e.redirects.clone().into();
+    details["attempts"] = e.attempts.into();
+    details["retryable"] = e.retryable().into();
+    true
+}
+
+#[cfg(not(feature = "fetch"))]
+fn network_details(_details: &mut serde_json::Value, _error: &io::Error) -> bool {
+    false
+}
+
+/// Runs `cmd` through the shell with the summary on stdin and its main fields
+/// in `SATGALAXY_*` environment variables, and waits for it. The command's
+/// stdout goes to stderr.
+pub fn run_command(cmd: &str, summary: &serde_json::Value) -> anyhow::Result<()> {
+    let field = |name: &str| match &summary[name] {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    let stat = |name: &str| match &summary["stats"][name] {
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    };
+    let mut child = shell(cmd)
+        .env("SATGALAXY_STATUS", field("status"))
+        .env("SATGALAXY_EXIT_CODE", field("exit_code"))
+        .env("SATGALAXY_INPUT", field("input"))
+        .env("SATGALAXY_OUTPUT", field("output"))
+        .env("SATGALAXY_JSON", field("json"))
+        .env("SATGALAXY_RUN_TIME", stat("run_time"))
+        .env("SATGALAXY_TOTAL_TIME", stat("total_time"))
+        .env("SATGALAXY_ERROR", field("error"))
+        .stdin(Stdio::piped())
+        // Keeps stdout for the solver's own output.
+        .stdout(std::io::stderr())
+        .spawn()
+        .with_context(|| format!("Cannot run `{cmd}`"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may well ignore its input and exit early.
+        let _ = writeln!(stdin, "{}", summary);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("`{cmd}` failed with {status}"));
+    }
+    Ok(())
+}
+
+/// POSTs the summary as JSON to `url`.
+#[cfg(feature = "fetch")]
+pub fn post_webhook(url: &url::Url, summary: &serde_json::Value) -> anyhow::Result<()> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?
+        .post(url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(summary.to_string())
+        .send()
+        .with_context(|| format!("Cannot reach webhook {url}"))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Webhook {url} answered {}", response.status()));
+    }
+    Ok(())
+}
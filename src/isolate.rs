@@ -0,0 +1,366 @@
+//! Solving in a child `satgalaxy` process under CPU, memory and wall-clock
+//! limits, so a job that exceeds them or crashes cannot take the calling
+//! worker or server down with it.
+//!
+//! Seed races and schedules run their solves as children too, as only a
+//! process can be stopped once its solve has started.
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    Reason, SolveRequest, SolveResult, Status,
+    cache::Spool,
+    core::{SmartPath, SmartReader, Stat},
+    dimacs::Limits,
+    pipeline::PassStats,
+};
+
+/// Limits a child run is started under; zero and `None` are no limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Isolated {
+    /// CPU time in seconds.
+    pub cpu_lim: u64,
+    /// Memory in megabytes.
+    pub mem_lim: u64,
+    pub wall_lim: Option<Duration>,
+    pub limits: Limits,
+}
+
+/// What the child reads: a path or URL, or bytes piped to its stdin.
+#[derive(Clone, Copy)]
+pub enum Input<'a> {
+    Path(&'a str),
+    Bytes(&'a [u8]),
+}
+
+impl Isolated {
+    /// Solves `input` with backend `solver`, given `options` (CLI options by
+    /// their long flag name), and returns the child's `--json` document. A
+    /// child stopped by a limit before writing one is reported as UNKNOWN
+    /// with the reason.
+    pub fn solve(
+        &self,
+        solver: &str,
+        options: &[(String, String)],
+        input: Input,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.solve_until(solver, options, input, &AtomicBool::new(false))
+    }
+
+    /// Like [`Isolated::solve`], but the child is killed as soon as `stop`
+    /// is set and the run is reported as UNKNOWN (INTERRUPTED).
+    pub fn solve_until(
+        &self,
+        solver: &str,
+        options: &[(String, String)],
+        input: Input,
+        stop: &AtomicBool,
+    ) -> anyhow::Result<serde_json::Value> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let json = TempJson(env::temp_dir().join(format!(
+            "satgalaxy-job-{}-{}.json",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        )));
+        let mut command = Command::new(env::current_exe()?);
+        command
+            .arg(solver)
+            .arg(format!("--cpu-lim={}", self.cpu_lim))
+            .arg(format!("--mem-lim={}", self.mem_lim))
+            .arg(format!("--max-vars={}", self.limits.vars))
+            .arg(format!("--max-clauses={}", self.limits.clauses))
+            .arg("--json")
+            .arg(&json.0);
+        for (name, value) in options {
+            command.arg(match value.as_str() {
+                "" => format!("--{name}"),
+                value => format!("--{name}={value}"),
+            });
+        }
+        let stdin = match input {
+            Input::Path(path) => {
+                command.arg("--").arg(path);
+                Stdio::null()
+            }
+            Input::Bytes(_) => Stdio::piped(),
+        };
+        let mut child = command
+            .stdin(stdin)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let (Input::Bytes(bytes), Some(mut stdin)) = (input, child.stdin.take()) {
+            // A child that stops reading early, e.g. at a limit, closes the
+            // pipe; that is reported by its result, not here.
+            let _ = stdin.write_all(bytes);
+        }
+        let deadline = self.wall_lim.map(|limit| Instant::now() + limit);
+        // The reason the child was killed for, if it was.
+        let (status, killed) = loop {
+            if let Some(status) = child.try_wait()? {
+                break (status, None);
+            }
+            let killed = if stop.load(Ordering::Relaxed) {
+                Some(Reason::Interrupted)
+            } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                Some(Reason::Timeout)
+            } else {
+                None
+            };
+            if killed.is_some() {
+                child.kill()?;
+                break (child.wait()?, killed);
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        if killed.is_none()
+            && let Some(document) = json.read()
+        {
+            return Ok(document);
+        }
+        // A run stopped by its limits exits without writing a document.
+        let reason = killed.or_else(|| {
+            [Reason::CpuLimit, Reason::Memout]
+                .into_iter()
+                .find(|r| status.code() == Some(r.exit_code()))
+        });
+        match reason {
+            Some(reason) => Ok(serde_json::json!({
+                "status": Status::Unknown.as_str(),
+                "reason": reason.as_str(),
+            })),
+            None => Err(anyhow!("solver gave no result ({})", status)),
+        }
+    }
+
+    /// The limits of `request`, and `wall_lim`.
+    pub(crate) fn of(request: &SolveRequest, wall_lim: Option<Duration>) -> Self {
+        Isolated {
+            cpu_lim: request.cpu_limit,
+            mem_lim: request.mem_limit,
+            wall_lim,
+            limits: request.limits,
+        }
+    }
+
+    /// Solves `request` from `input` in a child process killed as soon as
+    /// `stop` is set, taking the statistics of the child over into `stat`.
+    pub(crate) fn solve_request(
+        &self,
+        request: &SolveRequest,
+        input: &ChildInput,
+        stop: &AtomicBool,
+        stat: &Mutex<Stat>,
+    ) -> anyhow::Result<SolveResult> {
+        let options = request_options(request);
+        let document = self.solve_until(
+            request.solver.name(),
+            &options,
+            Input::Path(&input.path),
+            stop,
+        )?;
+        result_of(&document, stat)
+    }
+}
+
+/// The input of a request as a file every child can read: a local file by
+/// its path, anything else copied once to a temporary file, which is
+/// removed when this is dropped.
+pub(crate) struct ChildInput {
+    path: String,
+    _spool: Option<Spool>,
+}
+
+impl ChildInput {
+    pub(crate) fn of(request: &SolveRequest, input: Option<&mut dyn Read>) -> anyhow::Result<Self> {
+        if input.is_none()
+            && let Some(SmartPath::FilePath(path)) = &request.input
+            && let Some(path) = path.to_str()
+        {
+            return Ok(ChildInput {
+                path: path.to_string(),
+                _spool: None,
+            });
+        }
+        let spool = Spool::create(&env::temp_dir())?;
+        let mut copy = BufWriter::new(File::create(&spool.0)?);
+        match input {
+            Some(input) => io::copy(input, &mut copy)?,
+            None => io::copy(&mut SmartReader::open(request)?, &mut copy)?,
+        };
+        copy.flush()?;
+        let path = spool
+            .0
+            .to_str()
+            .ok_or_else(|| anyhow!("the temporary directory {} is not UTF-8", spool.0.display()))?
+            .to_string();
+        Ok(ChildInput {
+            path,
+            _spool: Some(spool),
+        })
+    }
+}
+
+/// The command line options of a child run answering `request`, by their
+/// long name. The input is given apart, and the limits by [`Isolated`].
+fn request_options(request: &SolveRequest) -> Vec<(String, String)> {
+    let join = |values: &[String]| values.join(",");
+    let numbers = |values: &[i32], separator: &str| {
+        let values: Vec<String> = values.iter().map(i32::to_string).collect();
+        values.join(separator)
+    };
+    let path = |path: &Path| path.display().to_string();
+    let mut options = request.solver.flags();
+    let mut add = |name: &str, value: String| options.push((name.to_string(), value));
+    if let Some(sha256) = &request.sha256 {
+        add("sha256", sha256.clone());
+    }
+    // Only what differs from the defaults, as some of them conflict.
+    let flags = [
+        ("pre", "false", !request.preprocess),
+        ("solve", "false", !request.solve),
+        ("strictp", "", request.strict),
+        ("lenient", "", request.lenient),
+        ("parse-only", "", request.parse_only),
+        ("fast-paths", "", request.fast_paths),
+        ("partial-model", "", request.partial_model),
+        ("equivalences", "", request.equivalences),
+        ("components", "", request.components),
+    ];
+    for (flag, value, set) in flags {
+        if set {
+            add(flag, value.to_string());
+        }
+    }
+    if request.components {
+        add("component-threads", request.component_threads.to_string());
+    }
+    if !request.freeze.is_empty() {
+        add("freeze", numbers(&request.freeze, ","));
+    }
+    for file in &request.extra_clauses {
+        add("extra-clauses", path(file));
+    }
+    for constraint in &request.at_most {
+        add(
+            "at-most",
+            format!("{}: {}", constraint.bound, numbers(&constraint.lits, " ")),
+        );
+    }
+    if let Some(vars) = &request.unique_check {
+        add("unique-check", numbers(vars, ","));
+    }
+    if !request.pipeline.is_empty() {
+        let passes: Vec<String> = request
+            .pipeline
+            .iter()
+            .map(|pass| pass.name().to_string())
+            .collect();
+        add("pipeline", join(&passes));
+    }
+    if let Some(file) = &request.reconstruction_log {
+        add("reconstruction-log", path(file));
+    }
+    if let Some(dir) = &request.result_cache {
+        add("result-cache", path(dir));
+    }
+    if let Some(file) = &request.symbols {
+        add("symbols", path(file));
+    }
+    if !request.show.is_empty() {
+        add("show", join(&request.show));
+    }
+    if let Some(prefixes) = &request.keep_comments {
+        add("keep-comments", join(prefixes));
+    }
+    options
+}
+
+/// The result in the `--json` `document` of a child run, whose statistics
+/// `stat` takes over; a document with an `error` is that error.
+fn result_of(document: &serde_json::Value, stat: &Mutex<Stat>) -> anyhow::Result<SolveResult> {
+    if let Some(error) = document["error"].as_str() {
+        return Err(anyhow!("{}", error));
+    }
+    let status = match document["status"].as_str() {
+        Some("SATISFIABLE") => Status::Satisfiable,
+        Some("UNSATISFIABLE") => Status::Unsatisfiable,
+        Some("UNKNOWN") => Status::Unknown,
+        _ => return Err(anyhow!("solver gave no status")),
+    };
+    let reason = [
+        Reason::Timeout,
+        Reason::CpuLimit,
+        Reason::Memout,
+        Reason::Budget,
+        Reason::Interrupted,
+    ]
+    .into_iter()
+    .find(|reason| document["reason"].as_str() == Some(reason.as_str()));
+    let named = document["named_model"]
+        .as_object()
+        .map(|named| {
+            named
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_bool()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let strings = |name: &str| -> Vec<String> {
+        serde_json::from_value(document[name].clone()).unwrap_or_default()
+    };
+    let passes = document["passes"]
+        .as_array()
+        .map(|passes| passes.iter().filter_map(PassStats::from_json).collect())
+        .unwrap_or_default();
+    let mut stat = stat.lock().unwrap();
+    stat.adopt_json(&document["stats"]);
+    Ok(SolveResult {
+        status,
+        model: serde_json::from_value(document["model"].clone()).unwrap_or_default(),
+        stats: stat.stats(),
+        warnings: strings("warnings"),
+        fast_path: ["2-SAT", "Horn"]
+            .into_iter()
+            .find(|&class| document["fast_path"].as_str() == Some(class)),
+        components: document["components"].as_u64().map(|n| n as usize),
+        named,
+        comments: strings("comments"),
+        unique: document["unique"].as_bool(),
+        passes,
+        reason,
+        race_winner: None,
+        stage: None,
+        parse_only: document["parse_only"].as_bool().unwrap_or_default(),
+        repetitions: None,
+        cached: document["cached"].as_bool().unwrap_or_default(),
+    })
+}
+
+/// The `--json` file of a child run, removed once read.
+struct TempJson(PathBuf);
+
+impl TempJson {
+    fn read(&self) -> Option<serde_json::Value> {
+        serde_json::from_str(&fs::read_to_string(&self.0).ok()?).ok()
+    }
+}
+
+impl Drop for TempJson {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,951 @@
+//! The `satgalaxy` runner as a library.
+//!
+//! [`solve`] runs exactly the pipeline behind the `minisat` and `glucose`
+//! subcommands (fetch, decompress, parse, simplify, solve) and returns the
+//! outcome as data instead of printing it:
+//!
+//! ```no_run
+//! use satgalaxy_cli::{SolveRequest, Solver, Status, core::SmartPath};
+//!
+//! let request = SolveRequest {
+//!     input: Some(SmartPath::FilePath("problem.cnf".into())),
+//!     ..SolveRequest::new(Solver::Minisat(Default::default()))
+//! };
+//! let result = satgalaxy_cli::solve(&request)?;
+//! if result.status == Status::Satisfiable {
+//!     println!("{:?}", result.model);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "glucose")]
+use satgalaxy::solver::GlucoseSolver;
+#[cfg(feature = "minisat")]
+use satgalaxy::solver::MinisatSolver;
+use satgalaxy::solver::RawStatus;
+
+#[cfg(not(any(feature = "minisat", feature = "glucose")))]
+compile_error!("at least one solver backend feature (`minisat`, `glucose`) must be enabled");
+
+pub mod analyze;
+pub mod autarky;
+#[cfg(all(feature = "fetch", feature = "compression"))]
+pub mod benchmarks;
+pub mod binary;
+pub mod cache;
+#[cfg(unix)]
+mod capture;
+pub mod card;
+pub mod classes;
+pub mod cofactor;
+pub mod convert;
+pub mod core;
+pub mod dimacs;
+pub mod distribute;
+pub mod exec;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod format;
+pub mod fuzz;
+#[cfg(feature = "glucose")]
+pub mod glucose;
+pub mod hook;
+pub mod isolate;
+#[cfg(feature = "minisat")]
+pub mod minisat;
+#[cfg(feature = "fetch")]
+mod objstore;
+pub mod opb;
+pub mod optimize;
+pub mod pipeline;
+pub mod queue;
+pub mod reconstruct;
+pub mod report;
+pub mod retention;
+pub mod script;
+pub mod selftest;
+pub mod serve;
+pub mod shrink;
+pub mod split;
+pub mod symbols;
+pub mod teach;
+pub mod trace;
+pub mod utils;
+pub mod version;
+pub mod watch;
+
+use crate::card::AtMost;
+use crate::core::{SmartPath, Stat};
+#[cfg(feature = "fetch")]
+use crate::fetch::FetchArg;
+use crate::opb::{self, Linear, Problem};
+use crate::optimize::{DeltaLine, Optimizer, Outcome};
+use crate::pipeline::{Pass, PassStats};
+use crate::script::Line;
+
+/// Backend to solve with, together with its options.
+#[derive(Clone, Debug)]
+pub enum Solver {
+    #[cfg(feature = "minisat")]
+    Minisat(minisat::Options),
+    #[cfg(feature = "glucose")]
+    Glucose(glucose::Options),
+}
+
+/// Everything a single solver run needs.
+#[derive(Clone, Debug)]
+pub struct SolveRequest {
+    /// Input source; `None` reads from stdin.
+    pub input: Option<SmartPath>,
+    /// Expected SHA-256 of the input as read (before decompression).
+    pub sha256: Option<String>,
+    /// How URL inputs are downloaded.
+    #[cfg(feature = "fetch")]
+    pub fetch: FetchArg,
+    pub solver: Solver,
+    /// Solver verbosity level (0, 1 or 2).
+    pub verbosity: i32,
+    /// Run preprocessing.
+    pub preprocess: bool,
+    /// Variables that preprocessing must not eliminate.
+    pub freeze: Vec<i32>,
+    /// Solve after preprocessing.
+    pub solve: bool,
+    /// Stop after parsing, before simplifying and solving.
+    pub parse_only: bool,
+    /// Check the DIMACS header while parsing.
+    pub strict: bool,
+    /// Accept common deviations from the DIMACS format, with a warning each.
+    pub lenient: bool,
+    /// Largest input accepted; the parse stops at the first clause beyond it.
+    pub limits: dimacs::Limits,
+    /// CPU time limit of the process in seconds, 0 for none.
+    pub cpu_limit: u64,
+    /// Memory limit of the process in megabytes, 0 for none.
+    pub mem_limit: u64,
+    /// Draw a progress bar on stderr while reading the input.
+    pub progress: bool,
+    /// Solve 2-SAT and Horn formulas with their linear-time algorithms.
+    pub fast_paths: bool,
+    /// Report only the literals of the model the input clauses need, see
+    /// [`core::partial_model`].
+    pub partial_model: bool,
+    /// Substitute equivalent literals before solving.
+    pub equivalences: bool,
+    /// DIMACS files whose clauses are added to the input.
+    pub extra_clauses: Vec<PathBuf>,
+    /// Cardinality constraints added to the input.
+    pub at_most: Vec<AtMost>,
+    /// Check whether the model is unique over these variables, or over all
+    /// of them if the list is empty.
+    pub unique_check: Option<Vec<i32>>,
+    /// Preprocessing passes run on the formula, in order, before solving.
+    pub pipeline: Vec<Pass>,
+    /// Where the reconstruction log of `pipeline` and `equivalences` is
+    /// written, for model extension to be done or checked elsewhere.
+    pub reconstruction_log: Option<PathBuf>,
+    /// Copies of the backend raced with different seeds, each in a child
+    /// process; 1 for a plain run. Command line only, as `schedule`.
+    pub race_seeds: usize,
+    /// Stages run one after another, each in a child process killed when
+    /// its budget runs out; empty for a plain run. Command line only, as
+    /// the children of races and schedules are started from the running
+    /// executable, which is only `satgalaxy` there; [`solve`] and
+    /// [`solve_reader`] refuse it.
+    pub schedule: Vec<Stage>,
+    /// Times the run is repeated for a benchmark; 1 for a plain run.
+    pub repeat: usize,
+    /// Give every repetition but the first its own seed, as in a race.
+    pub repeat_seeds: bool,
+    /// Directory of the solve-result cache, if answers are cached.
+    pub result_cache: Option<PathBuf>,
+    /// Solve the variable-disjoint components of the formula separately.
+    pub components: bool,
+    /// Components solved at a time.
+    pub component_threads: usize,
+    /// File of variable names added to those of the input's comments.
+    pub symbols: Option<PathBuf>,
+    /// Name patterns `named` is projected onto; all names if empty.
+    pub show: Vec<String>,
+    /// Comment prefixes of the input kept in `comments`, all if empty; none
+    /// are kept if `None`.
+    pub keep_comments: Option<Vec<String>>,
+}
+
+impl SolveRequest {
+    /// A request reading stdin with the CLI's defaults.
+    pub fn new(solver: Solver) -> Self {
+        Self {
+            input: None,
+            sha256: None,
+            #[cfg(feature = "fetch")]
+            fetch: core::defaults(),
+            solver,
+            verbosity: 0,
+            preprocess: true,
+            freeze: Vec::new(),
+            solve: true,
+            parse_only: false,
+            strict: false,
+            lenient: false,
+            limits: Default::default(),
+            cpu_limit: 0,
+            mem_limit: 0,
+            progress: false,
+            fast_paths: false,
+            partial_model: false,
+            equivalences: false,
+            extra_clauses: Vec::new(),
+            at_most: Vec::new(),
+            unique_check: None,
+            pipeline: Vec::new(),
+            reconstruction_log: None,
+            race_seeds: 1,
+            schedule: Vec::new(),
+            repeat: 1,
+            repeat_seeds: false,
+            result_cache: None,
+            components: false,
+            component_threads: 1,
+            symbols: None,
+            show: Vec::new(),
+            keep_comments: None,
+        }
+    }
+}
+
+impl Solver {
+    /// Every compiled-in backend with its default options.
+    pub fn all() -> Vec<Solver> {
+        vec![
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(Default::default()),
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(Default::default()),
+        ]
+    }
+
+    /// Subcommand name of the backend.
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(_) => "minisat",
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(_) => "glucose",
+        }
+    }
+
+    /// Backend `name` with options given as `(long flag, value)` pairs,
+    /// parsed and validated like the subcommand's command line.
+    pub fn from_args(name: &str, args: &[(String, String)]) -> anyhow::Result<Solver> {
+        match name {
+            #[cfg(feature = "minisat")]
+            "minisat" => Ok(Solver::Minisat(core::parse_args(args)?)),
+            #[cfg(feature = "glucose")]
+            "glucose" => Ok(Solver::Glucose(core::parse_args(args)?)),
+            _ => name.parse().map_err(anyhow::Error::msg),
+        }
+    }
+
+    /// The backend's options by name, as the result cache keys them.
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => options.fields(),
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => options.fields(),
+        }
+    }
+
+    /// The backend's options as command line options by their long name,
+    /// for a child process.
+    pub(crate) fn flags(&self) -> Vec<(String, String)> {
+        use clap::{Args, Command};
+
+        let command = match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(_) => minisat::Options::augment_args(Command::new("")),
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(_) => glucose::Options::augment_args(Command::new("")),
+        };
+        self.fields()
+            .into_iter()
+            .map(|(id, value)| {
+                let arg = command.get_arguments().find(|arg| arg.get_id() == id);
+                let name = arg.and_then(|arg| arg.get_long()).unwrap_or(id);
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+
+    /// Version of the embedded upstream solver.
+    pub fn version(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(_) => "2.2.0",
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(_) => "4.2.1",
+        }
+    }
+
+    /// The backend with the options of copy `copy` of a `race_seeds` race.
+    pub fn reseeded(&self, copy: usize) -> Solver {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => Solver::Minisat(options.reseeded(copy)),
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => Solver::Glucose(options.reseeded(copy)),
+        }
+    }
+
+    /// The backend with `preset` applied over its options.
+    pub fn preset(&self, preset: Preset) -> Solver {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => Solver::Minisat(options.preset(preset)),
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => Solver::Glucose(options.preset(preset)),
+        }
+    }
+
+    /// Upstream repository of the embedded solver.
+    pub fn upstream(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(_) => "https://github.com/niklasso/minisat",
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(_) => "https://github.com/arminbiere/glucose",
+        }
+    }
+
+    /// Simplifies and solves `clauses` in memory, returning the status and,
+    /// if satisfiable, the model.
+    pub fn solve_clauses(&self, clauses: &[Vec<i32>]) -> (Status, Option<Vec<i32>>) {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => {
+                core::solve_clauses::<MinisatSolver>(clauses, true, || options.set_opt(0))
+            }
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => {
+                core::solve_clauses::<GlucoseSolver>(clauses, true, || options.set_opt(0))
+            }
+        }
+    }
+
+    /// Minimizes `objectives` over `problem` lexicographically by iterative
+    /// SAT calls, calling `improved` with the index of the objective and
+    /// every cheaper model. An error if the coefficients of an objective sum
+    /// beyond `i64::MAX`.
+    pub fn minimize(
+        &self,
+        problem: &Problem,
+        objectives: &[Linear],
+        improved: impl FnMut(usize, i64, &[i32]),
+    ) -> anyhow::Result<Vec<Outcome>> {
+        for objective in objectives {
+            opb::weight(objective)?;
+        }
+        Ok(match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => {
+                Optimizer::<MinisatSolver>::new(problem, || options.set_opt(0))
+                    .minimize_lex(objectives, improved)
+            }
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => {
+                Optimizer::<GlucoseSolver>::new(problem, || options.set_opt(0))
+                    .minimize_lex(objectives, improved)
+            }
+        })
+    }
+
+    /// Applies the WCNF `deltas` to one solver holding `problem`, see
+    /// [`optimize::execute_deltas`].
+    pub fn run_deltas(
+        &self,
+        problem: &Problem,
+        deltas: &[DeltaLine],
+        solved: impl FnMut(Option<&[i32]>, bool) -> std::io::Result<()>,
+    ) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => {
+                optimize::execute_deltas::<MinisatSolver>(problem, deltas, || options.set_opt(0), solved)
+            }
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => {
+                optimize::execute_deltas::<GlucoseSolver>(problem, deltas, || options.set_opt(0), solved)
+            }
+        }
+    }
+
+    /// Executes the commands of a script against one solver, writing its
+    /// solves to `json` if given.
+    pub fn run_script(&self, lines: &[Line], json: Option<&Path>) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "minisat")]
+            Solver::Minisat(options) => {
+                script::execute::<MinisatSolver>(lines, json, || options.set_opt(0))
+            }
+            #[cfg(feature = "glucose")]
+            Solver::Glucose(options) => {
+                script::execute::<GlucoseSolver>(lines, json, || options.set_opt(0))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Solver {
+    type Err = String;
+
+    /// Looks up a compiled-in backend by name, with default options.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let all = Solver::all();
+        let names = all.iter().map(Solver::name).collect::<Vec<_>>().join(", ");
+        all.into_iter()
+            .find(|s| s.name() == name)
+            .ok_or_else(|| format!("unknown solver `{name}`, expected one of: {names}"))
+    }
+}
+
+/// Configuration of a `schedule` stage, applied over the backend's options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// The options as given.
+    Default,
+    /// No variable elimination and frequent restarts, to answer easy
+    /// instances quickly.
+    Agile,
+    /// Asymmetric branching and longer restart intervals, for hard ones.
+    Thorough,
+}
+
+impl Preset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Agile => "agile",
+            Preset::Thorough => "thorough",
+        }
+    }
+}
+
+/// A stage of a `schedule`: a preset and how long it runs alone before the
+/// next stage starts, `None` for the rest of the run.
+#[derive(Clone, Copy, Debug)]
+pub struct Stage {
+    pub preset: Preset,
+    pub budget: Option<Duration>,
+}
+
+/// Parses `preset:NAME@DURATION` or `preset:NAME@rest`, e.g. `preset:agile@30s`.
+impl std::str::FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (config, budget) = s
+            .split_once('@')
+            .ok_or_else(|| format!("`{s}` is not of the form `preset:NAME@DURATION`"))?;
+        let preset = match config.strip_prefix("preset:") {
+            Some("default") => Preset::Default,
+            Some("agile") => Preset::Agile,
+            Some("thorough") => Preset::Thorough,
+            _ => {
+                return Err(format!(
+                    "unknown configuration `{config}`, expected preset:default, preset:agile or preset:thorough"
+                ));
+            }
+        };
+        let budget = match budget {
+            "rest" => None,
+            budget => Some(core::parse_duration(budget)?),
+        };
+        Ok(Stage { preset, budget })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Satisfiable,
+    Unsatisfiable,
+    Unknown,
+}
+
+impl From<RawStatus> for Status {
+    fn from(status: RawStatus) -> Self {
+        match status {
+            RawStatus::Satisfiable => Status::Satisfiable,
+            RawStatus::Unsatisfiable => Status::Unsatisfiable,
+            RawStatus::Unknown => Status::Unknown,
+        }
+    }
+}
+
+impl Status {
+    /// The word used on the `s` line of the SAT competition output format.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Satisfiable => "SATISFIABLE",
+            Status::Unsatisfiable => "UNSATISFIABLE",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Process exit code the CLI uses for this status.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Satisfiable => 0,
+            Status::Unsatisfiable => 20,
+            Status::Unknown => 30,
+        }
+    }
+
+    /// Exit code of the SAT competition format.
+    pub fn competition_exit_code(self) -> i32 {
+        match self {
+            Status::Satisfiable => 10,
+            Status::Unsatisfiable => 20,
+            Status::Unknown => 0,
+        }
+    }
+}
+
+/// Why a run ended without an answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Wall-clock limit.
+    Timeout,
+    /// CPU time limit.
+    CpuLimit,
+    /// Memory limit.
+    Memout,
+    /// The solver gave up within its own budget.
+    Budget,
+    /// SIGINT, SIGTERM or SIGHUP.
+    Interrupted,
+}
+
+impl Reason {
+    /// The word reported after `UNKNOWN`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Timeout => "TIMEOUT",
+            Reason::CpuLimit => "CPU-LIMIT",
+            Reason::Memout => "MEMOUT",
+            Reason::Budget => "BUDGET",
+            Reason::Interrupted => "INTERRUPTED",
+        }
+    }
+
+    /// Process exit code the CLI uses instead of the 30 of an unexplained
+    /// UNKNOWN.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Reason::Timeout => 31,
+            Reason::CpuLimit => 32,
+            Reason::Memout => 33,
+            Reason::Budget => 34,
+            Reason::Interrupted => 35,
+        }
+    }
+}
+
+/// Number of variables and clauses of a formula; only the variables that
+/// occur in a clause count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Size {
+    pub vars: usize,
+    pub clauses: usize,
+}
+
+impl Size {
+    pub fn of(clauses: &[Vec<i32>]) -> Self {
+        let mut vars: Vec<_> = clauses.iter().flatten().map(|lit| lit.abs()).collect();
+        vars.sort_unstable();
+        vars.dedup();
+        Size {
+            vars: vars.len(),
+            clauses: clauses.len(),
+        }
+    }
+
+    /// Percentages of the variables and clauses of `before` that are gone.
+    pub fn reduction(self, before: Size) -> (f64, f64) {
+        let percent = |now: usize, was: usize| match was {
+            0 => 0.0,
+            was => 100.0 * (was as f64 - now as f64) / was as f64,
+        };
+        (
+            percent(self.vars, before.vars),
+            percent(self.clauses, before.clauses),
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub input_sha256: String,
+    /// Size of the input as parsed, before any added constraint.
+    pub parsed_size: Option<Size>,
+    /// Size of the formula handed to the backend, if `pipeline` or
+    /// `equivalences` simplified it; the backend's own elimination happens
+    /// out of sight.
+    pub simplified_size: Option<Size>,
+    /// Resident memory in bytes after parsing.
+    pub parse_memory: Option<u64>,
+    /// Resident memory in bytes after simplification.
+    pub simplify_memory: Option<u64>,
+    pub parse_time: Duration,
+    pub simplify_time: Duration,
+    pub solve_time: Option<Duration>,
+    /// CPU time of the whole run.
+    pub total_time: Duration,
+    /// Wall-clock time of the whole run.
+    pub run_time: Duration,
+    /// Resident memory of the process in bytes, if it could be determined.
+    pub memory: Option<u64>,
+    /// CPU time of the child processes, summed over all of them; `None`
+    /// without any, or where it is not collected.
+    pub child_time: Option<Duration>,
+    /// Peak resident memory in bytes of the largest child process.
+    pub child_memory: Option<u64>,
+}
+
+impl Stats {
+    /// Zeroes the times and drops the memory, the statistics that vary from
+    /// one run to the next.
+    pub fn clear_measurements(&mut self) {
+        self.parse_time = Duration::ZERO;
+        self.simplify_time = Duration::ZERO;
+        self.solve_time = self.solve_time.map(|_| Duration::ZERO);
+        self.total_time = Duration::ZERO;
+        self.run_time = Duration::ZERO;
+        self.memory = None;
+        self.child_time = self.child_time.map(|_| Duration::ZERO);
+        self.child_memory = None;
+        self.parse_memory = None;
+        self.simplify_memory = None;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SolveResult {
+    pub status: Status,
+    /// Satisfying assignment as DIMACS literals, one per variable; with
+    /// `partial_model` only the literals the clauses need.
+    pub model: Option<Vec<i32>>,
+    pub stats: Stats,
+    /// Non-fatal problems, such as limits the platform cannot enforce.
+    pub warnings: Vec<String>,
+    /// Class of the formula if a fast path solved it instead of the backend.
+    pub fast_path: Option<&'static str>,
+    /// Number of components solved separately, if `components` split the
+    /// formula.
+    pub components: Option<usize>,
+    /// Values of the named variables of the model, by name, if the input
+    /// or `symbols` names any.
+    pub named: Vec<(String, bool)>,
+    /// Comment lines of the input selected by `keep_comments`.
+    pub comments: Vec<String>,
+    /// Whether the model is the only one, if `unique_check` asked and the
+    /// second solve was conclusive.
+    pub unique: Option<bool>,
+    /// Statistics of every pass of `pipeline` that ran.
+    pub passes: Vec<PassStats>,
+    /// Why the status is UNKNOWN, if known.
+    pub reason: Option<Reason>,
+    /// Copy of a `race_seeds` race whose answer this is.
+    pub race_winner: Option<usize>,
+    /// Stage of a `schedule` whose answer this is, from 0.
+    pub stage: Option<usize>,
+    /// Whether the run stopped after parsing, as `parse_only` asks; the
+    /// status is then UNKNOWN.
+    pub parse_only: bool,
+    /// Measurements of every run, if `repeat` ran it more than once; the
+    /// rest of the result is that of the last run.
+    pub repetitions: Option<Repetitions>,
+    /// Whether the answer came from `result_cache` instead of a solve.
+    pub cached: bool,
+}
+
+/// Measurements of the runs of a `repeat` benchmark, in run order.
+#[derive(Clone, Debug)]
+pub struct Repetitions {
+    pub parse_times: Vec<Duration>,
+    pub solve_times: Vec<Duration>,
+    /// Resident memory after each run, where it could be determined.
+    pub memory: Vec<u64>,
+    /// Whether every run reached the same status.
+    pub consistent: bool,
+}
+
+/// Minimum, median, mean and standard deviation of a sample.
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Summary {
+    /// The summary of `values`, `None` if there are none.
+    pub fn of(values: impl IntoIterator<Item = f64>) -> Option<Summary> {
+        let mut values: Vec<f64> = values.into_iter().collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let n = values.len();
+        let median = if n % 2 == 1 {
+            values[n / 2]
+        } else {
+            (values[n / 2 - 1] + values[n / 2]) / 2.0
+        };
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64;
+        Some(Summary {
+            min: values[0],
+            median,
+            mean,
+            stddev: variance.sqrt(),
+        })
+    }
+}
+
+impl SolveResult {
+    /// Process exit code the CLI uses for this result: 0 for a parse-only
+    /// run that read its input.
+    pub fn exit_code(&self) -> i32 {
+        if self.parse_only {
+            return 0;
+        }
+        match self.reason {
+            Some(reason) if self.status == Status::Unknown => reason.exit_code(),
+            _ => self.status.exit_code(),
+        }
+    }
+}
+
+/// Runs `request` to completion. Races and schedules are refused, see
+/// [`SolveRequest::race_seeds`].
+pub fn solve(request: &SolveRequest) -> anyhow::Result<SolveResult> {
+    check_library(request)?;
+    solve_with(request, None, &Mutex::new(Stat::silent()))
+}
+
+/// Like [`solve`], but reads the (possibly compressed) CNF from `input`
+/// instead of `request.input`.
+pub fn solve_reader(request: &SolveRequest, input: &mut dyn Read) -> anyhow::Result<SolveResult> {
+    check_library(request)?;
+    solve_with(request, Some(input), &Mutex::new(Stat::silent()))
+}
+
+/// Refuses what the caller's process cannot run: the copies of a race and
+/// the stages of a schedule run as children of the `satgalaxy` executable.
+fn check_library(request: &SolveRequest) -> anyhow::Result<()> {
+    if request.race_seeds > 1 {
+        return Err(anyhow::anyhow!("seed races are only supported on the command line"));
+    }
+    if !request.schedule.is_empty() {
+        return Err(anyhow::anyhow!("schedules are only supported on the command line"));
+    }
+    Ok(())
+}
+
+/// Recording progress in `stat` as it goes so it can be reported if the run
+/// is interrupted.
+pub(crate) fn solve_with(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+) -> anyhow::Result<SolveResult> {
+    if !request.schedule.is_empty() {
+        return schedule(request, input, stat);
+    }
+    if request.race_seeds > 1 {
+        return race(request, input, stat);
+    }
+    if request.repeat > 1 {
+        return repeat(request, input, stat);
+    }
+    if let Some(dir) = &request.result_cache {
+        return cache::solve(request, input, stat, dir);
+    }
+    let verbosity = request.verbosity;
+    match &request.solver {
+        #[cfg(feature = "minisat")]
+        Solver::Minisat(options) => {
+            core::solve::<MinisatSolver>(request, input, stat, || options.set_opt(verbosity))
+        }
+        #[cfg(feature = "glucose")]
+        Solver::Glucose(options) => {
+            core::solve::<GlucoseSolver>(request, input, stat, || options.set_opt(verbosity))
+        }
+    }
+}
+
+/// Runs `request.race_seeds` copies of the backend in parallel, each in a
+/// child process with its own seed, and returns the first answer; the other
+/// copies are killed then. The children read the input from a file, see
+/// [`isolate::ChildInput`].
+fn race(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+) -> anyhow::Result<SolveResult> {
+    let input = isolate::ChildInput::of(request, input)?;
+    let stop = AtomicBool::new(false);
+    // The CPU time limit is that of all copies together.
+    let cpu_lim = request.cpu_limit.div_ceil(request.race_seeds as u64);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for copy in 0..request.race_seeds {
+            let request = SolveRequest {
+                solver: request.solver.reseeded(copy),
+                race_seeds: 1,
+                ..request.clone()
+            };
+            let (input, stop, sender) = (&input, &stop, sender.clone());
+            scope.spawn(move || {
+                let stat = Mutex::new(Stat::silent());
+                let isolated = isolate::Isolated {
+                    cpu_lim,
+                    ..isolate::Isolated::of(&request, None)
+                };
+                let result = isolated.solve_request(&request, input, stop, &stat);
+                let stat = stat.into_inner().unwrap_or_else(|e| e.into_inner());
+                let _ = sender.send((copy, result, stat));
+            });
+        }
+        drop(sender);
+        // Copies that end UNKNOWN or fail only count if none answers.
+        let mut fallback = None;
+        for (copy, result, copy_stat) in &receiver {
+            let answered = matches!(&result, Ok(result) if result.status != Status::Unknown);
+            if answered || fallback.is_none() {
+                stat.lock().unwrap().adopt(&copy_stat);
+                fallback = Some(result.map(|result| SolveResult {
+                    race_winner: Some(copy),
+                    ..result
+                }));
+            }
+            if answered {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        fallback.expect("at least one copy ran")
+    })
+}
+
+/// Runs the stages of `request.schedule` one after another, each in a child
+/// process killed when its budget runs out, and returns the first answer.
+/// The children read the input from a file, see [`isolate::ChildInput`].
+fn schedule(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+) -> anyhow::Result<SolveResult> {
+    let stages = &request.schedule;
+    if stages[..stages.len() - 1].iter().any(|stage| stage.budget.is_none()) {
+        return Err(anyhow::anyhow!("only the last stage of a schedule can run for the rest"));
+    }
+    let input = isolate::ChildInput::of(request, input)?;
+    let never = AtomicBool::new(false);
+    // Stages that end UNKNOWN or fail only count if none answers, the last
+    // of them then.
+    let mut last = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let request = SolveRequest {
+            solver: request.solver.preset(stage.preset),
+            schedule: Vec::new(),
+            progress: false,
+            ..request.clone()
+        };
+        let isolated = isolate::Isolated::of(&request, stage.budget);
+        let result = isolated.solve_request(&request, &input, &never, stat);
+        let result = result.map(|result| SolveResult {
+            stage: Some(i),
+            ..result
+        });
+        if matches!(&result, Ok(result) if result.status != Status::Unknown) {
+            return result;
+        }
+        last = Some(result);
+    }
+    last.expect("a schedule has a stage")
+}
+
+/// Runs `request` `request.repeat` times one after another and returns the
+/// last result with the measurements of all of them. Standard input is read
+/// once and replayed.
+fn repeat(
+    request: &SolveRequest,
+    input: Option<&mut dyn Read>,
+    stat: &Mutex<Stat>,
+) -> anyhow::Result<SolveResult> {
+    let data = match input {
+        Some(input) => {
+            let mut data = Vec::new();
+            input.read_to_end(&mut data)?;
+            Some(data)
+        }
+        None if request.input.is_none() => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            Some(data)
+        }
+        None => None,
+    };
+    let measured = stat.lock().unwrap().measured;
+    let mut repetitions = Repetitions {
+        parse_times: Vec::new(),
+        solve_times: Vec::new(),
+        memory: Vec::new(),
+        consistent: true,
+    };
+    let mut last: Option<SolveResult> = None;
+    for run in 0..request.repeat {
+        let request = SolveRequest {
+            solver: if request.repeat_seeds {
+                request.solver.reseeded(run)
+            } else {
+                request.solver.clone()
+            },
+            repeat: 1,
+            progress: request.progress && run == 0,
+            ..request.clone()
+        };
+        let run_stat = Mutex::new(Stat::silent());
+        run_stat.lock().unwrap().measured = measured;
+        let result = match &data {
+            Some(data) => solve_with(&request, Some(&mut data.as_slice()), &run_stat)?,
+            None => solve_with(&request, None, &run_stat)?,
+        };
+        stat.lock().unwrap().adopt(&run_stat.lock().unwrap());
+        repetitions.parse_times.push(result.stats.parse_time);
+        repetitions.solve_times.push(result.stats.solve_time.unwrap_or_default());
+        repetitions.memory.extend(result.stats.memory);
+        if last.as_ref().is_some_and(|last| last.status != result.status) {
+            repetitions.consistent = false;
+        }
+        last = Some(result);
+    }
+    Ok(SolveResult {
+        repetitions: Some(repetitions),
+        ..last.expect("at least one run")
+    })
+}
@@ -1,13 +1,85 @@
+#[cfg(not(target_family = "wasm"))]
 #[global_allocator]
-static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+static GLOBAL: Allocator = Allocator(mimalloc::MiMalloc);
+
+/// mimalloc, reporting a failed allocation (usually `--mem-lim`) as MEMOUT
+/// instead of aborting. Allocations inside the solvers' C++ code do not go
+/// through it.
+#[cfg(not(target_family = "wasm"))]
+struct Allocator(mimalloc::MiMalloc);
+
+/// Memory set aside at start and given back when an allocation fails, so
+/// that the MEMOUT exit has room to remove the unfinished outputs.
+#[cfg(not(target_family = "wasm"))]
+static RESERVE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+
+#[cfg(not(target_family = "wasm"))]
+const RESERVE_LAYOUT: Layout = match Layout::from_size_align(4 << 20, 16) {
+    Ok(layout) => layout,
+    Err(_) => panic!("invalid reserve layout"),
+};
+
+#[cfg(not(target_family = "wasm"))]
+impl Allocator {
+    fn reserve(&self) {
+        // SAFETY: the layout has a non-zero size.
+        RESERVE.store(unsafe { self.0.alloc(RESERVE_LAYOUT) }, Ordering::SeqCst);
+    }
+
+    fn check(&self, ptr: *mut u8) -> *mut u8 {
+        if ptr.is_null() {
+            let reserve = RESERVE.swap(ptr::null_mut(), Ordering::SeqCst);
+            if !reserve.is_null() {
+                // SAFETY: allocated by `reserve` with this layout, and taken
+                // out of `RESERVE` before it is freed, so only once.
+                unsafe { self.0.dealloc(reserve, RESERVE_LAYOUT) };
+            }
+            satgalaxy_cli::core::exit_memout();
+        }
+        ptr
+    }
+}
+
+// SAFETY: every call is forwarded to mimalloc unchanged; only its null
+// results are diverted.
+#[cfg(not(target_family = "wasm"))]
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.check(unsafe { self.0.alloc(layout) })
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.check(unsafe { self.0.alloc_zeroed(layout) })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.check(unsafe { self.0.realloc(ptr, layout, new_size) })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+}
 
-mod core;
-mod glucose;
-mod minisat;
-mod utils;
 use std::process::exit;
+#[cfg(not(target_family = "wasm"))]
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+#[cfg(all(feature = "fetch", feature = "compression"))]
+use satgalaxy_cli::benchmarks;
+#[cfg(feature = "glucose")]
+use satgalaxy_cli::glucose;
+#[cfg(feature = "minisat")]
+use satgalaxy_cli::minisat;
+use satgalaxy_cli::{
+    analyze, cofactor, convert, core::resolved_options, distribute, exec, format, fuzz, optimize, pipeline, queue,
+    report, script, selftest, serve, shrink, split, teach, version, watch,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -19,16 +91,93 @@ struct Cli {
 enum Commands {
     /// Use minisat(2.2.0) solver
     /// https://github.com/niklasso/minisat
+    #[cfg(feature = "minisat")]
     Minisat(minisat::Arg),
     /// Use glucose(4.2.1) solver
     /// https://github.com/arminbiere/glucose
+    #[cfg(feature = "glucose")]
     Glucose(glucose::Arg),
+    /// Download a named benchmark family (SATLIB, SAT Competition) and verify it
+    #[cfg(all(feature = "fetch", feature = "compression"))]
+    Fetch(benchmarks::Arg),
+    /// Analyze the structure of a CNF without solving it
+    Analyze(analyze::Arg),
+    /// Simplify a CNF by preprocessing passes in a given order, with statistics per pass
+    Pipeline(pipeline::Arg),
+    /// Condition a CNF on a partial assignment, simplified by unit propagation
+    Cofactor(cofactor::Arg),
+    /// Split a CNF into independent subproblem files, by cubes over variables or by components
+    Split(split::Arg),
+    /// Convert an instance between CNF, OPB and WCNF
+    Convert(convert::Arg),
+    /// Format a CNF for version control: normalized whitespace, wrapped clauses, recomputed header
+    Fmt(format::Arg),
+    /// Minimize a linear objective over an OPB or CNF instance by iterative SAT calls
+    Optimize(optimize::Arg),
+    /// Run a script of clause additions, assumption frames and solves against one incremental solver
+    Script(script::Arg),
+    /// Run an external solver under limits, verify its answer and report it like the backends
+    Exec(exec::Arg),
+    /// Check every compiled-in backend against a few embedded instances
+    Selftest(selftest::Arg),
+    /// Cross-check the backends on random small CNFs
+    Fuzz(fuzz::Arg),
+    /// Minimize a CNF while a property (status, wrong model, exit code) still holds
+    Shrink(shrink::Arg),
+    /// Solve a small CNF with a textbook CDCL solver, exporting the implication graph of every conflict
+    Teach(teach::Arg),
+    /// Compare configurations: cactus and scatter plot data from --json results
+    Report(report::Arg),
+    /// Serve solve requests over HTTP, with Prometheus metrics at /metrics
+    Serve(serve::Arg),
+    /// Hand out the jobs of one or more CNFs to `work` processes over the network
+    Coordinate(distribute::CoordinateArg),
+    /// Solve jobs from a `coordinate` process until it has none left
+    Work(distribute::WorkArg),
+    /// Solve jobs pulled from a Redis queue under limits and push back the results
+    Worker(queue::Arg),
+    /// Solve every CNF dropped into a spool directory, with results next to them
+    Watch(watch::Arg),
+    /// Report the versions of the CLI and the embedded solvers, and the build features
+    Version(version::Arg),
 }
 fn main() {
-    let cli = Cli::parse();
+    #[cfg(not(target_family = "wasm"))]
+    GLOBAL.reserve();
+    let command = Cli::command();
+    let matches = command.clone().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let options = matches
+        .subcommand()
+        .and_then(|(name, sub)| Some(resolved_options(command.find_subcommand(name)?, sub)))
+        .unwrap_or_default();
     let ret: Result<i32, anyhow::Error> = match cli.command {
-        Commands::Minisat(arg) => arg.run(),
-        Commands::Glucose(arg) => arg.run(),
+        #[cfg(feature = "minisat")]
+        Commands::Minisat(arg) => arg.run(&options),
+        #[cfg(feature = "glucose")]
+        Commands::Glucose(arg) => arg.run(&options),
+        #[cfg(all(feature = "fetch", feature = "compression"))]
+        Commands::Fetch(arg) => arg.run(),
+        Commands::Analyze(arg) => arg.run(),
+        Commands::Pipeline(arg) => arg.run(),
+        Commands::Cofactor(arg) => arg.run(),
+        Commands::Split(arg) => arg.run(),
+        Commands::Convert(arg) => arg.run(),
+        Commands::Fmt(arg) => arg.run(),
+        Commands::Optimize(arg) => arg.run(),
+        Commands::Script(arg) => arg.run(),
+        Commands::Exec(arg) => arg.run(&options),
+        Commands::Selftest(arg) => arg.run(),
+        Commands::Fuzz(arg) => arg.run(),
+        Commands::Shrink(arg) => arg.run(),
+        Commands::Teach(arg) => arg.run(),
+        Commands::Report(arg) => arg.run(),
+        Commands::Serve(arg) => arg.run(),
+        Commands::Coordinate(arg) => arg.run(),
+        Commands::Work(arg) => arg.run(),
+        Commands::Worker(arg) => arg.run(),
+        Commands::Watch(arg) => arg.run(),
+        Commands::Version(arg) => arg.run(),
     };
 
     match ret {
@@ -1,29 +1,27 @@
-use std::{
-    io::Write,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-};
-
 use clap::Args;
-use satgalaxy::{
-    parser::read_dimacs_from_reader,
-    solver::{self, MinisatSolver},
-};
+use satgalaxy::solver::{MinisatSolver, RawStatus};
 use validator::Validate;
 
 use crate::{
-    core::{Stat, Writer,parse_path, SmartPath, SmartReader}, utils::{self}
+    Preset, Solver,
+    core::{self, Backend, CommonArg},
 };
 
 #[derive(Args, Validate)]
 pub struct Arg {
-    ///Input source: local file (.cnf, .xz, .tar.gz), URL, default for stdin
-    #[arg(value_name = "INPUT",value_parser = parse_path)]
-    input: Option<SmartPath>,
-    #[arg(value_name = "OUTPUT")]
-    output: Option<PathBuf>,
+    #[command(flatten)]
+    #[validate(nested)]
+    common: CommonArg,
+    #[command(flatten)]
+    #[validate(nested)]
+    options: Options,
+}
+
+/// Minisat solver options.
+#[derive(Args, Validate, Clone, Debug)]
+pub struct Options {
     /// The variable activity decay factor
-    #[arg(long, value_name = "VAR_DECAY", default_value_t = 0.95, group = "core")]
+    #[arg(long, value_name = "VAR_DECAY", default_value_t = 0.95, help_heading = "Core")]
     #[validate(range(
         exclusive_min = 0.0,
         exclusive_max = 1.0,
@@ -31,24 +29,24 @@ pub struct Arg {
     ))]
     var_decay: f64,
     /// The clause activity decay factor
-    #[arg(long = "cla-decay", default_value_t = 0.999, group = "core")]
+    #[arg(long = "cla-decay", default_value_t = 0.999, help_heading = "Core")]
     #[validate(range(
         exclusive_min = 0.0,
         exclusive_max = 1.0,
         message = "Clause decay must be in (0, 1)"
     ))]
     clause_decay: f64,
-    #[arg(long = "rnd-freq", default_value_t = 0.0, group = "core")]
+    #[arg(long = "rnd-freq", default_value_t = 0.0, help_heading = "Core")]
     #[validate(range(min = 0.0, max = 1.0))]
     /// The frequency with which the decision heuristic tries to choose a random variable
     random_var_freq: f64,
 
-    #[arg(long = "rnd-seed", default_value_t = 91648253.0, group = "core")]
+    #[arg(long = "rnd-seed", default_value_t = 91648253.0, help_heading = "Core")]
     #[validate(range(exclusive_min = 0.0, message = "Random seed must be positive"))]
     /// Used by the random variable selection
     random_seed: f64,
 
-    #[arg(long, default_value_t = 2, group = "core")]
+    #[arg(long, default_value_t = 2, help_heading = "Core")]
     #[validate(range(
         min = 0,
         max = 2,
@@ -56,106 +54,117 @@ pub struct Arg {
     ))]
     /// Controls conflict clause minimization (0=none, 1=basic, 2=deep)
     ccmin_mode: i32,
-    #[arg(long, default_value_t = 2, group = "core")]
+    #[arg(long, default_value_t = 2, help_heading = "Core")]
     #[validate(range(min = 0, max = 2, message = "Phase saving level must be 0, 1, or 2"))]
     /// Controls the level of phase saving (0=none, 1=limited, 2=full)
     phase_saving: i32,
-    #[arg(long = "rnd-init", num_args(0..=1),default_value_t = false, group = "core")]
+    #[arg(long = "rnd-init", num_args(0..=1),default_value_t = false, help_heading = "Core")]
     /// Randomize the initial activity
     rnd_init_act: bool,
-    #[arg(long = "luby", num_args(0..=1), default_value_t = true, group = "core")]
+    #[arg(long = "luby", num_args(0..=1), default_value_t = true, help_heading = "Core")]
     /// Use the Luby restart sequence
     luby_restart: bool,
-    #[arg(long = "rfirst", default_value_t = 100, group = "core")]
+    #[arg(long = "rfirst", default_value_t = 100, help_heading = "Core")]
     /// The base restart interval
     restart_first: i32,
-    #[arg(long = "rinc", default_value_t = 2.0, group = "core")]
+    #[arg(long = "rinc", default_value_t = 2.0, help_heading = "Core")]
     #[validate(range(
         min = 1.0,
         message = "Restart interval increase factor must be at least 1.0"
     ))]
     /// Restart interval increase factor
     restart_inc: f64,
-    #[arg(long = "gc-frac", default_value_t = 0.2, group = "core")]
+    #[arg(long = "gc-frac", default_value_t = 0.2, help_heading = "Core")]
     #[validate(range(
         exclusive_min = 0.0,
         message = "Garbage collection fraction must be positive"
     ))]
     /// The fraction of wasted memory allowed before a garbage collection is triggered
     garbage_frac: f64,
-    #[arg(long = "min-learnts", default_value_t = 0, group = "core")]
+    #[arg(long = "min-learnts", default_value_t = 0, help_heading = "Core")]
     #[validate(range(min = 0, message = "Min learnts lim must be at least 0"))]
     /// Minimum learnt clause limit
     min_learnts_lim: i32,
 
     // simp
-    #[arg(long = "asymm", num_args(0..=1),default_value_t = false, group = "simp")]
+    #[arg(long = "asymm", num_args(0..=1),default_value_t = false, help_heading = "Simplification")]
     /// Shrink clauses by asymmetric branching.
     use_asymm: bool,
 
-    #[arg(long = "rcheck",num_args(0..=1), default_value_t = false, group = "simp")]
+    #[arg(long = "rcheck",num_args(0..=1), default_value_t = false, help_heading = "Simplification")]
     /// Check if a clause is already implied. (costly)
     use_rcheck: bool,
-    #[arg(long = "elim", num_args(0..=1), default_value_t = true, group = "simp")]
+    #[arg(long = "elim", num_args(0..=1), default_value_t = true, help_heading = "Simplification")]
     /// Perform variable elimination.
     use_elim: bool,
 
-    #[arg(long = "grow", default_value_t = 0, group = "simp")]
+    #[arg(long = "grow", default_value_t = 0, help_heading = "Simplification")]
     #[validate(range(min = 0, message = "Grow must be at least 0"))]
     /// Allow a variable elimination step to grow by a number of clauses.
     grow: i32,
 
-    #[arg(long = "cl-lim", default_value_t = 20, group = "simp")]
-    #[validate(range(min = -1))]
+    #[arg(long = "cl-lim", default_value_t = 20, help_heading = "Simplification")]
+    #[validate(range(min = -1, message = "Clause limit must be -1 or a positive integer"))]
     /// Variables are not eliminated if it produces a resolvent with a length above this limit. -1 means no limit
     clause_lim: i32,
 
-    #[arg(long = "sub-lim", default_value_t = 1000, group = "simp")]
+    #[arg(long = "sub-lim", default_value_t = 1000, help_heading = "Simplification")]
     #[validate(range(min = -1, message = "Subsumption limit must be -1 or a positive integer"))]
     /// Do not check if subsumption against a clause larger than this. -1 means no limit.
     subsumption_lim: i32,
 
-    #[arg(long = "simp-gc-frac", default_value_t = 0.5, group = "simp")]
+    #[arg(long = "simp-gc-frac", default_value_t = 0.5, help_heading = "Simplification")]
     #[validate(range(
         exclusive_min = 0.0,
         message = "Simplification garbage collection fraction must be positive"
     ))]
     /// The fraction of wasted memory allowed before a garbage collection is triggered during simplification.
     simp_garbage_frac: f64,
+}
 
-    // MAIN
-    #[arg(long = "verb", default_value_t = 0, group = "main")]
-    #[validate(range(min = 0, max = 2, message = "Verbosity level must be 0, 1, or 2"))]
-    /// Verbosity level (0=silent, 1=some, 2=more).
-    verb: i32,
-
-    #[arg(long = "pre", num_args(0..=1),default_value_t = true, group = "main")]
-    /// Completely turn on/off any preprocessing.
-    pre: bool,
-    #[arg(long = "solve",num_args(0..=1), default_value_t = true, group = "main")]
-    /// Completely turn on/off solving after preprocessing.
-    solve: bool,
-
-    // #[arg(long = "dimacs")]
-    // /// If given, stop after preprocessing and write the result to this file.
-    // dimacs: Option<String>,
-    #[arg(long = "cpu-lim", default_value_t = 0, group = "main")]
-    #[validate(range(min = 0, message = "CPU time limit must be a non-negative integer"))]
-    /// Limit on CPU time allowed in seconds.
-    cpu_lim: u32,
-
-    #[arg(long = "mem-lim", default_value_t = 0, group = "main")]
-    #[validate(range(min = 0, message = "Memory limit must be a non-negative integer"))]
-    /// Limit on memory usage in megabytes.
-    mem_lim: u32,
-
-    #[arg(long = "strictp", default_value_t = false, group = "main")]
-    /// Validate DIMACS header during parsing.
-    strictp: bool,
+impl Default for Options {
+    fn default() -> Self {
+        core::defaults()
+    }
 }
 
-impl Arg {
-    fn set_opt(&self) {
+impl Options {
+    /// The options of copy `copy` of a `--race-seeds` race: the first runs
+    /// as configured, the others with their own seed and randomized initial
+    /// activities, so that the seed makes a difference.
+    pub fn reseeded(&self, copy: usize) -> Self {
+        if copy == 0 {
+            return self.clone();
+        }
+        Self {
+            random_seed: self.random_seed + copy as f64,
+            rnd_init_act: true,
+            ..self.clone()
+        }
+    }
+
+    /// The options with `preset` applied: `agile` turns variable elimination
+    /// off and restarts after 50 conflicts on the Luby sequence, `thorough`
+    /// adds asymmetric branching and starts restarts at 300 conflicts.
+    pub fn preset(&self, preset: Preset) -> Self {
+        match preset {
+            Preset::Default => self.clone(),
+            Preset::Agile => Self {
+                use_elim: false,
+                luby_restart: true,
+                restart_first: 50,
+                ..self.clone()
+            },
+            Preset::Thorough => Self {
+                use_asymm: true,
+                restart_first: 300,
+                ..self.clone()
+            },
+        }
+    }
+
+    /// Installs the options for solvers constructed afterwards.
+    pub fn set_opt(&self, verbosity: i32) {
         MinisatSolver::set_opt_var_decay(self.var_decay);
         MinisatSolver::set_opt_clause_decay(self.clause_decay);
         MinisatSolver::set_opt_random_var_freq(self.random_var_freq);
@@ -175,77 +184,62 @@ impl Arg {
         MinisatSolver::set_opt_clause_lim(self.clause_lim);
         MinisatSolver::set_opt_subsumption_lim(self.subsumption_lim);
         MinisatSolver::set_opt_simp_garbage_frac(self.simp_garbage_frac);
-        MinisatSolver::set_opt_verbosity(self.verb);
+        MinisatSolver::set_opt_verbosity(verbosity);
+    }
+
+    /// Every option by name, as the result cache keys them.
+    pub(crate) fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("var_decay", self.var_decay.to_string()),
+            ("clause_decay", self.clause_decay.to_string()),
+            ("random_var_freq", self.random_var_freq.to_string()),
+            ("random_seed", self.random_seed.to_string()),
+            ("ccmin_mode", self.ccmin_mode.to_string()),
+            ("phase_saving", self.phase_saving.to_string()),
+            ("rnd_init_act", self.rnd_init_act.to_string()),
+            ("luby_restart", self.luby_restart.to_string()),
+            ("restart_first", self.restart_first.to_string()),
+            ("restart_inc", self.restart_inc.to_string()),
+            ("garbage_frac", self.garbage_frac.to_string()),
+            ("min_learnts_lim", self.min_learnts_lim.to_string()),
+            ("use_asymm", self.use_asymm.to_string()),
+            ("use_rcheck", self.use_rcheck.to_string()),
+            ("use_elim", self.use_elim.to_string()),
+            ("grow", self.grow.to_string()),
+            ("clause_lim", self.clause_lim.to_string()),
+            ("subsumption_lim", self.subsumption_lim.to_string()),
+            ("simp_garbage_frac", self.simp_garbage_frac.to_string()),
+        ]
     }
 
-    pub fn run(&self) -> anyhow::Result<i32> {
+}
+
+impl Arg {
+    /// Solves the input; `options` are the resolved command line options
+    /// echoed into the log and the JSON result.
+    pub fn run(&self, options: &[(String, String)]) -> anyhow::Result<i32> {
         self.validate()?;
-        let stat = Arc::new(Mutex::new(Stat::new()));
-        let mut output: Writer = self.output.as_ref().into();
-
-        self.set_opt();
-        let cloned_stat = stat.clone();
-        ctrlc::set_handler(move || {
-            if let Ok(mut stat) = cloned_stat.lock() {
-                if stat.print() {
-                    println!("c Interrupted");
-                }
-                std::process::exit(30);
-            }
-        })?;
-        let mut solver = MinisatSolver::new();
-        if let Err(e) = utils::limit_time(self.cpu_lim as u64) {
-            println!("c WARNING: {}", e);
-        }
-        if let Err(e) = utils::limit_memory(self.mem_lim as u64) {
-            println!("c WARNING: {}", e);
-        }
-        if !self.pre {
-            solver.eliminate(true);
-        }
-        stat.lock().unwrap().start_log();
-        let reader:SmartReader= self.input.as_ref().try_into()?;
-        read_dimacs_from_reader(reader, self.strictp, &mut solver)?;
-        stat.lock().unwrap().parsed();
-        solver.eliminate(true);
-        stat.lock().unwrap().simplified();
-        if !solver.okay() {
-            stat.lock().unwrap().print();
-            println!("UNSATISFIABLE");
-            writeln!(output, "UNSAT")?;
-
-            return Ok(20);
-        }
-        let mut ret = Default::default();
-        if self.solve {
-            ret = solver.solve_limited(&[], true, false);
-        }
-        stat.lock().unwrap().solved();
-        stat.lock().unwrap().print();
-        match ret {
-            solver::RawStatus::Satisfiable => {
-                println!("c SATISFIABLE");
-                writeln!(output, "SAT")?;
-                (0..solver.vars()).map(|v| v + 1).try_for_each(|v| {
-                    if solver.model_value(v) {
-                        write!(output, "{} ", v)
-                    } else {
-                        write!(output, "-{} ", v)
-                    }
-                })?;
-                writeln!(output, "0")?;
-                return Ok(0);
-            }
-            solver::RawStatus::Unsatisfiable => {
-                println!("c UNSATISFIABLE");
-                writeln!(output, "UNSAT")?;
-                return Ok(20);
-            }
-            solver::RawStatus::Unknown => {
-                println!("c UNKNOWN");
-                writeln!(output, "UNKNOWN")?;
-                return Ok(30);
-            }
-        }
+        core::run(&self.common, Solver::Minisat(self.options.clone()), options)
+    }
+}
+
+impl Backend for MinisatSolver {
+    fn new() -> Self {
+        MinisatSolver::new()
+    }
+    fn eliminate(&mut self, turn_off_simp: bool) {
+        MinisatSolver::eliminate(self, turn_off_simp);
+    }
+    fn okay(&self) -> bool {
+        MinisatSolver::okay(self)
+    }
+    fn solve_limited(&mut self, assumps: &[i32]) -> RawStatus {
+        MinisatSolver::solve_limited(self, assumps, true, false)
+    }
+    fn vars(&self) -> i32 {
+        MinisatSolver::vars(self)
+    }
+    fn model_value(&self, var: i32) -> bool {
+        MinisatSolver::model_value(self, var)
     }
 }
@@ -0,0 +1,516 @@
+//! Linear pseudo-Boolean problems: OPB files, and CNFs carrying an objective.
+//!
+//! An OPB file has one statement per `;`, after `*` comment lines:
+//!
+//! ```text
+//! * #variable= 3 #constraint= 2
+//! min: +2 x1 -1 x3 ;
+//! +1 x1 +1 x2 +1 ~x3 >= 2 ;
+//! +3 x2 -2 x3 = 1 ;
+//! ```
+//!
+//! A DIMACS CNF gives its objective in a comment with the terms of an OPB
+//! `min:` line, e.g. `c objective +2 x1 -1 x3`. A weighted partial MaxSAT
+//! instance (WCNF, with `h` lines for hard clauses or in the older format
+//! with a `p wcnf` header) minimizes the weight of the falsified soft
+//! clauses: each soft clause gets a relaxation variable, which the
+//! objective weighs. Constraints are encoded into clauses as soon as the
+//! problem is read.
+use std::io::{self, Read, Write};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    card,
+    dimacs::{AsDimacs, decompress, read_dimacs},
+};
+
+/// A weighted sum of literals, as `(coefficient, literal)` terms.
+pub type Linear = Vec<(i64, i32)>;
+
+/// A problem in clausal form with the objective to minimize, if any.
+pub struct Problem {
+    pub clauses: Vec<Vec<i32>>,
+    /// Variables of the problem; auxiliary variables of the encoded
+    /// constraints come after them.
+    pub vars: i32,
+    /// Variables of the input, fewer than `vars` for a WCNF whose soft
+    /// clauses have relaxation variables.
+    pub input_vars: i32,
+    pub objective: Option<Linear>,
+    /// Whether the problem was read from a WCNF.
+    pub wcnf: bool,
+    /// Soft clauses of a WCNF with their weights.
+    pub soft: Vec<(i64, Vec<i32>)>,
+}
+
+impl Problem {
+    /// Reads an OPB file, or a (possibly compressed) DIMACS CNF if it has a
+    /// `p cnf` header, or WCNF if it has a `p wcnf` header or `h` lines.
+    pub fn read<R: Read>(reader: R) -> anyhow::Result<Self> {
+        let text = read_text(reader)?;
+        let starts = |prefix: &str| {
+            text.lines()
+                .any(|line| line.trim_start().starts_with(prefix))
+        };
+        if starts("p wcnf") || starts("h ") {
+            read_wcnf(&text)
+        } else if starts("p cnf") {
+            read_cnf(&text)
+        } else {
+            read_opb(&text)
+        }
+    }
+
+    /// Reads a (possibly compressed) DIMACS CNF and its `c objective`
+    /// comment, if any.
+    pub fn read_cnf<R: Read>(reader: R) -> anyhow::Result<Self> {
+        read_cnf(&read_text(reader)?)
+    }
+
+    /// Reads a (possibly compressed) OPB file.
+    pub fn read_opb<R: Read>(reader: R) -> anyhow::Result<Self> {
+        read_opb(&read_text(reader)?)
+    }
+
+    /// Weight of the soft clauses `model` falsifies.
+    pub fn soft_cost(&self, model: &[i32]) -> i64 {
+        let holds = |lit: i32| model.get(lit.unsigned_abs() as usize - 1) == Some(&lit);
+        self.soft
+            .iter()
+            .filter(|(_, clause)| !clause.iter().any(|&lit| holds(lit)))
+            .map(|(weight, _)| weight)
+            .sum()
+    }
+}
+
+fn read_text<R: Read>(reader: R) -> anyhow::Result<String> {
+    let mut text = String::new();
+    decompress(reader)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Terms in OPB syntax, e.g. `+2 x1 -1 ~x3`.
+pub fn format_terms(linear: &[(i64, i32)]) -> String {
+    linear
+        .iter()
+        .map(|&(coef, lit)| {
+            let var = if lit < 0 {
+                format!("~x{}", -lit)
+            } else {
+                format!("x{lit}")
+            };
+            format!("{coef:+} {var}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes `clauses` as an OPB file, each clause as the constraint that at
+/// least one of its literals is true, and `objective` as its `min:` line.
+pub fn write_opb<W: Write>(
+    mut writer: W,
+    clauses: &[Vec<i32>],
+    objective: Option<&[(i64, i32)]>,
+) -> io::Result<()> {
+    let vars = clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.abs())
+        .chain(objective.map(max_var))
+        .max()
+        .unwrap_or(0);
+    writeln!(writer, "* #variable= {} #constraint= {}", vars.max(1), clauses.len())?;
+    if let Some(objective) = objective {
+        writeln!(writer, "min: {} ;", format_terms(objective))?;
+    }
+    for clause in clauses {
+        if clause.is_empty() {
+            // An empty clause has no literal to be true.
+            writeln!(writer, "0 x1 >= 1 ;")?;
+            continue;
+        }
+        let terms: Linear = clause.iter().map(|&lit| (1, lit)).collect();
+        writeln!(writer, "{} >= 1 ;", format_terms(&terms))?;
+    }
+    Ok(())
+}
+
+/// Value of `linear` under `model`; unassigned variables count as false.
+pub fn value(linear: &[(i64, i32)], model: &[i32]) -> i64 {
+    linear
+        .iter()
+        .filter(|&&(_, lit)| model.get(lit.unsigned_abs() as usize - 1) == Some(&lit))
+        .map(|&(coef, _)| coef)
+        .sum()
+}
+
+/// `linear <= bound` as positive weights over possibly negated literals and
+/// the bound on their sum: `c·l` with `c < 0` is `c + |c|·¬l`. A bound that
+/// would exceed `i64::MAX` is held there, above any sum of the weights.
+pub fn normalize(linear: &[(i64, i32)], mut bound: i64) -> (Linear, i64) {
+    let mut terms = Vec::new();
+    for &(coef, lit) in linear {
+        if coef > 0 {
+            terms.push((coef, lit));
+        } else if coef < 0 {
+            terms.push((-coef, -lit));
+            bound = bound.saturating_sub(coef);
+        }
+    }
+    (terms, bound)
+}
+
+/// Sum of the absolute coefficients of `linear`; an error if it exceeds
+/// `i64::MAX`, which no constraint or objective over it may then use.
+pub fn weight(linear: &[(i64, i32)]) -> anyhow::Result<i64> {
+    linear
+        .iter()
+        .try_fold(0i64, |sum, &(coef, _)| sum.checked_add(coef.checked_abs()?))
+        .ok_or_else(|| anyhow!("the coefficients sum beyond {}", i64::MAX))
+}
+
+/// Clauses for `linear <= bound`, with auxiliary variables after `*next_var`.
+pub fn at_most(
+    linear: &[(i64, i32)],
+    bound: i64,
+    next_var: &mut i32,
+) -> anyhow::Result<Vec<Vec<i32>>> {
+    weight(linear)?;
+    let (terms, bound) = normalize(linear, bound);
+    card::at_most_weighted(&terms, bound, next_var)
+}
+
+fn max_var(linear: &[(i64, i32)]) -> i32 {
+    linear.iter().map(|(_, lit)| lit.abs()).max().unwrap_or(0)
+}
+
+#[derive(Default)]
+struct CnfWithObjective {
+    clauses: Vec<Vec<i32>>,
+    objective: Option<String>,
+}
+
+impl AsDimacs for CnfWithObjective {
+    fn add_clause(&mut self, clause: &[i32]) {
+        self.clauses.push(clause.to_vec());
+    }
+
+    fn add_comment(&mut self, comment: &str) {
+        if let Some(terms) = comment.trim().strip_prefix("objective") {
+            self.objective = Some(terms.to_string());
+        }
+    }
+}
+
+fn read_cnf(text: &str) -> anyhow::Result<Problem> {
+    let mut cnf = CnfWithObjective::default();
+    read_dimacs(text.as_bytes(), false, &mut cnf)?;
+    let objective = match &cnf.objective {
+        Some(terms) => Some(
+            parse_terms(terms.trim().trim_end_matches(';'))
+                .map_err(|e| anyhow!("`c objective` comment: {}", e))?,
+        ),
+        None => None,
+    };
+    let vars = cnf
+        .clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.abs())
+        .max()
+        .unwrap_or(0)
+        .max(objective.as_deref().map_or(0, max_var));
+    Ok(Problem {
+        clauses: cnf.clauses,
+        vars,
+        input_vars: vars,
+        objective,
+        wcnf: false,
+        soft: Vec::new(),
+    })
+}
+
+fn read_wcnf(text: &str) -> anyhow::Result<Problem> {
+    // The weight from which on a clause is hard in the older format.
+    let mut top = None;
+    let mut hard = Vec::new();
+    let mut soft = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let context = || format!("line {}: `{}`", i + 1, line);
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("p wcnf") {
+            // `p wcnf VARS CLAUSES [TOP]`
+            top = match header.split_whitespace().nth(2) {
+                Some(top) => Some(
+                    top.parse::<u64>()
+                        .map_err(|_| anyhow!("{}: invalid top weight", context()))?,
+                ),
+                None => Some(u64::MAX),
+            };
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let weight = tokens.next().unwrap_or_default();
+        // `-2147483648` is no literal, as its variable is beyond `i32`.
+        let clause = tokens
+            .map(|token| token.parse::<i32>().ok().filter(|&lit| lit != i32::MIN))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("{}: invalid literal", context()))?;
+        let Some((0, clause)) = clause.split_last() else {
+            bail!("{}: clause is not terminated by `0`", context());
+        };
+        if weight == "h" {
+            hard.push(clause.to_vec());
+            continue;
+        }
+        let weight: u64 = weight
+            .parse()
+            .map_err(|_| anyhow!("{}: invalid weight `{}`", context(), weight))?;
+        if top.is_some_and(|top| weight >= top) {
+            hard.push(clause.to_vec());
+        } else if weight > 0 {
+            let weight = i64::try_from(weight)
+                .map_err(|_| anyhow!("{}: weight beyond 2^63", context()))?;
+            soft.push((weight, clause.to_vec()));
+        }
+    }
+    let input_vars = hard
+        .iter()
+        .chain(soft.iter().map(|(_, clause)| clause))
+        .flatten()
+        .map(|lit| lit.abs())
+        .max()
+        .unwrap_or(0);
+    let mut vars = input_vars;
+    let mut objective = Linear::new();
+    for (weight, clause) in &soft {
+        // A falsified unit soft clause costs its weight through its
+        // literal; any other is relaxed by a fresh variable.
+        if let [lit] = clause[..] {
+            objective.push((*weight, -lit));
+            continue;
+        }
+        vars += 1;
+        let mut relaxed = clause.clone();
+        relaxed.push(vars);
+        hard.push(relaxed);
+        objective.push((*weight, vars));
+    }
+    Ok(Problem {
+        clauses: hard,
+        vars,
+        input_vars,
+        objective: Some(objective),
+        wcnf: true,
+        soft,
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Relation {
+    AtLeast,
+    AtMost,
+    Equal,
+}
+
+fn read_opb(text: &str) -> anyhow::Result<Problem> {
+    let body: String = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('*'))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    let mut objective = None;
+    let mut constraints = Vec::new();
+    for (i, statement) in body.split(';').enumerate() {
+        let statement = statement.trim();
+        if statement.is_empty() {
+            continue;
+        }
+        let context = || format!("statement {}: `{}`", i + 1, statement);
+        if let Some(terms) = statement.strip_prefix("min:") {
+            if objective.is_some() {
+                bail!("{}: more than one objective", context());
+            }
+            objective = Some(parse_terms(terms).map_err(|e| anyhow!("{}: {}", context(), e))?);
+        } else {
+            let constraint =
+                parse_constraint(statement).map_err(|e| anyhow!("{}: {}", context(), e))?;
+            constraints.push(constraint);
+        }
+    }
+    let vars = constraints
+        .iter()
+        .map(|(linear, _, _)| max_var(linear))
+        .chain(objective.as_deref().map(max_var))
+        .max()
+        .unwrap_or(0);
+    let mut next_var = vars;
+    let mut clauses = Vec::new();
+    for (linear, relation, rhs) in &constraints {
+        if matches!(relation, Relation::AtMost | Relation::Equal) {
+            clauses.extend(at_most(linear, *rhs, &mut next_var)?);
+        }
+        if matches!(relation, Relation::AtLeast | Relation::Equal) {
+            let negated: Linear = linear.iter().map(|&(coef, lit)| (-coef, lit)).collect();
+            clauses.extend(at_most(&negated, rhs.saturating_neg(), &mut next_var)?);
+        }
+    }
+    Ok(Problem {
+        clauses,
+        vars,
+        input_vars: vars,
+        objective,
+        wcnf: false,
+        soft: Vec::new(),
+    })
+}
+
+fn parse_constraint(statement: &str) -> anyhow::Result<(Linear, Relation, i64)> {
+    let relations = [
+        (">=", Relation::AtLeast),
+        ("<=", Relation::AtMost),
+        ("=", Relation::Equal),
+    ];
+    let (at, relation, len) = relations
+        .into_iter()
+        .find_map(|(op, relation)| statement.find(op).map(|at| (at, relation, op.len())))
+        .ok_or_else(|| anyhow!("expected `>=`, `<=` or `=`"))?;
+    let rhs = statement[at + len..].trim();
+    let rhs = rhs
+        .parse()
+        .map_err(|_| anyhow!("invalid right-hand side `{}`", rhs))?;
+    Ok((parse_terms(&statement[..at])?, relation, rhs))
+}
+
+/// Parses terms such as `+2 x1 -1 ~x3`.
+fn parse_terms(text: &str) -> anyhow::Result<Linear> {
+    let mut tokens = text.split_whitespace().peekable();
+    let mut terms = Vec::new();
+    while let Some(token) = tokens.next() {
+        let coef = token
+            .parse()
+            .map_err(|_| anyhow!("invalid coefficient `{}`", token))?;
+        let lit = tokens
+            .next()
+            .ok_or_else(|| anyhow!("coefficient `{}` has no variable", token))?;
+        if tokens
+            .peek()
+            .is_some_and(|next| next.parse::<i64>().is_err())
+        {
+            bail!("non-linear terms are not supported");
+        }
+        terms.push((coef, parse_literal(lit)?));
+    }
+    Ok(terms)
+}
+
+fn parse_literal(token: &str) -> anyhow::Result<i32> {
+    let (negated, var) = match token.strip_prefix('~') {
+        Some(var) => (true, var),
+        None => (false, token),
+    };
+    let var: i32 = var
+        .strip_prefix('x')
+        .and_then(|v| v.parse().ok())
+        .filter(|&v| v > 0)
+        .ok_or_else(|| anyhow!("invalid variable `{}`", token))?;
+    Ok(if negated { -var } else { var })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WCNF: &str = "c weighted\nh 1 2 0\n3 -1 0\n5 -2 3 0\n0 4 0\n";
+
+    fn error(text: &str) -> String {
+        match Problem::read(text.as_bytes()) {
+            Ok(_) => panic!("`{}` was read", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn join(clause: &[i32]) -> String {
+        clause
+            .iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn wcnf() {
+        let problem = Problem::read(WCNF.as_bytes()).unwrap();
+        assert!(problem.wcnf);
+        assert_eq!(problem.soft, vec![(3, vec![-1]), (5, vec![-2, 3])]);
+        assert_eq!(problem.input_vars, 3);
+        assert_eq!(problem.vars, 4);
+        assert_eq!(problem.clauses, vec![vec![1, 2], vec![-2, 3, 4]]);
+        assert_eq!(problem.objective, Some(vec![(3, 1), (5, 4)]));
+        assert_eq!(problem.soft_cost(&[1, -2, 3]), 3);
+        assert_eq!(problem.soft_cost(&[-1, 2, -3]), 5);
+    }
+
+    #[test]
+    fn wcnf_round_trip() {
+        let problem = Problem::read(WCNF.as_bytes()).unwrap();
+        let mut text = String::new();
+        let hard = problem
+            .clauses
+            .iter()
+            .filter(|clause| clause.iter().all(|lit| lit.abs() <= problem.input_vars));
+        for clause in hard {
+            text += &format!("h {} 0\n", join(clause));
+        }
+        for (weight, clause) in &problem.soft {
+            text += &format!("{} {} 0\n", weight, join(clause));
+        }
+        let read_back = Problem::read(text.as_bytes()).unwrap();
+        assert_eq!(read_back.clauses, problem.clauses);
+        assert_eq!(read_back.soft, problem.soft);
+        assert_eq!(read_back.objective, problem.objective);
+    }
+
+    #[test]
+    fn older_wcnf_format() {
+        let text = "p wcnf 3 4 10\n10 1 2 0\n3 -1 0\n5 -2 3 0\n0 4 0\n";
+        let problem = Problem::read(text.as_bytes()).unwrap();
+        let newer = Problem::read(WCNF.as_bytes()).unwrap();
+        assert_eq!(problem.clauses, newer.clauses);
+        assert_eq!(problem.soft, newer.soft);
+        assert_eq!(problem.objective, newer.objective);
+        // Without a top weight every clause is soft.
+        let problem = Problem::read("p wcnf 2 1\n10 1 2 0\n".as_bytes()).unwrap();
+        assert_eq!(problem.soft, vec![(10, vec![1, 2])]);
+        assert!(problem.clauses.iter().all(|clause| clause.len() == 3));
+    }
+
+    #[test]
+    fn malformed_wcnf() {
+        assert!(error("h 1 2\n").contains("clause is not terminated by `0`"));
+        assert!(error("h 1 x 0\n").contains("invalid literal"));
+        assert!(
+            error("h 1 0\n1 -2147483648 0\n")
+                .contains("line 2: `1 -2147483648 0`: invalid literal")
+        );
+        assert!(error("h 1 0\nw 1 0\n").contains("invalid weight `w`"));
+        assert!(error("h 1 0\n-1 2 0\n").contains("invalid weight `-1`"));
+        assert!(error("p wcnf 1 1 top\n1 1 0\n").contains("invalid top weight"));
+    }
+
+    #[test]
+    fn opb_round_trip() {
+        let clauses = vec![vec![1, -2], vec![3]];
+        let objective = vec![(2, 1), (-1, 3)];
+        let mut text = Vec::new();
+        write_opb(&mut text, &clauses, Some(&objective)).unwrap();
+        let problem = Problem::read(&text[..]).unwrap();
+        assert!(!problem.wcnf);
+        assert_eq!(problem.vars, 3);
+        assert_eq!(problem.objective, Some(objective));
+    }
+}
@@ -0,0 +1,759 @@
+//! Linear optimization by iterative SAT calls: every model found tightens an
+//! upper bound on the objective until the bound is unsatisfiable.
+//!
+//! Several objectives are optimized lexicographically: each in turn, with
+//! the optima of those before it kept as constraints.
+//!
+//! With weighted `--assume` literals, the objective is the weight of the
+//! assumptions a model falsifies: the best model satisfies a
+//! maximum-weight subset of them, and the others are reported as dropped.
+//!
+//! A WCNF is reported as the MaxSAT Evaluation expects: an `o COST` line
+//! for every better model, then `s OPTIMUM FOUND`, `s SATISFIABLE`,
+//! `s UNSATISFIABLE` or `s UNKNOWN` and, with a model, its `v` line, and
+//! the exit codes 30, 10, 20 and 0.
+//!
+//! Every `o` line follows a `c` line with the time since the start. On
+//! SIGINT or SIGTERM the search stops and the best model found so far is
+//! reported as it would be at a limit: `s SATISFIABLE` and its `v` line, or
+//! `SAT` and the values so far, and `UNKNOWN` without one.
+//!
+//! `--deltas` changes a WCNF step by step while one solver keeps its learnt
+//! clauses, one command per line:
+//!
+//! ```text
+//! # comments start with `#` or `c`
+//! soft ID W 1 -2 0   add a soft clause of weight W, named ID
+//! hard 3 4 0         add a hard clause
+//! drop ID            remove a soft clause
+//! solve              optimize and report
+//! ```
+//!
+//! The soft clauses of the input are named by their number, from 1. Every
+//! `solve` is reported like a WCNF, after a `c solve` line; the exit code
+//! is that of the last one. The clauses may only use the variables of the
+//! input, as those after them encode the bounds.
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Instant,
+};
+
+use anyhow::{Context, anyhow, bail};
+use clap::{Args, ValueEnum};
+
+use crate::{
+    Solver, Status,
+    core::{self, Backend, Writer, check_writable, open_input, write_model},
+    opb::{self, Linear, Problem},
+    script::parse_literals,
+};
+
+#[derive(Args)]
+pub struct Arg {
+    /// OPB file, WCNF, or DIMACS CNF with a `c objective` comment (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Result and best model output, compressed when it ends in .gz, .xz or .zst; default for stdout
+    #[arg(value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+    /// Backend the SAT calls are made with
+    #[arg(long, default_value = "minisat")]
+    solver: Solver,
+    /// Optimize these groups of literals in priority order instead of the
+    /// input's objective, e.g. `[{"maximize": [1, 2]}, {"minimize": [-3, 4]}]`;
+    /// each group counts its true literals
+    #[arg(long = "lex-opt", value_name = "FILE")]
+    lex_opt: Option<PathBuf>,
+    /// Weighted assumptions as LIT:WEIGHT (weight 1 if omitted), e.g. 3:5,-7,12:2; satisfy a maximum-weight subset and report the dropped ones, instead of the input's objective
+    #[arg(long, value_name = "LITS", value_delimiter = ',', allow_hyphen_values = true, value_parser = parse_weighted, conflicts_with = "lex_opt")]
+    assume: Vec<(i32, i64)>,
+    /// Changes to a WCNF input applied to one solver, each `solve` in it re-optimizing; see `soft`, `hard`, `drop` and `solve` in the README
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["lex_opt", "assume"])]
+    deltas: Option<PathBuf>,
+    /// Report in the MaxSAT Evaluation format, with `o`, `s` and `v` lines and its exit codes [default: for a WCNF input]
+    #[arg(long = "maxsat-output", conflicts_with = "lex_opt")]
+    maxsat_output: bool,
+    /// The `v` line of the MaxSAT Evaluation format
+    #[arg(long = "v-format", value_enum, default_value_t = VFormat::Bits)]
+    v_format: VFormat,
+}
+
+/// Form of the `v` line in the MaxSAT Evaluation format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum VFormat {
+    /// One `0` or `1` per variable, as since the 2022 evaluation.
+    Bits,
+    /// The literals of the model, as before 2022.
+    Literals,
+}
+
+/// A group of `--lex-opt`.
+struct Group {
+    maximize: bool,
+    lits: Vec<i32>,
+}
+
+impl Group {
+    /// The group as an objective to minimize.
+    fn objective(&self) -> Linear {
+        let coef = if self.maximize { -1 } else { 1 };
+        self.lits.iter().map(|&lit| (coef, lit)).collect()
+    }
+
+    /// The number of true literals for a cost of [`Group::objective`].
+    fn value(&self, cost: i64) -> i64 {
+        if self.maximize { -cost } else { cost }
+    }
+}
+
+fn parse_weighted(s: &str) -> Result<(i32, i64), String> {
+    let (lit, weight) = s.split_once(':').unwrap_or((s, "1"));
+    let lit = lit
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&lit: &i32| lit != 0)
+        .ok_or_else(|| format!("`{}` is not a literal", lit))?;
+    let weight = weight
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&weight: &i64| weight > 0)
+        .ok_or_else(|| format!("`{}` is not a positive weight", weight))?;
+    Ok((lit, weight))
+}
+
+fn read_groups(path: &Path) -> anyhow::Result<Vec<Group>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not valid JSON", path.display()))?;
+    let invalid = || {
+        anyhow!(
+            "{}: expected a list of {{\"minimize\": [...]}} or {{\"maximize\": [...]}} groups",
+            path.display()
+        )
+    };
+    let groups = json.as_array().ok_or_else(invalid)?;
+    groups
+        .iter()
+        .map(|group| {
+            let object = group
+                .as_object()
+                .filter(|o| o.len() == 1)
+                .ok_or_else(invalid)?;
+            let (sense, lits) = object.iter().next().ok_or_else(invalid)?;
+            let maximize = match sense.as_str() {
+                "maximize" => true,
+                "minimize" => false,
+                _ => return Err(invalid()),
+            };
+            let lits = lits
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|lit| {
+                    lit.as_i64()
+                        .and_then(|lit| i32::try_from(lit).ok())
+                        .filter(|&lit| lit != 0)
+                        .ok_or_else(|| anyhow!("{}: invalid literal {}", path.display(), lit))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok(Group { maximize, lits })
+        })
+        .collect()
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let problem = Problem::read(open_input(self.input.as_deref())?)?;
+        let groups = match &self.lex_opt {
+            Some(path) => read_groups(path)?,
+            None => Vec::new(),
+        };
+        if let Some(path) = &self.deltas {
+            return self.run_deltas(&problem, path);
+        }
+        if problem.wcnf && !self.assume.is_empty() {
+            return Err(anyhow!("--assume needs a CNF or OPB input, not a WCNF"));
+        }
+        let objectives: Vec<Linear> = if self.lex_opt.is_some() {
+            groups.iter().map(Group::objective).collect()
+        } else if !self.assume.is_empty() {
+            // An assumption costs its weight when its literal is false.
+            vec![self.assume.iter().map(|&(lit, weight)| (weight, -lit)).collect()]
+        } else {
+            vec![
+                problem
+                    .objective
+                    .clone()
+                    .ok_or_else(|| anyhow!("The input has no objective"))?,
+            ]
+        };
+        // Variables past the input's are taken by the encoded constraints.
+        if let Some(lit) = groups
+            .iter()
+            .flat_map(|g| &g.lits)
+            .find(|l| l.abs() > problem.input_vars)
+        {
+            return Err(anyhow!("--lex-opt: {} is not a variable of the input", lit));
+        }
+        if let Some((lit, _)) = self.assume.iter().find(|(l, _)| l.abs() > problem.input_vars) {
+            return Err(anyhow!("--assume: {} is not a variable of the input", lit));
+        }
+        let value = |i: usize, cost: i64| groups.get(i).map_or(cost, |group| group.value(cost));
+        let maxsat = self.maxsat_output || (problem.wcnf && self.lex_opt.is_none());
+        // Relaxation variables of a WCNF are not part of the model.
+        let input_model = |model: &[i32]| model[..problem.input_vars as usize].to_vec();
+        // The output is only created for the report, by the interrupt
+        // handler or after the search, so that neither leaves a part behind.
+        if let Some(path) = core::non_dash(self.output.as_deref()) {
+            check_writable(path)?;
+        }
+        let mut unknown = Vec::new();
+        let code = match maxsat {
+            true => self.report_maxsat(&mut unknown, None, false)?,
+            false => self.report(&mut unknown, "", None, false)?,
+        };
+        *ANYTIME.lock().unwrap() = Some(Anytime {
+            output: self.output.clone(),
+            report: unknown,
+            code,
+        });
+        #[cfg(not(target_family = "wasm"))]
+        ctrlc::set_handler(interrupted)?;
+        let start = Instant::now();
+        let mut values: Vec<i64> = Vec::new();
+        let outcomes = self.solver.minimize(&problem, &objectives, |i, cost, model| {
+            println!("c Better model after {:.3}s", start.elapsed().as_secs_f64());
+            if problem.wcnf && groups.is_empty() {
+                // A relaxation variable may be true with its clause
+                // satisfied; the `o` line is the cost of the model itself.
+                println!("o {}", problem.soft_cost(model));
+            } else if groups.is_empty() {
+                println!("o {}", cost);
+            } else {
+                println!("o {} {}", i + 1, value(i, cost));
+            }
+            values.truncate(i);
+            values.push(value(i, cost));
+            // What an interrupt from now on reports.
+            let model = input_model(model);
+            let mut report = Vec::new();
+            let code = match maxsat {
+                true => self.report_maxsat(&mut report, Some(&model), false),
+                false => self.report(&mut report, &join(&values), Some(&model), false),
+            };
+            if let (Ok(code), Some(anytime)) = (code, ANYTIME.lock().unwrap().as_mut()) {
+                anytime.report = report;
+                anytime.code = code;
+            }
+        })?;
+        // An interrupt now has the process to itself, and exits it.
+        if ANYTIME.lock().unwrap().take().is_none() {
+            loop {
+                thread::park();
+            }
+        }
+        let mut output = Writer::create(self.output.as_deref(), None)?;
+        // An unsatisfiable formula ends the search at the first group, which
+        // leaves the groups after it without an outcome.
+        let unsatisfiable = outcomes
+            .first()
+            .is_some_and(|o| o.best.is_none() && o.complete);
+        let complete = unsatisfiable
+            || (outcomes.len() == objectives.len() && outcomes.iter().all(|o| o.complete));
+        let best = outcomes
+            .last()
+            .and_then(|o| o.best.as_ref())
+            .map(|(_, model)| input_model(model));
+        if let Some(model) = &best {
+            self.print_dropped(model);
+        }
+        let code = if maxsat {
+            self.report_maxsat(&mut output, best.as_deref(), complete)?
+        } else {
+            let values: Vec<i64> = outcomes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, outcome)| Some(value(i, outcome.best.as_ref()?.0)))
+                .collect();
+            let values = join(&values);
+            match (&best, complete) {
+                (Some(_), true) => println!("c OPTIMUM FOUND: {}", values),
+                (Some(_), false) => println!("c SATISFIABLE: best {}", values),
+                (None, true) => println!("c UNSATISFIABLE"),
+                (None, false) => println!("c UNKNOWN"),
+            }
+            self.report(&mut output, &values, best.as_deref(), complete)?
+        };
+        output.finish()?;
+        Ok(code)
+    }
+
+    /// Writes the result of a search without the MaxSAT Evaluation format:
+    /// `OPTIMUM` or `SAT` with the `values` of the objectives, the dropped
+    /// assumptions and the `best` model, or `UNSAT` or `UNKNOWN` without
+    /// one, and returns its exit code.
+    fn report(
+        &self,
+        output: &mut impl Write,
+        values: &str,
+        best: Option<&[i32]>,
+        complete: bool,
+    ) -> io::Result<i32> {
+        let status = match (best, complete) {
+            (Some(model), complete) => {
+                if complete {
+                    writeln!(output, "OPTIMUM {}", values)?;
+                } else {
+                    writeln!(output, "SAT {}", values)?;
+                }
+                if !self.assume.is_empty() {
+                    write!(output, "DROPPED")?;
+                    for (lit, _) in self.dropped(model) {
+                        write!(output, " {}", lit)?;
+                    }
+                    writeln!(output, " 0")?;
+                }
+                write_model(output, model, None)?;
+                Status::Satisfiable
+            }
+            (None, true) => {
+                writeln!(output, "UNSAT")?;
+                Status::Unsatisfiable
+            }
+            (None, false) => {
+                writeln!(output, "UNKNOWN")?;
+                Status::Unknown
+            }
+        };
+        Ok(status.exit_code())
+    }
+
+    fn run_deltas(&self, problem: &Problem, path: &Path) -> anyhow::Result<i32> {
+        if !problem.wcnf {
+            bail!("--deltas needs a WCNF input");
+        }
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let deltas = parse_deltas(&text)?;
+        let mut output = Writer::create(self.output.as_deref(), None)?;
+        let mut code = 0;
+        self.solver.run_deltas(problem, &deltas, |best, complete| {
+            code = self.report_maxsat(&mut output, best, complete)?;
+            output.flush()
+        })?;
+        output.finish()?;
+        Ok(code)
+    }
+
+    /// The weighted `--assume` literals `model` falsifies, which are
+    /// reported.
+    fn dropped<'a>(&'a self, model: &'a [i32]) -> impl Iterator<Item = &'a (i32, i64)> {
+        self.assume
+            .iter()
+            .filter(|&&(lit, _)| model[lit.unsigned_abs() as usize - 1] != lit)
+    }
+
+    /// Prints the `--assume` literals `model` falsifies and their weight.
+    fn print_dropped(&self, model: &[i32]) {
+        if self.assume.is_empty() {
+            return;
+        }
+        let dropped: Vec<_> = self.dropped(model).collect();
+        let weight: i64 = dropped.iter().map(|(_, w)| w).sum();
+        let lits: Vec<String> = dropped.iter().map(|(lit, _)| lit.to_string()).collect();
+        println!(
+            "c {} of {} assumptions dropped (weight {}): {}",
+            dropped.len(),
+            self.assume.len(),
+            weight,
+            lits.join(" ")
+        );
+    }
+
+    /// Writes the `s` and `v` lines of the MaxSAT Evaluation format and
+    /// returns its exit code.
+    fn report_maxsat(
+        &self,
+        output: &mut impl Write,
+        best: Option<&[i32]>,
+        complete: bool,
+    ) -> io::Result<i32> {
+        let (status, code) = match (best, complete) {
+            (Some(_), true) => ("OPTIMUM FOUND", 30),
+            (Some(_), false) => ("SATISFIABLE", 10),
+            (None, true) => ("UNSATISFIABLE", 20),
+            (None, false) => ("UNKNOWN", 0),
+        };
+        writeln!(output, "s {}", status)?;
+        let Some(model) = best else {
+            return Ok(code);
+        };
+        match self.v_format {
+            VFormat::Bits => {
+                let bits: String = model
+                    .iter()
+                    .map(|&lit| if lit > 0 { '1' } else { '0' })
+                    .collect();
+                writeln!(output, "v {}", bits)?;
+            }
+            VFormat::Literals => {
+                write!(output, "v")?;
+                for lit in model {
+                    write!(output, " {}", lit)?;
+                }
+                writeln!(output)?;
+            }
+        }
+        Ok(code)
+    }
+}
+
+/// What an interrupt of the search writes, see [`interrupted`].
+static ANYTIME: Mutex<Option<Anytime>> = Mutex::new(None);
+
+struct Anytime {
+    output: Option<PathBuf>,
+    /// The report of the best model so far, or UNKNOWN before the first.
+    report: Vec<u8>,
+    code: i32,
+}
+
+/// Writes the report of the best model so far to OUTPUT and exits with its
+/// code, unless the search has ended and its own report is being written.
+#[cfg(not(target_family = "wasm"))]
+fn interrupted() {
+    let Some(anytime) = ANYTIME.lock().unwrap().take() else {
+        return;
+    };
+    println!("c Interrupted");
+    let written = Writer::create(anytime.output.as_deref(), None).and_then(|mut output| {
+        output.write_all(&anytime.report)?;
+        output.finish()
+    });
+    if let Err(e) = written {
+        eprintln!("Error: {}", e);
+    }
+    std::process::exit(anytime.code);
+}
+
+/// `values` separated by spaces.
+fn join(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(i64::to_string).collect();
+    values.join(" ")
+}
+
+/// Result of [`Optimizer::minimize`].
+pub struct Outcome {
+    /// The cheapest model found and its cost.
+    pub best: Option<(i64, Vec<i32>)>,
+    /// Whether the search ran to the end, which makes `best` optimal, or
+    /// proves the problem unsatisfiable if there is none.
+    pub complete: bool,
+}
+
+/// Why encoding a bound cannot fail: [`Solver::minimize`] and
+/// [`execute_deltas`] check the weight of an objective before minimizing it.
+const CHECKED: &str = "the coefficients of an objective are checked before it is minimized";
+
+/// A solver holding a problem across SAT calls, with its simplification
+/// turned off so that bounds over any variable can be added between them.
+pub struct Optimizer<S> {
+    solver: S,
+    vars: i32,
+    next_var: i32,
+}
+
+impl<S: Backend> Optimizer<S> {
+    pub fn new(problem: &Problem, configure: impl FnOnce()) -> Self {
+        let mut solver = core::new_solver::<S>(configure);
+        solver.eliminate(true);
+        for clause in &problem.clauses {
+            solver.add_clause(clause);
+        }
+        let next_var = problem.vars.max(solver.vars());
+        Self {
+            solver,
+            vars: problem.vars,
+            next_var,
+        }
+    }
+
+    /// Adds the constraint `linear <= bound` for all later calls. Panics if
+    /// the coefficients sum beyond `i64::MAX`, see [`opb::weight`].
+    pub fn bound(&mut self, linear: &[(i64, i32)], bound: i64) {
+        for clause in opb::at_most(linear, bound, &mut self.next_var).expect(CHECKED) {
+            self.solver.add_clause(&clause);
+        }
+    }
+
+    /// Adds `linear <= bound` under a fresh selector variable, which is
+    /// returned; the constraint only holds while the selector is assumed.
+    fn bound_if(&mut self, linear: &[(i64, i32)], bound: i64) -> i32 {
+        self.next_var += 1;
+        let selector = self.next_var;
+        for mut clause in opb::at_most(linear, bound, &mut self.next_var).expect(CHECKED) {
+            clause.push(-selector);
+            self.solver.add_clause(&clause);
+        }
+        selector
+    }
+
+    /// Adds `clause` for all later calls.
+    pub fn add_clause(&mut self, clause: &[i32]) {
+        self.solver.add_clause(clause);
+    }
+
+    /// A fresh variable, part of the models from now on.
+    pub fn new_var(&mut self) -> i32 {
+        self.next_var += 1;
+        self.vars = self.next_var;
+        self.next_var
+    }
+
+    /// Minimizes `objective`, calling `improved` with every cheaper model.
+    /// The bound of the best cost stays in place for later calls.
+    pub fn minimize(
+        &mut self,
+        objective: &[(i64, i32)],
+        improved: impl FnMut(i64, &[i32]),
+    ) -> Outcome {
+        self.search(objective, improved, true)
+    }
+
+    /// Minimizes `objective` like [`Optimizer::minimize`], but leaves no
+    /// bound behind, so a later call may minimize another objective.
+    pub fn minimize_scoped(
+        &mut self,
+        objective: &[(i64, i32)],
+        improved: impl FnMut(i64, &[i32]),
+    ) -> Outcome {
+        self.search(objective, improved, false)
+    }
+
+    fn search(
+        &mut self,
+        objective: &[(i64, i32)],
+        mut improved: impl FnMut(i64, &[i32]),
+        keep: bool,
+    ) -> Outcome {
+        let mut best: Option<(i64, Vec<i32>)> = None;
+        let mut selector = None;
+        loop {
+            let assumptions: Vec<i32> = selector.into_iter().collect();
+            let status = if self.solver.okay() {
+                Status::from(self.solver.solve_limited(&assumptions))
+            } else {
+                Status::Unsatisfiable
+            };
+            if status == Status::Satisfiable {
+                // Variables only the objective mentions are unknown to the
+                // solver and false.
+                let known = self.solver.vars();
+                let model: Vec<i32> = (1..=self.vars)
+                    .map(|v| {
+                        if v <= known && self.solver.model_value(v) {
+                            v
+                        } else {
+                            -v
+                        }
+                    })
+                    .collect();
+                let cost = opb::value(objective, &model);
+                improved(cost, &model);
+                best = Some((cost, model));
+                // The bound has a model, so it can stay for good, unless
+                // no bound may stay; the next one is tighter anyway.
+                if let Some(selector) = selector {
+                    self.solver.add_clause(&[if keep { selector } else { -selector }]);
+                }
+                selector = Some(self.bound_if(objective, cost - 1));
+                continue;
+            }
+            // A failed bound is dropped, the best cost bounds from now on.
+            if let Some(selector) = selector {
+                self.solver.add_clause(&[-selector]);
+            }
+            if keep && let Some((cost, _)) = &best {
+                self.bound(objective, *cost);
+            }
+            return Outcome {
+                best,
+                complete: status == Status::Unsatisfiable,
+            };
+        }
+    }
+
+    /// Minimizes `objectives` lexicographically, calling `improved` with the
+    /// index of the objective and every cheaper model. Stops after the first
+    /// objective whose search does not complete.
+    pub fn minimize_lex(
+        &mut self,
+        objectives: &[Linear],
+        mut improved: impl FnMut(usize, i64, &[i32]),
+    ) -> Vec<Outcome> {
+        let mut outcomes = Vec::new();
+        for (i, objective) in objectives.iter().enumerate() {
+            let outcome = self.minimize(objective, |cost, model| improved(i, cost, model));
+            let done = !outcome.complete || outcome.best.is_none();
+            outcomes.push(outcome);
+            if done {
+                break;
+            }
+        }
+        outcomes
+    }
+}
+
+/// A change of a WCNF in a `--deltas` file.
+pub enum Delta {
+    Soft {
+        id: String,
+        weight: i64,
+        clause: Vec<i32>,
+    },
+    Hard(Vec<i32>),
+    Drop(String),
+    Solve,
+}
+
+/// A delta with the line it came from, for error messages.
+pub struct DeltaLine {
+    pub number: usize,
+    pub delta: Delta,
+}
+
+/// Parses a whole `--deltas` file, so syntax errors surface before anything
+/// runs.
+pub fn parse_deltas(text: &str) -> anyhow::Result<Vec<DeltaLine>> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('c') {
+            continue;
+        }
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let delta = parse_delta(word, rest.trim())
+            .map_err(|e| anyhow!("line {}: `{}`: {}", i + 1, line, e))?;
+        lines.push(DeltaLine {
+            number: i + 1,
+            delta,
+        });
+    }
+    Ok(lines)
+}
+
+fn parse_delta(word: &str, rest: &str) -> anyhow::Result<Delta> {
+    match word {
+        "soft" => {
+            let mut tokens = rest.split_whitespace();
+            let (Some(id), Some(weight)) = (tokens.next(), tokens.next()) else {
+                bail!("expected `soft ID WEIGHT LITS 0`");
+            };
+            let weight = weight
+                .parse()
+                .ok()
+                .filter(|&weight: &i64| weight > 0)
+                .ok_or_else(|| anyhow!("invalid weight `{}`", weight))?;
+            Ok(Delta::Soft {
+                id: id.to_string(),
+                weight,
+                clause: parse_literals(&tokens.collect::<Vec<_>>().join(" "))?,
+            })
+        }
+        "hard" => Ok(Delta::Hard(parse_literals(rest)?)),
+        "drop" if !rest.is_empty() && !rest.contains(char::is_whitespace) => {
+            Ok(Delta::Drop(rest.to_string()))
+        }
+        "drop" => bail!("expected `drop ID`"),
+        "solve" if rest.is_empty() => Ok(Delta::Solve),
+        "solve" => bail!("`solve` takes no arguments"),
+        _ => bail!("unknown command `{}`", word),
+    }
+}
+
+/// Applies `deltas` to one solver holding the WCNF `problem`, calling
+/// `solved` with the outcome of every `solve`: the best model, cut to the
+/// input's variables, and whether it is optimal.
+pub fn execute_deltas<S: Backend>(
+    problem: &Problem,
+    deltas: &[DeltaLine],
+    configure: impl FnOnce(),
+    mut solved: impl FnMut(Option<&[i32]>, bool) -> io::Result<()>,
+) -> anyhow::Result<()> {
+    let mut optimizer = Optimizer::<S>::new(problem, configure);
+    // Soft clauses by name: weight, the literal the objective weighs for
+    // them, and the clause.
+    let mut softs: BTreeMap<String, (i64, i32, Vec<i32>)> = BTreeMap::new();
+    let objective = problem.objective.as_deref().unwrap_or_default();
+    for (i, ((weight, clause), &(_, lit))) in problem.soft.iter().zip(objective).enumerate() {
+        softs.insert((i + 1).to_string(), (*weight, lit, clause.clone()));
+    }
+    let input_vars = problem.input_vars;
+    let check = |number: usize, clause: &[i32]| match clause.iter().find(|l| l.abs() > input_vars) {
+        Some(lit) => Err(anyhow!("line {}: {} is not a variable of the input", number, lit)),
+        None => Ok(()),
+    };
+    for line in deltas {
+        match &line.delta {
+            Delta::Hard(clause) => {
+                check(line.number, clause)?;
+                optimizer.add_clause(clause);
+            }
+            Delta::Soft { id, weight, clause } => {
+                check(line.number, clause)?;
+                if softs.contains_key(id) {
+                    bail!("line {}: soft clause `{}` exists already", line.number, id);
+                }
+                let lit = match clause[..] {
+                    [lit] => -lit,
+                    _ => {
+                        let var = optimizer.new_var();
+                        let mut relaxed = clause.clone();
+                        relaxed.push(var);
+                        optimizer.add_clause(&relaxed);
+                        var
+                    }
+                };
+                softs.insert(id.clone(), (*weight, lit, clause.clone()));
+            }
+            Delta::Drop(id) => {
+                let (_, lit, clause) = softs
+                    .remove(id)
+                    .ok_or_else(|| anyhow!("line {}: no soft clause `{}`", line.number, id))?;
+                // A relaxation variable set for good frees its clause.
+                if clause.len() != 1 {
+                    optimizer.add_clause(&[lit]);
+                }
+            }
+            Delta::Solve => {
+                println!("c solve at line {}", line.number);
+                let objective: Linear = softs.values().map(|&(weight, lit, _)| (weight, lit)).collect();
+                opb::weight(&objective).map_err(|e| anyhow!("line {}: {}", line.number, e))?;
+                let cost = |model: &[i32]| -> i64 {
+                    let holds = |lit: i32| model[lit.unsigned_abs() as usize - 1] == lit;
+                    softs
+                        .values()
+                        .filter(|(_, _, clause)| !clause.iter().any(|&lit| holds(lit)))
+                        .map(|(weight, ..)| weight)
+                        .sum()
+                };
+                let outcome =
+                    optimizer.minimize_scoped(&objective, |_, model| println!("o {}", cost(model)));
+                let best = outcome
+                    .best
+                    .as_ref()
+                    .map(|(_, model)| &model[..input_vars as usize]);
+                solved(best, outcome.complete)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,807 @@
+//! Preprocessing passes run in a user-chosen order, each recording in a
+//! reconstruction log how to extend a model of its output to its input.
+//!
+//! Unsatisfiability found by a pass is returned as a formula with the empty
+//! clause, after which the remaining passes are skipped.
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+
+use crate::{
+    autarky, card, classes,
+    core::{non_dash, open_input},
+    dimacs::{read_commented, write_comments, write_dimacs},
+    reconstruct::{Entry, Log},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Pass {
+    /// Pure literals and propagation autarkies
+    Autarky,
+    /// Substitution of equivalent literals
+    Equiv,
+    /// Blocked clause elimination
+    Bce,
+    /// Failed literal probing
+    Probe,
+    /// Clause vivification by unit propagation
+    Vivify,
+    /// Bounded variable elimination by clause distribution
+    Elim,
+    /// Re-encoding of pairwise at-most-one groups with the sequential encoding
+    Amo,
+}
+
+impl Pass {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Autarky => "autarky",
+            Pass::Equiv => "equiv",
+            Pass::Bce => "bce",
+            Pass::Probe => "probe",
+            Pass::Vivify => "vivify",
+            Pass::Elim => "elim",
+            Pass::Amo => "amo",
+        }
+    }
+}
+
+/// Size of the formula before and after a pass, and the time it took.
+#[derive(Clone, Debug)]
+pub struct PassStats {
+    pub pass: Pass,
+    pub clauses: (usize, usize),
+    pub variables: (usize, usize),
+    pub literals: (usize, usize),
+    pub time: Duration,
+    /// At-most-one groups found, for the `amo` pass.
+    pub groups: Option<usize>,
+}
+
+impl PassStats {
+    /// The statistics as a `c` comment line.
+    pub fn line(&self) -> String {
+        let groups = match self.groups {
+            Some(groups) => format!(", {} at-most-one groups", groups),
+            None => String::new(),
+        };
+        format!(
+            "c Pass {:<8} clauses {} -> {}, variables {} -> {}, literals {} -> {}{} in {:?}",
+            self.pass.name(),
+            self.clauses.0,
+            self.clauses.1,
+            self.variables.0,
+            self.variables.1,
+            self.literals.0,
+            self.literals.1,
+            groups,
+            self.time
+        )
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "pass": self.pass.name(),
+            "clauses": [self.clauses.0, self.clauses.1],
+            "variables": [self.variables.0, self.variables.1],
+            "literals": [self.literals.0, self.literals.1],
+            "time": self.time.as_secs_f64(),
+        });
+        if let Some(groups) = self.groups {
+            json["amo_groups"] = groups.into();
+        }
+        json
+    }
+
+    /// The statistics in `json`, as [`PassStats::json`] writes them.
+    pub fn from_json(json: &serde_json::Value) -> Option<Self> {
+        let pair = |name: &str| -> Option<(usize, usize)> {
+            serde_json::from_value(json[name].clone()).ok()
+        };
+        Some(PassStats {
+            pass: Pass::from_str(json["pass"].as_str()?, false).ok()?,
+            clauses: pair("clauses")?,
+            variables: pair("variables")?,
+            literals: pair("literals")?,
+            time: Duration::try_from_secs_f64(json["time"].as_f64()?).ok()?,
+            groups: json["amo_groups"].as_u64().map(|groups| groups as usize),
+        })
+    }
+}
+
+/// Result of [`run`].
+pub struct Simplified {
+    pub clauses: Vec<Vec<i32>>,
+    pub log: Log,
+    pub stats: Vec<PassStats>,
+}
+
+/// Runs `passes` on `clauses` in order. Variable elimination keeps the
+/// variables of `frozen`. Auxiliary variables a pass introduces come after
+/// every variable of the input.
+pub fn run(clauses: Vec<Vec<i32>>, passes: &[Pass], frozen: &[i32]) -> Simplified {
+    // The passes assume clauses without duplicate literals or tautologies.
+    let mut clauses: Vec<Vec<i32>> = clauses.into_iter().filter_map(normalize).collect();
+    // Not the variables left after a pass: eliminated ones stay in the log.
+    let mut next_var = frozen
+        .iter()
+        .copied()
+        .fold(max_var(&clauses) as i32, i32::max);
+    let mut log = Log::default();
+    let mut stats = Vec::new();
+    for &pass in passes {
+        if clauses.iter().any(Vec::is_empty) {
+            break;
+        }
+        let before = size(&clauses);
+        let start = Instant::now();
+        let mut groups = None;
+        clauses = match pass {
+            Pass::Autarky => {
+                let reduction = autarky::reduce(&clauses);
+                let steps = reduction.steps.iter();
+                log.entries
+                    .extend(steps.map(|step| Entry::Set(step.literals().to_vec())));
+                reduction.remaining
+            }
+            Pass::Equiv => match classes::equivalences(&clauses) {
+                Some(repr) => {
+                    let substituted = (1..).zip(&repr).filter(|&(var, &lit)| lit != var);
+                    log.entries
+                        .extend(substituted.map(|(var, &lit)| Entry::Equivalent { var, lit }));
+                    classes::substitute(&clauses, &repr)
+                }
+                None => vec![Vec::new()],
+            },
+            Pass::Bce => bce(clauses, &mut log),
+            Pass::Probe => probe(clauses, &mut log),
+            Pass::Vivify => vivify(clauses),
+            Pass::Elim => elim(clauses, frozen, &mut log),
+            Pass::Amo => {
+                let (clauses, found) = amo(clauses, &mut next_var);
+                groups = Some(found);
+                clauses
+            }
+        };
+        let after = size(&clauses);
+        stats.push(PassStats {
+            pass,
+            clauses: (before.0, after.0),
+            variables: (before.1, after.1),
+            literals: (before.2, after.2),
+            time: start.elapsed(),
+            groups,
+        });
+    }
+    Simplified {
+        clauses,
+        log,
+        stats,
+    }
+}
+
+/// Clauses, distinct variables and literal occurrences of a formula.
+fn size(clauses: &[Vec<i32>]) -> (usize, usize, usize) {
+    let vars: HashSet<u32> = clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.unsigned_abs())
+        .collect();
+    let literals = clauses.iter().map(Vec::len).sum();
+    (clauses.len(), vars.len(), literals)
+}
+
+fn index(lit: i32) -> usize {
+    2 * (lit.unsigned_abs() as usize - 1) + usize::from(lit < 0)
+}
+
+fn max_var(clauses: &[Vec<i32>]) -> usize {
+    clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.unsigned_abs() as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Clause indices of every literal.
+fn occurrences(clauses: &[Vec<i32>]) -> Vec<Vec<usize>> {
+    let mut occurs = vec![Vec::new(); 2 * max_var(clauses)];
+    for (i, clause) in clauses.iter().enumerate() {
+        for &lit in clause {
+            occurs[index(lit)].push(i);
+        }
+    }
+    occurs
+}
+
+/// Removes clauses that are blocked on one of their literals: every
+/// resolvent on it with the remaining clauses is a tautology.
+fn bce(clauses: Vec<Vec<i32>>, log: &mut Log) -> Vec<Vec<i32>> {
+    let occurs = occurrences(&clauses);
+    let mut removed = vec![false; clauses.len()];
+    // Literals of the clause being checked, by `index`.
+    let mut marked = vec![false; occurs.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, clause) in clauses.iter().enumerate() {
+            if removed[i] {
+                continue;
+            }
+            for &lit in clause {
+                marked[index(lit)] = true;
+            }
+            let blocked = clause.iter().copied().find(|&lit| {
+                occurs[index(-lit)].iter().all(|&j| {
+                    removed[j] || clauses[j].iter().any(|&l| l != -lit && marked[index(-l)])
+                })
+            });
+            for &lit in clause {
+                marked[index(lit)] = false;
+            }
+            if let Some(lit) = blocked {
+                removed[i] = true;
+                log.entries.push(Entry::Witness {
+                    lit,
+                    clause: clause.clone(),
+                });
+                changed = true;
+            }
+        }
+    }
+    clauses
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(clause, _)| clause)
+        .collect()
+}
+
+/// Unit propagation over a clause list with occurrence lists.
+struct Propagator {
+    clauses: Vec<Vec<i32>>,
+    occurs: Vec<Vec<usize>>,
+    /// 1 true, -1 false, 0 unassigned; indexed by variable.
+    value: Vec<i8>,
+    trail: Vec<i32>,
+}
+
+impl Propagator {
+    fn new(clauses: Vec<Vec<i32>>) -> Self {
+        let occurs = occurrences(&clauses);
+        let value = vec![0; occurs.len() / 2 + 1];
+        Self {
+            clauses,
+            occurs,
+            value,
+            trail: Vec::new(),
+        }
+    }
+
+    fn lit_value(&self, lit: i32) -> i8 {
+        let value = self.value[lit.unsigned_abs() as usize];
+        if lit > 0 { value } else { -value }
+    }
+
+    fn assign(&mut self, lit: i32) {
+        self.value[lit.unsigned_abs() as usize] = if lit > 0 { 1 } else { -1 };
+        self.trail.push(lit);
+    }
+
+    /// Propagates the trail from position `from`, ignoring clause `skip`;
+    /// false on a conflict.
+    fn propagate(&mut self, mut from: usize, skip: Option<usize>) -> bool {
+        while from < self.trail.len() {
+            let falsified = index(-self.trail[from]);
+            from += 1;
+            for k in 0..self.occurs[falsified].len() {
+                let c = self.occurs[falsified][k];
+                if Some(c) == skip {
+                    continue;
+                }
+                let mut unit = None;
+                let mut open = 0;
+                let mut satisfied = false;
+                for &lit in &self.clauses[c] {
+                    match self.lit_value(lit) {
+                        1 => {
+                            satisfied = true;
+                            break;
+                        }
+                        0 => {
+                            open += 1;
+                            unit = Some(lit);
+                        }
+                        _ => {}
+                    }
+                }
+                match (satisfied, open, unit) {
+                    (true, _, _) => {}
+                    (false, 0, _) => return false,
+                    (false, 1, Some(unit)) => self.assign(unit),
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    fn backtrack(&mut self, len: usize) {
+        for lit in self.trail.drain(len..) {
+            self.value[lit.unsigned_abs() as usize] = 0;
+        }
+    }
+
+    /// Assigns and propagates the unit clauses; false on a conflict.
+    fn propagate_units(&mut self) -> bool {
+        for c in 0..self.clauses.len() {
+            match self.clauses[c].as_slice() {
+                [] => return false,
+                &[lit] => match self.lit_value(lit) {
+                    0 => self.assign(lit),
+                    -1 => return false,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        self.propagate(0, None)
+    }
+
+    /// The clauses with the assignment applied: satisfied clauses and false
+    /// literals are dropped.
+    fn applied(&self) -> Vec<Vec<i32>> {
+        self.clauses
+            .iter()
+            .filter(|clause| !clause.iter().any(|&lit| self.lit_value(lit) > 0))
+            .map(|clause| {
+                let open = clause.iter().filter(|&&lit| self.lit_value(lit) == 0);
+                open.copied().collect()
+            })
+            .collect()
+    }
+
+    /// [`Propagator::applied`], with the assigned literals logged.
+    fn finish(self, log: &mut Log) -> Vec<Vec<i32>> {
+        if self.trail.is_empty() {
+            return self.clauses;
+        }
+        let clauses = self.applied();
+        log.entries.push(Entry::Set(self.trail));
+        clauses
+    }
+}
+
+/// `clauses` conditioned on the partial assignment `lits` and simplified by
+/// unit propagation: the remaining clauses, over none of the assigned
+/// variables, and every literal assigned or implied. `None` if propagation
+/// runs into a conflict.
+pub fn cofactor(clauses: Vec<Vec<i32>>, lits: &[i32]) -> Option<(Vec<Vec<i32>>, Vec<i32>)> {
+    let mut propagator = Propagator::new(clauses.into_iter().filter_map(normalize).collect());
+    // Variables no clause mentions are assigned, but cannot imply anything.
+    let mut unused: Vec<i32> = Vec::new();
+    for &lit in lits {
+        if lit.unsigned_abs() as usize >= propagator.value.len() {
+            if unused.contains(&-lit) {
+                return None;
+            }
+            if !unused.contains(&lit) {
+                unused.push(lit);
+            }
+            continue;
+        }
+        match propagator.lit_value(lit) {
+            0 => propagator.assign(lit),
+            -1 => return None,
+            _ => {}
+        }
+    }
+    if !propagator.propagate_units() {
+        return None;
+    }
+    let clauses = propagator.applied();
+    let mut implied = propagator.trail;
+    implied.extend(unused);
+    Some((clauses, implied))
+}
+
+/// Assigns the negation of every literal whose propagation fails.
+fn probe(clauses: Vec<Vec<i32>>, log: &mut Log) -> Vec<Vec<i32>> {
+    let mut propagator = Propagator::new(clauses);
+    if !propagator.propagate_units() {
+        return vec![Vec::new()];
+    }
+    for var in 1..propagator.value.len() as i32 {
+        for lit in [var, -var] {
+            if propagator.value[var as usize] != 0 {
+                break;
+            }
+            let mark = propagator.trail.len();
+            propagator.assign(lit);
+            let failed = !propagator.propagate(mark, None);
+            propagator.backtrack(mark);
+            if failed {
+                propagator.assign(-lit);
+                if !propagator.propagate(mark, None) {
+                    return vec![Vec::new()];
+                }
+            }
+        }
+    }
+    propagator.finish(log)
+}
+
+/// Shortens clauses by propagating the negations of their literals over the
+/// other clauses: a conflict, or a literal of the clause becoming true or
+/// false, shows that fewer of its literals suffice.
+fn vivify(clauses: Vec<Vec<i32>>) -> Vec<Vec<i32>> {
+    let mut propagator = Propagator::new(clauses);
+    if !propagator.propagate_units() {
+        return vec![Vec::new()];
+    }
+    for c in 0..propagator.clauses.len() {
+        let clause = propagator.clauses[c].clone();
+        if clause.len() < 3 || clause.iter().any(|&lit| propagator.lit_value(lit) > 0) {
+            continue;
+        }
+        let mark = propagator.trail.len();
+        let mut kept = Vec::new();
+        for &lit in &clause {
+            match propagator.lit_value(lit) {
+                1 => {
+                    kept.push(lit);
+                    break;
+                }
+                -1 => {}
+                _ => {
+                    kept.push(lit);
+                    propagator.assign(-lit);
+                    if !propagator.propagate(mark, Some(c)) {
+                        break;
+                    }
+                }
+            }
+        }
+        propagator.backtrack(mark);
+        if kept.len() < clause.len() {
+            if let &[unit] = kept.as_slice() {
+                propagator.assign(unit);
+                if !propagator.propagate(mark, None) {
+                    return vec![Vec::new()];
+                }
+            }
+            propagator.clauses[c] = kept;
+        }
+    }
+    // The units found stay as unit clauses; nothing needs reconstruction.
+    propagator.clauses
+}
+
+/// Longest resolvent variable elimination may add.
+const ELIM_CLAUSE_LIMIT: usize = 20;
+
+/// Eliminates every variable whose non-tautological resolvents are no more
+/// than the clauses they replace.
+fn elim(clauses: Vec<Vec<i32>>, frozen: &[i32], log: &mut Log) -> Vec<Vec<i32>> {
+    let mut occurs = occurrences(&clauses);
+    let mut clauses: Vec<Option<Vec<i32>>> = clauses.into_iter().map(Some).collect();
+    let vars = occurs.len() / 2;
+    let mut order: Vec<i32> = (1..=vars as i32)
+        .filter(|var| !frozen.contains(var))
+        .collect();
+    order.sort_by_key(|&var| occurs[index(var)].len() * occurs[index(-var)].len());
+    for var in order {
+        let live = |lit: i32, clauses: &[Option<Vec<i32>>]| -> Vec<usize> {
+            occurs[index(lit)]
+                .iter()
+                .copied()
+                .filter(|&c| {
+                    clauses[c]
+                        .as_ref()
+                        .is_some_and(|clause| clause.contains(&lit))
+                })
+                .collect()
+        };
+        let (pos, neg) = (live(var, &clauses), live(-var, &clauses));
+        if pos.is_empty() && neg.is_empty() {
+            continue;
+        }
+        let mut resolvents = Vec::new();
+        let mut eliminable = true;
+        'resolve: for &p in &pos {
+            for &n in &neg {
+                let (Some(p), Some(n)) = (&clauses[p], &clauses[n]) else {
+                    continue;
+                };
+                if let Some(resolvent) = resolve(p, n, var) {
+                    if resolvent.len() > ELIM_CLAUSE_LIMIT
+                        || resolvents.len() == pos.len() + neg.len()
+                    {
+                        eliminable = false;
+                        break 'resolve;
+                    }
+                    resolvents.push(resolvent);
+                }
+            }
+        }
+        if !eliminable {
+            continue;
+        }
+        for (lit, removed) in [(var, &pos), (-var, &neg)] {
+            for &c in removed {
+                if let Some(clause) = clauses[c].take() {
+                    log.entries.push(Entry::Witness { lit, clause });
+                }
+            }
+        }
+        for resolvent in resolvents {
+            for &lit in &resolvent {
+                occurs[index(lit)].push(clauses.len());
+            }
+            let empty = resolvent.is_empty();
+            clauses.push(Some(resolvent));
+            if empty {
+                break;
+            }
+        }
+    }
+    clauses.into_iter().flatten().collect()
+}
+
+/// Smallest at-most-one group worth re-encoding: below it the pairwise
+/// clauses are no more than the `3n - 4` of the sequential encoding.
+const AMO_MIN_GROUP: usize = 6;
+
+/// Finds groups of literals that are pairwise exclusive through binary
+/// clauses `¬a ∨ ¬b`, as encoders write at-most-one constraints, and
+/// replaces the pairwise clauses of every group of at least
+/// [`AMO_MIN_GROUP`] literals by the sequential encoding, with auxiliary
+/// variables after `next_var`. Returns the clauses and the number of groups.
+///
+/// Groups are grown greedily from the literals in the most exclusions, and
+/// a literal joins at most one. The auxiliary variables are defined by the
+/// group's literals, so a model of the output is one of the input.
+fn amo(clauses: Vec<Vec<i32>>, next_var: &mut i32) -> (Vec<Vec<i32>>, usize) {
+    let vars = max_var(&clauses);
+    // exclusive[index(a)]: the literals b of the clauses ¬a ∨ ¬b.
+    let mut exclusive = vec![HashSet::new(); 2 * vars];
+    for clause in &clauses {
+        if let &[a, b] = clause.as_slice() {
+            exclusive[index(-a)].insert(-b);
+            exclusive[index(-b)].insert(-a);
+        }
+    }
+    let degree = |lit: i32| exclusive[index(lit)].len();
+    let mut seeds: Vec<i32> = (1..=vars as i32)
+        .flat_map(|var| [var, -var])
+        .filter(|&lit| degree(lit) + 1 >= AMO_MIN_GROUP)
+        .collect();
+    seeds.sort_by_key(|&lit| Reverse(degree(lit)));
+    // group[index(lit)]: the group `lit` joined.
+    let mut group = vec![None; 2 * vars];
+    let mut groups: Vec<Vec<i32>> = Vec::new();
+    for seed in seeds {
+        if group[index(seed)].is_some() {
+            continue;
+        }
+        let mut candidates: Vec<i32> = exclusive[index(seed)]
+            .iter()
+            .copied()
+            .filter(|&lit| group[index(lit)].is_none())
+            .collect();
+        candidates.sort_by_key(|&lit| (Reverse(degree(lit)), lit));
+        let mut members = vec![seed];
+        for lit in candidates {
+            if members
+                .iter()
+                .all(|member| exclusive[index(lit)].contains(member))
+            {
+                members.push(lit);
+            }
+        }
+        if members.len() >= AMO_MIN_GROUP {
+            for &lit in &members {
+                group[index(lit)] = Some(groups.len());
+            }
+            groups.push(members);
+        }
+    }
+    let mut reduced: Vec<Vec<i32>> = clauses
+        .into_iter()
+        .filter(|clause| match clause.as_slice() {
+            &[a, b] => group[index(-a)].is_none() || group[index(-a)] != group[index(-b)],
+            _ => true,
+        })
+        .collect();
+    for members in &groups {
+        reduced.extend(card::at_most(members, 1, next_var));
+    }
+    (reduced, groups.len())
+}
+
+/// The resolvent of `p` (containing `var`) and `n` (containing `-var`), or
+/// `None` if it is a tautology.
+fn resolve(p: &[i32], n: &[i32], var: i32) -> Option<Vec<i32>> {
+    normalize(p.iter().chain(n).copied().filter(|lit| lit.abs() != var).collect())
+}
+
+/// `clause` sorted without duplicate literals, or `None` if it is a
+/// tautology.
+fn normalize(mut clause: Vec<i32>) -> Option<Vec<i32>> {
+    clause.sort_unstable_by_key(|lit| (lit.abs(), *lit));
+    clause.dedup();
+    let tautology = clause.windows(2).any(|w| w[0] == -w[1]);
+    (!tautology).then_some(clause)
+}
+
+#[derive(Args)]
+pub struct Arg {
+    /// CNF to simplify (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Comma-separated passes, run in this order; a pass may appear more than once
+    #[arg(
+        long,
+        value_name = "PASSES",
+        value_delimiter = ',',
+        value_enum,
+        required = true
+    )]
+    passes: Vec<Pass>,
+    /// Where the simplified CNF is written; default for stdout
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Write the reconstruction log to this file, for `analyze reconstruct`
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+    /// Variables that variable elimination must keep
+    #[arg(long = "freeze", value_name = "VARS", value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..))]
+    freeze: Vec<i32>,
+    /// Keep the input's comment lines that start with these comma-separated prefixes, or all if none are given, and write them ahead of the simplified CNF
+    #[arg(long = "keep-comments", value_name = "PREFIXES", num_args(0..=1), value_delimiter = ',')]
+    keep_comments: Option<Vec<String>>,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let reader = open_input(self.input.as_deref())?;
+        let (clauses, comments) = read_commented(reader, self.keep_comments.as_deref())?;
+        let simplified = run(clauses, &self.passes, &self.freeze);
+        // The statistics go to stdout as comments ahead of the formula.
+        let mut out: Box<dyn Write> = match non_dash(self.out.as_deref()) {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).with_context(|| format!("Cannot create {}", path.display()))?,
+            )),
+            None => Box::new(io::stdout()),
+        };
+        for stats in &simplified.stats {
+            println!("{}", stats.line());
+        }
+        write_comments(&mut out, &comments)?;
+        write_dimacs(&mut out, &simplified.clauses)?;
+        if let Some(path) = &self.log {
+            std::fs::write(path, simplified.log.text())
+                .with_context(|| format!("Cannot write {}", path.display()))?;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satisfies(clauses: &[Vec<i32>], model: &[i32]) -> bool {
+        clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|&lit| model[lit.unsigned_abs() as usize - 1] == lit)
+        })
+    }
+
+    /// Every model of `clauses` over `vars` variables.
+    fn models(clauses: &[Vec<i32>], vars: usize) -> Vec<Vec<i32>> {
+        (0..1u32 << vars)
+            .map(|bits| {
+                (1..=vars as i32)
+                    .map(|var| {
+                        if bits >> (var - 1) & 1 == 1 {
+                            var
+                        } else {
+                            -var
+                        }
+                    })
+                    .collect::<Vec<i32>>()
+            })
+            .filter(|model| satisfies(clauses, model))
+            .collect()
+    }
+
+    /// Formulas of up to `len` clauses of up to `width` literals over
+    /// `vars` variables, from a fixed pseudo-random sequence.
+    fn formulas(count: usize, vars: u32, len: u32, width: u32) -> Vec<Vec<Vec<i32>>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |bound: u32| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % u64::from(bound)) as u32
+        };
+        (0..count)
+            .map(|_| {
+                (0..next(len) + 1)
+                    .map(|_| {
+                        (0..next(width) + 1)
+                            .map(|_| {
+                                let var = (next(vars) + 1) as i32;
+                                if next(2) == 0 { var } else { -var }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Runs `passes` on `clauses` over `vars` variables and checks that the
+    /// result is satisfiable exactly when `clauses` are, and that the log
+    /// extends each of its models to a model of `clauses`.
+    fn check(clauses: &[Vec<i32>], vars: usize, passes: &[Pass]) -> Simplified {
+        let simplified = run(clauses.to_vec(), passes, &[]);
+        let satisfiable = !models(clauses, vars).is_empty();
+        if simplified.clauses.iter().any(Vec::is_empty) {
+            assert!(!satisfiable, "{passes:?} refuted {clauses:?}");
+            return simplified;
+        }
+        let out = models(&simplified.clauses, vars.max(max_var(&simplified.clauses)));
+        assert_eq!(!out.is_empty(), satisfiable, "{passes:?} on {clauses:?}");
+        for model in &out {
+            let extended = simplified.log.extend(model);
+            assert!(
+                satisfies(clauses, &extended),
+                "{passes:?} on {clauses:?}: {model:?} extends to {extended:?}"
+            );
+        }
+        simplified
+    }
+
+    #[test]
+    fn passes_preserve_models() {
+        let passes = [Pass::Bce, Pass::Probe, Pass::Vivify, Pass::Elim, Pass::Amo];
+        for clauses in formulas(200, 6, 14, 3) {
+            for pass in passes {
+                check(&clauses, 6, &[pass]);
+            }
+            check(&clauses, 6, &passes);
+        }
+    }
+
+    #[test]
+    fn amo_group_preserves_models() {
+        // Variables 1 to 6 are pairwise exclusive, and one of them is true.
+        let mut clauses = vec![
+            vec![1, 2, 3, 4, 5, 6],
+            vec![-1, 7],
+            vec![-7, 2, 8],
+            vec![-8, -3],
+        ];
+        for a in 1..=6 {
+            for b in a + 1..=6 {
+                clauses.push(vec![-a, -b]);
+            }
+        }
+        let simplified = check(&clauses, 8, &[Pass::Amo]);
+        assert_eq!(simplified.stats[0].groups, Some(1));
+        // The 15 pairwise clauses became 3·6 - 4 sequential ones.
+        assert_eq!(simplified.clauses.len(), 4 + 14);
+    }
+}
@@ -0,0 +1,370 @@
+//! Solving jobs pulled from a Redis list, so any number of machines can share
+//! one queue without a service of their own in front of it.
+//!
+//! A job is a JSON object pushed onto the jobs list (`LPUSH satgalaxy:jobs
+//! '{...}'`):
+//!
+//! ```json
+//! {"id": "42", "input": "https://example.org/x.cnf.xz", "solver": "glucose", "options": {"K": "0.7"}}
+//! ```
+//!
+//! `solver` defaults to the first compiled-in backend and `options` are
+//! backend options by their long flag name. Every job runs as a child
+//! `satgalaxy` process under the worker's `--cpu-lim` and `--mem-lim`, so a
+//! job exceeding them cannot take the worker down. The `--json` document of
+//! the run, with the job's `id` added, is pushed onto the results list, or
+//! onto the job's `reply_to` list if it names one; a job that could not be
+//! run gets an `error` document instead.
+//!
+//! A job is moved atomically onto the processing list when it is pulled and
+//! removed from there in the transaction that pushes its result, so the
+//! jobs of a worker that died are left on the processing list rather than
+//! lost, and can be pushed back onto the jobs list.
+use std::{
+    convert::Infallible,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+use clap::Args;
+use validator::Validate;
+
+use crate::{
+    Solver,
+    core::{describe_megabytes, describe_seconds, parse_megabytes, parse_seconds},
+    isolate::{Input, Isolated},
+};
+
+/// Seconds a worker blocks on an empty queue before asking again.
+const POLL: &str = "5";
+
+/// Largest bulk string accepted in a reply; jobs name their input rather
+/// than carry it, so this is far beyond any real one.
+const MAX_BULK: i64 = 16 << 20;
+
+/// Most elements accepted in an array reply.
+const MAX_ARRAY: i64 = 1024;
+
+/// Longest wait between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// Queue to pull jobs from: redis://[[USER]:PASSWORD@]HOST[:PORT][/DB]
+    #[arg(long, value_name = "URL")]
+    queue: String,
+    /// List the jobs are pulled from
+    #[arg(long, value_name = "KEY", default_value = "satgalaxy:jobs")]
+    jobs_key: String,
+    /// List the jobs being solved are kept on, until their result is pushed
+    #[arg(long, value_name = "KEY", default_value = "satgalaxy:processing")]
+    processing_key: String,
+    /// List the results are pushed onto, unless a job names its own `reply_to`
+    #[arg(long, value_name = "KEY", default_value = "satgalaxy:results")]
+    results_key: String,
+    /// Number of jobs solved at the same time [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    #[validate(range(min = 1, message = "Jobs must be at least 1"))]
+    jobs: Option<usize>,
+    /// CPU time limit of every job, e.g. 90s or 2h; 0 for none
+    #[arg(long = "cpu-lim", value_name = "DURATION", default_value = "0", value_parser = parse_seconds)]
+    cpu_lim: u64,
+    /// Memory limit of every job, e.g. 512MB or 8GiB; 0 for none
+    #[arg(long = "mem-lim", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes)]
+    mem_lim: u64,
+}
+
+/// A job as pulled from the queue.
+struct Job {
+    id: serde_json::Value,
+    input: String,
+    solver: String,
+    options: Vec<(String, String)>,
+    reply_to: Option<String>,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let jobs = self
+            .jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        // Fail now rather than in every thread.
+        Redis::connect(&self.queue)?;
+        println!("c Pulling jobs from {} with {} slots", self.jobs_key, jobs);
+        if self.cpu_lim > 0 {
+            println!("c CPU limit: {}", describe_seconds(self.cpu_lim));
+        }
+        if self.mem_lim > 0 {
+            println!("c Memory limit: {}", describe_megabytes(self.mem_lim));
+        }
+        thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(|| self.serve());
+            }
+        });
+        Ok(0)
+    }
+
+    /// Pulls and solves jobs until the process is stopped, reconnecting
+    /// whenever the connection to the queue fails.
+    fn serve(&self) {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let error = match Redis::connect(&self.queue) {
+                Ok(mut redis) => {
+                    backoff = Duration::from_secs(1);
+                    let Err(e) = self.pull(&mut redis);
+                    e
+                }
+                Err(e) => e,
+            };
+            println!("c WARNING: queue: {:#}, retrying in {:?}", error, backoff);
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Pulls and solves jobs until the connection fails.
+    fn pull(&self, redis: &mut Redis) -> anyhow::Result<Infallible> {
+        loop {
+            let pulled = redis.command(&[
+                "BLMOVE",
+                &self.jobs_key,
+                &self.processing_key,
+                "RIGHT",
+                "LEFT",
+                POLL,
+            ])?;
+            let text = match pulled {
+                Reply::Bulk(text) => text,
+                Reply::Nil => continue,
+                _ => bail!("unexpected BLMOVE reply"),
+            };
+            let (reply_to, result) = match parse_job(&text) {
+                Ok(job) => {
+                    let start = Instant::now();
+                    let mut result = self.solve(&job).unwrap_or_else(|e| error_json(&e));
+                    println!(
+                        "c Job {}: {} in {:?}",
+                        job.id,
+                        result["status"].as_str().unwrap_or("ERROR"),
+                        start.elapsed()
+                    );
+                    result["id"] = job.id;
+                    (job.reply_to, result)
+                }
+                Err(e) => {
+                    println!("c WARNING: invalid job skipped: {:#}", e);
+                    (None, error_json(&e))
+                }
+            };
+            let key = reply_to.as_deref().unwrap_or(&self.results_key);
+            redis.command(&["MULTI"])?;
+            redis.command(&["LPUSH", key, &result.to_string()])?;
+            redis.command(&["LREM", &self.processing_key, "1", &text])?;
+            redis.command(&["EXEC"])?;
+        }
+    }
+
+    /// Runs `job` in a child process and returns its `--json` document.
+    fn solve(&self, job: &Job) -> anyhow::Result<serde_json::Value> {
+        let solver = Solver::from_args(&job.solver, &job.options)?;
+        let isolated = Isolated {
+            cpu_lim: self.cpu_lim,
+            mem_lim: self.mem_lim,
+            ..Default::default()
+        };
+        isolated.solve(solver.name(), &job.options, Input::Path(&job.input))
+    }
+}
+
+fn parse_job(text: &str) -> anyhow::Result<Job> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    let input = json["input"]
+        .as_str()
+        .ok_or_else(|| anyhow!("job has no `input`"))?
+        .to_string();
+    let solver = match json["solver"].as_str() {
+        Some(name) => name.to_string(),
+        None => Solver::all()
+            .first()
+            .map(|s| s.name().to_string())
+            .unwrap_or_default(),
+    };
+    let mut options = Vec::new();
+    if let Some(map) = json["options"].as_object() {
+        for (name, value) in map {
+            if matches!(name.as_str(), "cpu-lim" | "mem-lim" | "max-vars" | "max-clauses" | "json") {
+                bail!("`{name}` is set by the worker, not the job");
+            }
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Bool(true) | serde_json::Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            options.push((name.clone(), value));
+        }
+    }
+    Ok(Job {
+        id: json["id"].clone(),
+        input,
+        solver,
+        options,
+        reply_to: json["reply_to"].as_str().map(str::to_string),
+    })
+}
+
+fn error_json(error: &anyhow::Error) -> serde_json::Value {
+    crate::hook::error_summary(error, None)
+}
+
+/// Reply of a Redis command.
+enum Reply {
+    Nil,
+    /// A status or an integer, whose value is not needed.
+    Done,
+    Bulk(String),
+    Array(Vec<Reply>),
+}
+
+/// A minimal Redis client speaking RESP2 over one connection.
+struct Redis<S = TcpStream> {
+    reader: BufReader<S>,
+}
+
+impl Redis {
+    /// Connects to `redis://[[USER]:PASSWORD@]HOST[:PORT][/DB]`,
+    /// authenticating and selecting the database if the URL says so.
+    fn connect(url: &str) -> anyhow::Result<Self> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| anyhow!("`{url}` is not a redis:// URL"))?;
+        let (auth, rest) = match rest.rsplit_once('@') {
+            Some((auth, rest)) => (Some(auth), rest),
+            None => (None, rest),
+        };
+        let (host, db) = rest.split_once('/').unwrap_or((rest, ""));
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:6379")
+        };
+        let stream = TcpStream::connect(&address)
+            .map_err(|e| anyhow!("Cannot connect to {}: {}", address, e))?;
+        let mut redis = Self {
+            reader: BufReader::new(stream),
+        };
+        match auth.map(|a| a.split_once(':').unwrap_or(("", a))) {
+            Some(("", password)) => redis.command(&["AUTH", password]).map(drop)?,
+            Some((user, password)) => redis.command(&["AUTH", user, password]).map(drop)?,
+            None => {}
+        }
+        if !db.is_empty() {
+            redis.command(&["SELECT", db])?;
+        }
+        Ok(redis)
+    }
+}
+
+impl<S: Read + Write> Redis<S> {
+    fn command(&mut self, args: &[&str]) -> io::Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request += &format!("${}\r\n{}\r\n", arg.len(), arg);
+        }
+        let stream = self.reader.get_mut();
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+        self.reply()
+    }
+
+    fn reply(&mut self) -> io::Result<Reply> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+        let line = line.trim_end();
+        let invalid = || io::Error::other(format!("invalid reply `{line}`"));
+        let (kind, body) = line.split_at_checked(1).ok_or_else(invalid)?;
+        let length = || body.parse::<i64>().map_err(|_| invalid());
+        match kind {
+            "+" | ":" => Ok(Reply::Done),
+            "-" => Err(io::Error::other(body.to_string())),
+            "$" => match length()? {
+                -1 => Ok(Reply::Nil),
+                n if !(0..=MAX_BULK).contains(&n) => Err(invalid()),
+                n => {
+                    let mut data = vec![0; n as usize + 2];
+                    self.reader.read_exact(&mut data)?;
+                    data.truncate(n as usize);
+                    Ok(Reply::Bulk(String::from_utf8_lossy(&data).into_owned()))
+                }
+            },
+            "*" => match length()? {
+                -1 => Ok(Reply::Nil),
+                n if !(0..=MAX_ARRAY).contains(&n) => Err(invalid()),
+                n => (0..n).map(|_| self.reply()).collect::<io::Result<_>>().map(Reply::Array),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(bytes: &str) -> io::Result<Reply> {
+        let mut redis = Redis {
+            reader: BufReader::new(io::Cursor::new(bytes.as_bytes().to_vec())),
+        };
+        redis.reply()
+    }
+
+    #[test]
+    fn replies() {
+        assert!(matches!(reply("+OK\r\n"), Ok(Reply::Done)));
+        assert!(matches!(reply(":3\r\n"), Ok(Reply::Done)));
+        assert!(matches!(reply("$-1\r\n"), Ok(Reply::Nil)));
+        assert!(matches!(reply("*-1\r\n"), Ok(Reply::Nil)));
+        assert!(matches!(reply("$3\r\nabc\r\n"), Ok(Reply::Bulk(text)) if text == "abc"));
+        assert!(matches!(reply("$0\r\n\r\n"), Ok(Reply::Bulk(text)) if text.is_empty()));
+        let Ok(Reply::Array(items)) = reply("*3\r\n$1\r\na\r\n:1\r\n$-1\r\n") else {
+            panic!("expected an array");
+        };
+        assert!(matches!(
+            items.as_slice(),
+            [Reply::Bulk(a), Reply::Done, Reply::Nil] if a == "a"
+        ));
+    }
+
+    #[test]
+    fn invalid_replies() {
+        let bulk = format!("${}\r\n", MAX_BULK + 1);
+        let array = format!("*{}\r\n", MAX_ARRAY + 1);
+        for bytes in [
+            "$-2\r\n",
+            "$-9223372036854775808\r\n",
+            "*-2\r\n",
+            bulk.as_str(),
+            array.as_str(),
+            "$99999999999999999999\r\n",
+            "$abc\r\n",
+            "$3\r\nab",
+            "*2\r\n:1\r\n",
+            "-ERR wrong type\r\n",
+            "?\r\n",
+            "\r\n",
+            "",
+        ] {
+            assert!(reply(bytes).is_err(), "{bytes:?}");
+        }
+    }
+}
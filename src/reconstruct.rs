@@ -0,0 +1,153 @@
+//! Reconstruction logs: what a simplification did to a formula, so a model
+//! of the simplified formula can be extended to the original.
+//!
+//! The text format has one entry per line, after any `c` comments:
+//!
+//! ```text
+//! c satgalaxy reconstruction log
+//! 3 -5 0
+//! e 4 -1 0
+//! w 2 2 -6 7 0
+//! ```
+//!
+//! A line of literals sets them true (pure literals, autarkies); `e VAR LIT`
+//! gives `VAR` the value of `LIT` (equivalent literals); `w LIT CLAUSE` sets
+//! `LIT` true if the removed `CLAUSE` is false (blocked clauses, eliminated
+//! variables). Entries are undone last to first.
+use std::fmt::Write as _;
+
+use anyhow::{Context, anyhow};
+
+#[derive(Clone)]
+pub enum Entry {
+    /// Literals set true.
+    Set(Vec<i32>),
+    /// A variable substituted by an equivalent literal.
+    Equivalent { var: i32, lit: i32 },
+    /// A removed clause and the literal of it that can be made true to
+    /// satisfy it again.
+    Witness { lit: i32, clause: Vec<i32> },
+}
+
+#[derive(Default)]
+pub struct Log {
+    pub entries: Vec<Entry>,
+}
+
+impl Log {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('c') {
+                continue;
+            }
+            let entry = parse_entry(line).with_context(|| format!("line {}", i + 1))?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// The log of substituting every variable by its representative from
+    /// [`crate::classes::equivalences`].
+    pub fn equivalences(representatives: &[i32]) -> Self {
+        Self {
+            entries: (1..)
+                .zip(representatives)
+                .filter(|&(var, &lit)| lit != var)
+                .map(|(var, &lit)| Entry::Equivalent { var, lit })
+                .collect(),
+        }
+    }
+
+    /// The log in its text format.
+    pub fn text(&self) -> String {
+        let mut text = String::from("c satgalaxy reconstruction log\n");
+        for entry in &self.entries {
+            match entry {
+                Entry::Set(lits) => {
+                    for lit in lits {
+                        let _ = write!(text, "{} ", lit);
+                    }
+                }
+                Entry::Equivalent { var, lit } => {
+                    let _ = write!(text, "e {} {} ", var, lit);
+                }
+                Entry::Witness { lit, clause } => {
+                    let _ = write!(text, "w {} ", lit);
+                    for lit in clause {
+                        let _ = write!(text, "{} ", lit);
+                    }
+                }
+            }
+            text += "0\n";
+        }
+        text
+    }
+
+    /// Extends a model of the simplified formula to every variable the model
+    /// or the log mentions; variables neither gives a value are false.
+    pub fn extend(&self, model: &[i32]) -> Vec<i32> {
+        let vars = model
+            .iter()
+            .copied()
+            .chain(self.entries.iter().flat_map(|entry| match entry {
+                Entry::Set(lits) => lits.clone(),
+                Entry::Equivalent { var, lit } => vec![*var, *lit],
+                Entry::Witness { clause, .. } => clause.clone(),
+            }))
+            .map(|lit| lit.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0);
+        let mut extended: Vec<i32> = (1..=vars as i32).map(|var| -var).collect();
+        for &lit in model {
+            extended[lit.unsigned_abs() as usize - 1] = lit;
+        }
+        for entry in self.entries.iter().rev() {
+            match entry {
+                Entry::Set(lits) => {
+                    for &lit in lits {
+                        extended[lit.unsigned_abs() as usize - 1] = lit;
+                    }
+                }
+                Entry::Equivalent { var, lit } => {
+                    let value = extended[lit.unsigned_abs() as usize - 1] == *lit;
+                    extended[*var as usize - 1] = if value { *var } else { -var };
+                }
+                Entry::Witness { lit, clause } => {
+                    if !clause.iter().any(|&l| extended[l.unsigned_abs() as usize - 1] == l) {
+                        extended[lit.unsigned_abs() as usize - 1] = *lit;
+                    }
+                }
+            }
+        }
+        extended
+    }
+}
+
+fn parse_entry(line: &str) -> anyhow::Result<Entry> {
+    let (kind, rest) = match line.split_once(' ') {
+        Some((kind @ ("e" | "w"), rest)) => (kind, rest),
+        _ => ("", line),
+    };
+    let mut lits = Vec::new();
+    for token in rest.split_whitespace() {
+        let lit: i32 = token
+            .parse()
+            .map_err(|_| anyhow!("invalid literal `{token}`"))?;
+        if lit == 0 {
+            break;
+        }
+        lits.push(lit);
+    }
+    match (kind, lits.as_slice()) {
+        ("", _) => Ok(Entry::Set(lits)),
+        ("e", &[var, lit]) if var > 0 => Ok(Entry::Equivalent { var, lit }),
+        ("e", _) => Err(anyhow!("`e` takes a variable and a literal")),
+        (_, [lit, clause @ ..]) if clause.contains(lit) => Ok(Entry::Witness {
+            lit: *lit,
+            clause: clause.to_vec(),
+        }),
+        _ => Err(anyhow!("`w` takes a literal and a clause containing it")),
+    }
+}
@@ -0,0 +1,431 @@
+//! Cactus and scatter plot data from stored results.
+//!
+//! Results are the `--json` documents of individual runs. The configuration
+//! of a run is the name of the directory its document is in, and its instance
+//! is the input's SHA-256, so instances are matched across configurations
+//! whatever their file names.
+//!
+//! With `--expect`, the results are also checked against known answers, as
+//! a regression test of the solvers: a run answering the opposite of its
+//! instance's expected status is a failure, and any failure makes the exit
+//! code 1. Runs without an answer are not failures, only counted.
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow, bail};
+use clap::{Args, ValueEnum};
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::core::hex;
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// `--json` result files, or directories searched recursively for *.json
+    #[arg(value_name = "RESULTS", required = true)]
+    results: Vec<PathBuf>,
+    /// Directory the data files are written to
+    #[arg(long, value_name = "DIR", default_value = "report")]
+    out: PathBuf,
+    /// Time to compare: wall-clock or CPU
+    #[arg(long, value_enum, default_value = "wall")]
+    time: Time,
+    /// Time plotted for unsolved runs in the scatter plot [default: the longest solved time]
+    #[arg(long, value_name = "SECONDS")]
+    #[validate(range(exclusive_min = 0.0, message = "Timeout must be positive"))]
+    timeout: Option<f64>,
+    /// The two configurations compared in the scatter plot [default: the first two]
+    #[arg(long, value_name = "A,B", value_delimiter = ',')]
+    #[validate(length(equal = 2, message = "Scatter takes exactly two configurations"))]
+    scatter: Option<Vec<String>>,
+    /// Also render cactus.svg and scatter.svg
+    #[arg(long)]
+    svg: bool,
+    /// Expected statuses, one `INSTANCE STATUS` per line: the instance as its SHA-256 or its path relative to FILE, the status SAT or UNSAT; a run answering otherwise fails the report
+    #[arg(long, value_name = "FILE")]
+    expect: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Time {
+    Wall,
+    Cpu,
+}
+
+struct Run {
+    instance: String,
+    /// Time to solve, `None` if the status was unknown.
+    time: Option<f64>,
+    status: String,
+    /// The result document.
+    document: PathBuf,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let mut configs: BTreeMap<String, Vec<Run>> = BTreeMap::new();
+        for path in &self.results {
+            self.load(path, &mut configs)?;
+        }
+        if configs.is_empty() {
+            return Err(anyhow!("No result documents found"));
+        }
+        let expected = match &self.expect {
+            Some(path) => read_expected(path)?,
+            None => BTreeMap::new(),
+        };
+        let timeout = self.timeout.unwrap_or_else(|| {
+            let longest = configs.values().flatten().filter_map(|r| r.time);
+            longest.fold(0.0, f64::max)
+        });
+        for (config, runs) in &configs {
+            let solved: Vec<_> = runs.iter().filter_map(|r| r.time).collect();
+            let par2: f64 = runs
+                .iter()
+                .map(|r| r.time.unwrap_or(2.0 * timeout))
+                .sum::<f64>()
+                / runs.len() as f64;
+            println!(
+                "c {}: {} of {} solved, {:.3}s solved time, PAR-2 {:.3}s",
+                config,
+                solved.len(),
+                runs.len(),
+                solved.iter().sum::<f64>(),
+                par2
+            );
+        }
+        fs::create_dir_all(&self.out)
+            .with_context(|| format!("Cannot create {}", self.out.display()))?;
+
+        let cactus = cactus(&configs);
+        let mut csv = String::from("config,solved,time\n");
+        for (config, times) in &cactus {
+            for (i, time) in times.iter().enumerate() {
+                writeln!(csv, "{},{},{}", quote(config), i + 1, time)?;
+            }
+        }
+        self.write("cactus.csv", &csv)?;
+        if self.svg {
+            self.write("cactus.svg", &cactus_svg(&cactus))?;
+        }
+
+        let names: Vec<_> = match &self.scatter {
+            Some(names) => names.clone(),
+            None => configs.keys().take(2).cloned().collect(),
+        };
+        if let [a, b] = names.as_slice() {
+            let points = scatter(&configs, a, b, timeout)?;
+            let mut csv = format!("instance,{},{}\n", quote(a), quote(b));
+            for (instance, x, y) in &points {
+                writeln!(csv, "{},{},{}", instance, x, y)?;
+            }
+            self.write("scatter.csv", &csv)?;
+            if self.svg {
+                self.write("scatter.svg", &scatter_svg(&points, a, b, timeout))?;
+            }
+        } else {
+            println!("c Only one configuration, no scatter plot");
+        }
+        if self.expect.is_some() && !check_expected(&configs, &expected) {
+            return Ok(1);
+        }
+        Ok(0)
+    }
+
+    fn load(&self, path: &Path, configs: &mut BTreeMap<String, Vec<Run>>) -> anyhow::Result<()> {
+        if path.is_dir() {
+            let mut entries: Vec<_> = fs::read_dir(path)
+                .with_context(|| format!("Cannot read {}", path.display()))?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<_, _>>()?;
+            entries.sort();
+            for entry in entries {
+                if entry.is_dir() || entry.extension().is_some_and(|e| e == "json") {
+                    self.load(&entry, configs)?;
+                }
+            }
+            return Ok(());
+        }
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let json: serde_json::Value = serde_json::from_str(&text)
+            .with_context(|| format!("{} is not valid JSON", path.display()))?;
+        let key = match self.time {
+            Time::Wall => "run_time",
+            Time::Cpu => "total_time",
+        };
+        let (Some(status), Some(instance), Some(time)) = (
+            json["status"].as_str(),
+            json["stats"]["input_sha256"].as_str(),
+            json["stats"][key].as_f64(),
+        ) else {
+            println!(
+                "c WARNING: {} is not a result document, skipped",
+                path.display()
+            );
+            return Ok(());
+        };
+        let config = path
+            .parent()
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| ".".to_string());
+        configs.entry(config).or_default().push(Run {
+            instance: instance.to_string(),
+            time: matches!(status, "SATISFIABLE" | "UNSATISFIABLE").then_some(time),
+            status: status.to_string(),
+            document: path.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn write(&self, name: &str, contents: &str) -> anyhow::Result<()> {
+        let path = self.out.join(name);
+        fs::write(&path, contents).with_context(|| format!("Cannot write {}", path.display()))?;
+        println!("c Wrote {}", path.display());
+        Ok(())
+    }
+}
+
+/// Solved times of every configuration in ascending order.
+/// The expected status of every instance of an `--expect` file, by SHA-256.
+fn read_expected(path: &Path) -> anyhow::Result<BTreeMap<String, &'static str>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut expected = BTreeMap::new();
+    for (number, line) in (1..).zip(text.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let context = || format!("{}, line {}", path.display(), number);
+        let [instance, status] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            bail!("{}: expected `INSTANCE STATUS`", context());
+        };
+        let status = match status {
+            "SAT" | "SATISFIABLE" => "SATISFIABLE",
+            "UNSAT" | "UNSATISFIABLE" => "UNSATISFIABLE",
+            _ => bail!("{}: status `{}` is not SAT or UNSAT", context(), status),
+        };
+        let digest = if instance.len() == 64 && instance.bytes().all(|b| b.is_ascii_hexdigit()) {
+            instance.to_ascii_lowercase()
+        } else {
+            // The digest of a result document is of the input as read.
+            let file = dir.join(instance);
+            let data = fs::read(&file)
+                .with_context(|| format!("{}: cannot read {}", context(), file.display()))?;
+            hex(&Sha256::digest(&data))
+        };
+        expected.insert(digest, status);
+    }
+    Ok(expected)
+}
+
+/// Prints a `FAILURE` line for every run that contradicts `expected`, and a
+/// summary per configuration; false if any run failed.
+fn check_expected(
+    configs: &BTreeMap<String, Vec<Run>>,
+    expected: &BTreeMap<String, &'static str>,
+) -> bool {
+    let mut passed = true;
+    for (config, runs) in configs {
+        let (mut matched, mut failed, mut unsolved) = (0, 0, 0);
+        for run in runs {
+            let Some(&status) = expected.get(&run.instance) else {
+                continue;
+            };
+            if run.time.is_none() {
+                unsolved += 1;
+            } else if run.status == status {
+                matched += 1;
+            } else {
+                failed += 1;
+                println!(
+                    "c FAILURE {}: {} answered {}, expected {}",
+                    config,
+                    run.document.display(),
+                    run.status,
+                    status
+                );
+            }
+        }
+        println!(
+            "c {}: {} as expected, {} failures, {} unsolved, {} without expectation",
+            config,
+            matched,
+            failed,
+            unsolved,
+            runs.len() - matched - failed - unsolved
+        );
+        passed &= failed == 0;
+    }
+    passed
+}
+
+fn cactus(configs: &BTreeMap<String, Vec<Run>>) -> BTreeMap<&str, Vec<f64>> {
+    configs
+        .iter()
+        .map(|(config, runs)| {
+            let mut times: Vec<_> = runs.iter().filter_map(|r| r.time).collect();
+            times.sort_by(f64::total_cmp);
+            (config.as_str(), times)
+        })
+        .collect()
+}
+
+/// Times of `a` and `b` on the instances both ran, unsolved runs at `timeout`.
+fn scatter(
+    configs: &BTreeMap<String, Vec<Run>>,
+    a: &str,
+    b: &str,
+    timeout: f64,
+) -> anyhow::Result<Vec<(String, f64, f64)>> {
+    let runs = |name: &str| {
+        configs
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown configuration `{name}`"))
+    };
+    let theirs: BTreeMap<_, _> = runs(b)?
+        .iter()
+        .map(|r| (r.instance.as_str(), r.time))
+        .collect();
+    Ok(runs(a)?
+        .iter()
+        .filter_map(|r| {
+            let other = theirs.get(r.instance.as_str())?;
+            Some((
+                r.instance.clone(),
+                r.time.unwrap_or(timeout),
+                other.unwrap_or(timeout),
+            ))
+        })
+        .collect())
+}
+
+/// A CSV field, quoted if it needs to be.
+pub(crate) fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 480.0;
+const MARGIN: f64 = 60.0;
+const COLORS: &[&str] = &[
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+/// An SVG document with axes labelled and ticked at 0 and the given maxima.
+fn svg_frame(xlabel: &str, ylabel: &str, xmax: &str, ymax: &str) -> String {
+    let (left, right, top, bottom) = (MARGIN, WIDTH - MARGIN / 2.0, MARGIN / 2.0, HEIGHT - MARGIN);
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="12">
+<rect width="100%" height="100%" fill="white"/>
+<path d="M{left},{top} V{bottom} H{right}" fill="none" stroke="black"/>
+<text x="{left}" y="{ty}" text-anchor="middle">0</text>
+<text x="{right}" y="{ty}" text-anchor="end">{xmax}</text>
+<text x="{lx}" y="{bottom}" text-anchor="end">0</text>
+<text x="{lx}" y="{top}" text-anchor="end" dominant-baseline="hanging">{ymax}</text>
+<text x="{cx}" y="{xy}" text-anchor="middle">{xlabel}</text>
+<text transform="translate(15,{cy}) rotate(-90)" text-anchor="middle">{ylabel}</text>
+"#,
+        ty = bottom + 15.0,
+        lx = left - 5.0,
+        cx = (left + right) / 2.0,
+        xy = HEIGHT - 15.0,
+        cy = (top + bottom) / 2.0,
+        xlabel = escape(xlabel),
+        ylabel = escape(ylabel),
+    )
+}
+
+/// Maps `value` in [0, max] onto the plot area along x or y.
+fn scale(value: f64, max: f64, vertical: bool) -> f64 {
+    let ratio = if max > 0.0 { value / max } else { 0.0 };
+    if vertical {
+        HEIGHT - MARGIN - ratio * (HEIGHT - 1.5 * MARGIN)
+    } else {
+        MARGIN + ratio * (WIDTH - 1.5 * MARGIN)
+    }
+}
+
+fn cactus_svg(cactus: &BTreeMap<&str, Vec<f64>>) -> String {
+    let xmax = cactus.values().map(Vec::len).max().unwrap_or(0) as f64;
+    let ymax = cactus.values().flatten().copied().fold(0.0, f64::max);
+    let mut svg = svg_frame(
+        "instances solved",
+        "time (s)",
+        &xmax.to_string(),
+        &format!("{ymax:.1}"),
+    );
+    for (i, (config, times)) in cactus.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points: Vec<_> = times
+            .iter()
+            .enumerate()
+            .map(|(n, t)| {
+                format!(
+                    "{:.1},{:.1}",
+                    scale((n + 1) as f64, xmax, false),
+                    scale(*t, ymax, true)
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}"/>"#,
+            points.join(" ")
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" fill="{color}">{}</text>"#,
+            MARGIN + 10.0,
+            MARGIN / 2.0 + 15.0 * (i + 1) as f64,
+            escape(config)
+        );
+    }
+    svg + "</svg>\n"
+}
+
+fn scatter_svg(points: &[(String, f64, f64)], a: &str, b: &str, timeout: f64) -> String {
+    let max = points
+        .iter()
+        .map(|(_, x, y)| x.max(*y))
+        .fold(timeout, f64::max);
+    let label = format!("{max:.1}");
+    let mut svg = svg_frame(&format!("{a} (s)"), &format!("{b} (s)"), &label, &label);
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#999" stroke-dasharray="4"/>"##,
+        scale(0.0, max, false),
+        scale(0.0, max, true),
+        scale(max, max, false),
+        scale(max, max, true)
+    );
+    for (instance, x, y) in points {
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="3" fill="{}"><title>{}</title></circle>"#,
+            scale(*x, max, false),
+            scale(*y, max, true),
+            COLORS[0],
+            escape(instance)
+        );
+    }
+    svg + "</svg>\n"
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,108 @@
+//! Retention of the files a long-running service leaves behind, such as the
+//! result cache and the download cache, so they do not slowly fill the disk.
+//!
+//! A sweep removes the files older than the maximum age, then the oldest
+//! ones until the directory fits the maximum size. Age is the modification
+//! time, so an entry rewritten on use stays. Files still being written
+//! (`*.part`) are only removed once past the maximum age, or after an hour
+//! if there is none, as they are most likely left over from a crash.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Age after which a `*.part` file is considered abandoned when no maximum
+/// age is set.
+const ABANDONED: Duration = Duration::from_secs(3600);
+
+/// Limits of a directory; `None` and zero are no limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    /// Total size in bytes.
+    pub max_size: u64,
+}
+
+/// What a sweep removed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Swept {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Retention {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age.is_none() && self.max_size == 0
+    }
+
+    /// Removes the files of `dir` (not its subdirectories) past the limits.
+    /// A directory that does not exist yet is empty.
+    pub fn sweep(&self, dir: &Path) -> io::Result<Swept> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Swept::default()),
+            Err(e) => return Err(e),
+        };
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+            let age = meta
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+            files.push((age, meta.len(), entry.path()));
+        }
+        // Oldest first.
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+        let mut swept = Swept::default();
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        for (age, len, path) in files {
+            let expired = self.max_age.is_some_and(|max| age > max);
+            let part = path.extension().is_some_and(|ext| ext == "part");
+            let remove = if part {
+                expired || (self.max_age.is_none() && age > ABANDONED)
+            } else {
+                expired || (self.max_size > 0 && total > self.max_size)
+            };
+            if !remove {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                // Removed by someone else meanwhile.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            total -= len;
+            swept.files += 1;
+            swept.bytes += len;
+        }
+        Ok(swept)
+    }
+
+    /// Sweeps `dirs` every `interval` on a background thread, passing what
+    /// each sweep did, or why it failed, to `report`.
+    pub fn spawn(
+        self,
+        dirs: Vec<PathBuf>,
+        interval: Duration,
+        report: impl Fn(&Path, io::Result<Swept>) + Send + 'static,
+    ) {
+        thread::spawn(move || {
+            loop {
+                for dir in &dirs {
+                    report(dir, self.sweep(dir));
+                }
+                thread::sleep(interval);
+            }
+        });
+    }
+}
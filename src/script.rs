@@ -0,0 +1,410 @@
+//! Incremental solving driven by a command file, one command per line:
+//!
+//! ```text
+//! # comments start with `#` or `c`
+//! read base.cnf      add the clauses of a DIMACS file (plain, gzip or xz)
+//! add 1 -2 3 0       add a clause; the trailing 0 is optional
+//! push 4 -5          open a scope, assuming 4 and -5 while it is open
+//! pop                close the last scope
+//! solve [LITS]       solve under every pushed assumption and LITS
+//! value 1 2 3        print the values of variables in the last model
+//!                    (or `val`), not the whole model
+//! model              print the last model over every variable used
+//! stats              print solve counts and times
+//! ```
+//!
+//! Every command works on one solver, so learnt clauses carry over from one
+//! solve to the next. Variable elimination is off, as clauses may still be
+//! added over any variable.
+//!
+//! As in SMT-LIB, `pop` also retracts every clause added since the matching
+//! `push`. Such a clause is added with the negation of an activation
+//! literal of its scope, which is assumed while the scope is open and made
+//! false for good when it closes. Activation literals are variables of the
+//! solver, so the script's variables are numbered apart from them, in the
+//! order they are first used: a script with large variable numbers costs
+//! only the variables it uses.
+//!
+//! A solve that is unsatisfiable under assumptions is followed by an
+//! `f LITS 0` line of the failed assumptions: a subset of them that is
+//! unsatisfiable with the clauses, and minimal, as none can be left out.
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use clap::Args;
+
+use crate::{
+    Solver, Status,
+    core::{self, Backend, write_atomic},
+    dimacs::read_dimacs,
+};
+
+#[derive(Args)]
+pub struct Arg {
+    /// Command file to execute
+    #[arg(value_name = "SCRIPT")]
+    script: PathBuf,
+    /// Backend the commands run against
+    #[arg(long, default_value = "minisat")]
+    solver: Solver,
+    /// Write every solve, with its line, assumptions, status and failed assumptions, to FILE as a JSON array
+    #[arg(long, value_name = "FILE")]
+    json: Option<PathBuf>,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let text = fs::read_to_string(&self.script)
+            .with_context(|| format!("Cannot read {}", self.script.display()))?;
+        let commands = parse(&text)?;
+        self.solver.run_script(&commands, self.json.as_deref())?;
+        Ok(0)
+    }
+}
+
+pub enum Command {
+    Read(PathBuf),
+    Add(Vec<i32>),
+    Push(Vec<i32>),
+    Pop,
+    Solve(Vec<i32>),
+    Value(Vec<i32>),
+    Model,
+    Stats,
+}
+
+/// A command with the line it came from, for error messages.
+pub struct Line {
+    pub number: usize,
+    pub command: Command,
+}
+
+/// Parses a whole script, so syntax errors surface before anything runs.
+pub fn parse(text: &str) -> anyhow::Result<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('c') {
+            continue;
+        }
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let command = parse_command(word, rest.trim())
+            .map_err(|e| anyhow!("line {}: `{}`: {}", i + 1, line, e))?;
+        lines.push(Line {
+            number: i + 1,
+            command,
+        });
+    }
+    Ok(lines)
+}
+
+fn parse_command(word: &str, rest: &str) -> anyhow::Result<Command> {
+    let no_arguments = |command| {
+        if rest.is_empty() {
+            Ok(command)
+        } else {
+            Err(anyhow!("`{}` takes no arguments", word))
+        }
+    };
+    match word {
+        "read" if !rest.is_empty() => Ok(Command::Read(PathBuf::from(rest))),
+        "read" => bail!("`read` needs a file"),
+        "add" => Ok(Command::Add(parse_literals(rest)?)),
+        "push" => Ok(Command::Push(parse_literals(rest)?)),
+        "pop" => no_arguments(Command::Pop),
+        "solve" => Ok(Command::Solve(parse_literals(rest)?)),
+        "value" | "val" => {
+            let vars = parse_literals(rest)?;
+            if let Some(lit) = vars.iter().find(|&&lit| lit < 0) {
+                bail!("`{}` is not a variable", lit);
+            }
+            Ok(Command::Value(vars))
+        }
+        "model" => no_arguments(Command::Model),
+        "stats" => no_arguments(Command::Stats),
+        _ => bail!("unknown command `{}`", word),
+    }
+}
+
+/// Literals up to an optional terminating `0`; `-2147483648` is none, as
+/// its variable is beyond `i32`.
+pub(crate) fn parse_literals(text: &str) -> anyhow::Result<Vec<i32>> {
+    let mut lits = Vec::new();
+    let mut tokens = text.split_whitespace();
+    for token in tokens.by_ref() {
+        match token.parse::<i32>() {
+            Ok(0) => break,
+            Ok(lit) if lit != i32::MIN => lits.push(lit),
+            _ => bail!("invalid literal `{}`", token),
+        }
+    }
+    if let Some(token) = tokens.next() {
+        bail!("`{}` after the terminating 0", token);
+    }
+    Ok(lits)
+}
+
+/// A scope opened by `push`.
+struct Frame {
+    assumptions: Vec<i32>,
+    /// Solver literal that every clause added in the scope is conditional
+    /// on, allocated with the first of them.
+    activation: Option<i32>,
+}
+
+struct Session<S> {
+    solver: S,
+    frames: Vec<Frame>,
+    /// Solver variable of every script variable seen so far; the activation
+    /// literals are solver variables too, so the two numberings differ.
+    vars: HashMap<i32, i32>,
+    /// Solver variables allocated so far.
+    solver_vars: i32,
+    /// Model of the last solve over the script variables seen, in the order
+    /// of their variables.
+    model: Option<Vec<i32>>,
+    clauses: usize,
+    solves: usize,
+    solve_time: Duration,
+    /// Every solve so far, as written by `--json`.
+    results: Vec<serde_json::Value>,
+}
+
+/// Executes `lines` against a fresh `S`, printing the results on stdout and,
+/// with `json`, writing the solves to it.
+pub fn execute<S: Backend>(
+    lines: &[Line],
+    json: Option<&Path>,
+    configure: impl FnOnce(),
+) -> anyhow::Result<()> {
+    let mut solver = core::new_solver::<S>(configure);
+    solver.eliminate(true);
+    let mut session = Session {
+        solver,
+        frames: Vec::new(),
+        vars: HashMap::new(),
+        solver_vars: 0,
+        model: None,
+        clauses: 0,
+        solves: 0,
+        solve_time: Duration::ZERO,
+        results: Vec::new(),
+    };
+    for line in lines {
+        session
+            .execute(line)
+            .map_err(|e| anyhow!("line {}: {}", line.number, e))?;
+    }
+    if let Some(path) = json {
+        let text = serde_json::to_string_pretty(&session.results).expect("results serialize");
+        write_atomic(path, text + "\n")?;
+    }
+    Ok(())
+}
+
+impl<S: Backend> Session<S> {
+    fn execute(&mut self, line: &Line) -> anyhow::Result<()> {
+        match &line.command {
+            Command::Read(path) => self.read(path)?,
+            Command::Add(clause) => self.add(clause),
+            Command::Push(lits) => self.frames.push(Frame {
+                assumptions: lits.clone(),
+                activation: None,
+            }),
+            Command::Pop => {
+                let frame = self
+                    .frames
+                    .pop()
+                    .ok_or_else(|| anyhow!("`pop` without a pushed frame"))?;
+                // Falsifying the activation literal for good satisfies the
+                // clauses of the scope, which the solver then drops.
+                if let Some(activation) = frame.activation {
+                    self.solver.add_clause(&[-activation]);
+                }
+            }
+            Command::Solve(lits) => self.solve(lits, line.number),
+            Command::Value(vars) => {
+                let model = self.model()?;
+                let values = vars.iter().map(|&var| {
+                    match model.binary_search_by_key(&var, |lit| lit.abs()) {
+                        Ok(i) => model[i],
+                        // Variables the solver never saw are unconstrained.
+                        Err(_) => -var,
+                    }
+                });
+                println!("v {} 0", join(values));
+            }
+            Command::Model => {
+                let model = self.model()?;
+                println!("v {} 0", join(model.iter().copied()));
+            }
+            Command::Stats => {
+                println!("c Solves:      {}", self.solves);
+                println!("c Solve time:  {:?}", self.solve_time);
+                println!("c Variables:   {}", self.vars.len());
+                println!("c Clauses:     {}", self.clauses);
+                println!("c Scopes:      {}", self.frames.len());
+                println!(
+                    "c Assumptions: {}",
+                    self.frames
+                        .iter()
+                        .map(|f| f.assumptions.len())
+                        .sum::<usize>()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The solver literal of the script literal `lit`.
+    fn lit(&mut self, lit: i32) -> i32 {
+        let var = *self.vars.entry(lit.abs()).or_insert_with(|| {
+            self.solver_vars += 1;
+            self.solver_vars
+        });
+        if lit < 0 { -var } else { var }
+    }
+
+    /// Adds `clause`, conditional on the innermost scope if there is one;
+    /// the scopes around it close after it does.
+    fn add(&mut self, clause: &[i32]) {
+        let mut lits: Vec<i32> = clause.iter().map(|&lit| self.lit(lit)).collect();
+        if let Some(frame) = self.frames.last_mut() {
+            let activation = *frame.activation.get_or_insert_with(|| {
+                self.solver_vars += 1;
+                self.solver_vars
+            });
+            lits.push(-activation);
+        }
+        self.solver.add_clause(&lits);
+        self.clauses += 1;
+    }
+
+    fn read(&mut self, path: &Path) -> anyhow::Result<()> {
+        let mut clauses = Vec::new();
+        read_dimacs(
+            File::open(path).with_context(|| format!("Cannot open {}", path.display()))?,
+            false,
+            &mut clauses,
+        )?;
+        for clause in &clauses {
+            self.add(clause);
+        }
+        Ok(())
+    }
+
+    fn solve(&mut self, lits: &[i32], number: usize) {
+        let assumptions: Vec<i32> = self
+            .frames
+            .iter()
+            .flat_map(|frame| &frame.assumptions)
+            .chain(lits)
+            .copied()
+            .collect();
+        let activations: Vec<i32> = self.frames.iter().filter_map(|f| f.activation).collect();
+        let mapped: Vec<(i32, i32)> = assumptions
+            .iter()
+            .map(|&lit| (lit, self.lit(lit)))
+            .collect();
+        let start = Instant::now();
+        let status = self.solve_under(&activations, &mapped);
+        self.model = (status == Status::Satisfiable).then(|| {
+            let model = core::model(&self.solver);
+            let holds = |solver_var: i32| model.get(solver_var as usize - 1) == Some(&solver_var);
+            let mut vars: Vec<(i32, i32)> = self.vars.iter().map(|(&var, &s)| (var, s)).collect();
+            vars.sort_unstable();
+            vars.into_iter()
+                .map(|(var, solver_var)| if holds(solver_var) { var } else { -var })
+                .collect()
+        });
+        let failed = (status == Status::Unsatisfiable && !assumptions.is_empty())
+            .then(|| self.failed(&activations, mapped));
+        self.solve_time += start.elapsed();
+        self.solves += 1;
+        println!("s {}", status.as_str());
+        if let Some(failed) = &failed {
+            println!("f {}", join(failed.iter().copied().chain([0])));
+        }
+        self.results.push(serde_json::json!({
+            "line": number,
+            "status": status.as_str(),
+            "assumptions": assumptions,
+            "failed": failed,
+        }));
+    }
+
+    /// Solves under the `activations` of the open scopes and the solver
+    /// literals of `assumptions`, given as (script, solver) literal pairs.
+    fn solve_under(&mut self, activations: &[i32], assumptions: &[(i32, i32)]) -> Status {
+        if !self.solver.okay() {
+            return Status::Unsatisfiable;
+        }
+        let lits: Vec<i32> = activations
+            .iter()
+            .copied()
+            .chain(assumptions.iter().map(|&(_, lit)| lit))
+            .collect();
+        Status::from(self.solver.solve_limited(&lits))
+    }
+
+    /// A minimal subset of the unsatisfiable `assumptions` that is still
+    /// unsatisfiable, as script literals. The backends do not report which
+    /// assumptions a conflict used, so each is left out in turn and dropped
+    /// for good if the rest stay unsatisfiable; the learnt clauses keep
+    /// these solves short.
+    fn failed(&mut self, activations: &[i32], assumptions: Vec<(i32, i32)>) -> Vec<i32> {
+        let mut failed = assumptions;
+        let mut i = 0;
+        while i < failed.len() {
+            let mut rest = failed.clone();
+            rest.remove(i);
+            if self.solve_under(activations, &rest) == Status::Unsatisfiable {
+                failed = rest;
+            } else {
+                i += 1;
+            }
+        }
+        failed.into_iter().map(|(lit, _)| lit).collect()
+    }
+
+    fn model(&self) -> anyhow::Result<&[i32]> {
+        self.model
+            .as_deref()
+            .ok_or_else(|| anyhow!("no model, the last solve was not satisfiable"))
+    }
+}
+
+fn join(lits: impl Iterator<Item = i32>) -> String {
+    lits.map(|lit| lit.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals() {
+        assert_eq!(parse_literals("1 -2 3 0").unwrap(), vec![1, -2, 3]);
+        assert_eq!(parse_literals("1 -2").unwrap(), vec![1, -2]);
+        assert_eq!(parse_literals("").unwrap(), Vec::<i32>::new());
+        assert_eq!(
+            parse_literals("2147483647 -2147483647").unwrap(),
+            vec![i32::MAX, -i32::MAX]
+        );
+    }
+
+    #[test]
+    fn malformed_literals() {
+        assert!(parse_literals("1 x 0").is_err());
+        assert!(parse_literals("1 0 2").is_err());
+        assert!(parse_literals("-2147483648 0").is_err());
+        assert!(parse_literals("2147483648").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Smoke test of every compiled-in backend against a few embedded instances
+//! with known status.
+use clap::Args;
+
+use crate::{Solver, Status, core::verify_model, dimacs::read_dimacs};
+
+#[derive(Args)]
+pub struct Arg {}
+
+struct Instance {
+    name: &'static str,
+    expected: Status,
+    cnf: &'static str,
+}
+
+const SUITE: &[Instance] = &[
+    Instance {
+        name: "empty",
+        expected: Status::Satisfiable,
+        cnf: "p cnf 0 0\n",
+    },
+    Instance {
+        name: "unit-conflict",
+        expected: Status::Unsatisfiable,
+        cnf: "p cnf 1 2\n1 0\n-1 0\n",
+    },
+    Instance {
+        name: "empty-clause",
+        expected: Status::Unsatisfiable,
+        cnf: "p cnf 2 2\n1 2 0\n0\n",
+    },
+    // The implication chain is removed by variable elimination, so the model
+    // has to be reconstructed.
+    Instance {
+        name: "chain-sat",
+        expected: Status::Satisfiable,
+        cnf: "p cnf 6 6\n1 0\n-1 2 0\n-2 3 0\n-3 4 0\n-4 5 0\n-5 6 0\n",
+    },
+    Instance {
+        name: "chain-unsat",
+        expected: Status::Unsatisfiable,
+        cnf: "p cnf 5 6\n1 0\n-1 2 0\n-2 3 0\n-3 4 0\n-4 5 0\n-5 0\n",
+    },
+    // x1 ^ x2, x2 ^ x3, x1 ^ x3 cannot all hold.
+    Instance {
+        name: "xor-cycle",
+        expected: Status::Unsatisfiable,
+        cnf: "p cnf 3 6\n1 2 0\n-1 -2 0\n2 3 0\n-2 -3 0\n1 3 0\n-1 -3 0\n",
+    },
+    Instance {
+        name: "planted-3sat-20",
+        expected: Status::Satisfiable,
+        cnf: "p cnf 20 80
+    19 2 13 0  -18 5 10 0  19 10 18 0  12 4 18 0  12 10 8 0  -19 -10 -17 0  5 16 -14 0  -11 12 16 0
+    9 -16 -3 0  10 -13 -12 0  6 4 -16 0  -6 15 -13 0  -18 -9 14 0  5 3 6 0  -1 -16 6 0  -5 -14 18 0
+    -17 -2 -15 0  -13 4 16 0  20 2 4 0  12 -1 3 0  -9 12 19 0  -16 -15 20 0  -5 -4 11 0  -17 1 7 0
+    -17 -10 3 0  -12 8 18 0  8 -13 -20 0  -1 -20 9 0  -20 -12 15 0  -8 4 -20 0  -7 -16 1 0  -4 13 -7 0
+    -11 -3 13 0  6 20 -5 0  5 16 12 0  7 -1 9 0  -19 11 9 0  -12 15 17 0  -17 20 1 0  -5 6 20 0
+    -11 17 19 0  8 7 -9 0  -18 -1 3 0  -9 15 -17 0  18 -7 15 0  13 15 -11 0  -5 9 20 0  13 16 -6 0
+    -17 13 -11 0  11 -3 -12 0  -15 -1 13 0  4 -8 -20 0  2 -6 -9 0  -13 -5 18 0  -9 2 -6 0  1 3 9 0
+    9 -4 -15 0  9 5 2 0  9 -2 -6 0  -17 7 -10 0  12 1 9 0  -17 18 -7 0  -4 -14 16 0  8 -11 -7 0
+    2 -5 -1 0  10 2 -15 0  -15 -1 -9 0  8 -2 10 0  1 -11 -13 0  10 20 -8 0  -11 16 5 0  17 14 20 0
+    3 -1 2 0  13 15 18 0  -16 9 1 0  -17 3 -16 0  -8 -7 -20 0  3 16 10 0  -20 -5 11 0  -1 -16 2 0
+",
+    },
+    // Four pigeons do not fit into three holes.
+    Instance {
+        name: "pigeonhole-4-3",
+        expected: Status::Unsatisfiable,
+        cnf: "p cnf 12 22
+    1 2 3 0  4 5 6 0  7 8 9 0  10 11 12 0  -1 -4 0  -1 -7 0
+    -1 -10 0  -4 -7 0  -4 -10 0  -7 -10 0  -2 -5 0  -2 -8 0
+    -2 -11 0  -5 -8 0  -5 -11 0  -8 -11 0  -3 -6 0  -3 -9 0
+    -3 -12 0  -6 -9 0  -6 -12 0  -9 -12 0
+",
+    },
+];
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let mut failed = 0;
+        let mut total = 0;
+        for solver in Solver::all() {
+            for instance in SUITE {
+                total += 1;
+                let mut clauses = Vec::new();
+                read_dimacs(instance.cnf.as_bytes(), true, &mut clauses)?;
+                let (status, model) = solver.solve_clauses(&clauses);
+                let problem = if status != instance.expected {
+                    Some(format!(
+                        "expected {}, got {}",
+                        instance.expected.as_str(),
+                        status.as_str()
+                    ))
+                } else if model.is_some_and(|m| !verify_model(&clauses, &m)) {
+                    Some("model does not satisfy the formula".to_string())
+                } else {
+                    None
+                };
+                match problem {
+                    None => println!("{:<8} {:<16} ok", solver.name(), instance.name),
+                    Some(problem) => {
+                        failed += 1;
+                        println!(
+                            "{:<8} {:<16} FAILED ({problem})",
+                            solver.name(),
+                            instance.name
+                        );
+                    }
+                }
+            }
+        }
+        println!("{} of {} checks failed", failed, total);
+        Ok(if failed > 0 { 1 } else { 0 })
+    }
+}
@@ -0,0 +1,1372 @@
+//! HTTP server solving CNFs posted to it.
+//!
+//! * `POST /solve?solver=glucose&K=0.7`: the body is a DIMACS CNF (plain,
+//!   gzip or xz). `solver`, `strict`, `lenient`, `pre`, `model`, `vars`,
+//!   `cpu-lim`, `mem-lim` and `wall-lim` are handled by the server; every
+//!   other query parameter is a backend option by its long flag name. The
+//!   response is the JSON document written by `--json`; with `vars=1,5,9`
+//!   its model is replaced by `values`, the literals of only those
+//!   variables. With `async=1` the job is
+//!   started in the background and answered at once with 202 and its `id`.
+//! * `GET /jobs/{id}`: the `state` of an asynchronous job (`queued`,
+//!   `running`, `done`, `failed` or `cancelled`), with its `result` once
+//!   done. `DELETE /jobs/{id}` cancels a job that has not finished, killing
+//!   its solver, and forgets one that has.
+//! * `GET /metrics`: Prometheus text exposition of the job counters.
+//! * `GET /health`: liveness probe.
+//!
+//! `--on-complete` and `--webhook` are notified with the result summary of
+//! every finished job, see [`crate::hook`].
+//!
+//! Quotas keep one client from taking the service down: a body larger than
+//! `--max-body` or an instance beyond `--max-vars`/`--max-clauses` is refused
+//! with 413, and a request finding `--max-queued` jobs already waiting with
+//! 503, each with `error_details` of category `limit`. With a CPU, memory or
+//! wall-clock limit, from the server's `--cpu-lim`, `--mem-lim` and
+//! `--wall-lim` or lowered by the `cpu-lim`, `mem-lim` and `wall-lim` query
+//! parameters, each job runs in a child process under them (see
+//! [`crate::isolate`]). An asynchronous job always does, as that is how it
+//! is cancelled, and is held to `--async-wall-lim` besides. Its result is
+//! kept for `--job-ttl` after it finished, and of more than
+//! `--max-jobs-kept` finished jobs the oldest are forgotten first.
+//!
+//! `--result-cache` shares answers between jobs. With `--cache-max-age` or
+//! `--cache-max-size`, the result cache and the download cache are swept
+//! every `--clean-interval` (see [`crate::retention`]).
+//!
+//! With `--api-keys`, every request but `GET /health` must carry one of the
+//! keys as `Authorization: Bearer KEY` and is refused with 401 otherwise.
+//! `--tls-cert` and `--tls-key` serve HTTPS instead of HTTP.
+//!
+//! Each connection carries one request and is closed after the response.
+//! A client silent for a minute while sending or receiving is cut off, and
+//! beyond `--max-connections` open connections new ones are closed at once.
+//! Requests and warnings are logged to stdout, or to `--log-file`, which is
+//! rotated to `FILE.1`, `FILE.2`, … when it reaches `--log-max-size`.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use clap::Args;
+use validator::Validate;
+
+use crate::{
+    SolveRequest, Solver, Status,
+    core::{parse_duration, parse_megabytes, parse_seconds, result_json},
+    dimacs::Limits,
+    hook,
+    isolate::{Input, Isolated},
+    retention::Retention,
+    utils::get_memory,
+};
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// Maximum number of jobs solved at the same time; further jobs wait [default: number of CPUs]
+    #[arg(long, value_name = "N")]
+    #[validate(range(min = 1, message = "Jobs must be at least 1"))]
+    jobs: Option<usize>,
+    /// Run this shell command when a job finishes; it gets the result summary on stdin and in SATGALAXY_* variables
+    #[arg(long = "on-complete", value_name = "CMD")]
+    on_complete: Option<String>,
+    /// POST the result summary of every finished job to this URL as JSON
+    #[cfg(feature = "fetch")]
+    #[arg(long, value_name = "URL")]
+    webhook: Option<url::Url>,
+    /// Write the log to this file instead of stdout
+    #[arg(long = "log-file", value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// Size in megabytes at which the log file is rotated
+    #[arg(long = "log-max-size", value_name = "MB", default_value_t = 10)]
+    #[validate(range(min = 1, message = "Log size must be at least 1 MB"))]
+    log_max_size: u64,
+    /// Number of rotated log files kept besides the current one
+    #[arg(long = "log-keep", value_name = "N", default_value_t = 5)]
+    log_keep: usize,
+    /// Largest request body accepted, e.g. 100MB; 0 for no limit
+    #[arg(long = "max-body", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes)]
+    max_body: u64,
+    /// Refuse instances with a variable above N; 0 for no limit
+    #[arg(long = "max-vars", value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+    max_vars: i32,
+    /// Refuse instances with more than N clauses; 0 for no limit
+    #[arg(long = "max-clauses", value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+    max_clauses: i32,
+    /// Connections served at the same time; further ones are closed at once
+    #[arg(long = "max-connections", value_name = "N", default_value_t = 256)]
+    #[validate(range(min = 1, message = "Connections must be at least 1"))]
+    max_connections: usize,
+    /// Jobs allowed to wait for a slot; further requests are refused [default: no limit]
+    #[arg(long = "max-queued", value_name = "N")]
+    max_queued: Option<usize>,
+    /// CPU time limit of every job, e.g. 10m; 0 for none
+    #[arg(long = "cpu-lim", value_name = "DURATION", default_value = "0", value_parser = parse_seconds)]
+    cpu_lim: u64,
+    /// Memory limit of every job, e.g. 4GiB; 0 for none
+    #[arg(long = "mem-lim", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes)]
+    mem_lim: u64,
+    /// Wall-clock limit of every job, e.g. 15m
+    #[arg(long = "wall-lim", value_name = "DURATION", value_parser = parse_duration)]
+    wall_lim: Option<Duration>,
+    /// Wall-clock limit of every asynchronous job, on top of --wall-lim
+    #[arg(long = "async-wall-lim", value_name = "DURATION", default_value = "24h", value_parser = parse_duration)]
+    async_wall_lim: Duration,
+    /// Time the result of an asynchronous job is kept after it finished
+    #[arg(long = "job-ttl", value_name = "DURATION", default_value = "1h", value_parser = parse_duration)]
+    job_ttl: Duration,
+    /// Finished asynchronous jobs kept at most; the oldest are forgotten first
+    #[arg(long = "max-jobs-kept", value_name = "N", default_value_t = 1000)]
+    max_jobs_kept: usize,
+    /// Cache the SATISFIABLE and UNSATISFIABLE answers of the jobs in DIR
+    #[arg(long = "result-cache", value_name = "DIR")]
+    result_cache: Option<PathBuf>,
+    /// Remove files of the result and download caches older than this, e.g. 168h
+    #[arg(long = "cache-max-age", value_name = "DURATION", value_parser = parse_duration)]
+    cache_max_age: Option<Duration>,
+    /// Trim each cache to this size, oldest files first, e.g. 20GB; 0 for no limit
+    #[arg(long = "cache-max-size", value_name = "SIZE", default_value = "0", value_parser = parse_megabytes)]
+    cache_max_size: u64,
+    /// Time between two sweeps of the caches
+    #[arg(long = "clean-interval", value_name = "DURATION", default_value = "10m", value_parser = parse_duration)]
+    clean_interval: Duration,
+    /// File of API keys, one per line; every request but GET /health must send one as `Authorization: Bearer KEY`
+    #[arg(long = "api-keys", value_name = "FILE")]
+    api_keys: Option<PathBuf>,
+    /// PEM certificate chain to serve HTTPS with
+    #[cfg(feature = "tls")]
+    #[arg(long = "tls-cert", value_name = "FILE", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[cfg(feature = "tls")]
+    #[arg(long = "tls-key", value_name = "FILE", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let jobs = self
+            .jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        let log = match &self.log_file {
+            Some(path) => Logger::File(Mutex::new(
+                LogFile::open(path, self.log_max_size * 1024 * 1024, self.log_keep)
+                    .map_err(|e| anyhow!("Cannot open {}: {}", path.display(), e))?,
+            )),
+            None => Logger::Stdout,
+        };
+        let api_keys = match &self.api_keys {
+            Some(path) => read_api_keys(path)?,
+            None => Vec::new(),
+        };
+        #[cfg(feature = "tls")]
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(tls_config(cert, key)?),
+            _ => None,
+        };
+        #[cfg(feature = "tls")]
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        #[cfg(not(feature = "tls"))]
+        let scheme = "http";
+        let listener = TcpListener::bind(self.listen)
+            .map_err(|e| anyhow!("Cannot listen on {}: {}", self.listen, e))?;
+        log.line(&format!(
+            "c Listening on {}://{} with {} job slots{}",
+            scheme,
+            self.listen,
+            jobs,
+            if api_keys.is_empty() { "" } else { ", API keys required" }
+        ));
+        let log = Arc::new(log);
+        self.spawn_cleaner(&log);
+        let server = Arc::new(Server {
+            log: log.clone(),
+            on_complete: self.on_complete.clone(),
+            #[cfg(feature = "fetch")]
+            webhook: self.webhook.clone(),
+            api_keys,
+            max_body: self.max_body.saturating_mul(1024 * 1024),
+            max_queued: self.max_queued,
+            async_wall_lim: self.async_wall_lim,
+            job_ttl: self.job_ttl,
+            max_jobs_kept: self.max_jobs_kept,
+            result_cache: self.result_cache.clone(),
+            isolated: Isolated {
+                cpu_lim: self.cpu_lim,
+                mem_lim: self.mem_lim,
+                wall_lim: self.wall_lim,
+                limits: Limits {
+                    vars: self.max_vars,
+                    clauses: self.max_clauses,
+                },
+            },
+            ..Server::new(jobs)
+        });
+        let connections = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    server.log.line(&format!("c WARNING: accept failed: {}", e));
+                    continue;
+                }
+            };
+            let Some(open) = Open::new(&connections, self.max_connections) else {
+                server.log.line(&format!(
+                    "c WARNING: {} connections open, closing a new one",
+                    self.max_connections
+                ));
+                continue;
+            };
+            let timeouts = stream
+                .set_read_timeout(Some(TIMEOUT))
+                .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)));
+            if let Err(e) = timeouts {
+                server
+                    .log
+                    .line(&format!("c WARNING: cannot set timeouts: {}", e));
+                continue;
+            }
+            let server = server.clone();
+            #[cfg(feature = "tls")]
+            let tls = tls.clone();
+            thread::spawn(move || {
+                let _open = open;
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                #[cfg(feature = "tls")]
+                let handled = match tls {
+                    Some(config) => rustls::ServerConnection::new(config)
+                        .map_err(io::Error::other)
+                        .and_then(|conn| server.handle(rustls::StreamOwned::new(conn, stream), &peer)),
+                    None => server.handle(stream, &peer),
+                };
+                #[cfg(not(feature = "tls"))]
+                let handled = server.handle(stream, &peer);
+                if let Err(e) = handled {
+                    server
+                        .log
+                        .line(&format!("c {} connection error: {}", peer, e));
+                }
+            });
+        }
+        Ok(0)
+    }
+
+    /// Starts sweeping the result and download caches if they have a
+    /// retention limit.
+    fn spawn_cleaner(&self, log: &Arc<Logger>) {
+        let retention = Retention {
+            max_age: self.cache_max_age,
+            max_size: self.cache_max_size.saturating_mul(1024 * 1024),
+        };
+        if retention.is_unlimited() {
+            return;
+        }
+        let mut dirs: Vec<PathBuf> = self.result_cache.iter().cloned().collect();
+        #[cfg(feature = "fetch")]
+        dirs.extend(crate::fetch::cache_dir());
+        if dirs.is_empty() {
+            return;
+        }
+        for dir in &dirs {
+            log.line(&format!("c Cleaning {} every {:?}", dir.display(), self.clean_interval));
+        }
+        let log = log.clone();
+        retention.spawn(dirs, self.clean_interval, move |dir, swept| match swept {
+            Ok(swept) if swept.files > 0 => log.line(&format!(
+                "c Removed {} files ({} bytes) from {}",
+                swept.files,
+                swept.bytes,
+                dir.display()
+            )),
+            Ok(_) => {}
+            Err(e) => log.line(&format!("c WARNING: cannot clean {}: {}", dir.display(), e)),
+        });
+    }
+}
+
+struct Server {
+    log: Arc<Logger>,
+    jobs: usize,
+    running: Mutex<usize>,
+    slot_freed: Condvar,
+    metrics: Mutex<Metrics>,
+    on_complete: Option<String>,
+    #[cfg(feature = "fetch")]
+    webhook: Option<url::Url>,
+    /// Keys one of which a request must present; empty if none is needed.
+    api_keys: Vec<String>,
+    /// Largest body in bytes, 0 for no limit.
+    max_body: u64,
+    max_queued: Option<usize>,
+    /// Caps of every job; a job runs in a child process if it has a CPU,
+    /// memory or wall-clock limit.
+    isolated: Isolated,
+    /// Asynchronous jobs by id, until their results expire.
+    async_jobs: Mutex<BTreeMap<u64, Job>>,
+    next_job: AtomicU64,
+    async_wall_lim: Duration,
+    job_ttl: Duration,
+    max_jobs_kept: usize,
+    result_cache: Option<PathBuf>,
+}
+
+/// What of the model a `/solve` response carries.
+enum ModelView {
+    Full,
+    Omitted,
+    /// The literals of these variables only, as `values`.
+    Values(Vec<i32>),
+}
+
+impl ModelView {
+    /// The view asked for by the `vars` and `model` query parameters.
+    fn of(query: &[(String, String)]) -> anyhow::Result<ModelView> {
+        let Some((_, vars)) = query.iter().find(|(k, _)| k == "vars") else {
+            return Ok(if query_flag(query, "model", true) {
+                ModelView::Full
+            } else {
+                ModelView::Omitted
+            });
+        };
+        vars.split(',')
+            .map(|var| match var.trim().parse::<i32>() {
+                Ok(var) if var > 0 => Ok(var),
+                _ => Err(anyhow!("vars: `{}` is not a variable", var)),
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(ModelView::Values)
+    }
+
+    fn apply(&self, json: &mut serde_json::Value) {
+        match self {
+            ModelView::Full => {}
+            ModelView::Omitted => json["model"] = serde_json::Value::Null,
+            ModelView::Values(vars) => {
+                // A partial model lacks some variables, so look them up
+                // rather than index.
+                let values = json["model"].as_array().map(|lits| {
+                    let model: HashMap<i64, i64> = lits
+                        .iter()
+                        .filter_map(|lit| lit.as_i64())
+                        .map(|lit| (lit.abs(), lit))
+                        .collect();
+                    vars.iter()
+                        .filter_map(|&var| model.get(&i64::from(var)).copied())
+                        .collect::<Vec<_>>()
+                });
+                json["values"] = values.into();
+                json["model"] = serde_json::Value::Null;
+            }
+        }
+    }
+}
+
+/// An asynchronous job, submitted with `POST /solve?async=1`.
+struct Job {
+    state: JobState,
+    submitted: Instant,
+    finished: Option<Instant>,
+    /// Set to cancel the job.
+    stop: Arc<AtomicBool>,
+}
+
+enum JobState {
+    Queued,
+    Running,
+    /// The `--json` document of the job, or the summary of its failure.
+    Done(Result<serde_json::Value, serde_json::Value>),
+    Cancelled,
+}
+
+impl Job {
+    /// The document `GET /jobs/{id}` answers with.
+    fn document(&self, id: u64) -> serde_json::Value {
+        let state = match &self.state {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done(Ok(_)) => "done",
+            JobState::Done(Err(_)) => "failed",
+            JobState::Cancelled => "cancelled",
+        };
+        let elapsed = self
+            .finished
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.submitted);
+        let mut document = serde_json::json!({
+            "id": id.to_string(),
+            "state": state,
+            "elapsed": elapsed.as_secs_f64(),
+        });
+        match &self.state {
+            JobState::Done(Ok(json)) => document["result"] = json.clone(),
+            JobState::Done(Err(summary)) => {
+                document["error"] = summary["error"].clone();
+                document["error_details"] = summary["error_details"].clone();
+            }
+            _ => {}
+        }
+        document
+    }
+}
+
+/// Destination of the server's log lines.
+enum Logger {
+    Stdout,
+    File(Mutex<LogFile>),
+}
+
+impl Logger {
+    fn line(&self, line: &str) {
+        match self {
+            Logger::Stdout => println!("{}", line),
+            Logger::File(file) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                // A log that cannot be written falls back to stdout rather
+                // than take the server down.
+                if let Err(e) = file.write_line(line) {
+                    println!("c WARNING: cannot write {}: {}", file.path.display(), e);
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+}
+
+/// An appended log file, rotated by size.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+            max_size,
+            keep,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shifts `FILE.1`, `FILE.2`, … up by one, dropping the oldest, moves
+    /// the current file to `FILE.1` and starts a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |i: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", i));
+            PathBuf::from(name)
+        };
+        if self.keep > 0 {
+            for i in (1..self.keep).rev() {
+                if rotated(i).exists() {
+                    fs::rename(rotated(i), rotated(i + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Upper bounds of the solve time histogram, in seconds.
+const BUCKETS: [f64; 9] = [0.01, 0.1, 1.0, 10.0, 60.0, 300.0, 1800.0, 3600.0, 7200.0];
+
+#[derive(Default)]
+struct Metrics {
+    solved: BTreeMap<&'static str, u64>,
+    failed: u64,
+    running: u64,
+    queued: u64,
+    buckets: [u64; BUCKETS.len()],
+    seconds_sum: f64,
+    seconds_count: u64,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    content_length: Option<u64>,
+    authorization: Option<String>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into().into_bytes(),
+        }
+    }
+
+    fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: (value.to_string() + "\n").into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+
+    /// A failed solve, with the details harnesses classify it by.
+    fn failure(summary: &serde_json::Value) -> Self {
+        let status = match summary["error_details"]["category"].as_str() {
+            Some("parse") => 400,
+            Some("limit") => 413,
+            _ => 500,
+        };
+        Self::json(
+            status,
+            &serde_json::json!({
+                "error": summary["error"],
+                "error_details": summary["error_details"],
+            }),
+        )
+    }
+
+    /// A request refused by the quota `limit`, detailed like a failed solve.
+    fn refused(status: u16, limit: &str, max: u64, message: String) -> Self {
+        Self::json(
+            status,
+            &serde_json::json!({
+                "error": message,
+                "error_details": {
+                    "category": "limit",
+                    "message": message,
+                    "line": null,
+                    "column": null,
+                    "errno": null,
+                    "limit": limit,
+                    "max": max,
+                },
+            }),
+        )
+    }
+}
+
+impl Server {
+    fn new(jobs: usize) -> Self {
+        Self {
+            log: Arc::new(Logger::Stdout),
+            jobs,
+            running: Mutex::new(0),
+            slot_freed: Condvar::new(),
+            metrics: Mutex::new(Metrics::default()),
+            on_complete: None,
+            #[cfg(feature = "fetch")]
+            webhook: None,
+            api_keys: Vec::new(),
+            max_body: 0,
+            max_queued: None,
+            isolated: Isolated::default(),
+            async_jobs: Mutex::new(BTreeMap::new()),
+            next_job: AtomicU64::new(1),
+            async_wall_lim: Duration::from_secs(24 * 3600),
+            job_ttl: Duration::from_secs(3600),
+            max_jobs_kept: 1000,
+            result_cache: None,
+        }
+    }
+
+    fn handle<S: Read + Write>(self: &Arc<Self>, stream: S, peer: &str) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let response = match read_request(&mut reader) {
+            Ok(request) => {
+                let response = self.route(&request, &mut reader);
+                self.log.line(&format!(
+                    "c {} {} {} -> {}",
+                    peer, request.method, request.path, response.status
+                ));
+                response
+            }
+            Err(e) => Response::error(400, e),
+        };
+        write_response(reader.get_mut(), &response)
+    }
+
+    fn route(self: &Arc<Self>, request: &Request, body: &mut impl Read) -> Response {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => Response::text(200, "ok\n"),
+            _ if !self.authorized(request) => Response::error(401, "a valid API key is required"),
+            ("GET", "/metrics") => Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                body: self.metrics.lock().unwrap().render().into_bytes(),
+            },
+            ("POST", "/solve") => match request.content_length {
+                Some(length) if self.max_body > 0 && length > self.max_body => Response::refused(
+                    413,
+                    "body",
+                    self.max_body,
+                    format!("body of {} bytes exceeds the limit ({})", length, self.max_body),
+                ),
+                Some(length) => self.solve(request, &mut body.take(length)),
+                None => Response::error(411, "Content-Length is required"),
+            },
+            (method, path) if path.starts_with("/jobs/") => self.job(method, &path["/jobs/".len()..]),
+            (_, "/health" | "/metrics" | "/solve") => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn solve(self: &Arc<Self>, request: &Request, body: &mut dyn Read) -> Response {
+        let (mut solve_request, isolated) =
+            match solve_request(&request.query).and_then(|r| Ok((r, self.caps(&request.query)?))) {
+                Ok(parsed) => parsed,
+                Err(e) => return Response::error(400, e),
+            };
+        solve_request.limits = self.isolated.limits;
+        solve_request.result_cache = self.result_cache.clone();
+        let view = match ModelView::of(&request.query) {
+            Ok(view) => view,
+            Err(e) => return Response::error(400, e),
+        };
+        if query_flag(&request.query, "async", false) {
+            return self.submit(solve_request, &request.query, isolated, view, body);
+        }
+        if !self.acquire() {
+            return self.queue_full();
+        }
+        let start = Instant::now();
+        // The `--json` document of the job, or the summary of its failure.
+        let result = match isolated {
+            Some(isolated) => read_body(body).and_then(|data| {
+                let stop = AtomicBool::new(false);
+                self.solve_isolated(&solve_request, &request.query, isolated, &data, &stop)
+            }),
+            None => crate::solve_reader(&solve_request, body)
+                .map(|result| result_json(&result, &request.query))
+                .map_err(|e| hook::error_summary(&e, None)),
+        };
+        self.release();
+        match self.finish(result, start, &view) {
+            Ok(json) => Response::json(200, &json),
+            Err(summary) => Response::failure(&summary),
+        }
+    }
+
+    /// Starts an asynchronous job and answers at once with its document.
+    fn submit(
+        self: &Arc<Self>,
+        request: SolveRequest,
+        query: &[(String, String)],
+        isolated: Option<Isolated>,
+        view: ModelView,
+        body: &mut dyn Read,
+    ) -> Response {
+        // Refused before the body is read, which may be large.
+        if !self.enqueue() {
+            return self.queue_full();
+        }
+        let data = match read_body(body) {
+            Ok(data) => data,
+            Err(summary) => {
+                self.dequeue();
+                return Response::failure(&summary);
+            }
+        };
+        let mut isolated = isolated.unwrap_or(Isolated {
+            limits: self.isolated.limits,
+            ..Default::default()
+        });
+        isolated.wall_lim = Some(
+            isolated
+                .wall_lim
+                .map_or(self.async_wall_lim, |wall| wall.min(self.async_wall_lim)),
+        );
+        let id = self.next_job.fetch_add(1, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        let job = Job {
+            state: JobState::Queued,
+            submitted: Instant::now(),
+            finished: None,
+            stop: stop.clone(),
+        };
+        let document = job.document(id);
+        {
+            let mut jobs = self.async_jobs.lock().unwrap();
+            self.expire(&mut jobs);
+            jobs.insert(id, job);
+        }
+        let server = self.clone();
+        let query = query.to_vec();
+        thread::spawn(move || {
+            server.wait_slot();
+            let running = match server.async_jobs.lock().unwrap().get_mut(&id) {
+                Some(job) if matches!(job.state, JobState::Queued) => {
+                    job.state = JobState::Running;
+                    true
+                }
+                _ => false,
+            };
+            let result = running.then(|| {
+                let start = Instant::now();
+                let result = server.solve_isolated(&request, &query, isolated, &data, &stop);
+                server.finish(result, start, &view)
+            });
+            server.release();
+            if let Some(result) = result
+                && let Some(job) = server.async_jobs.lock().unwrap().get_mut(&id)
+                && matches!(job.state, JobState::Running)
+            {
+                job.state = JobState::Done(result);
+                job.finished = Some(Instant::now());
+            }
+        });
+        Response::json(202, &document)
+    }
+
+    /// `GET` or `DELETE /jobs/{id}`.
+    fn job(&self, method: &str, id: &str) -> Response {
+        let mut jobs = self.async_jobs.lock().unwrap();
+        self.expire(&mut jobs);
+        let Some((id, job)) = id
+            .parse()
+            .ok()
+            .and_then(|id| Some((id, jobs.get_mut(&id)?)))
+        else {
+            return Response::error(404, "no such job");
+        };
+        match method {
+            "GET" => Response::json(200, &job.document(id)),
+            "DELETE" if job.finished.is_some() => {
+                let document = job.document(id);
+                jobs.remove(&id);
+                Response::json(200, &document)
+            }
+            "DELETE" => {
+                job.stop.store(true, Ordering::Relaxed);
+                job.state = JobState::Cancelled;
+                job.finished = Some(Instant::now());
+                Response::json(200, &job.document(id))
+            }
+            _ => Response::error(405, "method not allowed"),
+        }
+    }
+
+    /// Forgets the jobs that finished more than `job_ttl` ago, and the
+    /// oldest finished ones beyond `max_jobs_kept`.
+    fn expire(&self, jobs: &mut BTreeMap<u64, Job>) {
+        jobs.retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < self.job_ttl));
+        let mut finished: Vec<_> = jobs
+            .iter()
+            .filter_map(|(id, job)| Some((job.finished?, *id)))
+            .collect();
+        if finished.len() > self.max_jobs_kept {
+            finished.sort();
+            for (_, id) in &finished[..finished.len() - self.max_jobs_kept] {
+                jobs.remove(id);
+            }
+        }
+    }
+
+    /// Records a finished job in the metrics, notifies the hooks and keeps
+    /// as much of the model as `view` asks for.
+    fn finish(
+        &self,
+        result: Result<serde_json::Value, serde_json::Value>,
+        start: Instant,
+        view: &ModelView,
+    ) -> Result<serde_json::Value, serde_json::Value> {
+        self.metrics.lock().unwrap().record(
+            result.as_ref().ok().and_then(status_of),
+            start.elapsed().as_secs_f64(),
+        );
+        self.notify(match &result {
+            Ok(json) => hook::summary(json.clone(), None, None, None),
+            Err(summary) => summary.clone(),
+        });
+        result.map(|mut json| {
+            view.apply(&mut json);
+            json
+        })
+    }
+
+    fn queue_full(&self) -> Response {
+        let max = self.max_queued.unwrap_or_default();
+        Response::refused(
+            503,
+            "queue",
+            max as u64,
+            format!("{} jobs are already waiting", max),
+        )
+    }
+
+    /// The limits of a job: the server's, lowered by the `cpu-lim`,
+    /// `mem-lim` and `wall-lim` query parameters; `None` if it has none and
+    /// can be solved in this process.
+    fn caps(&self, query: &[(String, String)]) -> anyhow::Result<Option<Isolated>> {
+        let lower = |cap: u64, requested: Option<u64>| match (cap, requested) {
+            (0, Some(requested)) => requested,
+            (cap, Some(requested)) if requested > 0 => cap.min(requested),
+            (cap, _) => cap,
+        };
+        let param = |name: &str| query.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+        let cpu = param("cpu-lim").map(parse_seconds).transpose().map_err(anyhow::Error::msg)?;
+        let mem = param("mem-lim").map(parse_megabytes).transpose().map_err(anyhow::Error::msg)?;
+        let wall = param("wall-lim").map(parse_duration).transpose().map_err(anyhow::Error::msg)?;
+        let isolated = Isolated {
+            cpu_lim: lower(self.isolated.cpu_lim, cpu),
+            mem_lim: lower(self.isolated.mem_lim, mem),
+            wall_lim: match (self.isolated.wall_lim, wall) {
+                (Some(cap), Some(wall)) => Some(cap.min(wall)),
+                (cap, wall) => cap.or(wall),
+            },
+            limits: self.isolated.limits,
+        };
+        let limited = isolated.cpu_lim > 0 || isolated.mem_lim > 0 || isolated.wall_lim.is_some();
+        Ok(limited.then_some(isolated))
+    }
+
+    /// Solves `data` in a child process under `isolated`, killed once
+    /// `stop` is set.
+    fn solve_isolated(
+        &self,
+        request: &SolveRequest,
+        query: &[(String, String)],
+        isolated: Isolated,
+        data: &[u8],
+        stop: &AtomicBool,
+    ) -> Result<serde_json::Value, serde_json::Value> {
+        let mut options: Vec<_> = query
+            .iter()
+            .filter(|(k, _)| !SERVER_PARAMS.contains(&k.as_str()))
+            .cloned()
+            .collect();
+        let flags = [
+            ("strictp", "", request.strict),
+            ("lenient", "", request.lenient),
+            ("pre", "false", !request.preprocess),
+        ];
+        for (flag, value, set) in flags {
+            if set {
+                options.push((flag.to_string(), value.to_string()));
+            }
+        }
+        if let Some(dir) = &request.result_cache {
+            options.push(("result-cache".to_string(), dir.display().to_string()));
+        }
+        match isolated.solve_until(request.solver.name(), &options, Input::Bytes(data), stop) {
+            Ok(json) if json.get("error").is_some() => Err(json),
+            Ok(json) => Ok(json),
+            Err(e) => Err(hook::error_summary(&e, None)),
+        }
+    }
+
+    /// Whether `request` presents one of the API keys, if any are set. The
+    /// keys are compared in constant time.
+    fn authorized(&self, request: &Request) -> bool {
+        if self.api_keys.is_empty() {
+            return true;
+        }
+        let Some(key) = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        self.api_keys.iter().fold(false, |found, expected| {
+            let same = expected.len() == key.len()
+                && expected.bytes().zip(key.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+            found | same
+        })
+    }
+
+    /// Runs the completion hooks in the background so the response is not
+    /// held up by them.
+    fn notify(&self, summary: serde_json::Value) {
+        let log = self.log.clone();
+        let on_complete = self.on_complete.clone();
+        #[cfg(feature = "fetch")]
+        let webhook = self.webhook.clone();
+        thread::spawn(move || {
+            if let Some(cmd) = on_complete
+                && let Err(e) = hook::run_command(&cmd, &summary)
+            {
+                log.line(&format!("c WARNING: {}", e));
+            }
+            #[cfg(feature = "fetch")]
+            if let Some(url) = webhook
+                && let Err(e) = hook::post_webhook(&url, &summary)
+            {
+                log.line(&format!("c WARNING: {}", e));
+            }
+        });
+    }
+
+    /// Waits for one of the `jobs` solve slots; false if every slot is taken
+    /// and `max_queued` jobs are already waiting.
+    fn acquire(&self) -> bool {
+        if !self.enqueue() {
+            return false;
+        }
+        self.wait_slot();
+        true
+    }
+
+    /// Joins the jobs waiting for a slot; false if every slot is taken and
+    /// `max_queued` jobs are already waiting.
+    fn enqueue(&self) -> bool {
+        let busy = *self.running.lock().unwrap() >= self.jobs;
+        let mut metrics = self.metrics.lock().unwrap();
+        if busy && self.max_queued.is_some_and(|max| metrics.queued >= max as u64) {
+            return false;
+        }
+        metrics.queued += 1;
+        true
+    }
+
+    /// Leaves the jobs waiting for a slot without taking one.
+    fn dequeue(&self) {
+        self.metrics.lock().unwrap().queued -= 1;
+    }
+
+    /// Waits until a job that [enqueued](Server::enqueue) gets a slot.
+    fn wait_slot(&self) {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.jobs {
+            running = self.slot_freed.wait(running).unwrap();
+        }
+        *running += 1;
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.queued -= 1;
+        metrics.running += 1;
+    }
+
+    fn release(&self) {
+        *self.running.lock().unwrap() -= 1;
+        self.metrics.lock().unwrap().running -= 1;
+        self.slot_freed.notify_one();
+    }
+}
+
+impl Metrics {
+    fn record(&mut self, status: Option<Status>, seconds: f64) {
+        match status {
+            Some(status) => *self.solved.entry(status.as_str()).or_default() += 1,
+            None => self.failed += 1,
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.seconds_sum += seconds;
+        self.seconds_count += 1;
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        out += "# HELP satgalaxy_jobs_total Jobs finished, by status.\n";
+        out += "# TYPE satgalaxy_jobs_total counter\n";
+        for status in [Status::Satisfiable, Status::Unsatisfiable, Status::Unknown] {
+            let count = self
+                .solved
+                .get(status.as_str())
+                .copied()
+                .unwrap_or_default();
+            out += &format!(
+                "satgalaxy_jobs_total{{status=\"{}\"}} {}\n",
+                status.as_str(),
+                count
+            );
+        }
+        out += &format!("satgalaxy_jobs_total{{status=\"ERROR\"}} {}\n", self.failed);
+        out += "# HELP satgalaxy_jobs_running Jobs currently being solved.\n";
+        out += "# TYPE satgalaxy_jobs_running gauge\n";
+        out += &format!("satgalaxy_jobs_running {}\n", self.running);
+        out += "# HELP satgalaxy_jobs_queued Jobs waiting for a free slot.\n";
+        out += "# TYPE satgalaxy_jobs_queued gauge\n";
+        out += &format!("satgalaxy_jobs_queued {}\n", self.queued);
+        out += "# HELP satgalaxy_solve_seconds Wall-clock time per job.\n";
+        out += "# TYPE satgalaxy_solve_seconds histogram\n";
+        for (count, bound) in self.buckets.iter().zip(BUCKETS) {
+            out += &format!(
+                "satgalaxy_solve_seconds_bucket{{le=\"{}\"}} {}\n",
+                bound, count
+            );
+        }
+        out += &format!(
+            "satgalaxy_solve_seconds_bucket{{le=\"+Inf\"}} {}\n",
+            self.seconds_count
+        );
+        out += &format!("satgalaxy_solve_seconds_sum {}\n", self.seconds_sum);
+        out += &format!("satgalaxy_solve_seconds_count {}\n", self.seconds_count);
+        if let Some(memory) = get_memory() {
+            out += "# HELP process_resident_memory_bytes Resident memory size in bytes.\n";
+            out += "# TYPE process_resident_memory_bytes gauge\n";
+            out += &format!("process_resident_memory_bytes {}\n", memory);
+        }
+        out
+    }
+}
+
+/// Query parameters handled by the server rather than passed to the backend.
+const SERVER_PARAMS: &[&str] = &[
+    "solver", "strict", "lenient", "pre", "model", "vars", "cpu-lim", "mem-lim", "wall-lim",
+    "async",
+];
+
+fn solve_request(query: &[(String, String)]) -> anyhow::Result<SolveRequest> {
+    let name = query
+        .iter()
+        .find(|(k, _)| k == "solver")
+        .map(|(_, v)| v.clone())
+        .or_else(|| Solver::all().first().map(|s| s.name().to_string()))
+        .unwrap_or_default();
+    let options: Vec<_> = query
+        .iter()
+        .filter(|(k, _)| !SERVER_PARAMS.contains(&k.as_str()))
+        .cloned()
+        .collect();
+    let mut request = SolveRequest::new(Solver::from_args(&name, &options)?);
+    request.strict = query_flag(query, "strict", false);
+    request.lenient = query_flag(query, "lenient", false);
+    request.preprocess = query_flag(query, "pre", true);
+    Ok(request)
+}
+
+fn read_body(body: &mut dyn Read) -> Result<Vec<u8>, serde_json::Value> {
+    let mut data = Vec::new();
+    body.read_to_end(&mut data)
+        .map_err(|e| hook::error_summary(&anyhow::Error::from(e), None))?;
+    Ok(data)
+}
+
+/// The status of a `--json` document.
+fn status_of(json: &serde_json::Value) -> Option<Status> {
+    [Status::Satisfiable, Status::Unsatisfiable, Status::Unknown]
+        .into_iter()
+        .find(|status| json["status"] == status.as_str())
+}
+
+fn query_flag(query: &[(String, String)], name: &str, default: bool) -> bool {
+    query
+        .iter()
+        .find(|(k, _)| k == name)
+        .map_or(default, |(_, v)| {
+            !matches!(v.as_str(), "0" | "false" | "no")
+        })
+}
+
+/// Longest a client may take to send or receive one piece of a request or
+/// response.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One of the connections counted against `--max-connections`, until
+/// dropped.
+struct Open(Arc<AtomicUsize>);
+
+impl Open {
+    /// Counts a new connection; `None` if `max` are already open.
+    fn new(count: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Open(count.clone()))
+    }
+}
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Longest request line or header accepted.
+const MAX_LINE: u64 = 8192;
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let line = read_line(reader)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(io::Error::other("malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+    let mut content_length = None;
+    let mut authorization = None;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| io::Error::other("invalid Content-Length"))?,
+            );
+        } else if name.trim().eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        content_length,
+        authorization,
+    })
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::other("request head is truncated or too long"));
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The API keys in `path`, one per line; blank lines and `#` comments are
+/// skipped.
+fn read_api_keys(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Cannot read {}: {}", path.display(), e))?;
+    let keys: Vec<_> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if keys.is_empty() {
+        return Err(anyhow!("{} holds no API key", path.display()));
+    }
+    Ok(keys)
+}
+
+#[cfg(feature = "tls")]
+fn tls_config(cert: &Path, key: &Path) -> anyhow::Result<Arc<rustls::ServerConfig>> {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Cannot read {}: {}", cert.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow!("Cannot read {}: {}", key.display(), e))?;
+    let config =
+        rustls::ServerConfig::builder_with_provider(rustls::crypto::ring::default_provider().into())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+fn write_response<W: Write>(stream: &mut W, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let challenge = match response.status {
+        401 => "WWW-Authenticate: Bearer\r\n",
+        _ => "",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        challenge,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(head: &str) -> io::Result<Request> {
+        read_request(&mut head.as_bytes())
+    }
+
+    fn request(
+        method: &str,
+        path: &str,
+        content_length: Option<u64>,
+        key: Option<&str>,
+    ) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: Vec::new(),
+            content_length,
+            authorization: key.map(|key| format!("Bearer {key}")),
+        }
+    }
+
+    #[test]
+    fn request_head() {
+        let request = parse(
+            "POST /solve?seed=3&model=a%20b HTTP/1.1\r\n\
+             content-length: 12\r\nAuthorization: Bearer k\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/solve");
+        assert_eq!(
+            request.query,
+            [
+                ("seed".to_string(), "3".to_string()),
+                ("model".to_string(), "a b".to_string())
+            ]
+        );
+        assert_eq!(request.content_length, Some(12));
+        assert_eq!(request.authorization.as_deref(), Some("Bearer k"));
+    }
+
+    #[test]
+    fn malformed_request_heads() {
+        for head in [
+            "",
+            "\r\n\r\n",
+            "GET /health\r\n\r\n",
+            "GET /health HTTP/1.1",
+            "GET /health HTTP/1.1\r\nHost: x",
+            "POST /solve HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "POST /solve HTTP/1.1\r\nContent-Length: 99999999999999999999\r\n\r\n",
+        ] {
+            assert!(parse(head).is_err(), "{head:?}");
+        }
+    }
+
+    #[test]
+    fn oversized_heads() {
+        let long = "x".repeat(MAX_LINE as usize);
+        let line = format!("GET /{long} HTTP/1.1\r\n\r\n");
+        assert!(parse(&line).is_err());
+        let header = format!("GET /health HTTP/1.1\r\nX-Long: {long}\r\n\r\n");
+        assert!(parse(&header).is_err());
+        let fits = format!("GET /health HTTP/1.1\r\nX-Long: {}\r\n\r\n", &long[..1000]);
+        assert!(parse(&fits).is_ok());
+    }
+
+    #[test]
+    fn bearer_keys() {
+        let mut server = Server::new(1);
+        assert!(server.authorized(&request("GET", "/metrics", None, None)));
+        server.api_keys = vec!["first".to_string(), "second".to_string()];
+        assert!(server.authorized(&request("GET", "/metrics", None, Some("second"))));
+        assert!(!server.authorized(&request("GET", "/metrics", None, None)));
+        assert!(!server.authorized(&request("GET", "/metrics", None, Some("secon"))));
+        assert!(!server.authorized(&request("GET", "/metrics", None, Some("third"))));
+        let mut basic = request("GET", "/metrics", None, None);
+        basic.authorization = Some("Basic first".to_string());
+        assert!(!server.authorized(&basic));
+        let server = Arc::new(server);
+        let route = |sent: Request| server.route(&sent, &mut io::empty()).status;
+        assert_eq!(route(request("GET", "/metrics", None, Some("third"))), 401);
+        assert_eq!(route(request("POST", "/solve", Some(1), None)), 401);
+        assert_eq!(route(request("GET", "/health", None, None)), 200);
+        assert_eq!(route(request("GET", "/metrics", None, Some("first"))), 200);
+    }
+
+    #[test]
+    fn bodies_beyond_max_body() {
+        let mut server = Server::new(1);
+        server.max_body = 10;
+        let server = Arc::new(server);
+        let response = server.route(&request("POST", "/solve", Some(11), None), &mut io::empty());
+        assert_eq!(response.status, 413);
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json["error_details"]["limit"], "body");
+        assert_eq!(json["error_details"]["max"], 10);
+        let response = server.route(&request("POST", "/solve", None, None), &mut io::empty());
+        assert_eq!(response.status, 411);
+    }
+}
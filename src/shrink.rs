@@ -0,0 +1,254 @@
+//! Delta debugging of CNFs: removes clauses, then literals, for as long as a
+//! property of the formula keeps holding.
+//!
+//! A solver that crashes takes this process down with it, so crashes have to
+//! be reproduced through `--cmd`, e.g. `--cmd 'satgalaxy glucose {}'`.
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{Context, anyhow};
+use clap::{ArgGroup, Args, ValueEnum};
+
+use crate::{
+    Solver, Status,
+    core::verify_model,
+    dimacs::{read_dimacs, write_dimacs},
+    utils::shell,
+};
+
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("property")
+        .required(true)
+        .args(["status", "wrong_model", "disagree", "cmd"])
+))]
+pub struct Arg {
+    /// CNF to minimize
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
+    /// Where the minimized CNF is written [default: INPUT with a .min.cnf extension]
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Backend checked by --status and --wrong-model
+    #[arg(long, default_value = "minisat")]
+    solver: Solver,
+    /// Keep the formula while --solver answers this status
+    #[arg(long, value_enum)]
+    status: Option<Answer>,
+    /// Keep the formula while --solver returns a model that fails verification
+    #[arg(long)]
+    wrong_model: bool,
+    /// Keep the formula while the compiled-in backends disagree on its status
+    #[arg(long)]
+    disagree: bool,
+    /// Keep the formula while this shell command exits with --exit-code; `{}`
+    /// is replaced by the candidate file, which is appended if absent
+    #[arg(long, value_name = "CMD")]
+    cmd: Option<String>,
+    /// Exit code --cmd has to return [default: killed by a signal]
+    #[arg(long, requires = "cmd")]
+    exit_code: Option<i32>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Answer {
+    Sat,
+    Unsat,
+    Unknown,
+}
+
+impl From<Answer> for Status {
+    fn from(answer: Answer) -> Self {
+        match answer {
+            Answer::Sat => Status::Satisfiable,
+            Answer::Unsat => Status::Unsatisfiable,
+            Answer::Unknown => Status::Unknown,
+        }
+    }
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let mut clauses = Vec::new();
+        read_dimacs(
+            File::open(&self.input)
+                .with_context(|| format!("Cannot open {}", self.input.display()))?,
+            false,
+            &mut clauses,
+        )?;
+        let out = self
+            .out
+            .clone()
+            .unwrap_or_else(|| self.input.with_extension("min.cnf"));
+        let scratch = out.with_extension("tmp.cnf");
+        let mut shrinker = Shrinker {
+            arg: self,
+            scratch: &scratch,
+            out: &out,
+            tests: 0,
+        };
+        let result = shrinker.shrink(clauses);
+        // The scratch file is only there for --cmd.
+        let _ = fs::remove_file(&scratch);
+        let clauses = result?;
+        println!(
+            "c Minimized to {} clauses, {} literals in {} tests: {}",
+            clauses.len(),
+            literals(&clauses),
+            shrinker.tests,
+            out.display()
+        );
+        Ok(0)
+    }
+}
+
+struct Shrinker<'a> {
+    arg: &'a Arg,
+    scratch: &'a Path,
+    out: &'a Path,
+    tests: usize,
+}
+
+impl Shrinker<'_> {
+    fn shrink(&mut self, mut clauses: Vec<Vec<i32>>) -> anyhow::Result<Vec<Vec<i32>>> {
+        if !self.holds(&clauses)? {
+            return Err(anyhow!("The property does not hold for the input"));
+        }
+        self.save(&clauses)?;
+        loop {
+            let before = (clauses.len(), literals(&clauses));
+            clauses = self.ddmin(clauses)?;
+            clauses = self.drop_literals(clauses)?;
+            let renumbered = renumber(&clauses);
+            if renumbered != clauses && self.holds(&renumbered)? {
+                clauses = renumbered;
+                self.save(&clauses)?;
+            }
+            if (clauses.len(), literals(&clauses)) == before {
+                return Ok(clauses);
+            }
+        }
+    }
+
+    /// Zeller's ddmin over the clause list.
+    fn ddmin(&mut self, mut clauses: Vec<Vec<i32>>) -> anyhow::Result<Vec<Vec<i32>>> {
+        let mut granularity = 2;
+        while clauses.len() >= 2 {
+            let chunk = clauses.len().div_ceil(granularity);
+            let mut reduced = false;
+            for start in (0..clauses.len()).step_by(chunk) {
+                let complement: Vec<_> = clauses[..start]
+                    .iter()
+                    .chain(clauses.iter().skip(start + chunk))
+                    .cloned()
+                    .collect();
+                if self.holds(&complement)? {
+                    clauses = complement;
+                    self.save(&clauses)?;
+                    granularity = (granularity - 1).max(2);
+                    reduced = true;
+                    break;
+                }
+            }
+            if !reduced {
+                if granularity >= clauses.len() {
+                    break;
+                }
+                granularity = (granularity * 2).min(clauses.len());
+            }
+        }
+        Ok(clauses)
+    }
+
+    /// Removes single literals from clauses wherever the property allows it.
+    fn drop_literals(&mut self, mut clauses: Vec<Vec<i32>>) -> anyhow::Result<Vec<Vec<i32>>> {
+        for i in 0..clauses.len() {
+            let mut j = 0;
+            while j < clauses[i].len() {
+                let lit = clauses[i].remove(j);
+                if self.holds(&clauses)? {
+                    self.save(&clauses)?;
+                } else {
+                    clauses[i].insert(j, lit);
+                    j += 1;
+                }
+            }
+        }
+        Ok(clauses)
+    }
+
+    fn holds(&mut self, clauses: &[Vec<i32>]) -> anyhow::Result<bool> {
+        self.tests += 1;
+        let arg = self.arg;
+        if let Some(answer) = arg.status {
+            return Ok(arg.solver.solve_clauses(clauses).0 == answer.into());
+        }
+        if arg.wrong_model {
+            let (_, model) = arg.solver.solve_clauses(clauses);
+            return Ok(model.is_some_and(|m| !verify_model(clauses, &m)));
+        }
+        if arg.disagree {
+            let statuses: Vec<_> = Solver::all()
+                .iter()
+                .map(|s| s.solve_clauses(clauses).0)
+                .filter(|s| *s != Status::Unknown)
+                .collect();
+            return Ok(statuses.windows(2).any(|w| w[0] != w[1]));
+        }
+        let cmd = arg.cmd.as_deref().unwrap_or_default();
+        write_dimacs(BufWriter::new(File::create(self.scratch)?), clauses)?;
+        let file = self.scratch.display().to_string();
+        let cmd = if cmd.contains("{}") {
+            cmd.replace("{}", &file)
+        } else {
+            format!("{cmd} {file}")
+        };
+        let status = shell(&cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| format!("Cannot run `{cmd}`"))?;
+        Ok(match arg.exit_code {
+            Some(code) => status.code() == Some(code),
+            None => status.code().is_none(),
+        })
+    }
+
+    /// Keeps the best formula so far on disk in case the run is interrupted.
+    fn save(&self, clauses: &[Vec<i32>]) -> anyhow::Result<()> {
+        write_dimacs(BufWriter::new(File::create(self.out)?), clauses)?;
+        println!(
+            "c {} clauses, {} literals",
+            clauses.len(),
+            literals(clauses)
+        );
+        Ok(())
+    }
+}
+
+fn literals(clauses: &[Vec<i32>]) -> usize {
+    clauses.iter().map(Vec::len).sum()
+}
+
+/// Maps the variables in use onto 1..=n in order of first occurrence.
+fn renumber(clauses: &[Vec<i32>]) -> Vec<Vec<i32>> {
+    let mut map = std::collections::HashMap::new();
+    clauses
+        .iter()
+        .map(|clause| {
+            clause
+                .iter()
+                .map(|&lit| {
+                    let next = map.len() as i32 + 1;
+                    let var = *map.entry(lit.abs()).or_insert(next);
+                    if lit > 0 { var } else { -var }
+                })
+                .collect()
+        })
+        .collect()
+}
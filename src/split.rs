@@ -0,0 +1,173 @@
+//! Splitting a CNF into subproblems that can be solved independently, one
+//! DIMACS file each.
+//!
+//! By `--vars`, every assignment of the variables becomes a file of its own,
+//! with the assignment added as unit clauses: the input is satisfiable iff
+//! one of them is. By `--components`, the clauses are grouped into sets that
+//! share no variable: the input is satisfiable iff all of them are. Files
+//! keep the input's variable numbers, so their models combine directly.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Args;
+use validator::Validate;
+
+use crate::{
+    core::{non_dash, open_input},
+    dimacs::{read_commented, write_comments, write_dimacs},
+};
+
+#[derive(Args, Validate)]
+pub struct Arg {
+    /// CNF to split (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Directory the subproblems are written to, created if missing
+    #[arg(long = "out-dir", value_name = "DIR")]
+    out_dir: PathBuf,
+    /// Comma-separated variables; one subproblem per assignment of them
+    #[arg(long, value_name = "VARS", value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..), required_unless_present = "components", conflicts_with = "components")]
+    #[validate(length(max = 20, message = "At most 20 split variables are supported"))]
+    vars: Vec<i32>,
+    /// One subproblem per set of clauses that shares no variable with the rest
+    #[arg(long)]
+    components: bool,
+    /// Keep the input's comment lines that start with these comma-separated prefixes, or all if none are given, and write them ahead of the subproblems
+    #[arg(long = "keep-comments", value_name = "PREFIXES", num_args(0..=1), value_delimiter = ',')]
+    keep_comments: Option<Vec<String>>,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        self.validate()?;
+        let reader = open_input(self.input.as_deref())?;
+        let (clauses, comments) = read_commented(reader, self.keep_comments.as_deref())?;
+        fs::create_dir_all(&self.out_dir)
+            .with_context(|| format!("Cannot create {}", self.out_dir.display()))?;
+        let stem = non_dash(self.input.as_deref()).map_or("split".into(), stem);
+        if self.components {
+            let components = components(&clauses);
+            let width = components.len().to_string().len();
+            for (i, component) in components.iter().enumerate() {
+                let path = self
+                    .out_dir
+                    .join(format!("{}.component-{:0width$}.cnf", stem, i + 1));
+                write(&path, &comments, None, component)?;
+                println!("c {}: {} clauses", path.display(), component.len());
+            }
+            println!(
+                "c {} components; the input is satisfiable iff all of them are",
+                components.len()
+            );
+        } else {
+            let width = (1u64 << self.vars.len()).to_string().len();
+            for (i, cube) in cubes(&self.vars).enumerate() {
+                let path = self
+                    .out_dir
+                    .join(format!("{}.cube-{:0width$}.cnf", stem, i + 1));
+                write(&path, &comments, Some(&cube), &clauses)?;
+                let lits: Vec<String> = cube.iter().map(i32::to_string).collect();
+                println!("c {}: {}", path.display(), lits.join(" "));
+            }
+            println!(
+                "c {} cubes; the input is satisfiable iff one of them is",
+                1u64 << self.vars.len()
+            );
+        }
+        Ok(0)
+    }
+}
+
+/// Every assignment of `vars`, as literals; a single empty one if there
+/// are none.
+pub fn cubes(vars: &[i32]) -> impl Iterator<Item = Vec<i32>> + '_ {
+    (0..1u32 << vars.len()).map(|bits| {
+        vars.iter()
+            .enumerate()
+            .map(|(i, &var)| if bits & (1 << i) != 0 { var } else { -var })
+            .collect()
+    })
+}
+
+/// `clauses` grouped into sets over disjoint variables, in the order of
+/// their first clause. An empty clause is a set of its own.
+pub fn components(clauses: &[Vec<i32>]) -> Vec<Vec<Vec<i32>>> {
+    let vars = clauses
+        .iter()
+        .flatten()
+        .map(|lit| lit.unsigned_abs() as usize)
+        .max()
+        .unwrap_or(0);
+    // Union-find over the variables, with path halving.
+    let mut parent: Vec<usize> = (0..=vars).collect();
+    fn find(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+    for clause in clauses {
+        let mut lits = clause.iter().map(|lit| lit.unsigned_abs() as usize);
+        if let Some(first) = lits.next() {
+            let root = find(&mut parent, first);
+            for var in lits {
+                let other = find(&mut parent, var);
+                parent[other] = root;
+            }
+        }
+    }
+    let mut index = vec![usize::MAX; vars + 1];
+    let mut components: Vec<Vec<Vec<i32>>> = Vec::new();
+    for clause in clauses {
+        let Some(lit) = clause.first() else {
+            components.push(vec![Vec::new()]);
+            continue;
+        };
+        let root = find(&mut parent, lit.unsigned_abs() as usize);
+        if index[root] == usize::MAX {
+            index[root] = components.len();
+            components.push(Vec::new());
+        }
+        components[index[root]].push(clause.clone());
+    }
+    components
+}
+
+/// The file name of `path` without its CNF and compression extensions.
+fn stem(path: &Path) -> String {
+    let mut name = path
+        .file_name()
+        .map_or("split".into(), |name| name.to_string_lossy().into_owned());
+    for ext in [".gz", ".xz", ".zst", ".cnf", ".dimacs"] {
+        if let Some(rest) = name.strip_suffix(ext) {
+            name = rest.to_string();
+        }
+    }
+    name
+}
+
+/// Writes `clauses` to `path` after `comments`, with `cube` added as unit
+/// clauses.
+fn write(
+    path: &Path,
+    comments: &[String],
+    cube: Option<&[i32]>,
+    clauses: &[Vec<i32>],
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(
+        File::create(path).with_context(|| format!("Cannot create {}", path.display()))?,
+    );
+    write_comments(&mut writer, comments)?;
+    let mut all = clauses.to_vec();
+    if let Some(cube) = cube {
+        let lits: Vec<String> = cube.iter().map(i32::to_string).collect();
+        writeln!(writer, "c assumptions {}", lits.join(" "))?;
+        all.extend(cube.iter().map(|&lit| vec![lit]));
+    }
+    write_dimacs(writer, &all).with_context(|| format!("Cannot write {}", path.display()))
+}
@@ -0,0 +1,106 @@
+//! Human-readable variable names, from `c varname ID NAME` comments in the
+//! input or a sidecar file of `ID NAME` lines.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, anyhow};
+
+/// Names of variables, by variable.
+#[derive(Default, Clone, Debug)]
+pub struct Symbols {
+    names: BTreeMap<i32, String>,
+}
+
+impl Symbols {
+    /// Reads a symbol file: one `ID NAME` per line, or the `c varname ID
+    /// NAME` comment lines of a CNF; blank lines and `#` comments are
+    /// skipped.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let mut symbols = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = match line.strip_prefix('c') {
+                Some(comment) if comment.starts_with(char::is_whitespace) => comment
+                    .trim()
+                    .strip_prefix("varname")
+                    .ok_or_else(|| anyhow!("expected `c varname ID NAME`"))
+                    .and_then(parse_entry),
+                _ => parse_entry(line),
+            };
+            let (var, name) =
+                entry.map_err(|e| anyhow!("{}:{}: `{}`: {}", path.display(), i + 1, line, e))?;
+            symbols.names.insert(var, name);
+        }
+        Ok(symbols)
+    }
+
+    /// Records the name of a `varname ID NAME` comment; other comments are
+    /// ignored.
+    pub fn add_comment(&mut self, comment: &str) {
+        if let Some(entry) = comment.trim().strip_prefix("varname")
+            && let Ok((var, name)) = parse_entry(entry)
+        {
+            self.names.insert(var, name);
+        }
+    }
+
+    /// The named variables `model` assigns whose name matches one of
+    /// `patterns` (all of them if there are none), with their values.
+    pub fn project(&self, model: &[i32], patterns: &[String]) -> Vec<(String, bool)> {
+        let values: BTreeMap<i32, bool> = model.iter().map(|&lit| (lit.abs(), lit > 0)).collect();
+        self.names
+            .iter()
+            .filter(|(_, name)| patterns.is_empty() || patterns.iter().any(|p| glob(p, name)))
+            .filter_map(|(var, name)| Some((name.clone(), *values.get(var)?)))
+            .collect()
+    }
+}
+
+/// `ID NAME`, with the name running to the end of the line.
+fn parse_entry(text: &str) -> anyhow::Result<(i32, String)> {
+    let text = text.trim();
+    let (id, name) = text
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| anyhow!("expected a variable and a name"))?;
+    let var = id
+        .parse()
+        .ok()
+        .filter(|&var: &i32| var > 0)
+        .ok_or_else(|| anyhow!("invalid variable `{}`", id))?;
+    Ok((var, name.trim().to_string()))
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for any one.
+fn glob(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and the name position it currently covers.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
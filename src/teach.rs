@@ -0,0 +1,610 @@
+//! A textbook CDCL solver for small instances, written to be followed by
+//! hand rather than to be fast: propagation rescans every clause, decisions
+//! set the lowest unassigned variable false, and conflicts are analyzed to
+//! the first unique implication point (UIP). There are no restarts and no
+//! clause deletion.
+//!
+//! Clauses are numbered from 1 in input order, and learned clauses after
+//! them. With `--dot DIR`, the implication graph of every conflict is
+//! written as `conflict-N.dot` for Graphviz: decisions are boxes, literals
+//! of the conflict level are filled, the first UIP has a double border, and
+//! each edge carries the clause that implied its target.
+//!
+//! With `--step`, the search pauses at every decision, showing what
+//! happened since the last one and the trail level by level, until the
+//! user asks for the next one.
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, anyhow};
+use clap::Args;
+
+use crate::{Status, core::open_input, dimacs::read_dimacs};
+
+/// What one call of [`Cdcl::step`] did.
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// A decision opened `level`.
+    Decide { lit: i32, level: usize },
+    /// Unit propagation implied these literals, each with its reason clause.
+    Propagate(Vec<(i32, usize)>),
+    /// Unit propagation implied these literals, then falsified `clause`.
+    Conflict {
+        implied: Vec<(i32, usize)>,
+        clause: usize,
+    },
+    /// The last conflict was analyzed into `clause`, asserting at `level`.
+    Learn { clause: usize, level: usize },
+    Sat(Vec<i32>),
+    Unsat,
+}
+
+/// A conflict analyzed to the first UIP.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    /// Level of the conflict.
+    pub level: usize,
+    /// The literal of the conflict level every path from its decision to
+    /// the conflict goes through, closest to the conflict.
+    pub uip: i32,
+    /// The learned clause, the negation of the UIP first.
+    pub learned: Vec<i32>,
+    /// Level at which the learned clause becomes unit.
+    pub backjump: usize,
+}
+
+pub struct Cdcl {
+    /// The input clauses, then the learned ones; indexed from 0.
+    pub clauses: Vec<Vec<i32>>,
+    pub original: usize,
+    /// 1 true, -1 false, 0 unassigned; indexed by variable.
+    value: Vec<i8>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    pub trail: Vec<i32>,
+    /// Trail length at the start of every level after 0.
+    limits: Vec<usize>,
+    /// The conflict found by the last step, not analyzed yet.
+    conflict: Option<usize>,
+}
+
+impl Cdcl {
+    pub fn new(clauses: Vec<Vec<i32>>) -> Self {
+        // Repeated literals would keep a clause from becoming unit.
+        let clauses: Vec<Vec<i32>> = clauses
+            .into_iter()
+            .map(|mut clause| {
+                clause.sort_unstable_by_key(|lit| (lit.abs(), *lit));
+                clause.dedup();
+                clause
+            })
+            .collect();
+        let vars = clauses
+            .iter()
+            .flatten()
+            .map(|lit| lit.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0);
+        Self {
+            original: clauses.len(),
+            clauses,
+            value: vec![0; vars + 1],
+            level: vec![0; vars + 1],
+            reason: vec![None; vars + 1],
+            trail: Vec::new(),
+            limits: Vec::new(),
+            conflict: None,
+        }
+    }
+
+    pub fn decision_level(&self) -> usize {
+        self.limits.len()
+    }
+
+    /// Level `lit` was assigned at.
+    pub fn level_of(&self, lit: i32) -> usize {
+        self.level[lit.unsigned_abs() as usize]
+    }
+
+    /// The clause that implied `lit`, `None` for a decision.
+    pub fn reason_of(&self, lit: i32) -> Option<usize> {
+        self.reason[lit.unsigned_abs() as usize]
+    }
+
+    fn lit_value(&self, lit: i32) -> i8 {
+        let value = self.value[lit.unsigned_abs() as usize];
+        if lit > 0 { value } else { -value }
+    }
+
+    fn assign(&mut self, lit: i32, reason: Option<usize>) {
+        let var = lit.unsigned_abs() as usize;
+        self.value[var] = if lit > 0 { 1 } else { -1 };
+        self.level[var] = self.limits.len();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if level >= self.limits.len() {
+            return;
+        }
+        for lit in self.trail.drain(self.limits[level]..) {
+            let var = lit.unsigned_abs() as usize;
+            self.value[var] = 0;
+            self.reason[var] = None;
+        }
+        self.limits.truncate(level);
+    }
+
+    /// Propagates to a fixpoint; the falsified clause on a conflict.
+    fn propagate(&mut self) -> Option<usize> {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for c in 0..self.clauses.len() {
+                let mut unit = None;
+                let mut open = 0;
+                let mut satisfied = false;
+                for &lit in &self.clauses[c] {
+                    match self.lit_value(lit) {
+                        1 => {
+                            satisfied = true;
+                            break;
+                        }
+                        0 => {
+                            open += 1;
+                            unit = Some(lit);
+                        }
+                        _ => {}
+                    }
+                }
+                match (satisfied, open, unit) {
+                    (true, _, _) => {}
+                    (false, 0, _) => return Some(c),
+                    (false, 1, Some(unit)) => {
+                        self.assign(unit, Some(c));
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    /// Analyzes the falsified `conflict` by resolving it with the reasons
+    /// of the literals of its level, latest first, until one is left.
+    /// `None` for a conflict at level 0: the formula is unsatisfiable.
+    pub fn analyze(&self, conflict: usize) -> Option<Analysis> {
+        let level = self.clauses[conflict]
+            .iter()
+            .map(|&lit| self.level_of(lit))
+            .max()
+            .unwrap_or(0);
+        if level == 0 {
+            return None;
+        }
+        let mut seen = vec![false; self.value.len()];
+        let mut learned = vec![0];
+        // Literals of the conflict level still to resolve.
+        let mut open = 0;
+        let mut clause = conflict;
+        let mut position = self.trail.len();
+        loop {
+            for &lit in &self.clauses[clause] {
+                let var = lit.unsigned_abs() as usize;
+                if seen[var] || self.level[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                if self.level[var] == level {
+                    open += 1;
+                } else {
+                    learned.push(lit);
+                }
+            }
+            let lit = loop {
+                position -= 1;
+                let lit = self.trail[position];
+                if seen[lit.unsigned_abs() as usize] {
+                    break lit;
+                }
+            };
+            open -= 1;
+            if open == 0 {
+                learned[0] = -lit;
+                let backjump = learned[1..]
+                    .iter()
+                    .map(|&lit| self.level_of(lit))
+                    .max()
+                    .unwrap_or(0);
+                return Some(Analysis {
+                    level,
+                    uip: lit,
+                    learned,
+                    backjump,
+                });
+            }
+            // Not the decision of the level, which is the last one left.
+            clause = self.reason_of(lit).expect("implied literal without a reason");
+        }
+    }
+
+    /// Runs one step of the search: the analysis of a pending conflict, a
+    /// round of propagation, or a decision. Not to be called again after
+    /// [`Step::Sat`] or [`Step::Unsat`].
+    pub fn step(&mut self) -> Step {
+        if let Some(conflict) = self.conflict.take() {
+            let Some(analysis) = self.analyze(conflict) else {
+                return Step::Unsat;
+            };
+            self.backtrack(analysis.backjump);
+            self.clauses.push(analysis.learned);
+            let clause = self.clauses.len() - 1;
+            self.assign(-analysis.uip, Some(clause));
+            return Step::Learn {
+                clause,
+                level: analysis.backjump,
+            };
+        }
+        let mark = self.trail.len();
+        let conflict = self.propagate();
+        let implied: Vec<(i32, usize)> = self.trail[mark..]
+            .iter()
+            .map(|&lit| (lit, self.reason_of(lit).unwrap_or_default()))
+            .collect();
+        if let Some(clause) = conflict {
+            self.conflict = Some(clause);
+            return Step::Conflict { implied, clause };
+        }
+        if !implied.is_empty() {
+            return Step::Propagate(implied);
+        }
+        match (1..self.value.len()).find(|&var| self.value[var] == 0) {
+            Some(var) => {
+                self.limits.push(self.trail.len());
+                let lit = -(var as i32);
+                self.assign(lit, None);
+                Step::Decide {
+                    lit,
+                    level: self.limits.len(),
+                }
+            }
+            None => Step::Sat(
+                (1..self.value.len() as i32)
+                    .map(|var| if self.lit_value(var) > 0 { var } else { -var })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The implication graph of the falsified `conflict` in DOT: the
+    /// assigned literals it was derived from, back to the decisions, and a
+    /// conflict node.
+    pub fn dot(&self, conflict: usize, title: &str) -> String {
+        let analysis = self.analyze(conflict);
+        let mut position = vec![0; self.value.len()];
+        for (i, &lit) in self.trail.iter().enumerate() {
+            position[lit.unsigned_abs() as usize] = i;
+        }
+        let mut seen = vec![false; self.value.len()];
+        let mut nodes = Vec::new();
+        let mut stack: Vec<i32> = self.clauses[conflict].iter().map(|&lit| -lit).collect();
+        while let Some(lit) = stack.pop() {
+            let var = lit.unsigned_abs() as usize;
+            if seen[var] {
+                continue;
+            }
+            seen[var] = true;
+            nodes.push(lit);
+            if let Some(reason) = self.reason[var] {
+                let antecedents = self.clauses[reason].iter().filter(|&&l| l != lit);
+                stack.extend(antecedents.map(|&l| -l));
+            }
+        }
+        nodes.sort_by_key(|lit| position[lit.unsigned_abs() as usize]);
+
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph conflict {{");
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let mut label = format!("{}: C{} falsified", title, conflict + 1);
+        if let Some(analysis) = &analysis {
+            let _ = write!(
+                label,
+                " at level {}, learned ({}), back to level {}",
+                analysis.level,
+                clause_text(&analysis.learned),
+                analysis.backjump
+            );
+        }
+        let _ = writeln!(dot, "  label=\"{}\";", label);
+        let conflict_level = analysis.as_ref().map_or(0, |analysis| analysis.level);
+        for &lit in &nodes {
+            let var = lit.unsigned_abs() as usize;
+            let mut attrs = format!(
+                "label=\"x{}={} @{}\"",
+                var,
+                u8::from(lit > 0),
+                self.level[var]
+            );
+            if self.reason[var].is_none() {
+                attrs += ", shape=box";
+            }
+            if self.level[var] == conflict_level {
+                attrs += ", style=filled, fillcolor=lightblue";
+            }
+            if analysis.as_ref().is_some_and(|analysis| analysis.uip == lit) {
+                attrs += ", peripheries=2";
+            }
+            let _ = writeln!(dot, "  x{} [{}];", var, attrs);
+        }
+        let _ = writeln!(
+            dot,
+            "  conflict [label=\"conflict\", shape=octagon, color=red];"
+        );
+        for &lit in &nodes {
+            let var = lit.unsigned_abs();
+            if let Some(reason) = self.reason[var as usize] {
+                for &from in self.clauses[reason].iter().filter(|&&l| l != lit) {
+                    let _ = writeln!(
+                        dot,
+                        "  x{} -> x{} [label=\"C{}\"];",
+                        from.unsigned_abs(),
+                        var,
+                        reason + 1
+                    );
+                }
+            }
+        }
+        for &lit in &self.clauses[conflict] {
+            let _ = writeln!(
+                dot,
+                "  x{} -> conflict [label=\"C{}\", color=red];",
+                lit.unsigned_abs(),
+                conflict + 1
+            );
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+}
+
+/// One step of the search as a line for `--step`.
+fn describe(step: &Step, cdcl: &Cdcl) -> String {
+    let implied = |implied: &[(i32, usize)]| {
+        let lits: Vec<String> = implied
+            .iter()
+            .map(|&(lit, reason)| format!("{} (C{})", lit, reason + 1))
+            .collect();
+        lits.join(", ")
+    };
+    match step {
+        Step::Decide { lit, level } => format!("decide {} at level {}", lit, level),
+        Step::Propagate(lits) => format!("  propagate {}", implied(lits)),
+        Step::Conflict { implied: lits, clause } if lits.is_empty() => {
+            format!("  conflict on C{}", clause + 1)
+        }
+        Step::Conflict { implied: lits, clause } => format!(
+            "  propagate {}\n  conflict on C{} ({})",
+            implied(lits),
+            clause + 1,
+            clause_text(&cdcl.clauses[*clause])
+        ),
+        Step::Learn { clause, level } => format!(
+            "  learn C{} ({}), back to level {}, asserting {}",
+            clause + 1,
+            clause_text(&cdcl.clauses[*clause]),
+            level,
+            cdcl.clauses[*clause][0]
+        ),
+        Step::Sat(_) => "all variables assigned without a conflict".to_string(),
+        Step::Unsat => "conflict at level 0".to_string(),
+    }
+}
+
+/// The trail level by level, with the decisions in brackets.
+fn trail_text(cdcl: &Cdcl) -> String {
+    let mut text = String::from("trail");
+    let mut level = None;
+    for &lit in &cdcl.trail {
+        if level != Some(cdcl.level_of(lit)) {
+            level = Some(cdcl.level_of(lit));
+            let _ = write!(text, " | @{}:", cdcl.level_of(lit));
+        }
+        match cdcl.reason_of(lit) {
+            Some(_) => {
+                let _ = write!(text, " {}", lit);
+            }
+            None => {
+                let _ = write!(text, " [{}]", lit);
+            }
+        }
+    }
+    text
+}
+
+/// A clause as its literals separated by spaces.
+pub fn clause_text(clause: &[i32]) -> String {
+    let lits: Vec<String> = clause.iter().map(i32::to_string).collect();
+    lits.join(" ")
+}
+
+#[derive(Args)]
+pub struct Arg {
+    /// CNF to solve (plain, gzip or xz); default for stdin
+    #[arg(value_name = "INPUT")]
+    input: Option<PathBuf>,
+    /// Write the implication graph of every conflict to this directory, as conflict-N.dot
+    #[arg(long, value_name = "DIR")]
+    dot: Option<PathBuf>,
+    /// Refuse instances with more variables; the solver is meant for classroom examples
+    #[arg(long = "max-vars", value_name = "N", default_value_t = 100)]
+    max_vars: usize,
+    /// Pause at every decision, showing the propagations, conflicts and learned clauses since the last one and the trail; reads commands from stdin, so INPUT is required
+    #[arg(long, requires = "input")]
+    step: bool,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let reader = open_input(self.input.as_deref())?;
+        let mut clauses = Vec::new();
+        read_dimacs(reader, false, &mut clauses)?;
+        let mut cdcl = Cdcl::new(clauses);
+        let vars = cdcl.value.len() - 1;
+        if vars > self.max_vars {
+            return Err(anyhow!(
+                "{} variables, more than --max-vars {}; teach is meant for small instances",
+                vars,
+                self.max_vars
+            ));
+        }
+        if let Some(dir) = &self.dot {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let mut conflicts = 0;
+        let mut stepping = self.step;
+        let mut commands = io::stdin().lock();
+        loop {
+            let step = cdcl.step();
+            if self.step {
+                println!("{}", describe(&step, &cdcl));
+            }
+            if stepping && matches!(step, Step::Decide { .. }) {
+                println!("{}", trail_text(&cdcl));
+                print!("[Enter] next decision, c continue, q quit: ");
+                io::stdout().flush()?;
+                let mut command = String::new();
+                commands.read_line(&mut command)?;
+                // End of input quits as well.
+                if command.is_empty() || command.trim() == "q" {
+                    println!("s UNKNOWN");
+                    return Ok(Status::Unknown.exit_code());
+                }
+                if command.trim() == "c" {
+                    stepping = false;
+                }
+            }
+            match step {
+                Step::Conflict { clause, .. } => {
+                    conflicts += 1;
+                    if let Some(dir) = &self.dot {
+                        let path = dir.join(format!("conflict-{}.dot", conflicts));
+                        let title = format!("Conflict {}", conflicts);
+                        fs::write(&path, cdcl.dot(clause, &title))
+                            .with_context(|| format!("Cannot write {}", path.display()))?;
+                    }
+                }
+                Step::Learn { clause, level } if !self.step => println!(
+                    "c conflict {}: learned C{} ({}), back to level {}",
+                    conflicts,
+                    clause + 1,
+                    clause_text(&cdcl.clauses[clause]),
+                    level
+                ),
+                Step::Sat(model) => {
+                    println!("c {} conflicts", conflicts);
+                    println!("s SATISFIABLE");
+                    println!("v {} 0", clause_text(&model));
+                    return Ok(Status::Satisfiable.exit_code());
+                }
+                Step::Unsat => {
+                    println!("c {} conflicts", conflicts);
+                    println!("s UNSATISFIABLE");
+                    return Ok(Status::Unsatisfiable.exit_code());
+                }
+                Step::Decide { .. } | Step::Propagate(_) | Step::Learn { .. } => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the search to the end: the model, or `None` if unsatisfiable.
+    fn solve(clauses: &[Vec<i32>]) -> Option<Vec<i32>> {
+        let mut cdcl = Cdcl::new(clauses.to_vec());
+        loop {
+            match cdcl.step() {
+                Step::Sat(model) => return Some(model),
+                Step::Unsat => return None,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn pigeonhole_is_unsatisfiable() {
+        // Three pigeons in two holes; pigeon i in hole j is 2i + j + 1.
+        let p = |i: i32, j: i32| 2 * i + j + 1;
+        let mut clauses: Vec<Vec<i32>> = (0..3).map(|i| vec![p(i, 0), p(i, 1)]).collect();
+        for j in 0..2 {
+            for a in 0..3 {
+                for b in a + 1..3 {
+                    clauses.push(vec![-p(a, j), -p(b, j)]);
+                }
+            }
+        }
+        assert_eq!(solve(&clauses), None);
+    }
+
+    #[test]
+    fn model_satisfies_the_clauses() {
+        let clauses = vec![
+            vec![1, 2],
+            vec![3, 4],
+            vec![-4, 5],
+            vec![-4, -2, 6],
+            vec![-5, -6],
+            vec![-1, -3, -6],
+        ];
+        let model = solve(&clauses).unwrap();
+        assert_eq!(model.len(), 6);
+        for clause in &clauses {
+            assert!(clause.iter().any(|lit| model.contains(lit)), "{clause:?}");
+        }
+    }
+
+    #[test]
+    fn first_uip_of_a_conflict() {
+        // -1 implies 2 at level 1; -3 implies 4, which implies 5 and, with
+        // 2, 6 at level 2, falsifying C5. Every path from -3 to the
+        // conflict goes through 4, and 2 is the only literal of level 1.
+        let mut cdcl = Cdcl::new(vec![
+            vec![1, 2],
+            vec![3, 4],
+            vec![-4, 5],
+            vec![-4, -2, 6],
+            vec![-5, -6],
+        ]);
+        assert!(matches!(cdcl.step(), Step::Decide { lit: -1, level: 1 }));
+        assert!(matches!(cdcl.step(), Step::Propagate(implied) if implied == [(2, 0)]));
+        assert!(matches!(cdcl.step(), Step::Decide { lit: -3, level: 2 }));
+        let Step::Conflict { implied, clause } = cdcl.step() else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(implied, [(4, 1), (5, 2), (6, 3)]);
+        assert_eq!(clause, 4);
+        let analysis = cdcl.analyze(clause).unwrap();
+        assert_eq!(analysis.level, 2);
+        assert_eq!(analysis.uip, 4);
+        assert_eq!(analysis.learned, [-4, -2]);
+        assert_eq!(analysis.backjump, 1);
+        assert!(matches!(
+            cdcl.step(),
+            Step::Learn {
+                clause: 5,
+                level: 1
+            }
+        ));
+        assert_eq!(cdcl.trail, [-1, 2, -4]);
+        assert_eq!(cdcl.reason_of(-4), Some(5));
+    }
+}
@@ -0,0 +1,69 @@
+//! Time series of a run for plotting.
+//!
+//! The solvers run inside a single foreign call and expose no progress
+//! counters (conflicts, decisions, LBD), so the trace samples what can be
+//! observed from outside: wall-clock and CPU time, resident memory and the
+//! pipeline stage.
+use std::{
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{core::Stat, utils::get_memory};
+
+/// Background thread appending one CSV row per interval.
+pub struct Tracer {
+    stop: Sender<()>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Tracer {
+    pub fn start(path: &Path, interval: Duration, stat: Arc<Mutex<Stat>>) -> io::Result<Self> {
+        let mut out = LineWriter::new(File::create(path)?);
+        writeln!(out, "time,cpu_time,memory,stage")?;
+        let (stop, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            loop {
+                let finished = match rx.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+                };
+                let (time, cpu_time, stage) = {
+                    let stat = stat.lock().unwrap_or_else(|e| e.into_inner());
+                    (
+                        stat.run_time.elapsed(),
+                        stat.total_time.elapsed(),
+                        stat.stage(),
+                    )
+                };
+                writeln!(
+                    out,
+                    "{:.3},{:.3},{},{}",
+                    time.as_secs_f64(),
+                    cpu_time.as_secs_f64(),
+                    get_memory().map(|m| m.to_string()).unwrap_or_default(),
+                    stage
+                )?;
+                if finished {
+                    return Ok(());
+                }
+            }
+        });
+        Ok(Self { stop, handle })
+    }
+
+    /// Writes a last sample and waits for the thread to exit.
+    pub fn finish(self) -> io::Result<()> {
+        let _ = self.stop.send(());
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("trace thread panicked")))
+    }
+}
@@ -6,24 +6,55 @@ pub use windows::*;
 mod unix;
 #[cfg(unix)]
 pub use unix::*;
+#[cfg(target_family = "wasm")]
+mod wasm;
+#[cfg(target_family = "wasm")]
+pub use wasm::*;
 
+use std::{process::Command, time::Duration};
+
+/// What the child processes of this one used, over all of them that have
+/// ended and been waited for, their own children included.
+#[derive(Clone, Copy, Debug)]
+pub struct ChildUsage {
+    /// User and system CPU time, summed.
+    pub cpu_time: Duration,
+    /// Peak resident memory in bytes of the largest of them.
+    pub peak_memory: u64,
+}
+
+/// `cmd` run through the platform's shell.
+#[cfg(windows)]
+pub fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.args(["/C", cmd]);
+    command
+}
+
+/// `cmd` run through the platform's shell.
+#[cfg(not(windows))]
+pub fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", cmd]);
+    command
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub use cpu_time::ProcessTime;
+#[cfg(not(target_family = "wasm"))]
 use sysinfo::{Pid, ProcessesToUpdate};
 
+#[cfg(not(target_family = "wasm"))]
 pub fn get_memory()->Option<u64>{
       // 获取当前进程 ID
     let pid = Pid::from_u32(std::process::id());
     let mut sys = sysinfo::System::new();
 
     // 刷新进程信息
-    sys.refresh_processes(ProcessesToUpdate::All,true);
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]),true);
 
     // 查询当前进程的内存使用
-    if let Some(process) = sys.process(pid) {
-        // 内存使用量（单位：字节）
-        let memory_usage_bytes = process.memory();
-        return  Some(memory_usage_bytes);
-    } else {
-       return None;
-    }
+    // 内存使用量（单位：字节）
+    sys.process(pid).map(|process| process.memory())
 
 }
@@ -29,4 +29,24 @@ pub fn limit_memory(max_memory:u64) -> anyhow::Result<()> {
     }
     rlimit::setrlimit(rlimit::Resource::AS, max_memory, rlim_max)?;
     Ok(())
-}
\ No newline at end of file
+}
+/// The usage of the child processes waited for so far, or `None` if there
+/// were none.
+pub fn children_usage() -> Option<super::ChildUsage> {
+    // SAFETY: `rusage` is plain data, and getrusage only writes into it.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return None;
+    }
+    let time = |t: libc::timeval| {
+        std::time::Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000)
+    };
+    let cpu_time = time(usage.ru_utime) + time(usage.ru_stime);
+    // Kibibytes everywhere but on macOS, where it is bytes.
+    let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    let peak_memory = usage.ru_maxrss as u64 * unit;
+    (!cpu_time.is_zero() || peak_memory > 0).then_some(super::ChildUsage {
+        cpu_time,
+        peak_memory,
+    })
+}
@@ -0,0 +1,25 @@
+//! WASI has no resource limits, signals or process statistics; runs are
+//! expected to be bounded by the host runtime instead.
+pub use std::time::Instant as ProcessTime;
+
+pub fn limit_time(max_cpu_time: u64) -> anyhow::Result<()> {
+    if max_cpu_time == 0 {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("CPU limit not supported on WebAssembly"))
+}
+
+pub fn limit_memory(max_memory: u64) -> anyhow::Result<()> {
+    if max_memory == 0 {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("Memory limit not supported on WebAssembly"))
+}
+
+pub fn get_memory() -> Option<u64> {
+    None
+}
+
+pub fn children_usage() -> Option<super::ChildUsage> {
+    None
+}
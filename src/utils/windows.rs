@@ -11,3 +11,9 @@ pub fn limit_memory(max_memory: u64) -> anyhow::Result<()> {
     }
     Err(anyhow::anyhow!("Memory limit not supported on Windows"))
 }
+
+/// Not collected on Windows, where the children would have to run in a job
+/// object to be accounted for.
+pub fn children_usage() -> Option<super::ChildUsage> {
+    None
+}
@@ -0,0 +1,130 @@
+//! Build identification: the CLI version, the embedded solvers and the
+//! cargo features the binary was built with, and the machine it runs on.
+use clap::Args;
+
+use crate::Solver;
+
+#[derive(Args)]
+pub struct Arg {
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Cargo features enabled in this build.
+pub fn features() -> Vec<&'static str> {
+    [
+        ("minisat", cfg!(feature = "minisat")),
+        ("glucose", cfg!(feature = "glucose")),
+        ("fetch", cfg!(feature = "fetch")),
+        ("compression", cfg!(feature = "compression")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+fn profile() -> &'static str {
+    if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    }
+}
+
+/// Commit the binary was built from, if it was built from a git checkout.
+pub fn git_commit() -> Option<&'static str> {
+    option_env!("SATGALAXY_GIT_COMMIT")
+}
+
+fn target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// The build, as recorded in results.
+pub fn build_json() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": git_commit(),
+        "features": features(),
+        "target": target(),
+        "profile": profile(),
+    })
+}
+
+/// The machine, as recorded in results: host name, CPU model, logical
+/// cores and operating system.
+pub fn host_json() -> serde_json::Value {
+    let (hostname, cpu, os) = host();
+    serde_json::json!({
+        "hostname": hostname,
+        "cpu": cpu,
+        "cores": std::thread::available_parallelism().map(|n| n.get()).ok(),
+        "os": os.unwrap_or_else(|| std::env::consts::OS.to_string()),
+    })
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn host() -> (Option<String>, Option<String>, Option<String>) {
+    use sysinfo::{CpuRefreshKind, System};
+
+    let mut sys = System::new();
+    sys.refresh_cpu_list(CpuRefreshKind::nothing());
+    let cpu = sys.cpus().first().map(|cpu| cpu.brand().trim().to_string());
+    (System::host_name(), cpu, System::long_os_version())
+}
+
+#[cfg(target_family = "wasm")]
+fn host() -> (Option<String>, Option<String>, Option<String>) {
+    (None, None, None)
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let version = env!("CARGO_PKG_VERSION");
+        let target = target();
+        if self.json {
+            let backends: Vec<_> = Solver::all()
+                .iter()
+                .map(|s| {
+                    serde_json::json!({
+                        "name": s.name(),
+                        "version": s.version(),
+                        "upstream": s.upstream(),
+                    })
+                })
+                .collect();
+            let json = serde_json::json!({
+                "version": version,
+                "git_commit": git_commit(),
+                "backends": backends,
+                "features": features(),
+                "target": target,
+                "profile": profile(),
+                "host": host_json(),
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            return Ok(0);
+        }
+        match git_commit() {
+            Some(commit) => println!("satgalaxy {version} ({commit})"),
+            None => println!("satgalaxy {version}"),
+        }
+        println!("backends:");
+        for solver in Solver::all() {
+            println!(
+                "  {:<8} {:<8} {}",
+                solver.name(),
+                solver.version(),
+                solver.upstream()
+            );
+        }
+        println!("features: {}", features().join(", "));
+        println!("target:   {target} ({})", profile());
+        let host = host_json();
+        let field = |name: &str| host[name].as_str().unwrap_or("unknown").to_string();
+        println!("host:     {} ({})", field("hostname"), field("os"));
+        println!("cpu:      {} x {}", host["cores"], field("cpu"));
+        Ok(0)
+    }
+}
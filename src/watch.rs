@@ -0,0 +1,136 @@
+//! Spool directory mode: CNFs dropped into a directory are solved one after
+//! another.
+//!
+//! The directory is polled; a file is taken once its size and modification
+//! time have not changed between two polls, so one still being written is
+//! left alone. The result document written by `--json` is stored next to it
+//! as `NAME.json` (an `error` document if it could not be solved), and the
+//! input is moved to `done/`. Hidden files and `*.json` and `*.part` files
+//! are ignored, so results and files copied in under a temporary name are
+//! not taken for inputs.
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use clap::Args;
+
+use crate::{
+    SolveRequest, Solver,
+    core::{result_json, write_atomic},
+    hook,
+};
+
+#[derive(Args)]
+pub struct Arg {
+    /// Spool directory to watch
+    #[arg(value_name = "DIR")]
+    dir: PathBuf,
+    /// Backend the inputs are solved with
+    #[arg(long, default_value = "minisat")]
+    solver: String,
+    /// Backend option by its long flag name, e.g. `--option K=0.7`; may be repeated
+    #[arg(long = "option", value_name = "FLAG=VALUE", value_parser = parse_option)]
+    options: Vec<(String, String)>,
+    /// Time between two scans of the directory
+    #[arg(long, value_name = "DURATION", default_value = "1s", value_parser = crate::core::parse_duration)]
+    interval: Duration,
+    /// Solve the inputs already in the directory and exit instead of watching it
+    #[arg(long)]
+    once: bool,
+}
+
+impl Arg {
+    pub fn run(&self) -> anyhow::Result<i32> {
+        let request = SolveRequest::new(Solver::from_args(&self.solver, &self.options)?);
+        let done = self.dir.join("done");
+        fs::create_dir_all(&done).with_context(|| format!("Cannot create {}", done.display()))?;
+        println!(
+            "c Watching {} with {} every {:?}",
+            self.dir.display(),
+            self.solver,
+            self.interval
+        );
+        // Size and modification time of the inputs seen on the last scan.
+        let mut seen = BTreeMap::new();
+        loop {
+            let mut current = BTreeMap::new();
+            for path in self.inputs()? {
+                let Ok(meta) = fs::metadata(&path) else {
+                    continue;
+                };
+                let state = (meta.len(), meta.modified().ok());
+                if self.once || seen.get(&path) == Some(&state) {
+                    self.process(&request, &path, &done)?;
+                } else {
+                    current.insert(path, state);
+                }
+            }
+            if self.once {
+                return Ok(0);
+            }
+            seen = current;
+            thread::sleep(self.interval);
+        }
+    }
+
+    /// Candidate inputs in the spool directory, in name order.
+    fn inputs(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut inputs = Vec::new();
+        let entries =
+            fs::read_dir(&self.dir).with_context(|| format!("Cannot read {}", self.dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || name.ends_with(".json") || name.ends_with(".part") {
+                continue;
+            }
+            if entry.file_type()?.is_file() {
+                inputs.push(entry.path());
+            }
+        }
+        inputs.sort();
+        Ok(inputs)
+    }
+
+    /// Solves `path`, writes its result next to it and moves it to `done`.
+    fn process(&self, request: &SolveRequest, path: &Path, done: &Path) -> anyhow::Result<()> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+        let result = File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut file| crate::solve_reader(request, &mut file));
+        let (json, status) = match &result {
+            Ok(result) => (result_json(result, &self.options), result.status.as_str()),
+            Err(e) => (
+                serde_json::json!({
+                    "error": e.to_string(),
+                    "error_details": hook::error_details(e),
+                }),
+                "ERROR",
+            ),
+        };
+        let mut target = path.as_os_str().to_owned();
+        target.push(".json");
+        let target = PathBuf::from(target);
+        write_atomic(&target, json.to_string() + "\n")?;
+        fs::rename(path, done.join(name))
+            .with_context(|| format!("Cannot move {} to {}", path.display(), done.display()))?;
+        println!("c {}: {}", name.to_string_lossy(), status);
+        Ok(())
+    }
+}
+
+fn parse_option(s: &str) -> Result<(String, String), String> {
+    let (flag, value) = s.split_once('=').unwrap_or((s, ""));
+    let flag = flag.trim_start_matches("--");
+    if flag.is_empty() {
+        return Err(format!("`{s}` does not name an option"));
+    }
+    Ok((flag.to_string(), value.to_string()))
+}